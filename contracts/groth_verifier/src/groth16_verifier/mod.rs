@@ -13,6 +13,11 @@ extern crate wee_alloc;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+// sizes of the uncompressed encodings of a proof and of a public input,
+// so that neither needs a heap buffer to be decoded
+pub const PROOF_BYTES: usize = 384;
+pub const FR_BYTES: usize = 32;
+
 pub struct SorobanGroth16Verifier {
     pub vk_hash: BytesN<32>,
 }
@@ -37,10 +42,12 @@ impl SorobanGroth16Verifier {
         }
 
         // deserialize proof
-        let len = proof_bytes.len();
-        let mut bvec = alloc::vec![0u8;len as usize];
-        proof_bytes.copy_into_slice(bvec.as_mut_slice());
-        let proof = types::Proof::deserialize_uncompressed(bvec.as_slice()).unwrap();
+        if proof_bytes.len() as usize != PROOF_BYTES {
+            panic!("malformed proof")
+        }
+        let mut proof_buf = [0u8; PROOF_BYTES];
+        proof_bytes.copy_into_slice(&mut proof_buf);
+        let proof = types::Proof::deserialize_uncompressed(&proof_buf[..]).unwrap();
 
         // deserialize key
         let k_len = key_bytes.len();
@@ -49,16 +56,18 @@ impl SorobanGroth16Verifier {
         let vk =
             types::VerifyingKey::<Bls12_377>::deserialize_uncompressed(k_bvec.as_slice()).unwrap();
 
-        let prep_vk = prepare_vk(&vk);
+        let prep_vk = prepare_vk(vk);
 
-        let mut vimage = alloc::vec![];
+        let mut vimage = alloc::vec::Vec::with_capacity(image_vbytes.len() as usize);
 
         for image_bytes in image_vbytes.iter() {
-            let len = image_bytes.len();
-            let mut i_bvec = alloc::vec![0u8; len as usize];
-            image_bytes.copy_into_slice(&mut i_bvec);
+            if image_bytes.len() as usize != FR_BYTES {
+                panic!("malformed public input")
+            }
+            let mut i_buf = [0u8; FR_BYTES];
+            image_bytes.copy_into_slice(&mut i_buf);
 
-            let fr = Fr::deserialize_uncompressed(i_bvec.as_slice()).unwrap();
+            let fr = Fr::deserialize_uncompressed(&i_buf[..]).unwrap();
             vimage.push(fr)
         }
 
@@ -66,7 +75,5 @@ impl SorobanGroth16Verifier {
     }
 }
 
-mod key_wrap;
-mod proof_wrap;
-mod types;
+pub(crate) mod types;
 mod verify_utils;
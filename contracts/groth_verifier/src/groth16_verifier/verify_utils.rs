@@ -28,16 +28,17 @@ pub fn aggregate_inputs(
     g_ic
 }
 
-/// precompute params to be used in the verifying key
-pub fn prepare_vk<E>(vk: &VerifyingKey<E>) -> PreparedVK<E>
+/// precompute params to be used in the verifying key; takes the key by
+/// value so that it is moved, not cloned, into the prepared key
+pub fn prepare_vk<E>(vk: VerifyingKey<E>) -> PreparedVK<E>
 where
     E: PairingEngine,
 {
     PreparedVK {
-        vk: vk.clone(),
         e_alpha_beta: E::pairing(vk.alpha_g1, vk.beta_g2),
         gamma_neg: vk.gamma_g2.neg(),
         delta_neg: vk.delta_g2.neg(),
+        vk,
     }
 }
 
@@ -34,3 +34,4 @@ impl SanctumVerifier {
     }
 }
 
+mod test;
@@ -0,0 +1,73 @@
+#![cfg(test)]
+
+use ark_bls12_377::{Bls12_377, Fr, G1Affine, G2Affine};
+use ark_ec::AffineCurve;
+use ark_ff::Zero;
+use ark_serialize::CanonicalSerialize;
+use soroban_sdk::{Bytes, Env, Vec};
+
+use crate::groth16_verifier::{types::Proof, FR_BYTES, PROOF_BYTES};
+use crate::{SanctumVerifier, SanctumVerifierClient};
+
+extern crate std;
+
+// the network's limit on the size of a contract's wasm
+const WASM_SIZE_BUDGET: u64 = 64 * 1024;
+
+// written by userland's gen-soroban-vectors from arkworks proofs, see `make vectors`
+const VECTORS_FILE: &str = "testdata/vectors.json";
+
+#[test]
+fn test_fixed_size_encodings() {
+    // the verify path decodes into stack buffers of these sizes
+    let proof = Proof::<Bls12_377> {
+        a: G1Affine::prime_subgroup_generator(),
+        b: G2Affine::prime_subgroup_generator(),
+        c: G1Affine::prime_subgroup_generator(),
+    };
+    assert_eq!(proof.uncompressed_size(), PROOF_BYTES);
+    assert_eq!(Fr::zero().uncompressed_size(), FR_BYTES);
+}
+
+#[test]
+#[ignore = "needs the wasm built by `make build`; `make test` runs it"]
+fn test_wasm_size_budget() {
+    let wasm = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../target/wasm32-unknown-unknown/release/sanctum_proof_verifier_contract.wasm");
+
+    let metadata = std::fs::metadata(&wasm)
+        .unwrap_or_else(|_| panic!("{} is not built", wasm.display()));
+    assert!(
+        metadata.len() <= WASM_SIZE_BUDGET,
+        "wasm is {} bytes, over the budget of {}", metadata.len(), WASM_SIZE_BUDGET
+    );
+}
+
+#[test]
+fn test_arkworks_vectors() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_FILE);
+    let vectors: std::vec::Vec<serde_json::Value> = match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json).unwrap(),
+        Err(_) => return std::println!("skipping vector check, {} is not generated", path.display()),
+    };
+
+    for vector in vectors.iter() {
+        let env = Env::default();
+        env.budget().reset_unlimited();
+        let client = SanctumVerifierClient::new(&env, &env.register_contract(None, SanctumVerifier));
+
+        let bytes = |value: &serde_json::Value| Bytes::from_slice(&env, &hex::decode(value.as_str().unwrap()).unwrap());
+        let key = bytes(&vector["vk_bytes"]);
+        let mut image = Vec::new(&env);
+        for input in vector["image_bytes"].as_array().unwrap() {
+            image.push_back(bytes(input));
+        }
+
+        // each vector is checked against the key it was proven under, so the
+        // contract's key hash check passes; a proof the contract can't decode
+        // traps rather than returning false, and is a rejection all the same
+        client.init(&env.crypto().sha256(&key));
+        let verdict = matches!(client.try_verify(&key, &bytes(&vector["proof_bytes"]), &image), Ok(Ok(true)));
+        assert_eq!(verdict, vector["expected"].as_bool().unwrap(), "{}", vector["name"]);
+    }
+}
@@ -1,18 +1,27 @@
 #![no_std]
 
-mod utils;
+pub mod utils;
 
 use soroban_sdk::{
-    contract, contracterror, contractimpl, contracttype, log, 
-    Env,
-    Val, BytesN
+    contract, contracterror, contractimpl, contracttype, log, vec,
+    Env, Address, IntoVal, Map, Symbol, TryFromVal,
+    Val, Bytes, BytesN, Vec
 };
 
-// define the depth of the merkle tree as a constant
-const MERKLE_TREE_LEVELS: u32 = 15;
+// depth of the tree the circuits prove membership in, as no root over a tree
+// of another depth could ever match a proof; off-chain code carries the same
+// constant, see protocol::MERKLE_TREE_LEVELS, and its tests check they agree
+const MERKLE_TREE_LEVELS: u32 = 8;
 
-// how many historical roots to store
-const ROOT_HISTORY_SIZE: u32 = 30;
+// version of the storage layout written by this code; bump it alongside a
+// new step in migrate whenever the layout changes
+const SCHEMA_VERSION: u32 = 3;
+
+// version of the packing of public inputs into the Vec<Bytes> payment hands
+// the verifier; off-chain code carries the same constant, see
+// protocol::PUBLIC_INPUT_LAYOUT_VERSION, and must agree with it before it
+// builds a tx. Bump both whenever an input is added or reordered
+const PUBLIC_INPUT_LAYOUT_VERSION: u32 = 1;
 
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -22,6 +31,18 @@ pub enum SanctumError {
     IllegalContractCall = 2,
     DuplicateNullifier = 3,
     UnknownRoot = 4,
+    InvalidRootHistorySize = 5,
+    SchemaUpToDate = 6,
+    InvalidProof = 8,
+    MalformedProof = 9,
+    UnknownTree = 10,
+    TreeFull = 11,
+    // an entry every initialized contract holds is gone: its TTL ran out,
+    // and must be extended (after restoring the entry) for the contract to work
+    StateExpired = 12,
+    // the public inputs a payment carries are not those of its root, new
+    // coin and nullifier, so a valid proof of them would not be a proof of it
+    PublicInputMismatch = 13,
 }
 
 #[contracttype]
@@ -32,7 +53,32 @@ enum DataKey {
     Roots(u32),
     NextIndex,
     CurrentRootIndex,
+    RootHistorySize,
     Nullifier(BytesN<32>),
+    Admin,
+    SchemaVersion,
+    Verifier,
+    PaymentVk,
+    CurrentTree,
+    RetiredRoot(u32),
+    LayoutVersion,
+}
+
+// the contract instance lives as long as the contract can be called at all,
+// so unlike the persistent Initialized entry, its mark cannot expire on its own
+fn is_initialized(env: &Env) -> bool
+{
+    env.storage().instance().get(&DataKey::Initialized).unwrap_or(false) ||
+        env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false)
+}
+
+// reads an entry initialize writes; one missing from an initialized contract
+// has outlived its TTL, rather than never been written
+fn stored<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Result<V, SanctumError>
+{
+    env.storage().persistent().get(key).ok_or_else(|| {
+        if is_initialized(env) { SanctumError::StateExpired } else { SanctumError::ContractUnititialized }
+    })
 }
 
 #[contract]
@@ -41,14 +87,25 @@ pub struct SanctumContract;
 #[contractimpl]
 impl SanctumContract {
 
-    pub fn initialize(env: Env) -> Result<(), SanctumError>
+    // root_history_size is how many historical roots to store; off-chain
+    // verifiers must be configured with the same window. admin is the only
+    // address allowed to upgrade the contract
+    pub fn initialize(env: Env, admin: Address, root_history_size: u32) -> Result<(), SanctumError>
     {
         let levels = MERKLE_TREE_LEVELS;
         // only proceed if the contract is uninitialized
-        if env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false) {
+        if is_initialized(&env) {
             return Err(SanctumError::IllegalContractCall);
         }
 
+        if root_history_size == 0 {
+            return Err(SanctumError::InvalidRootHistorySize);
+        }
+        env.storage().persistent().set(&DataKey::RootHistorySize, &root_history_size);
+        env.storage().persistent().set(&DataKey::Admin, &admin);
+        env.storage().persistent().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        env.storage().persistent().set(&DataKey::LayoutVersion, &PUBLIC_INPUT_LAYOUT_VERSION);
+
         // initialize the filledSubtrees data structure 
         // for (uint32 i = 0; i < _levels; i++) {
         //   filledSubtrees[i] = zeros(i);
@@ -69,15 +126,150 @@ impl SanctumContract {
 
         // set persistent state to mark the contract as initialized
         env.storage().persistent().set(&DataKey::Initialized, &true);
+        env.storage().instance().set(&DataKey::Initialized, &true);
 
         Ok(())
     }
     
+    pub fn root_history_size(env: Env) -> Result<u32, SanctumError>
+    {
+        stored(&env, &DataKey::RootHistorySize)
+    }
+
+    pub fn tree_levels(_env: Env) -> u32
+    {
+        MERKLE_TREE_LEVELS
+    }
+
+    // replaces the contract's code, keeping its storage (tree, roots, nullifiers)
+    // intact; the new code then runs migrate once
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), SanctumError>
+    {
+        Self::admin(&env)?.require_auth();
+
+        log!(&env, "[CONTRACTCALL] upgrade({})", new_wasm_hash);
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+
+        Ok(())
+    }
+
+    // brings storage written by an older version up to SCHEMA_VERSION
+    pub fn migrate(env: Env) -> Result<(), SanctumError>
+    {
+        Self::admin(&env)?.require_auth();
+
+        // contracts deployed before the schema was versioned are at version 1
+        let stored_version: u32 = env.storage().persistent().get(&DataKey::SchemaVersion).unwrap_or(1);
+        if stored_version >= SCHEMA_VERSION {
+            return Err(SanctumError::SchemaUpToDate);
+        }
+
+        log!(&env, "[CONTRACTCALL] migrate({} -> {})", stored_version, SCHEMA_VERSION);
+
+        // one step per version goes here
+        if stored_version < 2 && env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false) {
+            // version 2 marks initialization in instance storage too
+            env.storage().instance().set(&DataKey::Initialized, &true);
+        }
+        if stored_version < 3 && env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false) {
+            // version 3 records the public input layout, which had not
+            // changed before it was versioned
+            env.storage().persistent().set(&DataKey::LayoutVersion, &1u32);
+        }
+
+        env.storage().persistent().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Ok(())
+    }
+
+    pub fn schema_version(env: Env) -> u32
+    {
+        env.storage().persistent().get(&DataKey::SchemaVersion).unwrap_or(1)
+    }
+
+    // the public input layout payment expects; see PUBLIC_INPUT_LAYOUT_VERSION
+    pub fn layout_version(env: Env) -> Result<u32, SanctumError>
+    {
+        stored(&env, &DataKey::LayoutVersion)
+    }
+
+    // points payment at the groth verifier contract, and the (uncompressed)
+    // verifying key it must check payment proofs against
+    pub fn set_verifier(env: Env, verifier: Address, payment_vk: Bytes) -> Result<(), SanctumError>
+    {
+        Self::admin(&env)?.require_auth();
+
+        log!(&env, "[CONTRACTCALL] set_verifier({})", verifier);
+        env.storage().persistent().set(&DataKey::Verifier, &verifier);
+        env.storage().persistent().set(&DataKey::PaymentVk, &payment_vk);
+
+        Ok(())
+    }
+
+    // retires the active tree, which is then spend-only against its final
+    // root, and opens an empty one in its place; returns the new tree's id.
+    // Nullifiers are kept, so a coin of a retired tree is still spent once
+    pub fn start_new_tree(env: Env) -> Result<u32, SanctumError>
+    {
+        Self::admin(&env)?.require_auth();
+
+        let levels = MERKLE_TREE_LEVELS;
+        let retired_tree = Self::current_tree(env.clone());
+        let current_root_index: u32 = stored(&env, &DataKey::CurrentRootIndex)?;
+        let root_history_size: u32 = stored(&env, &DataKey::RootHistorySize)?;
+        let final_root: BytesN<32> = stored(&env, &DataKey::Roots(current_root_index))?;
+        env.storage().persistent().set(&DataKey::RetiredRoot(retired_tree), &final_root);
+
+        log!(&env, "[CONTRACTCALL] start_new_tree({} -> {})", retired_tree, retired_tree + 1);
+
+        // the new tree starts out as initialize left the first one
+        for i in 0..levels {
+            env.storage().persistent().set(&DataKey::FilledSubtree(i), &BytesN::from_array(&env, &utils::zeros(i)));
+        }
+        for i in 1..root_history_size {
+            env.storage().persistent().remove(&DataKey::Roots(i));
+        }
+        env.storage().persistent().set(&DataKey::Roots(0u32), &BytesN::from_array(&env, &utils::zeros(levels - 1)));
+        env.storage().persistent().set(&DataKey::NextIndex, &0u32);
+        env.storage().persistent().set(&DataKey::CurrentRootIndex, &0u32);
+        env.storage().persistent().set(&DataKey::CurrentTree, &(retired_tree + 1));
+
+        Ok(retired_tree + 1)
+    }
+
+    // the tree new coins are inserted into; contracts deployed before trees
+    // rolled over have only ever had tree 0
+    pub fn current_tree(env: Env) -> u32
+    {
+        env.storage().persistent().get(&DataKey::CurrentTree).unwrap_or(0)
+    }
+
+    // the frontier of the active tree: the filled subtree of each level, and
+    // the index of the next leaf; see utils::root_from_frontier
+    pub fn frontier(env: Env) -> Result<(Map<u32, BytesN<32>>, u32), SanctumError>
+    {
+        let mut filled_subtrees = Map::new(&env);
+        for i in 0..MERKLE_TREE_LEVELS {
+            filled_subtrees.set(i, stored::<BytesN<32>>(&env, &DataKey::FilledSubtree(i))?);
+        }
+
+        Ok((filled_subtrees, stored(&env, &DataKey::NextIndex)?))
+    }
+
+    fn admin(env: &Env) -> Result<Address, SanctumError>
+    {
+        stored(env, &DataKey::Admin)
+    }
+
+    // root is a root of tree tree_id: any in the window of the active tree, or
+    // the final root of a retired one
     pub fn payment(
         env: Env,
+        tree_id: u32,
         root: BytesN<32>,
         new_coin_hash: BytesN<32>,
-        old_coin_nullifier: BytesN<32>
+        old_coin_nullifier: BytesN<32>,
+        proof: Bytes,
+        public_inputs: Vec<Bytes>
     ) -> Result<BytesN<32>, SanctumError>
     {
         // check for double spending
@@ -86,11 +278,25 @@ impl SanctumContract {
         }
 
         // check if the root (with respect to which proof is constructed) is known
-        if !Self::is_known_root(&env, &root) {
+        let current_tree = Self::current_tree(env.clone());
+        let known = if tree_id == current_tree {
+            Self::is_known_root(&env, &root)?
+        } else if tree_id < current_tree {
+            let final_root: Option<BytesN<32>> = env.storage().persistent().get(&DataKey::RetiredRoot(tree_id));
+            final_root == Some(root)
+        } else {
+            return Err(SanctumError::UnknownTree);
+        };
+        if !known {
             return Err(SanctumError::UnknownRoot);
         }
 
-        // TODO: verify the zk proof
+        // the proof must be of this payment's root, new coin and nullifier,
+        // rather than of any statement the verifier happens to accept
+        if public_inputs != utils::payment_public_inputs(&env, &root, &new_coin_hash, &old_coin_nullifier) {
+            return Err(SanctumError::PublicInputMismatch);
+        }
+        Self::verify_proof(&env, proof, public_inputs)?;
 
         // valid spend, so insert the new coin and nullifier
         let merkle_root = Self::insert_coin(&env, new_coin_hash)?;
@@ -98,20 +304,41 @@ impl SanctumContract {
         Ok(merkle_root)
     }
 
+    // asks the verifier contract for a verdict on the proof; it answers false
+    // for a well-formed proof that does not verify, and traps on one it cannot
+    // decode, so any failure to get an answer means the proof was malformed
+    fn verify_proof(env: &Env, proof: Bytes, public_inputs: Vec<Bytes>) -> Result<(), SanctumError>
+    {
+        let verifier: Address = env.storage().persistent()
+            .get(&DataKey::Verifier)
+            .ok_or(SanctumError::ContractUnititialized)?;
+        let payment_vk: Bytes = env.storage().persistent()
+            .get(&DataKey::PaymentVk)
+            .ok_or(SanctumError::ContractUnititialized)?;
+
+        let args = vec![env, payment_vk.into_val(env), proof.into_val(env), public_inputs.into_val(env)];
+        match env.try_invoke_contract::<bool, soroban_sdk::Error>(&verifier, &Symbol::new(env, "verify"), args) {
+            Ok(Ok(true)) => Ok(()),
+            Ok(Ok(false)) => Err(SanctumError::InvalidProof),
+            _ => Err(SanctumError::MalformedProof),
+        }
+    }
+
     fn insert_coin(env: &Env, leaf: BytesN<32>) -> Result<BytesN<32>, SanctumError>
     {
         let levels = MERKLE_TREE_LEVELS;
 
         // only proceed if the contract is initialized
-        if !env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false) {
+        if !is_initialized(env) {
             return Err(SanctumError::ContractUnititialized);
         }
 
         log!(&env, "[CONTRACTCALL] insert_coin({})", leaf);
 
-        // since the contract is initialized, it's safe to assume
-        // that the state variable NextIndex exists
-        let next_index: u32 = env.storage().persistent().get(&DataKey::NextIndex).unwrap();
+        let next_index: u32 = stored(env, &DataKey::NextIndex)?;
+        if next_index >= 1 << levels {
+            return Err(SanctumError::TreeFull);
+        }
         let mut current_index = next_index;
         let mut current_level_hash = leaf;
 
@@ -126,7 +353,7 @@ impl SanctumContract {
                 env.storage().persistent().set(&DataKey::FilledSubtree(i), &current_level_hash);
                 //log!(&env, "setting filledSubtree({}): {}", i, current_level_hash);
             } else {
-                left = env.storage().persistent().get(&DataKey::FilledSubtree(i)).unwrap();
+                left = stored(env, &DataKey::FilledSubtree(i))?;
                 right = current_level_hash.clone();
             }
 
@@ -134,12 +361,12 @@ impl SanctumContract {
             current_index = current_index / 2;
         }
 
-        // since the contract is initialized, it's safe to assume
-        // that the state variable CurrentRootIndex exists
-        let current_root_index: u32 = env.storage().persistent().get(&DataKey::CurrentRootIndex).unwrap();
+        let current_root_index: u32 = stored(env, &DataKey::CurrentRootIndex)?;
+
+        let root_history_size: u32 = stored(env, &DataKey::RootHistorySize)?;
 
         //uint32 newRootIndex = (currentRootIndex + 1) % ROOT_HISTORY_SIZE;
-        let new_root_index = (current_root_index + 1) % ROOT_HISTORY_SIZE;
+        let new_root_index = (current_root_index + 1) % root_history_size;
 
         //currentRootIndex = newRootIndex;
         env.storage().persistent().set(&DataKey::CurrentRootIndex, &new_root_index);
@@ -164,7 +391,7 @@ impl SanctumContract {
     {
         log!(&env, "[CONTRACTCALL] insert_nullifier({})", nullifier);
 
-        if !env.storage().persistent().get(&DataKey::Initialized).unwrap_or(false) {
+        if !is_initialized(env) {
             return Err(SanctumError::ContractUnititialized);
         }
 
@@ -179,20 +406,22 @@ impl SanctumContract {
         Ok(())
     }
 
-    fn is_known_root(env: &Env, root: &BytesN<32>) -> bool
+    fn is_known_root(env: &Env, root: &BytesN<32>) -> Result<bool, SanctumError>
     {
-        let current_root_index: u32 = env.storage().persistent().get(&DataKey::CurrentRootIndex).unwrap();
+        let current_root_index: u32 = stored(env, &DataKey::CurrentRootIndex)?;
+        let root_history_size: u32 = stored(env, &DataKey::RootHistorySize)?;
         let mut i = current_root_index;
 
         loop {
-            let root_at_i: BytesN<32> = env.storage().persistent().get(&DataKey::Roots(i)).unwrap();
-            if *root == root_at_i { return true; }
-            if i == 0 { i = ROOT_HISTORY_SIZE; }
+            // a tree younger than the window has not filled every slot yet
+            let root_at_i: Option<BytesN<32>> = env.storage().persistent().get(&DataKey::Roots(i));
+            if Some(root) == root_at_i.as_ref() { return Ok(true); }
+            if i == 0 { i = root_history_size; }
             i = i - 1;
             if i == current_root_index { break; }
         }
 
-        return false;
+        Ok(false)
     }
 }
 
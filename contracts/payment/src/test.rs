@@ -2,36 +2,458 @@
 
 use crate::utils;
 
-use super::{SanctumContract, SanctumContractClient};
-use soroban_sdk::{Env, testutils::Logs, BytesN};
+use super::{DataKey, SanctumContract, SanctumContractClient, SanctumError};
+use soroban_sdk::{contract, contractimpl, vec, Env, IntoVal, Address, Bytes, BytesN, Symbol, Vec};
+use soroban_sdk::testutils::{Address as _, Logs, MockAuth, MockAuthInvoke};
+use soroban_sdk::xdr::ScErrorType;
 
 extern crate std;
 
+// size of the uncompressed proof encoding the groth verifier decodes
+const PROOF_BYTES: usize = 384;
+
+// written by userland's gen-soroban-vectors from an arkworks proof, see `make vectors`
+const VECTOR_FILE: &str = "testdata/vectors.json";
+
+// stands in for the groth verifier contract, with the same interface: it
+// traps on a proof it cannot decode, and accepts those starting with a 1 byte
+#[contract]
+struct MockVerifier;
+
+#[contractimpl]
+impl MockVerifier {
+    pub fn verify(_env: Env, _key: Bytes, proof: Bytes, _image: Vec<Bytes>) -> bool {
+        if proof.len() as usize != PROOF_BYTES {
+            panic!("malformed proof")
+        }
+        proof.get(0) == Some(1)
+    }
+}
+
+// a well-formed proof, which the mock verifier accepts iff valid is set
+fn proof(env: &Env, valid: bool) -> Bytes {
+    let mut proof = [0u8; PROOF_BYTES];
+    proof[0] = valid as u8;
+    Bytes::from_array(env, &proof)
+}
+
+// the public inputs the contract expects of a payment against root, of the
+// new coin coin(i) and spending the nullifier coin(j), as the tests name them
+fn inputs(env: &Env, root: &BytesN<32>, i: u8, j: u8) -> Vec<Bytes> {
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(env, &[i; 32]).into());
+    utils::payment_public_inputs(env, root, &coin(i), &coin(j))
+}
+
+fn attach_mock_verifier(env: &Env, client: &SanctumContractClient) {
+    let verifier = env.register_contract(None, MockVerifier);
+    client.set_verifier(&verifier, &Bytes::from_array(env, &[0u8; 4]));
+}
+
+// uploads the contract as `make build` builds it, for the upgrade tests to
+// swap in through upgrade, in place of the natively registered contract
+fn upload_release_wasm(env: &Env) -> BytesN<32> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../target/wasm32-unknown-unknown/release/sanctum_payment_contract.wasm");
+    let wasm = std::fs::read(&path).unwrap_or_else(|_| panic!("{} is not built", path.display()));
+
+    env.deployer().upload_contract_wasm(Bytes::from_slice(env, &wasm))
+}
+
+// registers the groth verifier as `make build` builds it, holding the hash of key
+fn register_groth_verifier(env: &Env, key: &Bytes) -> Address {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../target/wasm32-unknown-unknown/release/sanctum_proof_verifier_contract.wasm");
+    let wasm = std::fs::read(&path).unwrap_or_else(|_| panic!("{} is not built", path.display()));
+
+    let verifier = env.register_contract_wasm(None, Bytes::from_slice(env, &wasm));
+    env.invoke_contract::<()>(&verifier, &Symbol::new(env, "init"), vec![env, env.crypto().sha256(key).into_val(env)]);
+    verifier
+}
+
+// a call refused for want of authorization, rather than failing for any other reason
+fn is_auth_error<T, E>(result: Result<T, Result<E, soroban_sdk::Error>>) -> bool {
+    matches!(result, Err(Err(e)) if e.is_type(ScErrorType::Auth))
+}
+
 #[test]
 fn test_nullifier() {
     let env = Env::default();
+    env.mock_all_auths();
     let contract_id = env.register_contract(None, SanctumContract);
     let client = SanctumContractClient::new(&env, &contract_id);
 
-    assert_eq!(client.initialize(), ());
+    assert_eq!(client.initialize(&Address::generate(&env), &30), ());
+    attach_mock_verifier(&env, &client);
+
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
 
     let new_root = client.payment(
-        &BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1)),
-        &env.crypto().sha256(&BytesN::from_array(&env, &[0u8; 32]).into()),
-        &env.crypto().sha256(&BytesN::from_array(&env, &[0u8; 32]).into())
+        &0,
+        &initial_root,
+        &coin(0),
+        &coin(0),
+        &proof(&env, true),
+        &inputs(&env, &initial_root, 0, 0)
     );
 
     let new_root = client.payment(
+        &0,
         &new_root,
-        &env.crypto().sha256(&BytesN::from_array(&env, &[1u8; 32]).into()),
-        &env.crypto().sha256(&BytesN::from_array(&env, &[1u8; 32]).into())
+        &coin(1),
+        &coin(1),
+        &proof(&env, true),
+        &inputs(&env, &new_root, 1, 1)
     );
 
     let _new_root = client.payment(
+        &0,
         &new_root,
-        &env.crypto().sha256(&BytesN::from_array(&env, &[2u8; 32]).into()),
-        &env.crypto().sha256(&BytesN::from_array(&env, &[2u8; 32]).into())
+        &coin(2),
+        &coin(2),
+        &proof(&env, true),
+        &inputs(&env, &new_root, 2, 2)
     );
 
     std::println!("{}", env.logs().all().join("\n"));
 }
+
+#[test]
+fn test_root_history_size() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, SanctumContract);
+    let client = SanctumContractClient::new(&env, &contract_id);
+
+    assert_eq!(
+        client.try_initialize(&Address::generate(&env), &0),
+        Err(Ok(SanctumError::InvalidRootHistorySize))
+    );
+    client.initialize(&Address::generate(&env), &2);
+    attach_mock_verifier(&env, &client);
+
+    // services compare their own window against the contract's at startup
+    assert_eq!(client.root_history_size(), 2);
+
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+
+    let root = client.payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 0, 0));
+    client.payment(&0, &root, &coin(1), &coin(1), &proof(&env, true), &inputs(&env, &root, 1, 1));
+
+    // a verifier keeping a longer window would still accept the initial root,
+    // but it has fallen out of the contract's two-root window
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &initial_root, 2, 2)),
+        Err(Ok(SanctumError::UnknownRoot))
+    );
+}
+
+#[test]
+#[ignore = "needs the wasm built by `make build`; `make test` runs it"]
+fn test_state_survives_upgrade() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+
+    // a contract that never upgrades, to compare against
+    let reference = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    reference.initialize(&Address::generate(&env), &30);
+    attach_mock_verifier(&env, &reference);
+
+    let contract_id = env.register_contract(None, SanctumContract);
+    let client = SanctumContractClient::new(&env, &contract_id);
+    client.initialize(&Address::generate(&env), &30);
+    attach_mock_verifier(&env, &client);
+
+    let mut root = initial_root.clone();
+    for i in 0..2 {
+        let next_root = client.payment(&0, &root, &coin(i), &coin(i), &proof(&env, true), &inputs(&env, &root, i, i));
+        assert_eq!(reference.payment(&0, &root, &coin(i), &coin(i), &proof(&env, true), &inputs(&env, &root, i, i)), next_root);
+        root = next_root;
+    }
+
+    // the release wasm replaces the running code; storage written before the
+    // schema was versioned is left for migrate to bring up to date
+    client.upgrade(&upload_release_wasm(&env));
+    env.as_contract(&contract_id, || env.storage().persistent().set(&DataKey::SchemaVersion, &0u32));
+
+    client.migrate();
+    assert_eq!(client.schema_version(), super::SCHEMA_VERSION);
+    assert_eq!(client.try_migrate(), Err(Ok(SanctumError::SchemaUpToDate)));
+
+    // roots, nullifiers and the leaf counter all carried over
+    assert_eq!(client.root_history_size(), 30);
+    assert_eq!(
+        client.try_payment(&0, &root, &coin(2), &coin(0), &proof(&env, true), &inputs(&env, &root, 2, 0)),
+        Err(Ok(SanctumError::DuplicateNullifier))
+    );
+    assert_eq!(
+        client.payment(&0, &initial_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &initial_root, 2, 2)),
+        reference.payment(&0, &initial_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &initial_root, 2, 2))
+    );
+}
+
+#[test]
+fn test_admin_entrypoints_require_admin() {
+    let env = Env::default();
+    let client = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    client.initialize(&Address::generate(&env), &30);
+
+    // without the admin's authorization none of the admin entrypoints go through
+    assert!(is_auth_error(client.try_migrate()));
+    assert!(is_auth_error(client.try_set_verifier(&Address::generate(&env), &Bytes::new(&env))));
+    assert!(is_auth_error(client.try_start_new_tree()));
+}
+
+#[test]
+#[ignore = "needs the wasm built by `make build`; `make test` runs it"]
+fn test_upgrade_requires_admin() {
+    let env = Env::default();
+    env.budget().reset_unlimited();
+    let contract_id = env.register_contract(None, SanctumContract);
+    let client = SanctumContractClient::new(&env, &contract_id);
+    let (admin, impostor) = (Address::generate(&env), Address::generate(&env));
+    client.initialize(&admin, &30);
+
+    // an upgrade to code that exists, authorized by anyone but the admin, is refused
+    let new_wasm = upload_release_wasm(&env);
+    let invoke = MockAuthInvoke {
+        contract: &contract_id,
+        fn_name: "upgrade",
+        args: (new_wasm.clone(),).into_val(&env),
+        sub_invokes: &[],
+    };
+    assert!(is_auth_error(
+        client.mock_auths(&[MockAuth { address: &impostor, invoke: &invoke }]).try_upgrade(&new_wasm)
+    ));
+
+    // while the admin's goes through, and the new code serves the same state
+    client.mock_auths(&[MockAuth { address: &admin, invoke: &invoke }]).upgrade(&new_wasm);
+    assert_eq!(client.root_history_size(), 30);
+}
+
+#[test]
+fn test_proof_errors() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let client = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    client.initialize(&Address::generate(&env), &30);
+
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+
+    // until a verifier is set, no payment can be checked
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 0, 0)),
+        Err(Ok(SanctumError::ContractUnititialized))
+    );
+    attach_mock_verifier(&env, &client);
+
+    // a proof the verifier cannot decode...
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(0), &coin(0), &Bytes::from_array(&env, &[1u8; 3]), &inputs(&env, &initial_root, 0, 0)),
+        Err(Ok(SanctumError::MalformedProof))
+    );
+
+    // ...is told apart from a well-formed one that doesn't verify
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, false), &inputs(&env, &initial_root, 0, 0)),
+        Err(Ok(SanctumError::InvalidProof))
+    );
+
+    // a valid proof counts only for the payment its public inputs are of
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 1, 0)),
+        Err(Ok(SanctumError::PublicInputMismatch))
+    );
+
+    // and none of them spent the nullifier
+    client.payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 0, 0));
+}
+
+#[test]
+#[ignore = "needs the wasms built by `make build` and the vector from `make vectors`; `make test` runs it"]
+fn test_payment_checked_by_groth_verifier() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTOR_FILE);
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("{} is not generated", path.display()));
+    let vector: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let decode = |name: &str| hex::decode(vector[name].as_str().unwrap()).unwrap();
+    let hash = |name: &str| BytesN::<32>::from_array(&env, &decode(name).try_into().unwrap());
+
+    let (key, proof) = (Bytes::from_slice(&env, &decode("vk_bytes")), Bytes::from_slice(&env, &decode("proof_bytes")));
+    let (root, new_coin_hash, nullifier) = (hash("root"), hash("new_coin_hash"), hash("nullifier"));
+
+    let client = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    client.initialize(&Address::generate(&env), &30);
+    client.set_verifier(&register_groth_verifier(&env, &key), &key);
+    assert_eq!(root, BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1)));
+
+    // the contract packs the public inputs as the prover did
+    let public_inputs = utils::payment_public_inputs(&env, &root, &new_coin_hash, &nullifier);
+    let image: std::vec::Vec<std::string::String> = vector["image_bytes"]
+        .as_array().unwrap()
+        .iter()
+        .map(|input| input.as_str().unwrap().into())
+        .collect();
+    let packed: std::vec::Vec<std::string::String> = public_inputs
+        .iter()
+        .map(|input| {
+            let mut bytes = [0u8; 32];
+            input.copy_into_slice(&mut bytes);
+            hex::encode(bytes)
+        })
+        .collect();
+    assert_eq!(packed, image);
+
+    // a proof of this payment, carried with the inputs of another, is refused
+    // before it reaches the verifier...
+    let other_coin = env.crypto().sha256(&BytesN::from_array(&env, &[2u8; 32]).into());
+    assert_eq!(
+        client.try_payment(&0, &root, &other_coin, &nullifier, &proof, &public_inputs),
+        Err(Ok(SanctumError::PublicInputMismatch))
+    );
+
+    // ...and carried with the inputs of the other payment, by the verifier
+    let other_inputs = utils::payment_public_inputs(&env, &root, &other_coin, &nullifier);
+    assert_eq!(
+        client.try_payment(&0, &root, &other_coin, &nullifier, &proof, &other_inputs),
+        Err(Ok(SanctumError::InvalidProof))
+    );
+
+    // while the payment it is a proof of goes through, once
+    client.payment(&0, &root, &new_coin_hash, &nullifier, &proof, &public_inputs);
+    assert_eq!(
+        client.try_payment(&0, &root, &new_coin_hash, &nullifier, &proof, &public_inputs),
+        Err(Ok(SanctumError::DuplicateNullifier))
+    );
+}
+
+#[test]
+fn test_spend_from_retired_tree() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let client = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    client.initialize(&Address::generate(&env), &30);
+    attach_mock_verifier(&env, &client);
+
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+
+    // tree 0 takes two coins before the admin rolls over to tree 1
+    let root = client.payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 0, 0));
+    let tree_0_root = client.payment(&0, &root, &coin(1), &coin(1), &proof(&env, true), &inputs(&env, &root, 1, 1));
+    assert_eq!(client.current_tree(), 0);
+    assert_eq!(client.start_new_tree(), 1);
+    assert_eq!(client.current_tree(), 1);
+
+    // tree 1 starts from the empty root, and knows none of tree 0's roots
+    assert_eq!(
+        client.try_payment(&1, &tree_0_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &tree_0_root, 2, 2)),
+        Err(Ok(SanctumError::UnknownRoot))
+    );
+    assert_eq!(
+        client.try_payment(&2, &initial_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &initial_root, 2, 2)),
+        Err(Ok(SanctumError::UnknownTree))
+    );
+    let tree_1_root = client.payment(&1, &initial_root, &coin(2), &coin(2), &proof(&env, true), &inputs(&env, &initial_root, 2, 2));
+
+    // tree 0 is spend-only, against its final root; the new coin lands in tree 1
+    assert_eq!(
+        client.try_payment(&0, &root, &coin(3), &coin(3), &proof(&env, true), &inputs(&env, &root, 3, 3)),
+        Err(Ok(SanctumError::UnknownRoot))
+    );
+    let new_root = client.payment(&0, &tree_0_root, &coin(3), &coin(3), &proof(&env, true), &inputs(&env, &tree_0_root, 3, 3));
+    client.payment(&1, &new_root, &coin(4), &coin(4), &proof(&env, true), &inputs(&env, &new_root, 4, 4));
+    assert_ne!(new_root, tree_1_root);
+
+    // nullifiers spent in tree 0 stay spent
+    assert_eq!(
+        client.try_payment(&1, &new_root, &coin(5), &coin(0), &proof(&env, true), &inputs(&env, &new_root, 5, 0)),
+        Err(Ok(SanctumError::DuplicateNullifier))
+    );
+}
+
+#[test]
+fn test_expired_state() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, SanctumContract);
+    let client = SanctumContractClient::new(&env, &contract_id);
+    client.initialize(&Address::generate(&env), &30);
+    attach_mock_verifier(&env, &client);
+
+    let initial_root = BytesN::from_array(&env, &utils::zeros(super::MERKLE_TREE_LEVELS - 1));
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+
+    // entries whose TTL ran out are gone from storage, as if never written;
+    // an initialized contract reports them as expired, not as uninitialized
+    env.as_contract(&contract_id, || env.storage().persistent().remove(&DataKey::NextIndex));
+    assert_eq!(
+        client.try_payment(&0, &initial_root, &coin(0), &coin(0), &proof(&env, true), &inputs(&env, &initial_root, 0, 0)),
+        Err(Ok(SanctumError::StateExpired))
+    );
+
+    env.as_contract(&contract_id, || env.storage().persistent().remove(&DataKey::RootHistorySize));
+    assert_eq!(client.try_root_history_size(), Err(Ok(SanctumError::StateExpired)));
+
+    // even once the persistent mark of initialization is gone too
+    env.as_contract(&contract_id, || env.storage().persistent().remove(&DataKey::Initialized));
+    assert_eq!(client.try_root_history_size(), Err(Ok(SanctumError::StateExpired)));
+
+    // the payment that hit the expired entry spent nothing
+    assert!(!env.as_contract(&contract_id, || env.storage().persistent().has(&DataKey::Nullifier(coin(0)))));
+}
+
+#[test]
+fn test_root_from_frontier() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let client = SanctumContractClient::new(&env, &env.register_contract(None, SanctumContract));
+    client.initialize(&Address::generate(&env), &30);
+    attach_mock_verifier(&env, &client);
+
+    let coin = |i: u8| env.crypto().sha256(&BytesN::from_array(&env, &[i; 32]).into());
+    let levels = client.tree_levels();
+
+    // the frontier alone gives the latest root, from the empty tree on
+    let mut root = BytesN::from_array(&env, &utils::zeros(levels - 1));
+    for i in 0..6 {
+        let (filled_subtrees, next_index) = client.frontier();
+        assert_eq!(next_index, i as u32);
+        assert_eq!(utils::root_from_frontier(&env, levels, &filled_subtrees, next_index), Some(root.clone()));
+
+        root = client.payment(&0, &root, &coin(i), &coin(i), &proof(&env, true), &inputs(&env, &root, i, i));
+    }
+
+    // a full tree's root needs leaves the frontier no longer holds
+    let (filled_subtrees, _) = client.frontier();
+    assert_eq!(utils::root_from_frontier(&env, levels, &filled_subtrees, 1 << levels), None);
+}
+
+#[test]
+fn test_layout_version() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, SanctumContract);
+    let client = SanctumContractClient::new(&env, &contract_id);
+    assert_eq!(client.try_layout_version(), Err(Ok(SanctumError::ContractUnititialized)));
+
+    client.initialize(&Address::generate(&env), &30);
+    assert_eq!(client.layout_version(), super::PUBLIC_INPUT_LAYOUT_VERSION);
+
+    // contracts from before the layout was versioned get it from migrate
+    env.as_contract(&contract_id, || {
+        env.storage().persistent().remove(&DataKey::LayoutVersion);
+        env.storage().persistent().set(&DataKey::SchemaVersion, &2u32);
+    });
+    client.migrate();
+    assert_eq!(client.layout_version(), 1);
+}
@@ -1,6 +1,9 @@
 use soroban_sdk::{
     Env,
-    BytesN
+    Bytes,
+    BytesN,
+    Map,
+    Vec
 };
 
 
@@ -18,6 +21,68 @@ pub fn sha256hash(env: &Env, left: BytesN<32>, right: BytesN<32>) -> BytesN<32>
     env.crypto().sha256(&concatenated.into())
 }
 
+// the public inputs of a payment proof, packed as PUBLIC_INPUT_LAYOUT_VERSION
+// lays them out: root, new_coin_hash and old_coin_nullifier in turn, each as
+// two field elements, its first 16 bytes and then its last 16, read as little
+// endian integers so that neither overflows the scalar field. Off-chain code
+// packs them alike, see protocol::contract_payment_inputs
+pub fn payment_public_inputs(
+    env: &Env,
+    root: &BytesN<32>,
+    new_coin_hash: &BytesN<32>,
+    old_coin_nullifier: &BytesN<32>
+) -> Vec<Bytes>
+{
+    let mut inputs = Vec::new(env);
+    for value in [root, new_coin_hash, old_coin_nullifier] {
+        for half in value.to_array().chunks(16) {
+            let mut input = [0u8; 32];
+            input[..16].copy_from_slice(half);
+            inputs.push_back(Bytes::from_array(env, &input));
+        }
+    }
+
+    inputs
+}
+
+// the root of a tree of the given depth, computed from its frontier alone:
+// the filled subtree of each level, and the index of the next leaf to fill;
+// a light client tracking only these O(levels) values can follow the root.
+// Levels missing from filled_subtrees are empty. The root of a full tree
+// depends on leaves the frontier no longer holds, so it has none
+pub fn root_from_frontier(
+    env: &Env,
+    levels: u32,
+    filled_subtrees: &Map<u32, BytesN<32>>,
+    next_index: u32
+) -> Option<BytesN<32>>
+{
+    if next_index >= 1 << levels {
+        return None;
+    }
+
+    // as initialize records the root of the empty tree
+    if next_index == 0 {
+        return Some(BytesN::from_array(env, &zeros(levels - 1)));
+    }
+
+    // walk up from the next (empty) leaf, as insert_coin walks up from the
+    // last one; both meet the same nodes
+    let mut current_index = next_index;
+    let mut current_level_hash = BytesN::from_array(env, &zeros(0));
+    for i in 0..levels {
+        let empty = BytesN::from_array(env, &zeros(i));
+        current_level_hash = if current_index % 2 == 0 {
+            sha256hash(env, current_level_hash, empty)
+        } else {
+            sha256hash(env, filled_subtrees.get(i).unwrap_or(empty), current_level_hash)
+        };
+        current_index = current_index / 2;
+    }
+
+    Some(current_level_hash)
+}
+
 pub fn zeros(i: u32) -> [u8; 32] {
 
     // zeros(0) = H([0; 32])
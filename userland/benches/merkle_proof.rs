@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use ark_ec::CurveGroup;
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::*;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
+
+use lib_sanctum::{protocol, utils};
+
+// the depth of the L1 contract's tree
+const MERKLE_TREE_LEVELS: u32 = 15;
+
+// compares serving a merkle proof by assembling an owned proof and encoding
+// all of it, against encoding borrowed parts with a cached root encoding
+fn bench_merkle_proof_serving(c: &mut Criterion) {
+    let (_, vc_params, crs) = utils::trusted_setup();
+
+    let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    let records = vec![dummy; 1 << MERKLE_TREE_LEVELS];
+    let db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records);
+    let root_bs58 = protocol::encode_compressed_as_bs58_str(&db.commitment());
+
+    let index = 12345;
+
+    c.bench_function("merkle proof: assemble and encode", |b| b.iter(|| {
+        let proof = JZVectorCommitmentOpeningProof::<MTParams, ark_bls12_377::G1Affine> {
+            root: db.commitment(),
+            record: db.get_record(index).clone(),
+            path: db.proof(index),
+        };
+        protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&proof)
+    }));
+
+    c.bench_function("merkle proof: borrowed parts, cached root", |b| b.iter(|| {
+        protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+            &db.proof(index), db.get_record(index), &root_bs58
+        )
+    }));
+}
+
+criterion_group!(benches, bench_merkle_proof_serving);
+criterion_main!(benches);
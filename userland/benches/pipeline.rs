@@ -0,0 +1,168 @@
+use criterion::{criterion_group, BenchmarkId, Criterion};
+
+use ark_bw6_761::BW6_761;
+use ark_ec::CurveGroup;
+use ark_groth16::{Groth16, prepare_verifying_key};
+use ark_snark::SNARK;
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::*;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
+
+use lib_sanctum::{logging, merkle_update_circuit, onramp_circuit, payment_circuit, protocol, utils};
+use lib_sanctum::protocol::MERKLE_TREE_LEVELS;
+
+fn coin(owner: &[u8; 31], rho: u8) -> JZRecord<5> {
+    let (_, _, crs) = utils::trusted_setup();
+    let mut amount = vec![0u8; 31];
+    amount[0] = 10;
+
+    let fields: [Vec<u8>; 5] =
+    [
+        vec![0u8; 31], //entropy
+        owner.to_vec(), //owner
+        vec![0u8; 31], //asset id
+        amount, //amount
+        vec![rho; 31], //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+}
+
+// a tree of dummy coins, as initialized by the sequencer
+fn dummy_db(levels: u32) -> JZVectorDB<MTParams, ark_bls12_377::G1Affine> {
+    let (_, vc_params, crs) = utils::trusted_setup();
+    let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &vec![dummy; 1 << levels])
+}
+
+fn opening_proof(
+    db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>,
+    index: usize
+) -> JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine> {
+    JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(index).clone(),
+        path: db.proof(index),
+    }
+}
+
+// proof generation and verification for each circuit on the tx path
+fn bench_circuits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("circuits");
+    group.sample_size(10);
+
+    let (prf_params, _, _) = utils::trusted_setup();
+    let sk = [20u8; 32];
+    let owner = utils::derive_pubkey(&prf_params, &sk);
+    let input_coin = coin(&owner, 1);
+    let output_coin = coin(&[7u8; 31], 2);
+
+    let (onramp_pk, onramp_vk) = onramp_circuit::circuit_setup();
+    let onramp_pvk = prepare_verifying_key(&onramp_vk);
+    group.bench_function("onramp: prove", |b| b.iter(|| {
+        onramp_circuit::generate_groth_proof(&onramp_pk, &input_coin)
+    }));
+    let (proof, public_inputs) = onramp_circuit::generate_groth_proof(&onramp_pk, &input_coin);
+    group.bench_function("onramp: verify", |b| b.iter(|| {
+        Groth16::<BW6_761>::verify_with_processed(&onramp_pvk, &public_inputs, &proof).unwrap()
+    }));
+
+    let mut db = dummy_db(MERKLE_TREE_LEVELS);
+    let old_merkle_proof = opening_proof(&db, 0);
+    db.update(0, &input_coin.commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db, 0);
+
+    let statement = protocol::StatementParams::default();
+    let (payment_pk, payment_vk) = payment_circuit::circuit_setup(&statement);
+    let payment_pvk = prepare_verifying_key(&payment_vk);
+    group.bench_function("payment: prove", |b| b.iter(|| {
+        payment_circuit::generate_groth_proof(&payment_pk, &statement, &input_coin, &output_coin, &new_merkle_proof, &sk, 0)
+    }));
+    let (proof, public_inputs) =
+        payment_circuit::generate_groth_proof(&payment_pk, &statement, &input_coin, &output_coin, &new_merkle_proof, &sk, 0);
+    group.bench_function("payment: verify", |b| b.iter(|| {
+        Groth16::<BW6_761>::verify_with_processed(&payment_pvk, &public_inputs, &proof).unwrap()
+    }));
+
+    let (merkle_update_pk, merkle_update_vk) = merkle_update_circuit::circuit_setup();
+    let merkle_update_pvk = prepare_verifying_key(&merkle_update_vk);
+    group.bench_function("merkle update: prove", |b| b.iter(|| {
+        merkle_update_circuit::generate_groth_proof(&merkle_update_pk, &old_merkle_proof, &new_merkle_proof, 0)
+    }));
+    let (proof, public_inputs) =
+        merkle_update_circuit::generate_groth_proof(&merkle_update_pk, &old_merkle_proof, &new_merkle_proof, 0);
+    group.bench_function("merkle update: verify", |b| b.iter(|| {
+        Groth16::<BW6_761>::verify_with_processed(&merkle_update_pvk, &public_inputs, &proof).unwrap()
+    }));
+
+    group.finish();
+}
+
+// inserting a leaf into the tree: the sequencer's tree, the contract's, and a deeper one
+fn bench_tree_insertion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tree insertion");
+
+    let com = coin(&[7u8; 31], 1).commitment().into_affine();
+    for levels in [8u32, 15, 20] {
+        let mut db = dummy_db(levels);
+        let mut index = 0;
+
+        group.bench_with_input(BenchmarkId::from_parameter(levels), &levels, |b, _| b.iter(|| {
+            db.update(index, &com);
+            index = (index + 1) % (1 << levels);
+        }));
+    }
+
+    group.finish();
+}
+
+// what the verifier does with every payment it is sent: encode and decode
+// the payment and merkle update proofs
+fn bench_payment_proof_encoding(c: &mut Criterion) {
+    let corpus = lib_sanctum::corpus::generate(1, &protocol::StatementParams::default()).unwrap();
+
+    let mut db = dummy_db(MERKLE_TREE_LEVELS);
+    let old_merkle_proof = opening_proof(&db, 2);
+    db.update(2, &coin(&[7u8; 31], 2).commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db, 2);
+
+    let (merkle_update_pk, _) = merkle_update_circuit::circuit_setup();
+    let (proof, public_inputs) =
+        merkle_update_circuit::generate_groth_proof(&merkle_update_pk, &old_merkle_proof, &new_merkle_proof, 2);
+
+    let payment_proof = protocol::PaymentProofBs58 {
+        payment_proof: corpus.payments[0].payment_proof.clone(),
+        merkle_update_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &proof, &public_inputs),
+    };
+    let json = serde_json::to_string(&payment_proof).unwrap();
+
+    let (payment, payment_inputs) = protocol::groth_proof_from_bs58(&payment_proof.payment_proof);
+    let (update_proof, update_inputs) = protocol::groth_proof_from_bs58(&payment_proof.merkle_update_proof);
+
+    c.bench_function("payment proof: encode", |b| b.iter(|| {
+        serde_json::to_string(&protocol::PaymentProofBs58 {
+            payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &payment, &payment_inputs),
+            merkle_update_proof:
+                protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &update_proof, &update_inputs),
+        }).unwrap()
+    }));
+
+    c.bench_function("payment proof: decode", |b| b.iter(|| {
+        let decoded: protocol::PaymentProofBs58 = serde_json::from_str(&json).unwrap();
+        (
+            protocol::groth_proof_from_bs58(&decoded.payment_proof),
+            protocol::groth_proof_from_bs58(&decoded.merkle_update_proof),
+        )
+    }));
+}
+
+criterion_group!(benches, bench_circuits, bench_tree_insertion, bench_payment_proof_encoding);
+// criterion_main!, behind a logger that keeps the per-proof timings out of
+// criterion's report; RUST_LOG still brings them back
+fn main() {
+    logging::init(&logging::LogConfig::from_env("error")).unwrap();
+
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
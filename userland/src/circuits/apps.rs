@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use ark_bw6_761::BW6_761;
+use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use ark_snark::SNARK;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey as OperatorKey};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::policy;
+use super::protocol;
+
+// domain separator prefixed to every signed app registration
+const APP_REGISTRATION_DOMAIN: &[u8] = b"sanctum/app-registration/v1";
+
+/// SignedAppRegistration is an app registration along with the operator's
+/// (bs58 encoded) ed25519 signature over it; only the operator registers apps
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SignedAppRegistration {
+    pub registration: protocol::AppRegistrationBs58,
+    pub signature: String,
+}
+
+fn signing_message(registration: &protocol::AppRegistrationBs58) -> Vec<u8> {
+    let mut message = APP_REGISTRATION_DOMAIN.to_vec();
+    message.extend_from_slice(&serde_json::to_vec(registration).unwrap());
+    message
+}
+
+pub fn sign_registration(registration: &protocol::AppRegistrationBs58, key: &SigningKey) -> SignedAppRegistration {
+    SignedAppRegistration {
+        registration: registration.clone(),
+        signature: bs58::encode(key.sign(&signing_message(registration)).to_bytes()).into_string(),
+    }
+}
+
+impl SignedAppRegistration {
+    // returns the registration iff it carries a valid signature by the operator key
+    pub fn verify(&self, operator_key: &OperatorKey) -> Result<protocol::AppRegistrationBs58, String> {
+        policy::verify_operator_signature(
+            operator_key, &signing_message(&self.registration), &self.signature, "app registration"
+        )?;
+
+        Ok(self.registration.clone())
+    }
+}
+
+/// AppCircuit is a registered app's circuit, with its verifying key
+/// preprocessed for verification
+pub struct AppCircuit {
+    pub pvk: PreparedVerifyingKey<BW6_761>,
+    pub public_inputs: Vec<String>,
+    pub commitment_x_index: usize,
+    pub commitment_y_index: usize,
+}
+
+/// AppRegistry holds the circuits that third-party apps have registered,
+/// keyed by app name
+pub struct AppRegistry {
+    apps: HashMap<String, AppCircuit>,
+}
+
+impl AppRegistry {
+
+    pub fn new() -> Self {
+        AppRegistry {
+            apps: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, registration: &protocol::AppRegistrationBs58) -> Result<(), String> {
+        if self.apps.contains_key(&registration.name) {
+            return Err(format!("app {} is already registered", registration.name));
+        }
+
+        let buf = bs58::decode(&registration.vk).into_vec().map_err(|e| e.to_string())?;
+        let vk = VerifyingKey::<BW6_761>::deserialize_compressed(buf.as_slice())
+            .map_err(|e| e.to_string())?;
+
+        // the schema must describe exactly the statement the vk verifies
+        let num_public_inputs = registration.public_inputs.len();
+        if vk.gamma_abc_g1.len() != num_public_inputs + 1 {
+            return Err(format!(
+                "vk expects {} public inputs, schema declares {}",
+                vk.gamma_abc_g1.len() - 1,
+                num_public_inputs
+            ));
+        }
+
+        if registration.commitment_x_index >= num_public_inputs ||
+            registration.commitment_y_index >= num_public_inputs {
+            return Err("commitment index is out of range".to_string());
+        }
+
+        self.apps.insert(registration.name.clone(), AppCircuit {
+            pvk: prepare_verifying_key(&vk),
+            public_inputs: registration.public_inputs.clone(),
+            commitment_x_index: registration.commitment_x_index,
+            commitment_y_index: registration.commitment_y_index,
+        });
+
+        Ok(())
+    }
+
+    // verifies an app tx against its registered circuit, returning the
+    // commitment it inserts into the tree
+    pub fn verify(&self, tx: &protocol::AppTxBs58) -> Result<ark_bls12_377::G1Affine, String> {
+        let app = self.apps
+            .get(&tx.app)
+            .ok_or(format!("app {} is not registered", tx.app))?;
+
+        // an app's statement is its own, whatever the params of the pool's
+        protocol::check_circuit_kind(&tx.proof, protocol::CircuitKind::App, &protocol::StatementParams::default())?;
+
+        if tx.proof.public_inputs.len() != app.public_inputs.len() {
+            return Err(format!(
+                "expected {} public inputs, got {}",
+                app.public_inputs.len(),
+                tx.proof.public_inputs.len()
+            ));
+        }
+
+        protocol::check_canonical_groth_proof(&tx.proof)?;
+        let (proof, public_inputs) = protocol::groth_proof_from_bs58(&tx.proof);
+        let valid = Groth16::<BW6_761>::verify_with_processed(&app.pvk, &public_inputs, &proof)
+            .map_err(|e| format!("invalid proof: {}", e))?;
+        if !valid {
+            return Err("invalid proof".to_string());
+        }
+
+        // the app's circuit is not ours to trust, so check the point ourselves
+        protocol::affine_from_xy(
+            public_inputs[app.commitment_x_index],
+            public_inputs[app.commitment_y_index]
+        ).map_err(|e| format!("invalid commitment: {}", e))
+    }
+}
@@ -0,0 +1,63 @@
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+
+use lib_mpc_zexe::prf::{*, constraints::*};
+
+use super::protocol;
+use super::utils;
+
+type ConstraintF = ark_bw6_761::Fr;
+
+/// asset_tag is PRF(asset_id; blind). Payments of the same asset under the
+/// same blind carry the same tag, which lets a sequencer pool them by asset
+/// without learning the asset; under fresh blinds, tags are unlinkable. The
+/// blind must be chosen independently of the spending key.
+pub fn asset_tag(prf_params: &JZPRFParams, asset_id: &protocol::AssetId, blind: &[u8; 32]) -> ConstraintF {
+    field_tag(prf_params, &asset_id.to_field_bytes(), blind)
+}
+
+/// the tag of an ASSETID field as a coin holds it, which the circuit evaluates
+/// the PRF on; for the field of an AssetId, it is that asset's tag
+pub fn field_tag(prf_params: &JZPRFParams, field: &[u8], blind: &[u8; 32]) -> ConstraintF {
+    utils::bytes_to_field::<ConstraintF, 6>(&JZPRFInstance::new(prf_params, field, blind).evaluate())
+}
+
+/// constrains tag_var to be the asset tag of the asset id held in
+/// asset_id_vars, under the blind of the (witnessed) PRF instance
+pub fn generate_constraints(
+    cs: ConstraintSystemRef<ConstraintF>,
+    prf_params_var: &JZPRFParamsVar,
+    prf_instance: JZPRFInstance,
+    asset_id_vars: &[UInt8<ConstraintF>],
+    tag_var: &ark_bls12_377::constraints::FqVar,
+) -> Result<(), SynthesisError> {
+    let prf_instance_var = JZPRFInstanceVar::new_witness(
+        cs.clone(),
+        || Ok(prf_instance)
+    )?;
+
+    lib_mpc_zexe::prf::constraints::generate_constraints(
+        cs.clone(),
+        prf_params_var,
+        &prf_instance_var
+    );
+
+    // the PRF must be evaluated on the asset id...
+    for (byte_var, asset_id_byte_var) in prf_instance_var.input_var.iter().zip(asset_id_vars.iter()) {
+        byte_var.enforce_equal(asset_id_byte_var)?;
+    }
+
+    // ...and the tag in the statement must be its output
+    let tag_byte_vars: Vec<UInt8<ConstraintF>> = tag_var.to_bytes()?;
+    for (i, byte_var) in prf_instance_var.output_var.iter().enumerate() {
+        byte_var.enforce_equal(&tag_byte_vars[i])?;
+    }
+
+    // the field holds more bytes than the output, which must all be zero, or
+    // the tag plus 2^256 would pass for the same tag
+    for byte_var in tag_byte_vars.iter().skip(prf_instance_var.output_var.len()) {
+        byte_var.enforce_equal(&UInt8::constant(0))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use super::protocol;
+
+// 10^19 is the largest power of ten a u64 holds
+pub const MAX_DECIMALS: u32 = 19;
+
+/// AssetInfo names an asset for display: amounts of it are integers on the
+/// protocol side, and decimal strings with `decimals` places on ours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetInfo {
+    pub symbol: String,
+    // the bs58 encoded ASSETID field of the asset's coins, which must name
+    // an AssetId
+    pub asset_id: String,
+    pub decimals: u32,
+}
+
+/// AssetRegistry is the client's view of the assets it knows, loaded from a
+/// json config; it never reaches the protocol, so sender and recipient need
+/// not agree on it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AssetRegistry {
+    assets: Vec<AssetInfo>,
+}
+
+impl AssetRegistry {
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let assets: Vec<AssetInfo> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+        for (i, asset) in assets.iter().enumerate() {
+            if asset.decimals > MAX_DECIMALS {
+                return Err(format!("{} has {} decimals, more than the {} a u64 amount supports",
+                    asset.symbol, asset.decimals, MAX_DECIMALS));
+            }
+            asset_id_from_bs58(&asset.asset_id)?;
+            if assets[..i].iter().any(|a| a.symbol == asset.symbol || a.asset_id == asset.asset_id) {
+                return Err(format!("{} is listed twice", asset.symbol));
+            }
+        }
+
+        Ok(AssetRegistry { assets })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+        AssetRegistry::from_json(&json)
+    }
+
+    pub fn by_symbol(&self, symbol: &str) -> Option<&AssetInfo> {
+        self.assets.iter().find(|a| a.symbol == symbol)
+    }
+
+    pub fn by_asset_id(&self, asset_id: &protocol::AssetId) -> Option<&AssetInfo> {
+        let asset_id = bs58::encode(asset_id.to_field_bytes()).into_string();
+        self.assets.iter().find(|a| a.asset_id == asset_id)
+    }
+
+    // resolves e.g. ("1.5", "USDC") to USDC's asset id and the integer amount
+    pub fn parse(&self, amount: &str, symbol: &str) -> Result<(protocol::AssetId, u64), String> {
+        let asset = self.by_symbol(symbol).ok_or_else(|| format!("unknown asset {}", symbol))?;
+
+        Ok((asset_id_from_bs58(&asset.asset_id)?, parse_amount(amount, asset.decimals)?))
+    }
+
+    // formats an amount of the given asset, e.g. "1.5 USDC"; assets missing
+    // from the registry are shown as the raw integer and their id
+    pub fn format(&self, amount: u64, asset_id: &protocol::AssetId) -> String {
+        match self.by_asset_id(asset_id) {
+            Some(asset) => format!("{} {}", format_amount(amount, asset.decimals), asset.symbol),
+            None => format!("{} of asset {}", amount, asset_id),
+        }
+    }
+}
+
+// the registry lists each asset by its whole field, so that one that doesn't
+// name an AssetId, e.g. with a high byte set, is refused rather than truncated
+fn asset_id_from_bs58(asset_id: &str) -> Result<protocol::AssetId, String> {
+    let bytes = bs58::decode(asset_id).into_vec().map_err(|e| e.to_string())?;
+    if bytes.len() != protocol::FIELD_BYTES {
+        return Err(format!("asset id {} is not {} bytes", asset_id, protocol::FIELD_BYTES));
+    }
+
+    protocol::AssetId::from_field_bytes(&bytes)
+}
+
+// parses a decimal string into the integer amount it denotes with the given
+// decimals; digits past that precision are refused rather than rounded away
+pub fn parse_amount(amount: &str, decimals: u32) -> Result<u64, String> {
+    let (whole, fraction) = match amount.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (amount, ""),
+    };
+
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || (amount.contains('.') && fraction.is_empty()) {
+        return Err(format!("{} is not a decimal amount", amount));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(format!("{} has more than the asset's {} decimals", amount, decimals));
+    }
+
+    // whole * 10^decimals + fraction, padded out to the asset's precision
+    let scale = 10u64.pow(decimals);
+    let fraction = format!("{:0<width$}", fraction, width = decimals as usize);
+    let fraction = if fraction.is_empty() { 0 } else { fraction.parse::<u64>().unwrap() };
+    whole
+        .parse::<u64>()
+        .ok()
+        .and_then(|whole| whole.checked_mul(scale))
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or_else(|| format!("{} exceeds the largest amount, {}", amount, format_amount(u64::MAX, decimals)))
+}
+
+// the inverse of parse_amount, without trailing zeros in the fraction
+pub fn format_amount(amount: u64, decimals: u32) -> String {
+    let scale = 10u64.pow(decimals);
+    let (whole, fraction) = (amount / scale, amount % scale);
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{:0>width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use super::protocol;
+
+// maximum number of entries served in a single page
+pub const MAX_AUDIT_PAGE: usize = 500;
+
+/// NullifierAuditLog is an append-only record of every spent nullifier, with
+/// the root it was spent against and when. Unlike the NullifierStore, which
+/// only answers membership, it is a history for compliance and forensics;
+/// each entry is a json line of a file, which is reloaded when reopened.
+pub struct NullifierAuditLog {
+    entries: Vec<protocol::NullifierAuditEntryBs58>,
+    file: std::fs::File,
+}
+
+impl NullifierAuditLog {
+
+    // opens the log at path, creating it if need be. A torn last line, left by
+    // a crash in the middle of writing it, is cut off
+    pub fn open(path: &str) -> Result<Self, String> {
+        let log = match std::fs::read_to_string(path) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("unable to read audit log: {}", e)),
+        };
+        let intact = &log[..log.rfind('\n').map(|i| i + 1).unwrap_or(0)];
+
+        let entries = intact
+            .lines()
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("malformed audit log entry: {}", e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("unable to open audit log: {}", e))?;
+        file.set_len(intact.len() as u64).map_err(|e| format!("unable to truncate audit log: {}", e))?;
+
+        Ok(NullifierAuditLog { entries, file })
+    }
+
+    // the log is opt-in: it is kept only if SANCTUM_AUDIT_LOG names its file
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var("SANCTUM_AUDIT_LOG") {
+            Ok(path) => NullifierAuditLog::open(&path).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // the entry is synced to disk before this returns
+    pub fn append(&mut self, nullifier: &str, root: &(String, String), timestamp: u64) -> Result<(), String> {
+        let entry = protocol::NullifierAuditEntryBs58 {
+            nullifier: nullifier.to_string(),
+            root_x: root.0.clone(),
+            root_y: root.1.clone(),
+            timestamp,
+        };
+
+        writeln!(self.file, "{}", serde_json::to_string(&entry).unwrap())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("unable to write audit log: {}", e))?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    // up to limit entries from the given position onwards, in the order spent
+    pub fn page(&self, from: usize, limit: usize) -> Result<protocol::NullifierAuditPageBs58, String> {
+        if limit > MAX_AUDIT_PAGE {
+            return Err(format!("page of {} entries exceeds the limit of {}", limit, MAX_AUDIT_PAGE));
+        }
+
+        let end = from.saturating_add(limit).min(self.entries.len());
+        let entries = self.entries.get(from..end).unwrap_or(&[]).to_vec();
+        let next = if end < self.entries.len() { Some(end) } else { None };
+
+        Ok(protocol::NullifierAuditPageBs58 { entries, next })
+    }
+}
@@ -0,0 +1,475 @@
+#[cfg(feature = "insecure-setup")]
+use rand_chacha::rand_core::SeedableRng;
+
+use std::cmp::Ordering;
+
+use ark_ff::*;
+use ark_bw6_761::{*};
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::*;
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_snark::SNARK;
+use ark_std::rand::rngs::OsRng;
+
+use lib_mpc_zexe::vector_commitment;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    *, constraints::*, constraints::JZVectorCommitmentParamsVar,
+    config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+    config::ed_on_bw6_761::MerkleTreeParamsVar as MTParamsVar,
+};
+use lib_mpc_zexe::record_commitment::kzg::{*, constraints::*};
+use lib_mpc_zexe::prf::{*, constraints::*};
+
+use super::payment_circuit;
+use super::utils;
+use super::protocol;
+
+// Finite Field used to encode the zk circuit
+type ConstraintF = ark_bw6_761::Fr;
+
+type MerkleProof = JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>;
+
+// how many notes a balance proof counts at most; unused slots repeat a counted note
+pub const NUM_NOTES: usize = 4;
+
+// the public inputs in the Groth proof are ordered as follows; note i's
+// nullifier is at NULLIFIERS + i, and is zero for a slot that is not counted
+#[allow(non_camel_case_types, unused)]
+pub enum GrothPublicInput {
+    ROOT_X = 0, // merkle root every note is proven against
+    ROOT_Y = 1, // merkle root every note is proven against
+    TREE_ID = 2, // the tree that root is of
+    ASSET_ID = 3, // the asset the balance is held in
+    THRESHOLD = 4, // the notes hold at least this much of the asset
+    NULLIFIERS = 5,
+}
+
+/// BalanceCircuit proves that the holder of sk owns notes of ASSET_ID in the
+/// tree, under the stated root, worth at least THRESHOLD in total, without
+/// revealing which leaves hold them. The notes' nullifiers are public, and
+/// pairwise distinct, so that no note is counted twice (two leaves holding
+/// the same coin share a nullifier, and only one of them can ever be spent)
+/// and so that an auditor can check each note is still unspent.
+pub struct BalanceCircuit<'a> {
+    /// public parameters (CRS) for the KZG commitment scheme
+    pub crs: &'a JZKZGCommitmentParams<5>,
+
+    /// public parameters for the PRF evaluation
+    pub prf_params: &'a JZPRFParams,
+
+    /// public parameters for the vector commitment scheme
+    pub vc_params: &'a JZVectorCommitmentParams<MTParams>,
+
+    /// secret key owning every counted note
+    pub sk: [u8; 32],
+
+    /// the pool nullifiers are bound to, as in the payment circuit
+    pub pool_id: Option<[u8; protocol::POOL_ID_BYTES]>,
+
+    /// the tree every note lives in
+    pub tree_id: u32,
+
+    /// the asset id every counted note holds
+    pub asset_id: [u8; 31],
+
+    /// the least the counted notes hold in total
+    pub threshold: u64,
+
+    /// NUM_NOTES notes, each with its opening against the same root; the
+    /// first num_notes are counted
+    pub notes: Vec<(&'a JZRecord<5>, &'a MerkleProof)>,
+
+    /// how many of the notes are counted
+    pub num_notes: usize,
+}
+
+impl<'a> ConstraintSynthesizer<ConstraintF> for BalanceCircuit<'a> {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<()> {
+        if self.notes.len() != NUM_NOTES {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let crs_var = JZKZGCommitmentParamsVar::<5>::new_constant(
+            cs.clone(),
+            self.crs
+        ).unwrap();
+
+        let prf_params_var = JZPRFParamsVar::new_constant(
+            cs.clone(),
+            self.prf_params
+        ).unwrap();
+
+        let merkle_params_var = JZVectorCommitmentParamsVar::new_constant(
+            cs.clone(),
+            self.vc_params
+        ).unwrap();
+
+        //--------------- Declare all the input variables ------------------
+
+        let root = self.notes[0].1.root;
+        let root_x_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "root_x"),
+            || { Ok(root.x) },
+        ).unwrap();
+
+        let root_y_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "root_y"),
+            || { Ok(root.y) },
+        ).unwrap();
+
+        let tree_id_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "tree_id"),
+            || Ok(ConstraintF::from(self.tree_id)),
+        ).unwrap();
+
+        let asset_id_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "asset_id"),
+            || Ok(utils::bytes_to_field::<ConstraintF, 6>(&self.asset_id)),
+        ).unwrap();
+
+        let threshold_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "threshold"),
+            || Ok(ConstraintF::from(self.threshold)),
+        ).unwrap();
+
+        //--------------- Private key knowledge ------------------
+        // pk = PRF(0; sk), as in the payment circuit; every counted note is owned by pk
+
+        let ownership_prf_instance = JZPRFInstance::new(
+            self.prf_params, &[0u8; 32], &self.sk
+        );
+        let ownership_prf_instance_var = JZPRFInstanceVar::new_witness(
+            cs.clone(),
+            || Ok(ownership_prf_instance)
+        ).unwrap();
+        lib_mpc_zexe::prf::constraints::generate_constraints(
+            cs.clone(),
+            &prf_params_var,
+            &ownership_prf_instance_var
+        );
+
+        // the tree id is a u32, so the remaining bytes of its input must be zero
+        let tree_id_byte_vars = tree_id_inputvar.to_bytes()?;
+        for byte_var in tree_id_byte_vars.iter().skip(protocol::TREE_ID_BYTES) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+        // as is every byte of the asset id input past the asset id's own
+        let asset_id_inputvar_bytes = asset_id_inputvar.to_bytes()?;
+        for byte_var in asset_id_inputvar_bytes.iter().skip(self.asset_id.len()) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+
+        let mut amount_sum_var = FpVar::<ConstraintF>::zero();
+        let mut counted_vars: Vec<Boolean<ConstraintF>> = Vec::new();
+        let mut nullifier_inputvars: Vec<FpVar<ConstraintF>> = Vec::new();
+
+        for (i, (note, opening)) in self.notes.iter().enumerate() {
+            let counted = Boolean::new_witness(
+                ark_relations::ns!(cs, "counted"),
+                || Ok(i < self.num_notes)
+            )?;
+
+            //--------------- knowledge of opening of the note's commitment ------------------
+
+            let note_var = JZRecordVar::<5>::new_witness(
+                cs.clone(),
+                || Ok(*note)
+            ).unwrap();
+
+            lib_mpc_zexe::record_commitment::kzg::constraints::generate_constraints(
+                cs.clone(),
+                &crs_var,
+                &note_var
+            ).unwrap();
+
+            //--------------- the note is a leaf of the tree under the stated root ------------------
+
+            let proof_var = JZVectorCommitmentOpeningProofVar
+            ::<ConstraintF, MTParams, MTParamsVar>
+            ::new_witness(
+                cs.clone(),
+                || Ok(*opening)
+            ).unwrap();
+
+            vector_commitment::bytes::pedersen::constraints::generate_constraints(
+                cs.clone(), &merkle_params_var, &proof_var
+            );
+
+            // the leaf is the full serialized point, x followed by y
+            let note_commitment_var = note_var.commitment.to_affine()?;
+            let mut note_commitment_byte_vars: Vec::<UInt8<ConstraintF>> = note_commitment_var.x.to_bytes()?;
+            note_commitment_byte_vars.extend(note_commitment_var.y.to_bytes()?);
+            if note_commitment_byte_vars.len() != proof_var.leaf_var.len() {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            for (commitment_byte_var, leaf_byte_var) in note_commitment_byte_vars
+                .iter()
+                .zip(proof_var.leaf_var.iter()) {
+                commitment_byte_var.enforce_equal(leaf_byte_var)?;
+            }
+
+            proof_var.root_var.x.enforce_equal(&root_x_inputvar)?;
+            proof_var.root_var.y.enforce_equal(&root_y_inputvar)?;
+
+            //--------------- a counted note is owned by sk, and holds the asset ------------------
+
+            for (byte_var, owner_byte_var) in note_var.fields[protocol::UtxoField::OWNER as usize]
+                .iter()
+                .zip(ownership_prf_instance_var.output_var.iter()) {
+                byte_var.conditional_enforce_equal(owner_byte_var, &counted)?;
+            }
+
+            for (a, b) in note_var.fields[protocol::UtxoField::ASSETID as usize]
+                .iter()
+                .zip(asset_id_inputvar_bytes.iter()) {
+                a.conditional_enforce_equal(b, &counted)?;
+            }
+
+            //--------------- the note's amount counts toward the balance ------------------
+
+            // amounts are u64s, so the high bytes of the amount field must be zero
+            let amount_bytes = &note_var.fields[protocol::UtxoField::AMOUNT as usize];
+            for byte_var in amount_bytes.iter().skip(protocol::AMOUNT_BYTES) {
+                byte_var.enforce_equal(&UInt8::constant(0))?;
+            }
+            let amount_var = Boolean::le_bits_to_fp_var(
+                &amount_bytes[..protocol::AMOUNT_BYTES].to_bits_le()?
+            )?;
+            amount_sum_var += counted.select(&amount_var, &FpVar::zero())?;
+
+            //--------------- the nullifier of a counted note, or zero ------------------
+
+            // nullifier = PRF(rho || pool_id || tree_id; sk), as the payment circuit computes it
+            let prf_instance_nullifier = JZPRFInstance::new(
+                self.prf_params,
+                &payment_circuit::nullifier_prf_input(
+                    &note.fields[protocol::UtxoField::RHO as usize],
+                    self.pool_id.as_ref(),
+                    self.tree_id
+                ),
+                &self.sk
+            );
+            let nullifier = prf_instance_nullifier.evaluate();
+
+            let nullifier_prf_instance_var = JZPRFInstanceVar::new_witness(
+                cs.clone(),
+                || Ok(prf_instance_nullifier)
+            ).unwrap();
+            lib_mpc_zexe::prf::constraints::generate_constraints(
+                cs.clone(),
+                &prf_params_var,
+                &nullifier_prf_instance_var
+            );
+
+            let nullifier_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+                ark_relations::ns!(cs, "nullifier"),
+                || Ok(if i < self.num_notes {
+                    utils::bytes_to_field::<ConstraintF, 6>(&nullifier)
+                } else {
+                    ConstraintF::zero()
+                }),
+            ).unwrap();
+
+            // the nullifier PRF is keyed by sk, and evaluated on rho, the pool id if any, and the tree id
+            for (byte_var, key_byte_var) in nullifier_prf_instance_var.key_var
+                .iter()
+                .zip(ownership_prf_instance_var.key_var.iter()) {
+                byte_var.enforce_equal(key_byte_var)?;
+            }
+            let nullifier_input_vars: Vec<UInt8<ConstraintF>> = note_var
+                .fields[protocol::UtxoField::RHO as usize]
+                .iter()
+                .cloned()
+                .chain(self.pool_id.iter().flatten().map(|byte| UInt8::constant(*byte)))
+                .chain(tree_id_byte_vars[..protocol::TREE_ID_BYTES].iter().cloned())
+                .collect();
+            if nullifier_prf_instance_var.input_var.len() != nullifier_input_vars.len() {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            for (byte_var, input_byte_var) in nullifier_prf_instance_var.input_var.iter().zip(nullifier_input_vars.iter()) {
+                byte_var.enforce_equal(input_byte_var)?;
+            }
+
+            payment_circuit::enforce_nullifier(&nullifier_inputvar, &nullifier_prf_instance_var.output_var, &counted)?;
+            nullifier_inputvar.conditional_enforce_equal(&FpVar::zero(), &counted.not())?;
+
+            counted_vars.push(counted);
+            nullifier_inputvars.push(nullifier_inputvar);
+        }
+
+        //--------------- Binding all circuit gadgets together ------------------
+
+        // 1. no note is counted twice: counted nullifiers are pairwise distinct
+        for i in 0..NUM_NOTES {
+            for j in (i + 1)..NUM_NOTES {
+                nullifier_inputvars[i]
+                    .is_eq(&nullifier_inputvars[j])?
+                    .and(&counted_vars[i])?
+                    .and(&counted_vars[j])?
+                    .enforce_equal(&Boolean::FALSE)?;
+            }
+        }
+
+        // 2. the counted notes hold at least the threshold
+        amount_sum_var.enforce_cmp(&threshold_inputvar, Ordering::Greater, true)?;
+
+        Ok(())
+    }
+}
+
+// pads the counted notes up to NUM_NOTES by repeating the first of them
+pub fn circuit<'a>(
+    statement: &protocol::StatementParams,
+    notes: &[(&'a JZRecord<5>, &'a MerkleProof)],
+    sk: &[u8; 32],
+    tree_id: u32,
+    threshold: u64,
+) -> BalanceCircuit<'a> {
+    assert!(!notes.is_empty() && notes.len() <= NUM_NOTES);
+
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+    let mut asset_id = [0u8; 31];
+    asset_id.copy_from_slice(&notes[0].0.fields[protocol::UtxoField::ASSETID as usize]);
+
+    let mut padded = notes.to_vec();
+    padded.resize(NUM_NOTES, notes[0]);
+
+    BalanceCircuit {
+        crs,
+        prf_params,
+        vc_params,
+        sk: *sk,
+        pool_id: statement.pool_id,
+        tree_id,
+        asset_id,
+        threshold,
+        notes: padded,
+        num_notes: notes.len(),
+    }
+}
+
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup(statement: &protocol::StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    let (_, vc_params, crs) = utils::shared_setup();
+
+    // create a circuit with a dummy witness
+    let merkle_proof = utils::dummy_opening_proof(vc_params, protocol::MERKLE_TREE_LEVELS);
+    let dummy_utxo = utils::get_dummy_utxo(crs);
+    let circuit = circuit(statement, &[(&dummy_utxo, &merkle_proof)], &[0u8; 32], 0, 0);
+
+    let seed = [0u8; 32];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+
+    let (pk, vk) = Groth16::<BW6_761>::
+        circuit_specific_setup(circuit, &mut rng)
+        .unwrap();
+
+    (pk, vk)
+}
+
+// proves that the notes, owned by sk and opened against the same root of
+// tree_id, hold at least threshold of their asset
+pub fn generate_groth_proof(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    notes: &[(&JZRecord<5>, &MerkleProof)],
+    sk: &[u8; 32],
+    tree_id: u32,
+    threshold: u64,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    let circuit = circuit(statement, notes, sk, tree_id, threshold);
+    let public_inputs = public_inputs(&circuit);
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, &mut OsRng).unwrap();
+
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "balance proof generated");
+
+    (proof, public_inputs)
+}
+
+// arranges the public inputs based on the GrothPublicInput enum definition
+pub fn public_inputs(circuit: &BalanceCircuit) -> Vec<ConstraintF> {
+    let root = circuit.notes[0].1.root;
+    let mut public_inputs: Vec<ConstraintF> = vec![
+        root.x,
+        root.y,
+        ConstraintF::from(circuit.tree_id),
+        utils::bytes_to_field::<ConstraintF, 6>(&circuit.asset_id),
+        ConstraintF::from(circuit.threshold),
+    ];
+
+    for (i, (note, _)) in circuit.notes.iter().enumerate() {
+        if i >= circuit.num_notes {
+            public_inputs.push(ConstraintF::zero());
+            continue;
+        }
+
+        let nullifier = payment_circuit::nullifier(
+            circuit.prf_params,
+            &note.fields[protocol::UtxoField::RHO as usize],
+            &circuit.sk,
+            circuit.pool_id.as_ref(),
+            circuit.tree_id
+        );
+        public_inputs.push(utils::bytes_to_field::<ConstraintF, 6>(&nullifier));
+    }
+
+    public_inputs
+}
+
+/// BalanceStatement is what a verified balance proof leaves an auditor to
+/// check against the pool: that the root is one the pool accepted, for the
+/// tree, and that none of the nullifiers is spent yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceStatement {
+    pub root_x: String,
+    pub root_y: String,
+    pub tree_id: u32,
+    pub nullifiers: Vec<String>,
+}
+
+/// verifies a balance proof, exported as json, claiming at least threshold of
+/// asset_id; the proof is checked against the balance circuit's keys alone,
+/// so it verifies without access to the pool
+pub fn verify_balance_proof(
+    vk: &VerifyingKey<BW6_761>,
+    proof: &protocol::GrothProofBs58,
+    asset_id: &protocol::AssetId,
+    threshold: u64
+) -> core::result::Result<BalanceStatement, String> {
+    // the balance statement has the same inputs under any pool's params
+    protocol::check_circuit_kind(proof, protocol::CircuitKind::Balance, &protocol::StatementParams::default())?;
+    protocol::check_canonical_groth_proof(proof)?;
+    let (groth_proof, public_inputs) = protocol::groth_proof_from_bs58(proof);
+
+    if public_inputs[GrothPublicInput::ASSET_ID as usize] != asset_id.to_field() {
+        return Err("the proof is of a balance in another asset".to_string());
+    }
+    if public_inputs[GrothPublicInput::THRESHOLD as usize] != ConstraintF::from(threshold) {
+        return Err(format!("the proof is not of a balance of at least {}", threshold));
+    }
+    if !Groth16::<BW6_761>::verify(vk, &public_inputs, &groth_proof).map_err(|e| e.to_string())? {
+        return Err("invalid balance proof".to_string());
+    }
+
+    // the circuit constrains the tree id to a u32
+    let tree_id = public_inputs[GrothPublicInput::TREE_ID as usize].into_bigint().0[0] as u32;
+    let nullifiers = proof.public_inputs[GrothPublicInput::NULLIFIERS as usize..]
+        .iter()
+        .zip(public_inputs[GrothPublicInput::NULLIFIERS as usize..].iter())
+        .filter(|(_, nullifier)| !nullifier.is_zero())
+        .map(|(nullifier, _)| nullifier.clone())
+        .collect();
+
+    Ok(BalanceStatement {
+        root_x: proof.public_inputs[GrothPublicInput::ROOT_X as usize].clone(),
+        root_y: proof.public_inputs[GrothPublicInput::ROOT_Y as usize].clone(),
+        tree_id,
+        nullifiers,
+    })
+}
@@ -0,0 +1,100 @@
+use ark_ff::{BigInteger, PrimeField};
+
+use lib_mpc_zexe::coin::*;
+
+use super::protocol::{self, UtxoField, FIELD_BYTES};
+
+type F = ark_bls12_377::Fr;
+
+/// Coin8Field indexes the fields of lib_mpc_zexe's 8-field coin, the
+/// Coin<F> that protocol::CoinBs58 encodes. Next to the fields of a 5-field
+/// coin (see UtxoField), it binds the coin to an app, which is handed the
+/// coin's app inputs. The fields correspond as follows:
+///
+/// | 5-field (UtxoField) | 8-field (Coin8Field) |
+/// |---------------------|----------------------|
+/// | ENTROPY             | ENTROPY              |
+/// | OWNER               | OWNER                |
+/// | ASSETID             | ASSET_ID             |
+/// | AMOUNT              | AMOUNT               |
+/// | -                   | APP_INPUT_0, zero    |
+/// | -                   | APP_INPUT_1, zero    |
+/// | RHO                 | RHO                  |
+/// | -                   | APP_ID, OWNED_APP_ID |
+///
+/// A 5-field coin is a plainly owned coin, so it converts losslessly; an
+/// 8-field coin converts back only if it is one too. Neither conversion
+/// carries a commitment over: the two models are committed differently, so a
+/// converted coin is a new coin, to be committed (and minted) afresh
+#[derive(Clone, Copy)]
+pub enum Coin8Field {
+    ENTROPY = 0,
+    OWNER = 1,
+    ASSET_ID = 2,
+    AMOUNT = 3,
+    APP_INPUT_0 = 4,
+    APP_INPUT_1 = 5,
+    RHO = 6,
+    APP_ID = 7,
+}
+
+// the mapping below, and the table above, are of lib_mpc_zexe's 8 fields
+const _: () = assert!(NUM_FIELDS == 8);
+
+// the app of a coin spendable by its owner alone
+pub const OWNED_APP_ID: u64 = 0;
+
+// the 8-field position of each 5-field field, in UtxoField order
+const FIELD_MAP: [(UtxoField, Coin8Field); 5] = [
+    (UtxoField::ENTROPY, Coin8Field::ENTROPY),
+    (UtxoField::OWNER, Coin8Field::OWNER),
+    (UtxoField::ASSETID, Coin8Field::ASSET_ID),
+    (UtxoField::AMOUNT, Coin8Field::AMOUNT),
+    (UtxoField::RHO, Coin8Field::RHO),
+];
+
+/// the 8-field coin owned by the owner of the 5-field coin with the given
+/// fields, e.g. a JZRecord<5>'s, bound to no app
+pub fn convert_coin_5_to_8(fields: &[Vec<u8>; 5]) -> Result<Coin<F>, String> {
+    protocol::AssetId::from_field_bytes(&fields[UtxoField::ASSETID as usize])?;
+    protocol::validate_amount(&fields[UtxoField::AMOUNT as usize])?;
+
+    let mut coin = [F::from(0u64); NUM_FIELDS];
+    for (from, to) in FIELD_MAP {
+        let field = &fields[from as usize];
+        if field.len() > FIELD_BYTES {
+            return Err(format!("field {} holds {} bytes, more than the {} a field element does", from as usize, field.len(), FIELD_BYTES));
+        }
+        coin[to as usize] = F::from_le_bytes_mod_order(field);
+    }
+    coin[Coin8Field::APP_ID as usize] = F::from(OWNED_APP_ID);
+
+    Ok(coin)
+}
+
+/// the fields of the 5-field coin matching the given 8-field coin, each
+/// FIELD_BYTES long; a coin bound to an app, or holding app inputs, has none
+pub fn convert_coin_8_to_5(coin: &Coin<F>) -> Result<[Vec<u8>; 5], String> {
+    if coin[Coin8Field::APP_ID as usize] != F::from(OWNED_APP_ID) {
+        return Err("the coin is bound to an app, which a 5-field coin cannot express".to_string());
+    }
+    for input in [Coin8Field::APP_INPUT_0, Coin8Field::APP_INPUT_1] {
+        if coin[input as usize] != F::from(0u64) {
+            return Err(format!("the coin holds app input {}, which a 5-field coin cannot express", input as usize - Coin8Field::APP_INPUT_0 as usize));
+        }
+    }
+
+    let mut fields: [Vec<u8>; 5] = Default::default();
+    for (to, from) in FIELD_MAP {
+        let mut bytes = coin[from as usize].into_bigint().to_bytes_le();
+        if bytes.iter().skip(FIELD_BYTES).any(|&b| b != 0) {
+            return Err(format!("field {} does not fit in {} bytes", from as usize, FIELD_BYTES));
+        }
+        bytes.truncate(FIELD_BYTES);
+        fields[to as usize] = bytes;
+    }
+    protocol::AssetId::from_field_bytes(&fields[UtxoField::ASSETID as usize])?;
+    protocol::validate_amount(&fields[UtxoField::AMOUNT as usize])?;
+
+    Ok(fields)
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use super::logging::{self, LogConfig};
+use super::policy;
+use super::protocol::CircuitKind;
+use super::receipts;
+use super::registry;
+use super::transfers::PeerPools;
+use super::verifiers::{self, VerifierSet};
+
+// names a service's config file, which SANCTUM_CONFIG does if the flag is absent
+pub const CONFIG_FLAG: &str = "--config";
+pub const CONFIG_ENV: &str = "SANCTUM_CONFIG";
+
+// writes a commented sample config to the given path, then exits
+pub const GENERATE_CONFIG_FLAG: &str = "--generate-config";
+
+/// Setting is one entry of a service's config: its key in the config file,
+/// the env var that overrides the file, an example value, as toml, and what
+/// it configures. The flag that overrides both is the key, dashed, e.g.
+/// --root-history-size
+pub struct Setting {
+    pub key: &'static str,
+    pub env: &'static str,
+    pub example: &'static str,
+    pub help: &'static str,
+}
+
+impl Setting {
+    pub fn flag(&self) -> String {
+        format!("--{}", self.key.replace('_', "-"))
+    }
+}
+
+/// ServiceConfig is the typed config of one service, assembled from its
+/// config file, the environment and its flags, in increasing precedence.
+/// Every setting is optional; one left unset takes the service's default
+pub trait ServiceConfig: Default + for<'de> Deserialize<'de> {
+    fn service() -> &'static str;
+    fn settings() -> &'static [Setting];
+    // a setting's value, as its env var holds it
+    fn get(&self, key: &str) -> Option<String>;
+    // parses a setting's value from its env var or flag
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String>;
+    // every problem with the config, rather than only the first
+    fn validate(&self) -> Vec<String>;
+}
+
+/// List is a setting of several values: an array in the config file, and
+/// comma separated in its env var or flag
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct List(pub Vec<String>);
+
+impl FromStr for List {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Ok(List(s.split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from).collect()))
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
+
+macro_rules! service_config {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident for $service:literal {
+            $( $field:ident: $ty:ty = ($env:literal, $example:literal, $help:literal), )*
+        }
+        validate = $validate:path;
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        pub struct $name {
+            $( pub $field: Option<$ty>, )*
+        }
+
+        impl ServiceConfig for $name {
+            fn service() -> &'static str {
+                $service
+            }
+
+            fn settings() -> &'static [Setting] {
+                &[ $( Setting { key: stringify!($field), env: $env, example: $example, help: $help }, )* ]
+            }
+
+            fn get(&self, key: &str) -> Option<String> {
+                match key {
+                    $( stringify!($field) => self.$field.as_ref().map(|v| v.to_string()), )*
+                    _ => None,
+                }
+            }
+
+            fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+                match key {
+                    $( stringify!($field) => {
+                        let parsed = value
+                            .parse::<$ty>()
+                            .map_err(|_| format!("{} is not a {}", value, stringify!($ty)))?;
+                        self.$field = Some(parsed);
+                    }, )*
+                    _ => return Err(format!("unknown setting {}", key)),
+                }
+                Ok(())
+            }
+
+            fn validate(&self) -> Vec<String> {
+                $validate(self)
+            }
+        }
+    };
+}
+
+service_config! {
+    /// SequencerConfig is the config of the sequencer
+    pub struct SequencerConfig for "sequencer" {
+        key_dir: String = ("SANCTUM_KEY_DIR", "\"/tmp/sanctum\"", "the dir the setup wrote the circuits' keys to"),
+        unchecked_keys: bool = ("SANCTUM_UNCHECKED_KEYS", "false", "read proving keys against the pk_sha256 of the key dir's manifest, rather than checking their points"),
+        root_history_size: u32 = ("SANCTUM_ROOT_HISTORY_SIZE", "32", "the number of recent roots proofs may be against; must match the contract's"),
+        pool_id: u64 = ("SANCTUM_POOL_ID", "1", "the pool nullifiers are bound to; the keys must be set up under the same id"),
+        expose_leaf_index: bool = ("SANCTUM_EXPOSE_LEAF_INDEX", "false", "whether payment statements expose the spent leaf's index; the keys must be set up under the same value"),
+        operator_key: String = ("SANCTUM_OPERATOR_KEY", "\"<bs58 ed25519 public key>\"", "the key that signs policies and key reloads"),
+        key_reload_file: String = ("SANCTUM_KEY_RELOAD_FILE", "\"keys.json\"", "where the last key reload applied is kept, so a restart loads its keys and refuses older reloads"),
+        sequencer_key: String = ("SANCTUM_SEQUENCER_KEY", "\"<bs58 ed25519 secret key>\"", "the key the sequencer signs receipts with"),
+        policy_file: String = ("SANCTUM_POLICY_FILE", "\"policy.json\"", "a policy signed by the operator key, in force from startup"),
+        tx_log: String = ("SANCTUM_TX_LOG", "\"tx.log\"", "the log of accepted txs, replayed at startup"),
+        peer_pools: String = ("SANCTUM_PEER_POOLS", "\"2=<bs58 key>\"", "the pools transfers are accepted from, as <pool id>=<bs58 key>, comma separated"),
+        verifier_urls: List = ("SANCTUM_VERIFIER_URLS", "[\"http://127.0.0.1:8081\"]", "the verifiers every accepted tx is forwarded to"),
+        verifier_quorum: usize = ("SANCTUM_VERIFIER_QUORUM", "1", "how many of the verifiers must accept a tx; all of them by default"),
+        verify_cache_capacity: usize = ("SANCTUM_VERIFY_CACHE_CAPACITY", "1024", "the number of proof verifications remembered"),
+        verify_cache_ttl_secs: u64 = ("SANCTUM_VERIFY_CACHE_TTL_SECS", "600", "how long a proof verification is remembered"),
+        ticket_ttl_secs: u64 = ("SANCTUM_TICKET_TTL_SECS", "600", "how long a ticket for an asynchronous submission is kept"),
+        proof_body_limit: usize = ("SANCTUM_PROOF_BODY_LIMIT", "1048576", "the largest proof submission accepted, in bytes"),
+        batch_body_limit: usize = ("SANCTUM_BATCH_BODY_LIMIT", "16777216", "the largest batch submission accepted, in bytes"),
+        query_body_limit: usize = ("SANCTUM_QUERY_BODY_LIMIT", "65536", "the largest query accepted, in bytes"),
+        log_level: String = ("RUST_LOG", "\"info\"", "the events logged, as a RUST_LOG filter"),
+        log_format: String = ("SANCTUM_LOG_FORMAT", "\"text\"", "text, or json for one json object per line"),
+    }
+    validate = validate_sequencer;
+}
+
+service_config! {
+    /// VerifierConfig is the config of the verifier
+    pub struct VerifierConfig for "verifier" {
+        key_dir: String = ("SANCTUM_KEY_DIR", "\"/tmp/sanctum\"", "the dir the setup wrote the circuits' keys to"),
+        root_history_size: u32 = ("SANCTUM_ROOT_HISTORY_SIZE", "32", "the number of recent roots proofs may be against; must match the contract's"),
+        pool_id: u64 = ("SANCTUM_POOL_ID", "1", "the pool nullifiers are bound to; the keys must be set up under the same id"),
+        expose_leaf_index: bool = ("SANCTUM_EXPOSE_LEAF_INDEX", "false", "whether payment statements expose the spent leaf's index; the keys must be set up under the same value"),
+        operator_key: String = ("SANCTUM_OPERATOR_KEY", "\"<bs58 ed25519 public key>\"", "the key that signs key reloads"),
+        key_reload_file: String = ("SANCTUM_KEY_RELOAD_FILE", "\"keys.json\"", "where the last key reload applied is kept, so a restart loads its keys and refuses older reloads"),
+        peer_pools: String = ("SANCTUM_PEER_POOLS", "\"2=<bs58 key>\"", "the pools transfers are accepted from, as <pool id>=<bs58 key>, comma separated"),
+        root_history_file: String = ("SANCTUM_ROOT_HISTORY_FILE", "\"roots.json\"", "where the accepted-root windows are kept across restarts"),
+        root_archive: String = ("SANCTUM_ROOT_ARCHIVE", "\"roots.archive\"", "where every root ever accepted is kept"),
+        audit_log: String = ("SANCTUM_AUDIT_LOG", "\"audit.jsonl\"", "where the nullifier of every accepted payment is logged"),
+        dedup_window: usize = ("SANCTUM_DEDUP_WINDOW", "1024", "the number of recent submissions a retry is answered from"),
+        proof_body_limit: usize = ("SANCTUM_PROOF_BODY_LIMIT", "1048576", "the largest proof submission accepted, in bytes"),
+        query_body_limit: usize = ("SANCTUM_QUERY_BODY_LIMIT", "65536", "the largest query accepted, in bytes"),
+        log_level: String = ("RUST_LOG", "\"info\"", "the events logged, as a RUST_LOG filter"),
+        log_format: String = ("SANCTUM_LOG_FORMAT", "\"text\"", "text, or json for one json object per line"),
+    }
+    validate = validate_verifier;
+}
+
+service_config! {
+    /// ProverConfig is the config of the prover
+    pub struct ProverConfig for "prover" {
+        key_dir: String = ("SANCTUM_KEY_DIR", "\"/tmp/sanctum\"", "the dir the setup wrote the circuits' keys to"),
+        unchecked_keys: bool = ("SANCTUM_UNCHECKED_KEYS", "false", "read proving keys against the pk_sha256 of the key dir's manifest, rather than checking their points"),
+        pool_id: u64 = ("SANCTUM_POOL_ID", "1", "the pool nullifiers are bound to; the keys must be set up under the same id"),
+        expose_leaf_index: bool = ("SANCTUM_EXPOSE_LEAF_INDEX", "false", "whether payment statements expose the spent leaf's index; the keys must be set up under the same value"),
+        proof_body_limit: usize = ("SANCTUM_PROOF_BODY_LIMIT", "1048576", "the largest witness accepted, in bytes"),
+        log_level: String = ("RUST_LOG", "\"info\"", "the events logged, as a RUST_LOG filter"),
+        log_format: String = ("SANCTUM_LOG_FORMAT", "\"text\"", "text, or json for one json object per line"),
+    }
+    validate = validate_prover;
+}
+
+fn validate_sequencer(config: &SequencerConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    check_keys(config.key_dir.as_ref(), &[
+        (CircuitKind::Onramp, "vk"),
+        (CircuitKind::OnrampMulti, "vk"),
+        (CircuitKind::Payment, "vk"),
+        (CircuitKind::Swap, "vk"),
+        (CircuitKind::Transfer, "vk"),
+        (CircuitKind::MerkleUpdate, "pk"),
+        (CircuitKind::MerkleUpdate, "vk"),
+    ], &mut errors);
+    check_manifest(config.key_dir.as_ref(), config.unchecked_keys, &mut errors);
+    check_nonzero("root_history_size", config.root_history_size.map(|s| s as usize), &mut errors);
+    check_operator_key(config.operator_key.as_ref(), &mut errors);
+    if let Some(key) = config.sequencer_key.as_ref() {
+        if let Err(e) = receipts::signing_key_from_bs58(key) {
+            errors.push(format!("sequencer_key: {}", e));
+        }
+    }
+    if let Some(path) = config.policy_file.as_ref() {
+        check_file("policy_file", path, &mut errors);
+        if config.operator_key.is_none() {
+            errors.push("policy_file: a policy is only accepted alongside the operator_key that signed it".to_string());
+        }
+    }
+    check_peer_pools(config.peer_pools.as_ref(), &mut errors);
+
+    let urls = config.verifier_urls
+        .as_ref()
+        .map(|urls| urls.0.clone())
+        .unwrap_or_else(|| vec![verifiers::DEFAULT_VERIFIER_URL.to_string()]);
+    for url in urls.iter() {
+        check_url("verifier_urls", url, &mut errors);
+    }
+    let quorum = config.verifier_quorum.unwrap_or(urls.len());
+    if let Err(e) = VerifierSet::new(urls, quorum) {
+        errors.push(format!("verifier_quorum: {}", e));
+    }
+
+    check_nonzero("verify_cache_capacity", config.verify_cache_capacity, &mut errors);
+    check_nonzero("proof_body_limit", config.proof_body_limit, &mut errors);
+    check_nonzero("batch_body_limit", config.batch_body_limit, &mut errors);
+    check_nonzero("query_body_limit", config.query_body_limit, &mut errors);
+    check_logging(config.log_level.as_ref(), config.log_format.as_ref(), &mut errors);
+
+    errors
+}
+
+fn validate_verifier(config: &VerifierConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    check_keys(config.key_dir.as_ref(), &[
+        (CircuitKind::Onramp, "vk"),
+        (CircuitKind::OnrampMulti, "vk"),
+        (CircuitKind::Payment, "vk"),
+        (CircuitKind::Swap, "vk"),
+        (CircuitKind::Transfer, "vk"),
+        (CircuitKind::MerkleUpdate, "vk"),
+    ], &mut errors);
+    check_nonzero("root_history_size", config.root_history_size.map(|s| s as usize), &mut errors);
+    check_operator_key(config.operator_key.as_ref(), &mut errors);
+    check_peer_pools(config.peer_pools.as_ref(), &mut errors);
+    check_nonzero("dedup_window", config.dedup_window, &mut errors);
+    check_nonzero("proof_body_limit", config.proof_body_limit, &mut errors);
+    check_nonzero("query_body_limit", config.query_body_limit, &mut errors);
+    check_logging(config.log_level.as_ref(), config.log_format.as_ref(), &mut errors);
+
+    errors
+}
+
+fn validate_prover(config: &ProverConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    check_keys(config.key_dir.as_ref(), &[(CircuitKind::Payment, "pk")], &mut errors);
+    check_manifest(config.key_dir.as_ref(), config.unchecked_keys, &mut errors);
+    check_nonzero("proof_body_limit", config.proof_body_limit, &mut errors);
+    check_logging(config.log_level.as_ref(), config.log_format.as_ref(), &mut errors);
+
+    errors
+}
+
+// every key file a service reads at startup must be in its key dir; builds
+// with insecure-setup run the setups instead when no key dir is set
+fn check_keys(key_dir: Option<&String>, keys: &[(CircuitKind, &str)], errors: &mut Vec<String>) {
+    #[cfg(feature = "insecure-setup")]
+    if key_dir.is_none() {
+        return;
+    }
+
+    let dir = key_dir.map(String::as_str).unwrap_or(registry::DEFAULT_KEY_DIR);
+    if !Path::new(dir).is_dir() {
+        errors.push(format!("key_dir: {} is not a directory", dir));
+        return;
+    }
+    for (kind, extension) in keys.iter() {
+        match registry::key_path(*kind, dir, extension) {
+            Ok(path) if !Path::new(&path).is_file() => errors.push(format!("key_dir: {} is missing", path)),
+            Ok(_) => {},
+            Err(e) => errors.push(format!("key_dir: {}", e)),
+        }
+    }
+}
+
+// proving keys read unchecked are checked against the key dir's manifest instead
+fn check_manifest(key_dir: Option<&String>, unchecked_keys: Option<bool>, errors: &mut Vec<String>) {
+    #[cfg(feature = "insecure-setup")]
+    if key_dir.is_none() {
+        return;
+    }
+
+    let dir = key_dir.map(String::as_str).unwrap_or(registry::DEFAULT_KEY_DIR);
+    let path = format!("{}/{}", dir, registry::MANIFEST_FILE);
+    if unchecked_keys == Some(true) && !Path::new(&path).is_file() {
+        errors.push(format!("unchecked_keys: {} is missing", path));
+    }
+}
+
+fn check_nonzero(key: &str, value: Option<usize>, errors: &mut Vec<String>) {
+    if value == Some(0) {
+        errors.push(format!("{}: must not be zero", key));
+    }
+}
+
+fn check_file(key: &str, path: &str, errors: &mut Vec<String>) {
+    if !Path::new(path).is_file() {
+        errors.push(format!("{}: {} is not a file", key, path));
+    }
+}
+
+// the services only reach their verifiers over http(s)
+fn check_url(key: &str, url: &str, errors: &mut Vec<String>) {
+    let host = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .map(|rest| rest.split('/').next().unwrap_or(""));
+
+    match host {
+        Some(host) if !host.is_empty() => {},
+        _ => errors.push(format!("{}: {} is not an http(s) url", key, url)),
+    }
+}
+
+fn check_operator_key(key: Option<&String>, errors: &mut Vec<String>) {
+    if let Some(key) = key {
+        if let Err(e) = policy::operator_key_from_bs58(key) {
+            errors.push(format!("operator_key: {}", e));
+        }
+    }
+}
+
+fn check_peer_pools(peers: Option<&String>, errors: &mut Vec<String>) {
+    if let Some(peers) = peers {
+        if let Err(e) = PeerPools::parse(peers) {
+            errors.push(format!("peer_pools: {}", e));
+        }
+    }
+}
+
+fn check_logging(level: Option<&String>, format: Option<&String>, errors: &mut Vec<String>) {
+    if let Some(level) = level {
+        if let Err(e) = EnvFilter::try_new(level) {
+            errors.push(format!("log_level: {} is not a valid filter: {}", level, e));
+        }
+    }
+    if let Some(format) = format {
+        if format != "text" && format != "json" {
+            errors.push(format!("log_format: {} is neither text nor json", format));
+        }
+    }
+}
+
+// parses a config file; it must parse before it can be validated, so a
+// malformed file is the one error reported
+pub fn parse<C: ServiceConfig>(toml: &str) -> Result<C, String> {
+    toml::from_str(toml).map_err(|e| e.to_string())
+}
+
+/// load assembles a service's config: the file named by --config (or by
+/// SANCTUM_CONFIG), overridden by env, overridden by the service's flags,
+/// which are taken out of args. Every problem found is reported together
+pub fn load<C: ServiceConfig>(
+    args: &mut Vec<String>,
+    env: impl Fn(&str) -> Option<String>
+) -> Result<C, Vec<String>> {
+    let mut errors = Vec::new();
+
+    // the flags are taken out first, leaving the service's own arguments
+    let mut path = None;
+    let mut flags = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        let setting = C::settings().iter().find(|s| s.flag() == args[i]);
+        if args[i] != CONFIG_FLAG && setting.is_none() {
+            i += 1;
+            continue;
+        }
+
+        let flag = args.remove(i);
+        if i == args.len() {
+            errors.push(format!("{} needs a value", flag));
+            break;
+        }
+        let value = args.remove(i);
+        match setting {
+            Some(setting) => flags.push((setting, flag, value)),
+            None => path = Some(value),
+        }
+    }
+
+    let mut config = match path.or_else(|| env(CONFIG_ENV)) {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(toml) => parse::<C>(&toml).unwrap_or_else(|e| {
+                errors.push(format!("{}: {}", path, e));
+                C::default()
+            }),
+            Err(e) => {
+                errors.push(format!("unable to read config file {}: {}", path, e));
+                C::default()
+            },
+        },
+        None => C::default(),
+    };
+
+    for setting in C::settings().iter() {
+        if let Some(value) = env(setting.env) {
+            if let Err(e) = config.set(setting.key, &value) {
+                errors.push(format!("{}: {}", setting.env, e));
+            }
+        }
+    }
+    for (setting, flag, value) in flags.iter() {
+        if let Err(e) = config.set(setting.key, value) {
+            errors.push(format!("{}: {}", flag, e));
+        }
+    }
+
+    errors.extend(config.validate());
+    if errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(errors)
+    }
+}
+
+// sets the env var of every setting the config holds, as the parts of the
+// service that read their settings from the environment expect
+pub fn export_env<C: ServiceConfig>(config: &C) {
+    for setting in C::settings().iter() {
+        if let Some(value) = config.get(setting.key) {
+            std::env::set_var(setting.env, value);
+        }
+    }
+}
+
+// how the config has the service log, the defaults aside
+pub fn log_config<C: ServiceConfig>(config: &C) -> LogConfig {
+    LogConfig {
+        filter: config.get("log_level").unwrap_or_else(|| logging::DEFAULT_LEVEL.to_string()),
+        json: config.get("log_format").as_deref() == Some("json"),
+    }
+}
+
+/// sample is a config file for the service, with every setting commented
+/// out, along with what it configures and where else it can be set
+pub fn sample<C: ServiceConfig>() -> String {
+    let mut sample = format!(
+        "# sanctum config for the {}, read from the file named by {} or {}.\n\
+         # Every setting is optional, and is overridden by its env var, which is\n\
+         # in turn overridden by its flag.\n",
+        C::service(), CONFIG_FLAG, CONFIG_ENV
+    );
+
+    for setting in C::settings().iter() {
+        sample.push_str(&format!(
+            "\n# {}\n# env {}, flag {}\n# {} = {}\n",
+            setting.help, setting.env, setting.flag(), setting.key, setting.example
+        ));
+    }
+
+    sample
+}
+
+// the services' usual start: writes a sample config if asked to, and
+// otherwise loads the config and installs the logger it configures; exits
+// on a malformed config, listing every problem
+pub fn init_from_args<C: ServiceConfig>(args: &mut Vec<String>) -> C {
+    if let Some(i) = args.iter().position(|arg| arg == GENERATE_CONFIG_FLAG) {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("{} needs a path", GENERATE_CONFIG_FLAG);
+            std::process::exit(2);
+        };
+        if Path::new(path).exists() {
+            eprintln!("{} already exists; not overwriting it", path);
+            std::process::exit(2);
+        }
+        if let Err(e) = std::fs::write(path, sample::<C>()) {
+            eprintln!("unable to write {}: {}", path, e);
+            std::process::exit(2);
+        }
+        println!("wrote a sample {} config to {}", C::service(), path);
+        std::process::exit(0);
+    }
+
+    let config = match load::<C>(args, |var| std::env::var(var).ok()) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("invalid {} config:", C::service());
+            for error in errors.iter() {
+                eprintln!("  {}", error);
+            }
+            std::process::exit(2);
+        },
+    };
+    export_env(&config);
+
+    if let Err(e) = logging::init(&log_config(&config)) {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+
+    config
+}
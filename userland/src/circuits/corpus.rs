@@ -0,0 +1,100 @@
+use ark_ec::CurveGroup;
+use serde::{Deserialize, Serialize};
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+
+use super::incremental_tree::IncrementalTree;
+use super::protocol;
+use super::registry;
+use super::utils;
+use super::{onramp_circuit, payment_circuit};
+
+// key owning every coin the corpus funds
+const CORPUS_SK: [u8; 32] = [20u8; 32];
+// owner of every coin the corpus pays out
+const CORPUS_RECIPIENT: [u8; 31] = [7u8; 31];
+
+/// CorpusBs58 is a sequence of txs that are valid when submitted, in order,
+/// to a fresh sequencer: onramps funding n coins, then n payments each
+/// spending one of them. Every payment proves membership against the root
+/// the tree has once all txs before it are applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorpusBs58 {
+    pub onramps: Vec<protocol::GrothProofBs58>,
+    pub payments: Vec<protocol::PaymentTxBs58>,
+}
+
+// n onramps and n payments insert 2n leaves, all of which must fit in the tree
+pub fn generate(n: usize, statement: &protocol::StatementParams) -> Result<CorpusBs58, String> {
+    let capacity = 1usize << protocol::MERKLE_TREE_LEVELS;
+    if 2 * n > capacity {
+        return Err(format!("a corpus of {} payments needs {} leaves, but the tree holds {}", n, 2 * n, capacity));
+    }
+
+    let (prf_params, _, _) = utils::trusted_setup();
+    // the corpus must prove against the keys the sequencer verifies with
+    let (onramp_pk, _) = registry::keys(protocol::CircuitKind::Onramp, statement)?;
+    let (payment_pk, _) = registry::keys(protocol::CircuitKind::Payment, statement)?;
+
+    let owner = utils::derive_pubkey(&prf_params, &CORPUS_SK);
+
+    // mirror the sequencer's tree, inserting each tx's coin as it would
+    let mut tree = IncrementalTree::new(protocol::MERKLE_TREE_LEVELS);
+
+    let input_coins: Vec<JZRecord<5>> = (0..n).map(|i| corpus_coin(&owner, 2 * i)).collect();
+
+    let mut onramps = Vec::with_capacity(n);
+    for coin in input_coins.iter() {
+        let (proof, public_inputs) = onramp_circuit::generate_groth_proof(&onramp_pk, coin);
+        onramps.push(protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs));
+        insert_coin(&mut tree, coin)?;
+    }
+
+    let mut payments = Vec::with_capacity(n);
+    for (i, coin) in input_coins.iter().enumerate() {
+        let output_coin = corpus_coin(&CORPUS_RECIPIENT, 2 * i + 1);
+        let merkle_proof = tree.opening_proof(i)?;
+
+        let (proof, public_inputs) = payment_circuit::generate_groth_proof(
+            &payment_pk, statement, coin, &output_coin, &merkle_proof, &CORPUS_SK, 0
+        );
+        payments.push(protocol::PaymentTxBs58 {
+            payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs),
+            memo: None,
+        });
+        insert_coin(&mut tree, &output_coin)?;
+    }
+
+    Ok(CorpusBs58 { onramps, payments })
+}
+
+fn insert_coin(tree: &mut IncrementalTree, coin: &JZRecord<5>) -> Result<(), String> {
+    let event = protocol::CoinInsertedEventBs58 {
+        leaf_index: tree.num_leaves(),
+        commitment: protocol::commitment_to_bs58(&coin.commitment().into_affine()),
+        inserted_at: None,
+    };
+    tree.ingest(&event)
+}
+
+// coins differ only in rho, which keeps their commitments and nullifiers distinct
+fn corpus_coin(owner: &[u8; 31], index: usize) -> JZRecord<5> {
+    let (_, _, crs) = utils::trusted_setup();
+
+    let mut amount = vec![0u8; 31];
+    amount[0] = 10;
+    let mut rho = vec![0u8; 31];
+    rho[..8].copy_from_slice(&(index as u64 + 1).to_le_bytes());
+
+    let fields: [Vec<u8>; 5] =
+    [
+        vec![0u8; 31], //entropy
+        owner.to_vec(), //owner
+        vec![0u8; 31], //asset id
+        amount, //amount
+        rho, //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+}
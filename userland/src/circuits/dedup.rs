@@ -0,0 +1,82 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// default, overridable with SANCTUM_DEDUP_WINDOW
+pub const DEFAULT_DEDUP_WINDOW: usize = 1024;
+
+/// DedupWindow remembers the last few bundles a service applied, keyed by a
+/// digest of the bundle, along with the response it gave. A bundle forwarded
+/// again, e.g. by a sequencer retrying after a timeout the first attempt in
+/// fact survived, gets that response back rather than being applied twice.
+/// Bundles are only ever forwarded once they are accepted, so retries arrive
+/// soon after; the window is bounded by count, the oldest bundle leaving first.
+pub struct DedupWindow {
+    capacity: usize,
+    order: VecDeque<[u8; 32]>,
+    responses: HashMap<[u8; 32], String>,
+}
+
+impl DedupWindow {
+
+    // a capacity of 0 disables deduplication
+    pub fn new(capacity: usize) -> Self {
+        DedupWindow {
+            capacity,
+            order: VecDeque::new(),
+            responses: HashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = match std::env::var("SANCTUM_DEDUP_WINDOW") {
+            Ok(c) => c.parse::<usize>().expect("SANCTUM_DEDUP_WINDOW must be a usize"),
+            Err(_) => DEFAULT_DEDUP_WINDOW,
+        };
+
+        DedupWindow::new(capacity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    // the response the bundle got, if it was applied within the window
+    pub fn get(&self, digest: &[u8; 32]) -> Option<&str> {
+        self.responses.get(digest).map(|response| response.as_str())
+    }
+
+    pub fn insert(&mut self, digest: [u8; 32], response: &str) {
+        if self.capacity == 0 || self.responses.contains_key(&digest) {
+            return;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.responses.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(digest);
+        self.responses.insert(digest, response.to_string());
+    }
+}
+
+// the digest covers the route too, so a bundle is never answered with the
+// response another route gave to the same bytes
+pub fn bundle_digest<T: Serialize>(route: &str, bundle: &T) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    let bundle = serde_json::to_vec(bundle).expect("bundles serialize to json");
+    for part in [route.as_bytes(), &bundle] {
+        // length-prefix each part so that adjacent ones can't run together
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+
+    hasher.finalize().into()
+}
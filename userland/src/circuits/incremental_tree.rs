@@ -0,0 +1,68 @@
+use ark_ec::CurveGroup;
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::*;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
+
+use super::protocol;
+use super::utils;
+
+/// IncrementalTree lets a client rebuild the coin tree from the stream of
+/// coin_inserted events, and open any leaf without asking the sequencer;
+/// like the sequencer's tree, every leaf not yet inserted holds the dummy coin.
+pub struct IncrementalTree {
+    db: JZVectorDB<MTParams, ark_bls12_377::G1Affine>,
+    num_leaves: usize,
+    capacity: usize,
+}
+
+impl IncrementalTree {
+
+    pub fn new(levels: u32) -> Self {
+        let (_, vc_params, crs) = utils::trusted_setup();
+
+        let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+        let records = vec![dummy; 1 << levels];
+
+        IncrementalTree {
+            db: JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records),
+            num_leaves: 0,
+            capacity: 1 << levels,
+        }
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    // events must be ingested in order, as a gap means one was missed
+    pub fn ingest(&mut self, event: &protocol::CoinInsertedEventBs58) -> Result<(), String> {
+        if event.leaf_index != self.num_leaves {
+            return Err(format!("expected leaf {}, got leaf {}", self.num_leaves, event.leaf_index));
+        }
+
+        if event.leaf_index >= self.capacity {
+            return Err("tree is full".to_string());
+        }
+
+        let commitment = protocol::commitment_from_bs58(&event.commitment)?;
+        self.db.update(event.leaf_index, &commitment);
+        self.num_leaves += 1;
+
+        Ok(())
+    }
+
+    pub fn opening_proof(
+        &self,
+        index: usize
+    ) -> Result<JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>, String> {
+        if index >= self.num_leaves {
+            return Err(format!("leaf {} has not been inserted", index));
+        }
+
+        Ok(JZVectorCommitmentOpeningProof {
+            root: self.db.commitment(),
+            record: self.db.get_record(index).clone(),
+            path: self.db.proof(index),
+        })
+    }
+}
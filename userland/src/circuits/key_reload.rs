@@ -0,0 +1,131 @@
+use ark_bw6_761::BW6_761;
+use ark_groth16::{ProvingKey, VerifyingKey};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::keys;
+use super::policy;
+use super::protocol::{CircuitKind, StatementParams};
+use super::registry;
+
+// domain separator prefixed to every signed key reload
+const KEY_RELOAD_DOMAIN: &[u8] = b"sanctum/key-reload/v1";
+
+/// KeyReload asks a service to swap in the keys found in key_dir, or in the
+/// dir it loaded its keys from at startup if unset. Its version must exceed
+/// that of the last reload applied, so that a reload can't be replayed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KeyReload {
+    pub version: u64,
+    pub key_dir: Option<String>,
+}
+
+/// SignedKeyReload is a key reload along with the operator's (bs58 encoded)
+/// ed25519 signature over it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SignedKeyReload {
+    pub reload: KeyReload,
+    pub signature: String,
+}
+
+/// KeysReloaded is a service's answer to a key reload: the fingerprint of
+/// every vk it now verifies with
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeysReloaded {
+    pub version: u64,
+    pub fingerprints: Vec<VkFingerprint>,
+}
+
+/// VkFingerprint names the vk a service holds for a circuit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct VkFingerprint {
+    pub circuit: String,
+    // hex encoding of keys::vk_fingerprint
+    pub fingerprint: String,
+}
+
+impl KeyReload {
+    fn signing_message(&self) -> Vec<u8> {
+        let mut message = KEY_RELOAD_DOMAIN.to_vec();
+        message.extend_from_slice(&serde_json::to_vec(self).unwrap());
+        message
+    }
+
+    pub fn sign(&self, key: &SigningKey) -> SignedKeyReload {
+        SignedKeyReload {
+            reload: self.clone(),
+            signature: bs58::encode(key.sign(&self.signing_message()).to_bytes()).into_string(),
+        }
+    }
+
+    /// the last reload applied, as persist_applied kept it at path; a service
+    /// that never applied one, or keeps no such file, starts from version 0
+    pub fn load_applied(path: Option<&str>) -> Result<KeyReload, String> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(KeyReload::default()),
+        };
+
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("unable to parse key reload file {}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(KeyReload::default()),
+            Err(e) => Err(format!("unable to read key reload file {}: {}", path, e)),
+        }
+    }
+
+    /// keeps this reload at path as the last applied, so that a restart loads
+    /// its keys again and still refuses every reload up to its version
+    pub fn persist_applied(&self, path: &str) -> Result<(), String> {
+        // write then rename, so a crash never leaves a truncated file behind
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_vec(self).unwrap())
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("unable to write key reload file {}: {}", path, e))
+    }
+
+    // the keys of a protocol circuit, read from key_dir if it is set, and
+    // otherwise obtained as at startup, under the service's statement params
+    pub fn keys(
+        &self,
+        kind: CircuitKind,
+        statement: &StatementParams
+    ) -> Result<(ProvingKey<BW6_761>, VerifyingKey<BW6_761>), String> {
+        match self.key_dir.as_deref() {
+            Some(dir) => registry::read_keys(kind, dir),
+            None => registry::keys(kind, statement),
+        }
+    }
+
+    // as keys, for the circuits a service only verifies
+    pub fn verifying_key(&self, kind: CircuitKind, statement: &StatementParams) -> Result<VerifyingKey<BW6_761>, String> {
+        match self.key_dir.as_deref() {
+            Some(dir) => registry::read_verifying_key(kind, dir),
+            None => registry::verifying_key(kind, statement),
+        }
+    }
+}
+
+impl SignedKeyReload {
+    // returns the reload iff it carries a valid signature by the operator key
+    pub fn verify(&self, operator_key: &ed25519_dalek::VerifyingKey) -> Result<KeyReload, String> {
+        policy::verify_operator_signature(operator_key, &self.reload.signing_message(), &self.signature, "key reload")?;
+
+        Ok(self.reload.clone())
+    }
+}
+
+// the fingerprint of a protocol circuit's vk, as the services log it
+pub fn fingerprint(kind: CircuitKind, vk: &VerifyingKey<BW6_761>) -> VkFingerprint {
+    let circuit = registry::descriptor(kind)
+        .map(|d| d.id.to_string())
+        .unwrap_or_else(|| format!("{:?}", kind));
+
+    VkFingerprint {
+        fingerprint: hex::encode(keys::vk_fingerprint(&circuit, vk)),
+        circuit,
+    }
+}
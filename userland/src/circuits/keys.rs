@@ -0,0 +1,97 @@
+use ark_bw6_761::BW6_761;
+use ark_groth16::VerifyingKey;
+use ark_serialize::CanonicalSerialize;
+use sha2::{Digest, Sha256};
+
+use crate::protocol::StatementParams;
+use crate::registry;
+
+// domain separator prefixed to every fingerprint preimage
+const VK_FINGERPRINT_DOMAIN: &[u8] = b"sanctum/vk-fingerprint/v1";
+
+/// vk_fingerprint identifies a verifying key by hashing its group elements,
+/// each serialized in compressed form in a fixed order, along with the
+/// name of the circuit; it is independent of how the key file was written.
+pub fn vk_fingerprint(circuit_name: &str, vk: &VerifyingKey<BW6_761>) -> [u8; 32] {
+    let mut buffer: Vec<u8> = Vec::new();
+    buffer.extend_from_slice(VK_FINGERPRINT_DOMAIN);
+
+    // length-prefix the name so it can't run into the key's bytes
+    buffer.extend_from_slice(&(circuit_name.len() as u64).to_le_bytes());
+    buffer.extend_from_slice(circuit_name.as_bytes());
+
+    vk.alpha_g1.serialize_compressed(&mut buffer).unwrap();
+    vk.beta_g2.serialize_compressed(&mut buffer).unwrap();
+    vk.gamma_g2.serialize_compressed(&mut buffer).unwrap();
+    vk.delta_g2.serialize_compressed(&mut buffer).unwrap();
+
+    buffer.extend_from_slice(&(vk.gamma_abc_g1.len() as u64).to_le_bytes());
+    for g in vk.gamma_abc_g1.iter() {
+        g.serialize_compressed(&mut buffer).unwrap();
+    }
+
+    Sha256::digest(&buffer).into()
+}
+
+/// vk_diff compares two verifying keys component by component and
+/// returns a description of each component that differs; an empty
+/// result means the keys are identical.
+pub fn vk_diff(a: &VerifyingKey<BW6_761>, b: &VerifyingKey<BW6_761>) -> Vec<String> {
+    let mut diffs: Vec<String> = Vec::new();
+
+    if a.alpha_g1 != b.alpha_g1 { diffs.push("alpha_g1 differs".to_string()); }
+    if a.beta_g2 != b.beta_g2 { diffs.push("beta_g2 differs".to_string()); }
+    if a.gamma_g2 != b.gamma_g2 { diffs.push("gamma_g2 differs".to_string()); }
+    if a.delta_g2 != b.delta_g2 { diffs.push("delta_g2 differs".to_string()); }
+
+    // a length mismatch means the keys are for circuits with different statements
+    if a.gamma_abc_g1.len() != b.gamma_abc_g1.len() {
+        diffs.push(format!(
+            "gamma_abc_g1 has {} elements vs {}",
+            a.gamma_abc_g1.len(),
+            b.gamma_abc_g1.len()
+        ));
+    }
+
+    for (i, (x, y)) in a.gamma_abc_g1.iter().zip(b.gamma_abc_g1.iter()).enumerate() {
+        if x != y { diffs.push(format!("gamma_abc_g1[{}] differs", i)); }
+    }
+
+    diffs
+}
+
+/// VkSummary is what an operator can learn of a verifying key without
+/// knowing which circuit it was set up for
+pub struct VkSummary {
+    pub curve: &'static str,
+    // one fewer than gamma_abc_g1, whose first element is for the constant one
+    pub num_public_inputs: usize,
+    // sha256 of the key in compressed form; unlike the fingerprint, it does
+    // not depend on the circuit's name
+    pub hash: [u8; 32],
+    // ids of the protocol circuits with that many public inputs, under
+    // any of the statement params they may be set up with
+    pub candidates: Vec<&'static str>,
+}
+
+pub fn vk_summary(vk: &VerifyingKey<BW6_761>) -> VkSummary {
+    let mut buffer: Vec<u8> = Vec::new();
+    vk.serialize_compressed(&mut buffer).unwrap();
+
+    let num_public_inputs = vk.gamma_abc_g1.len().saturating_sub(1);
+    let candidates = registry::CIRCUITS
+        .iter()
+        .filter(|d| [false, true].iter().any(|&expose_leaf_index| {
+            let statement = StatementParams { expose_leaf_index, ..Default::default() };
+            d.num_public_inputs(&statement) == num_public_inputs
+        }))
+        .map(|d| d.id)
+        .collect();
+
+    VkSummary {
+        curve: "BW6-761",
+        num_public_inputs,
+        hash: Sha256::digest(&buffer).into(),
+        candidates,
+    }
+}
@@ -1,6 +1,40 @@
 pub mod onramp_circuit;
+pub mod onramp_multi_circuit;
 pub mod payment_circuit;
 pub mod merkle_update_circuit;
+pub mod payment_update_circuit;
+pub mod swap_circuit;
+pub mod transfer_circuit;
+pub mod balance_circuit;
 
 pub mod utils;
-pub mod protocol;
\ No newline at end of file
+pub mod protocol;
+pub mod registry;
+pub mod keys;
+pub mod incremental_tree;
+pub mod apps;
+pub mod asset_tag;
+pub mod assets;
+pub mod audit;
+pub mod coin_models;
+pub mod config;
+pub mod corpus;
+pub mod dedup;
+pub mod key_reload;
+pub mod logging;
+pub mod memo;
+pub mod merkle_delta;
+pub mod multiproof;
+pub mod nullifiers;
+pub mod policy;
+pub mod receipts;
+pub mod root_archive;
+pub mod root_history;
+#[cfg(feature = "insecure-setup")]
+pub mod test_coins;
+pub mod tickets;
+pub mod transfers;
+pub mod verification_cache;
+pub mod verifiers;
+
+mod test;
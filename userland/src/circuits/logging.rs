@@ -0,0 +1,81 @@
+use tracing_subscriber::EnvFilter;
+
+// the level a service logs at when neither --log-level nor RUST_LOG sets one
+pub const DEFAULT_LEVEL: &str = "info";
+
+/// LogConfig is how a service logs: the filter, in RUST_LOG syntax, picks the
+/// events that are emitted, and json emits each as a json object on one line
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    pub filter: String,
+    pub json: bool,
+}
+
+impl LogConfig {
+
+    // RUST_LOG if it is set, else the given filter, as text
+    pub fn from_env(default_filter: &str) -> Self {
+        LogConfig {
+            filter: std::env::var("RUST_LOG").unwrap_or_else(|_| default_filter.to_string()),
+            json: false,
+        }
+    }
+
+    // takes --log-level <filter> and --log-format <text|json> out of args,
+    // leaving the service's own arguments where it expects them
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut config = Self::from_env(DEFAULT_LEVEL);
+
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].clone();
+            if flag != "--log-level" && flag != "--log-format" {
+                i += 1;
+                continue;
+            }
+
+            let value = args
+                .get(i + 1)
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            args.drain(i..i + 2);
+
+            if flag == "--log-level" {
+                config.filter = value;
+            } else {
+                config.json = match value.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    _ => return Err(format!("unknown log format {}; expected text or json", value)),
+                };
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// installs the process-wide subscriber, which also picks up the records of
+// crates that log through the log crate, as actix does
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|e| format!("invalid log filter {}: {}", config.filter, e))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let installed = if config.json {
+        builder.json().try_init()
+    } else {
+        builder.try_init()
+    };
+    installed.map_err(|e| format!("unable to install the logger: {}", e))
+}
+
+// the services' usual start: configure from the command line, or exit on a
+// malformed logging flag
+pub fn init_from_args(args: &mut Vec<String>) {
+    let installed = LogConfig::from_args(args).and_then(|config| init(&config));
+    if let Err(e) = installed {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
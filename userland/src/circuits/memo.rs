@@ -0,0 +1,75 @@
+use ark_std::rand::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::protocol::MAX_MEMO_BYTES;
+
+// an encrypted memo is the sender's ephemeral key, then the ciphertext and its tag
+pub const MEMO_OVERHEAD: usize = 32 + 16;
+pub const MAX_MEMO_PLAINTEXT_BYTES: usize = MAX_MEMO_BYTES - MEMO_OVERHEAD;
+
+// the memo key is derived from the spending key, so an identity stays a single
+// secret; it is domain separated, so it reveals nothing about the sk
+fn memo_secret(sk: &[u8; 32]) -> StaticSecret {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sanctum/memo-key");
+    hasher.update(sk);
+    StaticSecret::from(<[u8; 32]>::from(hasher.finalize()))
+}
+
+/// the key memos to the owner of sk are encrypted to, published with their pk
+pub fn memo_pubkey(sk: &[u8; 32]) -> [u8; 32] {
+    PublicKey::from(&memo_secret(sk)).to_bytes()
+}
+
+// every memo is under a fresh ephemeral key, so a fixed nonce is never reused
+fn memo_cipher(shared: &[u8; 32], ephemeral: &[u8; 32], recipient: &[u8; 32]) -> ChaCha20Poly1305 {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sanctum/memo-cipher");
+    hasher.update(shared);
+    hasher.update(ephemeral);
+    hasher.update(recipient);
+    ChaCha20Poly1305::new(Key::from_slice(&hasher.finalize()))
+}
+
+/// encrypts a memo to the holder of the memo key recipient, such that only
+/// they can read it, and tampering with it is detected
+pub fn encrypt<R: RngCore>(recipient: &[u8; 32], memo: &[u8], rng: &mut R) -> Result<Vec<u8>, String> {
+    if memo.len() > MAX_MEMO_PLAINTEXT_BYTES {
+        return Err(format!("memo exceeds {} bytes", MAX_MEMO_PLAINTEXT_BYTES));
+    }
+
+    let mut ephemeral_sk = [0u8; 32];
+    rng.fill_bytes(&mut ephemeral_sk);
+    let ephemeral_sk = StaticSecret::from(ephemeral_sk);
+    let ephemeral = PublicKey::from(&ephemeral_sk).to_bytes();
+
+    let shared = ephemeral_sk.diffie_hellman(&PublicKey::from(*recipient));
+    if !shared.was_contributory() {
+        return Err("memo key is not a valid recipient".to_string());
+    }
+
+    let ciphertext = memo_cipher(shared.as_bytes(), &ephemeral, recipient)
+        .encrypt(Nonce::from_slice(&[0u8; 12]), memo)
+        .map_err(|_| "unable to encrypt memo".to_string())?;
+
+    Ok([ephemeral.to_vec(), ciphertext].concat())
+}
+
+/// decrypts a memo encrypted to the memo key of sk
+pub fn decrypt(sk: &[u8; 32], memo: &[u8]) -> Result<Vec<u8>, String> {
+    if memo.len() < MEMO_OVERHEAD {
+        return Err("memo is too short to be encrypted".to_string());
+    }
+
+    let (ephemeral, ciphertext) = memo.split_at(32);
+    let ephemeral: [u8; 32] = ephemeral.try_into().unwrap();
+    let secret = memo_secret(sk);
+    let shared = secret.diffie_hellman(&PublicKey::from(ephemeral));
+
+    memo_cipher(shared.as_bytes(), &ephemeral, &PublicKey::from(&secret).to_bytes())
+        .decrypt(Nonce::from_slice(&[0u8; 12]), ciphertext)
+        .map_err(|_| "memo is not encrypted to this key".to_string())
+}
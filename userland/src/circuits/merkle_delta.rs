@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::protocol;
+
+/// MerkleDeltaRequestBs58 names a leaf a wallet holds an opening proof of,
+/// and the root that proof is against
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MerkleDeltaRequestBs58 {
+    pub index: usize,
+    pub known_root_x: String,
+    pub known_root_y: String,
+}
+
+/// MerkleDeltaBs58 brings an opening proof against an older root up to the
+/// current root: it holds only the nodes of the leaf's path that changed since
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MerkleDeltaBs58 {
+    pub index: usize,
+    // the current root, encoded as the root of an opening proof is
+    pub root: String,
+    // set iff the leaf's sibling changed
+    pub leaf_sibling_hash: Option<String>,
+    pub auth_path: Vec<AuthPathNodeBs58>,
+}
+
+/// AuthPathNodeBs58 is a changed node of an auth path, at its position there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AuthPathNodeBs58 {
+    pub position: usize,
+    pub node: String,
+}
+
+/// changed_nodes tells which nodes of the path of leaf index, in a tree of
+/// 2^levels leaves, change as the leaves from..to are inserted: whether its
+/// sibling does, and the positions of the auth path nodes that do. The auth
+/// path runs from the children of the root down to the leaf's parent's sibling
+pub fn changed_nodes(index: usize, levels: u32, from: usize, to: usize) -> (bool, Vec<usize>) {
+    // whether the subtree over leaves first..first + len holds an inserted leaf
+    let overlaps = |first: usize, len: usize| first < to && from < first + len;
+
+    let leaf_sibling = overlaps(index ^ 1, 1);
+    let positions = (0..levels as usize - 1)
+        .filter(|&position| {
+            // the node at this position is the sibling of the leaf's ancestor
+            // this many levels above it
+            let height = levels as usize - 1 - position;
+            let sibling = (index >> height) ^ 1;
+            overlaps(sibling << height, 1 << height)
+        })
+        .collect();
+
+    (leaf_sibling, positions)
+}
+
+/// apply patches an opening proof of the delta's leaf with the delta; the
+/// result is only as good as the delta, so wallets check it before proving
+pub fn apply(
+    proof: &protocol::VectorCommitmentOpeningProofBs58,
+    delta: &MerkleDeltaBs58
+) -> Result<protocol::VectorCommitmentOpeningProofBs58, String> {
+    if delta.index != proof.path_leaf_index {
+        return Err(format!("delta is for leaf {}, not leaf {}", delta.index, proof.path_leaf_index));
+    }
+
+    let mut patched = proof.clone();
+    if let Some(leaf_sibling_hash) = delta.leaf_sibling_hash.as_ref() {
+        patched.path_leaf_sibling_hash = leaf_sibling_hash.clone();
+    }
+    for entry in delta.auth_path.iter() {
+        let node = patched.path_auth_path
+            .get_mut(entry.position)
+            .ok_or_else(|| format!("delta patches node {} of an auth path of {}", entry.position, proof.path_auth_path.len()))?;
+        *node = entry.node.clone();
+    }
+    patched.root = delta.root.clone();
+
+    Ok(patched)
+}
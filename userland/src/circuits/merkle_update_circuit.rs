@@ -1,15 +1,18 @@
+#[cfg(feature = "insecure-setup")]
 use rand_chacha::rand_core::SeedableRng;
 use std::borrow::Borrow;
 use std::cmp::min;
 
 use ark_ff::*;
-use ark_ec::CurveGroup;
 use ark_bw6_761::{*};
 use ark_r1cs_std::prelude::*;
 use ark_std::*;
 use ark_relations::r1cs::{ConstraintSynthesizer, *};
-use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
 use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
 use ark_serialize::CanonicalSerialize;
 use ark_crypto_primitives::to_uncompressed_bytes;
 
@@ -22,13 +25,11 @@ use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
 use lib_mpc_zexe::merkle_tree::constraints::PathVar;
 
 use super::utils;
+use super::protocol::MERKLE_TREE_LEVELS;
 
 // Finite Field used to encode the zk circuit
 type ConstraintF = ark_bw6_761::Fr;
 
-// define the depth of the merkle tree as a constant
-const MERKLE_TREE_LEVELS: u32 = 8;
-
 // the public inputs in the Groth proof are ordered as follows
 #[allow(non_camel_case_types)]
 pub enum GrothPublicInput {
@@ -118,7 +119,7 @@ impl ConstraintSynthesizer<ConstraintF> for MerkleUpdateCircuit {
             || { Ok(self.new_merkle_proof.record.x) },
         ).unwrap();
 
-        let _leaf_value_y_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+        let leaf_value_y_inputvar = ark_bls12_377::constraints::FqVar::new_input(
             ark_relations::ns!(cs.clone(), "leaf_value_y"), 
             || { Ok(self.new_merkle_proof.record.y) },
         ).unwrap();
@@ -159,6 +160,12 @@ impl ConstraintSynthesizer<ConstraintF> for MerkleUpdateCircuit {
             byte_var.enforce_equal(&new_proof_var.leaf_var[i])?;
         }
 
+        let leaf_value_y_byte_vars = leaf_value_y_inputvar.to_bytes()?;
+        // similarly for the y-coordinate, which follows x in the serialized leaf
+        for (i, byte_var) in leaf_value_y_byte_vars.iter().enumerate() {
+            byte_var.enforce_equal(&new_proof_var.leaf_var[leaf_value_x_byte_vars.len() + i])?;
+        }
+
         Ok(())
     }
 }
@@ -179,36 +186,23 @@ fn enforce_fqvar_equality(
 }
 
 
+#[cfg(feature = "insecure-setup")]
 pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
 
-    let (_, vc_params, crs) = utils::trusted_setup();
-
     // create a circuit with a dummy witness
     let circuit = {
-    
-        // let's create the universe of dummy utxos
-        let mut records = Vec::new();
-        for _ in 0..(1 << MERKLE_TREE_LEVELS) {
-            records.push(utils::get_dummy_utxo(&crs).commitment().into_affine());
-        }
-    
-        let leaf_index = 0 as usize;
-        // let's create a database of coins, and generate a merkle proof
+        let leaf_index = 0;
+        let (_, vc_params, _) = utils::trusted_setup();
+        // let's open a leaf of a tree of dummy coins, without building the tree;
         // we need this in order to create a circuit with appropriate public inputs
-        let db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records);
-        let merkle_proof = JZVectorCommitmentOpeningProof {
-            root: db.commitment(),
-            record: db.get_record(leaf_index).clone(),
-            path: db.proof(leaf_index),
-        };
+        let merkle_proof = utils::dummy_opening_proof(&vc_params, MERKLE_TREE_LEVELS);
 
-        let (_, vc_params, _) = utils::trusted_setup();
         // note that circuit setup does not care about the values of witness variables
         MerkleUpdateCircuit {
-            vc_params: vc_params,
+            vc_params,
             old_merkle_proof: merkle_proof.clone(),
-            new_merkle_proof: merkle_proof.clone(),
-            leaf_index: leaf_index,
+            new_merkle_proof: merkle_proof,
+            leaf_index,
         }
     };
 
@@ -222,12 +216,24 @@ pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
     (pk, vk)
 }
 
+// proves with fresh OS randomness, so proofs of the same statement are unlinkable
 pub fn generate_groth_proof(
     pk: &ProvingKey<BW6_761>,
     old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
     new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
     leaf_index: usize,
 ) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(pk, old_merkle_proof, new_merkle_proof, leaf_index, &mut OsRng)
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    leaf_index: usize,
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
 
     let (_, vc_params, _) = utils::trusted_setup();
 
@@ -238,34 +244,32 @@ pub fn generate_groth_proof(
         new_merkle_proof: new_merkle_proof.clone(),
     };
 
-    // pub enum GrothPublicInput {
-    //     LEAF_INDEX = 0, // index (starting at 0) of the leaf node being inserted
-    //     LEAF_VALUE_X = 1, // leaf being inserted
-    //     LEAF_VALUE_Y = 2, // leaf being inserted
-    //     OLD_ROOT_X = 3, // merkle tree root before the update
-    //     OLD_ROOT_Y = 4, // merkle tree root before the update
-    //     NEW_ROOT_X = 5, // merkle tree root after the update
-    //     NEW_ROOT_Y = 6, // merkle tree root after the update
-    // }
-    let public_inputs: Vec<ConstraintF> = vec![
-        utils::bytes_to_field::<ConstraintF, 6>(&to_uncompressed_bytes!(leaf_index).unwrap()), //LEAF_INDEX
-        new_merkle_proof.record.x, //LEAF_VALUE_X
-        new_merkle_proof.record.y, //LEAF_VALUE_Y
-        old_merkle_proof.root.x, //OLD_ROOT_X
-        old_merkle_proof.root.y, //OLD_ROOT_Y
-        new_merkle_proof.root.x, //NEW_ROOT_X
-        new_merkle_proof.root.y, //NEW_ROOT_Y
-    ];
-
-    let seed = [0u8; 32];
-    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+    let public_inputs = public_inputs(&circuit);
 
     let now = std::time::Instant::now();
-    let proof = Groth16::<BW6_761>::prove(&pk, circuit, &mut rng).unwrap();
-    println!("merkle update proof generated in {}.{} secs", 
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_millis()
-    );
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "merkle update proof generated");
     
     (proof, public_inputs)
 }
+
+// pub enum GrothPublicInput {
+//     LEAF_INDEX = 0, // index (starting at 0) of the leaf node being inserted
+//     LEAF_VALUE_X = 1, // leaf being inserted
+//     LEAF_VALUE_Y = 2, // leaf being inserted
+//     OLD_ROOT_X = 3, // merkle tree root before the update
+//     OLD_ROOT_Y = 4, // merkle tree root before the update
+//     NEW_ROOT_X = 5, // merkle tree root after the update
+//     NEW_ROOT_Y = 6, // merkle tree root after the update
+// }
+pub fn public_inputs(circuit: &MerkleUpdateCircuit) -> Vec<ConstraintF> {
+    vec![
+        utils::bytes_to_field::<ConstraintF, 6>(&to_uncompressed_bytes!(circuit.leaf_index).unwrap()), //LEAF_INDEX
+        circuit.new_merkle_proof.record.x, //LEAF_VALUE_X
+        circuit.new_merkle_proof.record.y, //LEAF_VALUE_Y
+        circuit.old_merkle_proof.root.x, //OLD_ROOT_X
+        circuit.old_merkle_proof.root.y, //OLD_ROOT_Y
+        circuit.new_merkle_proof.root.x, //NEW_ROOT_X
+        circuit.new_merkle_proof.root.y, //NEW_ROOT_Y
+    ]
+}
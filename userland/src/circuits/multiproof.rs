@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::JZVectorCommitmentOpeningProof;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
+
+use super::protocol;
+use super::receipts;
+
+/// MultiProofBs58 opens several leaves of one tree against one root. The
+/// openings' paths share most of their nodes, e.g. every node above the
+/// leaves' common ancestor, so each distinct node is sent once, and every
+/// opening names the nodes of its path by their position in the node lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MultiProofBs58 {
+    pub root: String,
+    // the distinct leaf sibling hashes, and inner digests, of the openings
+    pub leaf_nodes: Vec<String>,
+    pub inner_nodes: Vec<String>,
+    pub openings: Vec<MultiProofOpeningBs58>,
+}
+
+/// MultiProofOpeningBs58 is one leaf of a multiproof, with its path given as
+/// positions in the multiproof's node lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MultiProofOpeningBs58 {
+    pub leaf_index: usize,
+    pub record: String,
+    pub leaf_sibling: usize,
+    pub auth_path: Vec<usize>,
+}
+
+// the position of node in nodes, appending it if it isn't there yet
+fn intern(node: &String, nodes: &mut Vec<String>, positions: &mut HashMap<String, usize>) -> usize {
+    *positions.entry(node.clone()).or_insert_with(|| {
+        nodes.push(node.clone());
+        nodes.len() - 1
+    })
+}
+
+/// compress bundles opening proofs, all against the same root, into one
+/// multiproof that lists every node they share once
+pub fn compress(proofs: &[protocol::VectorCommitmentOpeningProofBs58]) -> Result<MultiProofBs58, String> {
+    let root = proofs.first().ok_or("a multiproof opens at least one leaf")?.root.clone();
+
+    let mut multiproof = MultiProofBs58 {
+        root,
+        leaf_nodes: Vec::new(),
+        inner_nodes: Vec::new(),
+        openings: Vec::new(),
+    };
+    let mut leaf_positions = HashMap::new();
+    let mut inner_positions = HashMap::new();
+
+    for proof in proofs.iter() {
+        if proof.root != multiproof.root {
+            return Err(format!("the opening of leaf {} is against another root", proof.path_leaf_index));
+        }
+
+        multiproof.openings.push(MultiProofOpeningBs58 {
+            leaf_index: proof.path_leaf_index,
+            record: proof.record.clone(),
+            leaf_sibling: intern(&proof.path_leaf_sibling_hash, &mut multiproof.leaf_nodes, &mut leaf_positions),
+            auth_path: proof.path_auth_path
+                .iter()
+                .map(|node| intern(node, &mut multiproof.inner_nodes, &mut inner_positions))
+                .collect(),
+        });
+    }
+
+    Ok(multiproof)
+}
+
+/// expand recovers the opening proof of every leaf of the multiproof, in order
+pub fn expand(multiproof: &MultiProofBs58) -> Result<Vec<protocol::VectorCommitmentOpeningProofBs58>, String> {
+    let node = |nodes: &Vec<String>, position: usize| nodes
+        .get(position)
+        .cloned()
+        .ok_or_else(|| format!("multiproof names node {} of {}", position, nodes.len()));
+
+    multiproof.openings
+        .iter()
+        .map(|opening| Ok(protocol::VectorCommitmentOpeningProofBs58 {
+            path_leaf_sibling_hash: node(&multiproof.leaf_nodes, opening.leaf_sibling)?,
+            path_auth_path: opening.auth_path
+                .iter()
+                .map(|&position| node(&multiproof.inner_nodes, position))
+                .collect::<Result<Vec<String>, String>>()?,
+            path_leaf_index: opening.leaf_index,
+            record: opening.record.clone(),
+            root: multiproof.root.clone(),
+        }))
+        .collect()
+}
+
+/// verify checks every opening of the multiproof against its root, as a
+/// wallet does before proving with any of them, and returns them decoded
+pub fn verify(
+    multiproof: &MultiProofBs58
+) -> Result<Vec<JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>>, String> {
+    let mut proofs = Vec::new();
+
+    for proof in expand(multiproof)?.iter() {
+        let proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_from_bs58(proof);
+        if !receipts::is_valid_opening_proof(&proof)? {
+            return Err(format!("the opening of leaf {} is invalid", proof.path.leaf_index));
+        }
+        proofs.push(proof);
+    }
+
+    Ok(proofs)
+}
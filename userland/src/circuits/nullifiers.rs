@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use super::protocol;
+
+// maximum number of nullifiers that can be queried in a single batch
+pub const MAX_NULLIFIER_STATUS_BATCH: usize = 500;
+
+/// NullifierStore records every spent nullifier (base58 encoded, and
+/// normalized by protocol::normalize_field_bs58), along with the index of the
+/// leaf inserted by the transaction that spent it
+pub struct NullifierStore {
+    spent: HashMap<String, usize>,
+    // the reverse of spent, for serving the tree leaf by leaf
+    spent_at: HashMap<usize, String>,
+    // nullifiers spent by transfers to another pool, which insert no leaf
+    // here; each is numbered in the order it was exported
+    exported: HashMap<String, usize>,
+}
+
+impl NullifierStore {
+
+    // create a new store with no spent nullifiers
+    pub fn new() -> Self {
+        NullifierStore {
+            spent: HashMap::new(),
+            spent_at: HashMap::new(),
+            exported: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&self, nullifier: &String) -> bool {
+        let nullifier = protocol::normalize_field_bs58(nullifier);
+        self.spent.contains_key(&nullifier) || self.exported.contains_key(&nullifier)
+    }
+
+    // record a nullifier, consumed by the tx that inserted leaf_index
+    pub fn insert(&mut self, nullifier: &String, leaf_index: usize) {
+        let nullifier = protocol::normalize_field_bs58(nullifier);
+        self.spent.insert(nullifier.clone(), leaf_index);
+        self.spent_at.insert(leaf_index, nullifier);
+    }
+
+    // the nullifier consumed by the tx that inserted leaf_index, if any
+    pub fn spent_at(&self, leaf_index: usize) -> Option<&String> {
+        self.spent_at.get(&leaf_index)
+    }
+
+    // forgets the nullifier consumed by the tx that inserted leaf_index, once
+    // that tx is rolled back
+    pub fn remove(&mut self, leaf_index: usize) {
+        if let Some(nullifier) = self.spent_at.remove(&leaf_index) {
+            self.spent.remove(&nullifier);
+        }
+    }
+
+    // record a nullifier, consumed by a transfer out of this pool
+    pub fn export(&mut self, nullifier: &String) {
+        let number = self.exported.len();
+        self.exported.insert(protocol::normalize_field_bs58(nullifier), number);
+    }
+
+    pub fn num_exported(&self) -> usize {
+        self.exported.len()
+    }
+
+    // forgets the nullifiers exported after the first num_exported, once the
+    // transfers that consumed them are rolled back
+    pub fn truncate_exported(&mut self, num_exported: usize) {
+        self.exported.retain(|_, number| *number < num_exported);
+    }
+
+    // the leaves of a new tree are numbered from 0 again, so this hands back the
+    // nullifiers by leaf of the tree being retired; every nullifier stays spent
+    pub fn start_new_tree(&mut self) -> HashMap<usize, String> {
+        std::mem::take(&mut self.spent_at)
+    }
+
+    // undoes start_new_tree, once the tx that rolled the tree over is rolled
+    // back: the retired tree's nullifiers by leaf are the active tree's again
+    pub fn restore_tree(&mut self, spent_at: HashMap<usize, String>) {
+        self.spent_at = spent_at;
+    }
+
+    // answers a batched existence query, with results parallel to the request
+    pub fn status(
+        &self,
+        request: &protocol::NullifierStatusRequestBs58
+    ) -> Result<protocol::NullifierStatusBs58, String> {
+        if request.nullifiers.len() > MAX_NULLIFIER_STATUS_BATCH {
+            return Err(format!(
+                "batch of {} nullifiers exceeds the limit of {}",
+                request.nullifiers.len(),
+                MAX_NULLIFIER_STATUS_BATCH
+            ));
+        }
+
+        let leaf_indices: Vec<Option<usize>> = request.nullifiers
+            .iter()
+            .map(|n| self.spent.get(&protocol::normalize_field_bs58(n)).cloned())
+            .collect();
+
+        // an exported nullifier is spent, though at no leaf of this pool
+        let spent = request.nullifiers
+            .iter()
+            .map(|n| self.contains(n))
+            .collect();
+
+        Ok(protocol::NullifierStatusBs58 { spent, leaf_indices })
+    }
+}
@@ -1,4 +1,5 @@
 use std::cmp::min;
+#[cfg(feature = "insecure-setup")]
 use rand_chacha::rand_core::SeedableRng;
 use std::borrow::Borrow;
 
@@ -8,8 +9,11 @@ use ark_bw6_761::{*};
 use ark_r1cs_std::prelude::*;
 use ark_std::*;
 use ark_relations::r1cs::*;
-use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
 use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
 
 use lib_mpc_zexe::record_commitment::kzg::{*, constraints::*};
 
@@ -136,6 +140,13 @@ impl ConstraintSynthesizer<ConstraintF> for OnRampCircuit {
             utxo_var.fields[protocol::UtxoField::AMOUNT as usize][i].enforce_equal(&amount_inputvar_bytes[i])?;
         }
 
+        // amounts are u64s, so the high bytes of the amount field must be zero
+        for byte_var in utxo_var.fields[protocol::UtxoField::AMOUNT as usize]
+            .iter()
+            .skip(protocol::AMOUNT_BYTES) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+
         // let's constrain the asset_id bits to be equal to the asset_id_var
         let assetid_inputvar_bytes = asset_id_var.to_bytes()?;
         for i in 0..min(
@@ -149,6 +160,7 @@ impl ConstraintSynthesizer<ConstraintF> for OnRampCircuit {
     }
 }
 
+#[cfg(feature = "insecure-setup")]
 pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
     let (_, _, crs) = utils::trusted_setup();
     // create a circuit with a dummy witness
@@ -164,14 +176,41 @@ pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
     (pk, vk)
 }
 
+// proves with fresh OS randomness, so proofs of the same statement are unlinkable
 pub fn generate_groth_proof(
     pk: &ProvingKey<BW6_761>,
     utxo: &JZRecord<5>,
 ) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(pk, utxo, &mut OsRng)
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    utxo: &JZRecord<5>,
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
 
     let (_, _, crs) = utils::trusted_setup();
     let circuit = OnRampCircuit { crs, utxo: utxo.clone() };
+    let public_inputs = public_inputs(&circuit);
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
 
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "onramp proof generated");
+    
+    (proof, public_inputs)
+}
+
+// arrange the public inputs based on the GrothPublicInput enum definition
+// pub enum GrothPublicInput {
+//     ASSET_ID = 0,
+//     AMOUNT = 1,
+//     COMMITMENT_X = 2,
+//     COMMITMENT_Y = 3,
+// }
+pub fn public_inputs(circuit: &OnRampCircuit) -> Vec<ConstraintF> {
     // construct a BW6_761 field element from the asset_id bits
     let asset_id = utils::bytes_to_field::<ConstraintF, 6>(
         &circuit.utxo.fields[protocol::UtxoField::ASSETID as usize]
@@ -182,30 +221,10 @@ pub fn generate_groth_proof(
         &circuit.utxo.fields[protocol::UtxoField::AMOUNT as usize]
     );
 
-    // arrange the public inputs based on the GrothPublicInput enum definition
-    // pub enum GrothPublicInput {
-    //     ASSET_ID = 0,
-    //     AMOUNT = 1,
-    //     COMMITMENT_X = 2,
-    //     COMMITMENT_Y = 3,
-    // }
-    let public_inputs: Vec<ConstraintF> = vec![
+    vec![
         asset_id,
         amount,
         circuit.utxo.commitment().into_affine().x,
         circuit.utxo.commitment().into_affine().y
-    ];
-
-    let seed = [0u8; 32];
-    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
-
-    let now = std::time::Instant::now();
-    let proof = Groth16::<BW6_761>::prove(&pk, circuit, &mut rng).unwrap();
-
-    println!("onramp proof generated in {}.{} secs", 
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_millis()
-    );
-    
-    (proof, public_inputs)
+    ]
 }
@@ -0,0 +1,311 @@
+#[cfg(feature = "insecure-setup")]
+use rand_chacha::rand_core::SeedableRng;
+
+use ark_ff::*;
+use ark_ec::CurveGroup;
+use ark_bw6_761::{*};
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_std::*;
+use ark_relations::r1cs::*;
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
+use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
+
+use lib_mpc_zexe::record_commitment::kzg::{*, constraints::*};
+
+use super::utils;
+use super::protocol;
+
+// Finite Field used to encode the zk circuit
+type ConstraintF = ark_bw6_761::Fr;
+
+// how many notes a single deposit may mint; unused notes carry a zero amount
+pub const NUM_NOTES: usize = 4;
+
+// the pool's denominations, largest first; every minted note holds exactly one
+// of these, and changing them requires a new setup
+pub const DENOMINATIONS: [u64; 2] = [10, 5];
+
+// the public inputs in the Groth proof are ordered as follows; note i's
+// commitment is at COMMITMENTS + 2i (x) and COMMITMENTS + 2i + 1 (y)
+#[allow(non_camel_case_types, unused)]
+pub enum GrothPublicInput {
+    ASSET_ID = 0,
+    AMOUNT = 1, // the total deposited, across all notes
+    NUM_NOTES = 2, // how many of the notes are minted
+    COMMITMENTS = 3,
+}
+
+/// OnRampMultiCircuit proves that a single deposit of AMOUNT of an asset is
+/// split across NUM_NOTES minted notes of that asset, each holding one of the
+/// pool's denominations; the notes past the minted ones hold nothing, and
+/// the sequencer never inserts them
+pub struct OnRampMultiCircuit {
+    /// public parameters (CRS) for the KZG commitment scheme
+    pub crs: JZKZGCommitmentParams<5>,
+    /// the minted notes, followed by padding notes; all are secret witnesses
+    pub notes: Vec<JZRecord<5>>,
+    /// how many of the notes are minted
+    pub num_notes: usize,
+}
+
+impl ConstraintSynthesizer<ConstraintF> for OnRampMultiCircuit {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<()> {
+        if self.notes.len() != NUM_NOTES {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let crs_var = JZKZGCommitmentParamsVar::<5>::new_constant(
+            cs.clone(),
+            self.crs
+        ).unwrap();
+
+        //----------------- declaration of public values for the deposit ---------------------
+
+        let asset_id = utils::bytes_to_field::<ConstraintF, 6>(
+            &self.notes[0].fields[protocol::UtxoField::ASSETID as usize]
+        );
+        let asset_id_var = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "asset_id"),
+            || { Ok(asset_id) },
+        ).unwrap();
+
+        let total = self.notes
+            .iter()
+            .map(|note| ConstraintF::from(protocol::amount_u64(note)))
+            .sum::<ConstraintF>();
+        let total_var = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "amount"),
+            || { Ok(total) },
+        ).unwrap();
+
+        let num_notes_var = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "num_notes"),
+            || { Ok(ConstraintF::from(self.num_notes as u64)) },
+        ).unwrap();
+
+        let assetid_inputvar_bytes = asset_id_var.to_bytes()?;
+
+        let mut amount_sum_var = FpVar::<ConstraintF>::zero();
+        let mut minted_sum_var = FpVar::<ConstraintF>::zero();
+        let mut previous_minted: Option<Boolean<ConstraintF>> = None;
+
+        for (i, note) in self.notes.iter().enumerate() {
+
+            //--------------- knowledge of opening of the note's commitment ------------------
+
+            let note_var = JZRecordVar::<5>::new_witness(
+                cs.clone(),
+                || Ok(note)
+            ).unwrap();
+
+            let note_commitment = note.commitment().into_affine();
+
+            let commitment_x_input_var = ark_bls12_377::constraints::FqVar::new_input(
+                ark_relations::ns!(cs, "commitment_x"),
+                || { Ok(note_commitment.x) },
+            ).unwrap();
+
+            let commitment_y_input_var = ark_bls12_377::constraints::FqVar::new_input(
+                ark_relations::ns!(cs, "commitment_y"),
+                || { Ok(note_commitment.y) },
+            ).unwrap();
+
+            lib_mpc_zexe::record_commitment::kzg::constraints::generate_constraints(
+                cs.clone(),
+                &crs_var,
+                &note_var
+            ).unwrap();
+
+            let note_commitment_var = note_var.commitment.to_affine()?;
+            note_commitment_var.x.enforce_equal(&commitment_x_input_var)?;
+            note_commitment_var.y.enforce_equal(&commitment_y_input_var)?;
+
+            //--------------- every note holds the deposited asset ------------------
+
+            let asset_id_bytes = &note_var.fields[protocol::UtxoField::ASSETID as usize];
+            for (a, b) in asset_id_bytes.iter().zip(assetid_inputvar_bytes.iter()) {
+                a.enforce_equal(b)?;
+            }
+
+            //--------------- the note holds a denomination, or nothing ------------------
+
+            // amounts are u64s, so the high bytes of the amount field must be zero
+            let amount_bytes = &note_var.fields[protocol::UtxoField::AMOUNT as usize];
+            for byte_var in amount_bytes.iter().skip(protocol::AMOUNT_BYTES) {
+                byte_var.enforce_equal(&UInt8::constant(0))?;
+            }
+            let amount_var = Boolean::le_bits_to_fp_var(
+                &amount_bytes[..protocol::AMOUNT_BYTES].to_bits_le()?
+            )?;
+
+            // the minted notes come first, so a note is only minted if the one before it is
+            let minted = Boolean::new_witness(
+                ark_relations::ns!(cs, "minted"),
+                || Ok(i < self.num_notes)
+            )?;
+            if let Some(previous) = previous_minted.as_ref() {
+                minted.and(&previous.not())?.enforce_equal(&Boolean::FALSE)?;
+            }
+
+            let mut denomination_var = FpVar::<ConstraintF>::one();
+            for denomination in DENOMINATIONS.iter() {
+                denomination_var *= &amount_var - FpVar::constant(ConstraintF::from(*denomination));
+            }
+            denomination_var.conditional_enforce_equal(&FpVar::zero(), &minted)?;
+            amount_var.conditional_enforce_equal(&FpVar::zero(), &minted.not())?;
+
+            amount_sum_var += &amount_var;
+            minted_sum_var += FpVar::from(minted.clone());
+            previous_minted = Some(minted);
+        }
+
+        //--------------- Binding all circuit gadgets together ------------------
+
+        amount_sum_var.enforce_equal(&total_var)?;
+        minted_sum_var.enforce_equal(&num_notes_var)?;
+
+        Ok(())
+    }
+}
+
+/// splits an amount into notes of the given denominations (largest first),
+/// greedily taking as many of each as fit; errs if some remainder cannot be
+/// represented, or if the split takes more than max_notes notes
+pub fn decompose(amount: u64, denominations: &[u64], max_notes: usize) -> core::result::Result<Vec<u64>, String> {
+    // count the notes of each denomination before materializing any, as a
+    // large amount could otherwise take a great many notes
+    let mut counts = Vec::new();
+    let mut remainder = amount;
+    for denomination in denominations.iter().filter(|d| **d > 0) {
+        counts.push((*denomination, remainder / denomination));
+        remainder %= denomination;
+    }
+
+    let num_notes = counts.iter().map(|(_, count)| *count).sum::<u64>();
+    if remainder != 0 || num_notes == 0 {
+        return Err(format!("{} leaves a remainder of {} that no denomination in {:?} represents",
+            amount, remainder, denominations));
+    }
+    if num_notes > max_notes as u64 {
+        return Err(format!("{} takes {} notes, but a deposit mints at most {}",
+            amount, num_notes, max_notes));
+    }
+
+    Ok(counts
+        .into_iter()
+        .flat_map(|(denomination, count)| std::iter::repeat(denomination).take(count as usize))
+        .collect())
+}
+
+// a note holding nothing of the asset, filling a slot past the minted notes
+pub fn padding_note(crs: &JZKZGCommitmentParams<5>, asset_id: &[u8]) -> JZRecord<5> {
+    let mut fields = utils::get_dummy_utxo(crs).fields.clone();
+    fields[protocol::UtxoField::ASSETID as usize] = asset_id.to_vec();
+
+    JZRecord::<5>::new(crs, &fields, &[0u8; 31].to_vec())
+}
+
+// pads the minted notes up to NUM_NOTES
+fn circuit(notes: &[JZRecord<5>]) -> OnRampMultiCircuit {
+    assert!(!notes.is_empty() && notes.len() <= NUM_NOTES);
+
+    let (_, _, crs) = utils::trusted_setup();
+    let asset_id = notes[0].fields[protocol::UtxoField::ASSETID as usize].clone();
+
+    let mut padded = notes.to_vec();
+    padded.resize_with(NUM_NOTES, || padding_note(&crs, &asset_id));
+
+    OnRampMultiCircuit { crs, notes: padded, num_notes: notes.len() }
+}
+
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    let (_, _, crs) = utils::trusted_setup();
+    // create a circuit with a dummy witness
+    let circuit = circuit(&[utils::get_dummy_utxo(&crs)]);
+
+    let seed = [0u8; 32];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+
+    let (pk, vk) = Groth16::<BW6_761>::
+        circuit_specific_setup(circuit, &mut rng)
+        .unwrap();
+
+    (pk, vk)
+}
+
+// proves with fresh OS randomness, so proofs of the same statement are unlinkable
+pub fn generate_groth_proof(
+    pk: &ProvingKey<BW6_761>,
+    notes: &[JZRecord<5>],
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(pk, notes, &mut OsRng)
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    notes: &[JZRecord<5>],
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+
+    let circuit = circuit(notes);
+    let public_inputs = public_inputs(&circuit);
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
+
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "onramp multi proof generated");
+
+    (proof, public_inputs)
+}
+
+// arranges the public inputs based on the GrothPublicInput enum definition
+pub fn public_inputs(circuit: &OnRampMultiCircuit) -> Vec<ConstraintF> {
+    let mut public_inputs: Vec<ConstraintF> = vec![
+        utils::bytes_to_field::<ConstraintF, 6>(
+            &circuit.notes[0].fields[protocol::UtxoField::ASSETID as usize]
+        ),
+        circuit.notes
+            .iter()
+            .map(|note| ConstraintF::from(protocol::amount_u64(note)))
+            .sum::<ConstraintF>(),
+        ConstraintF::from(circuit.num_notes as u64),
+    ];
+
+    for note in circuit.notes.iter() {
+        let commitment = note.commitment().into_affine();
+        public_inputs.push(commitment.x);
+        public_inputs.push(commitment.y);
+    }
+
+    public_inputs
+}
+
+// the commitments of the minted notes, as the public inputs of a proof declare them
+pub fn minted_commitments(public_inputs: &[ConstraintF]) -> core::result::Result<Vec<ark_bls12_377::G1Affine>, String> {
+    let num_notes = public_inputs
+        .get(GrothPublicInput::NUM_NOTES as usize)
+        .ok_or("missing NUM_NOTES public input")?
+        .into_bigint();
+    if num_notes > BigInteger384::from(NUM_NOTES as u64) || num_notes.is_zero() {
+        return Err(format!("a deposit mints between 1 and {} notes", NUM_NOTES));
+    }
+
+    (0..num_notes.0[0] as usize)
+        .map(|i| {
+            let x = GrothPublicInput::COMMITMENTS as usize + 2 * i;
+            match (public_inputs.get(x), public_inputs.get(x + 1)) {
+                (Some(x), Some(y)) => protocol::affine_from_xy(*x, *y),
+                _ => Err(format!("missing commitment of note {}", i)),
+            }
+        })
+        .collect()
+}
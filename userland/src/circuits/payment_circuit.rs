@@ -1,15 +1,18 @@
+#[cfg(feature = "insecure-setup")]
 use rand_chacha::rand_core::SeedableRng;
-use std::borrow::Borrow;
-use std::cmp::min;
 
 use ark_ec::*;
 use ark_ff::*;
 use ark_bw6_761::{*};
 use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
 use ark_std::*;
 use ark_relations::r1cs::*;
-use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
 use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
 
 use lib_mpc_zexe::vector_commitment;
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
@@ -20,15 +23,13 @@ use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
 use lib_mpc_zexe::record_commitment::kzg::{*, constraints::*};
 use lib_mpc_zexe::prf::{*, constraints::*};
 
+use super::asset_tag;
 use super::utils;
 use super::protocol;
 
 // Finite Field used to encode the zk circuit
 type ConstraintF = ark_bw6_761::Fr;
 
-// define the depth of the merkle tree as a constant
-const MERKLE_TREE_LEVELS: u32 = 8;
-
 // the public inputs in the Groth proof are ordered as follows
 #[allow(non_camel_case_types, unused)]
 pub enum GrothPublicInput {
@@ -37,45 +38,69 @@ pub enum GrothPublicInput {
     NULLIFIER = 2, // nullifier to the input utxo
     COMMITMENT_X = 3, // commitment of the output utxo
     COMMITMENT_Y = 4, // commitment of the output utxo
+    ASSET_TAG = 5, // blinded tag of the asset being transferred
+    TREE_ID = 6, // the tree whose root the input utxo is proven against
+    LEAF_INDEX = 7, // the leaf the input utxo is opened at, if the circuit exposes it
 }
 
 
-/// OnRampCircuit is used to prove that the new coin being created
-/// during the on-ramp process commits to the amount and asset_id
-/// being claimed by the client.
-pub struct PaymentCircuit {
+/// PaymentCircuit proves that the sender owns an unspent coin in the tree,
+/// and pays its value to the output coin. It borrows its parameters and
+/// witness: the opening proof alone is a path of the tree's depth, and a
+/// proof is generated from the caller's copies without cloning any of them.
+pub struct PaymentCircuit<'a> {
     /// public parameters (CRS) for the KZG commitment scheme
-    pub crs: JZKZGCommitmentParams<5>,
+    pub crs: &'a JZKZGCommitmentParams<5>,
 
     /// public parameters for the PRF evaluation
-    pub prf_params: JZPRFParams,
+    pub prf_params: &'a JZPRFParams,
 
      /// public parameters for the vector commitment scheme
-     pub vc_params: JZVectorCommitmentParams<MTParams>,
+     pub vc_params: &'a JZVectorCommitmentParams<MTParams>,
 
     /// all fields of the input utxo, for the asset owned by the sender
-    pub input_utxo: JZRecord<5>,
+    pub input_utxo: &'a JZRecord<5>,
 
     // all fields of the output utxo listing recepient as the owner
-    pub output_utxo: JZRecord<5>,
+    pub output_utxo: &'a JZRecord<5>,
 
     /// secret key for proving ownership of the spent coin
     pub sk: [u8; 32],
 
+    /// blind for the asset tag; fresh per payment, unless the deployment
+    /// pools payments by asset under a shared blind
+    pub asset_blind: [u8; 32],
+
+    /// the pool the nullifier is bound to, if the deployment sets one; a
+    /// constant of the circuit, so each pool has its own keys
+    pub pool_id: Option<[u8; protocol::POOL_ID_BYTES]>,
+
+    /// the tree the input utxo lives in; the nullifier is bound to it, so
+    /// that a coin's nullifier differs across trees
+    pub tree_id: u32,
+
+    /// whether the statement exposes the leaf the input utxo is opened at,
+    /// so that auditors can correlate a nullifier with the coin's position; a
+    /// constant of the circuit, so keys are set up with it or without it
+    pub expose_leaf_index: bool,
+
     /// Merkle opening proof for proving existence of the unspent coin
-    pub unspent_coin_existence_proof: JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    pub unspent_coin_existence_proof: &'a JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
 }
 
 /// ConstraintSynthesizer is a trait that is implemented for the OnRampCircuit;
 /// it contains the logic for generating the constraints for the SNARK circuit
 /// that will be used to generate the local proof encoding a valid coin creation.
-impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
+impl<'a> ConstraintSynthesizer<ConstraintF> for PaymentCircuit<'a> {
     //#[tracing::instrument(target = "r1cs", skip(self, cs))]
     fn generate_constraints(
         self,
         cs: ConstraintSystemRef<ConstraintF>,
     ) -> Result<()> {
 
+        // the commitment of the coin padding every unoccupied leaf
+        let dummy_utxo_commitment = utils::get_dummy_utxo(self.crs).commitment().into_affine();
+
         let crs_var = JZKZGCommitmentParamsVar::<5>::new_constant(
             cs.clone(),
             self.crs
@@ -84,17 +109,17 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
         // PRF makes use of public parameters, so we make them constant
         let prf_params_var = JZPRFParamsVar::new_constant(
             cs.clone(),
-            &self.prf_params
+            self.prf_params
         ).unwrap();
 
         let merkle_params_var = JZVectorCommitmentParamsVar::new_constant(
             cs.clone(),
-            &self.vc_params
+            self.vc_params
         ).unwrap();
 
         //--------------- knowledge of opening of input UTXO commitment ------------------
 
-        let input_utxo_record = self.input_utxo.borrow();
+        let input_utxo_record = self.input_utxo;
 
         let input_utxo_var = JZRecordVar::<5>::new_witness(
             cs.clone(),
@@ -110,7 +135,7 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
 
         //--------------- knowledge of opening of output UTXO commitment ------------------
         
-        let output_utxo_record = self.output_utxo.borrow();
+        let output_utxo_record = self.output_utxo;
         let output_utxo_commitment = output_utxo_record.commitment().into_affine();
 
         let output_utxo_var = JZRecordVar::<5>::new_witness(
@@ -130,9 +155,16 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
 
         // prf_instance nullifier is responsible for proving that the computed
         // nullifier encoded in the L1-destined proof is correct; 
-        // we use the same idea as zCash here, where nullifier = PRF(rho; sk)
+        // we use the same idea as zCash here, where nullifier = PRF(rho || tree_id; sk),
+        // or PRF(rho || pool_id || tree_id; sk) in a deployment that sets a pool id
         let prf_instance_nullifier = JZPRFInstance::new(
-            &self.prf_params, self.input_utxo.fields[protocol::UtxoField::RHO as usize].as_slice(), &self.sk
+            self.prf_params,
+            &nullifier_prf_input(
+                &self.input_utxo.fields[protocol::UtxoField::RHO as usize],
+                self.pool_id.as_ref(),
+                self.tree_id
+            ),
+            &self.sk
         );
         let nullifier = prf_instance_nullifier.evaluate();
 
@@ -156,7 +188,7 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
         // of the secret key corresponding to the coin's public key;
         // we use the same idea as zCash here, where pk = PRF(0; sk)
         let ownership_prf_instance = JZPRFInstance::new(
-            &self.prf_params, &[0u8; 32], &self.sk
+            self.prf_params, &[0u8; 32], &self.sk
         );
 
         // PRF arguments for the secret witness
@@ -181,7 +213,7 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
         ::<ConstraintF, MTParams, MTParamsVar>
         ::new_witness(
             cs.clone(),
-            || Ok(&self.unspent_coin_existence_proof)
+            || Ok(self.unspent_coin_existence_proof)
         ).unwrap();
 
         // //generate the merkle proof verification circuitry
@@ -219,6 +251,26 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
             || { Ok(output_utxo_commitment.y) },
         ).unwrap();
 
+        let asset_id = &self.input_utxo.fields[protocol::UtxoField::ASSETID as usize];
+        let asset_tag_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "asset_tag"), 
+            || Ok(asset_tag::field_tag(self.prf_params, asset_id, &self.asset_blind)),
+        ).unwrap();
+
+        let tree_id_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "tree_id"), 
+            || Ok(ConstraintF::from(self.tree_id)),
+        ).unwrap();
+
+        let leaf_index_inputvar = if self.expose_leaf_index {
+            Some(FpVar::<ConstraintF>::new_input(
+                ark_relations::ns!(cs, "leaf_index"),
+                || Ok(ConstraintF::from(self.unspent_coin_existence_proof.path.leaf_index as u64)),
+            )?)
+        } else {
+            None
+        };
+
 
         //--------------- Binding all circuit gadgets together ------------------
 
@@ -227,9 +279,25 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
             byte_var.enforce_equal(&nullifier_prf_instance_var.key_var[i])?;
         }
 
-        // 2. does the nullifier PRF use rho as input?
-        for (i, byte_var) in nullifier_prf_instance_var.input_var.iter().enumerate() {
-            byte_var.enforce_equal(&input_utxo_var.fields[protocol::UtxoField::RHO as usize][i])?;
+        // 2. does the nullifier PRF use rho, followed by the pool id if any and
+        // then the tree id in the statement, as input? the tree id is a u32, so
+        // the remaining bytes of its input must be zero
+        let tree_id_byte_vars = tree_id_inputvar.to_bytes()?;
+        for byte_var in tree_id_byte_vars.iter().skip(protocol::TREE_ID_BYTES) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+        let nullifier_input_vars: Vec<UInt8<ConstraintF>> = input_utxo_var
+            .fields[protocol::UtxoField::RHO as usize]
+            .iter()
+            .cloned()
+            .chain(self.pool_id.iter().flatten().map(|byte| UInt8::constant(*byte)))
+            .chain(tree_id_byte_vars[..protocol::TREE_ID_BYTES].iter().cloned())
+            .collect();
+        if nullifier_prf_instance_var.input_var.len() != nullifier_input_vars.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        for (byte_var, input_byte_var) in nullifier_prf_instance_var.input_var.iter().zip(nullifier_input_vars.iter()) {
+            byte_var.enforce_equal(input_byte_var)?;
         }
 
         // 3. prove ownership of the coin. Does sk correspond to coin's pk?
@@ -238,12 +306,7 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
         }
 
         // 4. constrain the nullifier in the statement to equal the PRF output
-        let nullifier_prf_byte_vars: Vec::<UInt8<ConstraintF>> = nullifier_inputvar
-            .to_bytes()?
-            .to_vec();
-        for (i, byte_var) in nullifier_prf_instance_var.output_var.iter().enumerate() {
-            byte_var.enforce_equal(&nullifier_prf_byte_vars[i])?;
-        }
+        enforce_nullifier(&nullifier_inputvar, &nullifier_prf_instance_var.output_var, &Boolean::TRUE)?;
 
         // 5. constrain the output utxo commitment in the statement to equal the computed commitment output
         let output_utxo_commitment_x_byte_vars: Vec::<UInt8<ConstraintF>> = output_utxo_commitment_x_input_var
@@ -261,24 +324,34 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
         }
 
         // 6. does the leaf node in the merkle proof equal the input utxo commitment?
-        let input_utxo_commitment_byte_vars: Vec::<UInt8<ConstraintF>> = input_utxo_var
-            .commitment // grab the commitment variable
-            .to_affine()? // convert it to an affine point
-            .x // grab the x-coordinate
-            .to_bytes()?; // let's use arkworks' to_bytes gadget
-        let proof_var_leaf_var_bytes: Vec::<UInt8<ConstraintF>> = proof_var.leaf_var
+        // the leaf is the full serialized point, x followed by y, so every byte of
+        // both coordinates is bound; binding x alone would admit the negated point
+        let input_utxo_commitment_var = input_utxo_var.commitment.to_affine()?;
+        let mut input_utxo_commitment_byte_vars: Vec::<UInt8<ConstraintF>> =
+            input_utxo_commitment_var.x.to_bytes()?;
+        input_utxo_commitment_byte_vars.extend(input_utxo_commitment_var.y.to_bytes()?);
+        if input_utxo_commitment_byte_vars.len() != proof_var.leaf_var.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        for (commitment_byte_var, leaf_byte_var) in input_utxo_commitment_byte_vars
             .iter()
-            .cloned()
-            .collect();
-        for i in 0..min(input_utxo_commitment_byte_vars.len(), proof_var_leaf_var_bytes.len()) {
-            input_utxo_commitment_byte_vars[i].enforce_equal(&proof_var_leaf_var_bytes[i])?;
+            .zip(proof_var.leaf_var.iter()) {
+            commitment_byte_var.enforce_equal(leaf_byte_var)?;
         }
 
         // 7. does the proof use the same root as what is declared in the statement?
         proof_var.root_var.x.enforce_equal(&root_x_inputvar)?;
         proof_var.root_var.y.enforce_equal(&root_y_inputvar)?;
 
-        // 8. conservation of asset value
+        // 8. amounts are u64s, so the high bytes of the amount field must be zero;
+        // conservation below carries this over to the output utxo
+        for byte_var in input_utxo_var.fields[protocol::UtxoField::AMOUNT as usize]
+            .iter()
+            .skip(protocol::AMOUNT_BYTES) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+
+        // 9. conservation of asset value
         for field in [protocol::UtxoField::AMOUNT, protocol::UtxoField::ASSETID] {
             input_utxo_var
             .fields[field as usize]
@@ -289,44 +362,73 @@ impl ConstraintSynthesizer<ConstraintF> for PaymentCircuit {
             });
         }
 
+        // 10. is the asset tag in the statement derived from the transferred asset id?
+        asset_tag::generate_constraints(
+            cs.clone(),
+            &prf_params_var,
+            JZPRFInstance::new(self.prf_params, asset_id, &self.asset_blind),
+            &input_utxo_var.fields[protocol::UtxoField::ASSETID as usize],
+            &asset_tag_inputvar
+        )?;
+
+        // 11. the input utxo is not the dummy coin: every unoccupied leaf holds
+        // it, so spending it would mint value out of thin air
+        let is_dummy_x = input_utxo_commitment_var.x.is_eq(
+            &ark_bls12_377::constraints::FqVar::constant(dummy_utxo_commitment.x)
+        )?;
+        let is_dummy_y = input_utxo_commitment_var.y.is_eq(
+            &ark_bls12_377::constraints::FqVar::constant(dummy_utxo_commitment.y)
+        )?;
+        is_dummy_x.and(&is_dummy_y)?.enforce_equal(&Boolean::FALSE)?;
+
+        // 12. the output utxo is freshly created: were it to reuse the input's
+        // rho, the sender could predict its nullifier, and a coin paid back to
+        // the same key would collide with the nullifier of the one spent here
+        let input_rho_vars = &input_utxo_var.fields[protocol::UtxoField::RHO as usize];
+        let output_rho_vars = &output_utxo_var.fields[protocol::UtxoField::RHO as usize];
+        input_rho_vars.is_eq(output_rho_vars)?.enforce_equal(&Boolean::FALSE)?;
+
+        // 13. if the statement exposes a leaf index, it is the one the path
+        // opens: the leaf's own direction bit is the lowest bit of its index,
+        // and the path's bits, which run from the root down, are the rest
+        if let Some(leaf_index_inputvar) = leaf_index_inputvar {
+            let path_var = &proof_var.path_var;
+            let index_bits: Vec<Boolean<ConstraintF>> = std::iter::once(path_var.leaf_is_right_child.clone())
+                .chain(path_var.path.iter().rev().cloned())
+                .collect();
+            Boolean::le_bits_to_fp_var(&index_bits)?.enforce_equal(&leaf_index_inputvar)?;
+        }
+
         Ok(())
     }
 }
 
 
-pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+// the keys of the payment statement under the deployment's params: its pool
+// id and whether it exposes the spent leaf's index are constants of the circuit
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup(statement: &protocol::StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
 
-    let (prf_params, vc_params, crs) = utils::trusted_setup();
+    let (prf_params, vc_params, crs) = utils::shared_setup();
 
-    // create a circuit with a dummy witness
-    let circuit = {
-    
-        // let's create the universe of dummy utxos
-        let mut records = Vec::new();
-        for _ in 0..(1 << MERKLE_TREE_LEVELS) {
-            records.push(utils::get_dummy_utxo(&crs).commitment().into_affine());
-        }
-    
-        // let's create a database of coins, and generate a merkle proof
-        // we need this in order to create a circuit with appropriate public inputs
-        let db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records[..]);
-        let merkle_proof = JZVectorCommitmentOpeningProof {
-            root: db.commitment(),
-            record: db.get_record(0).clone(),
-            path: db.proof(0),
-        };
+    // let's open a leaf of a tree of dummy coins, without building the tree;
+    // we need this in order to create a circuit with appropriate public inputs
+    let merkle_proof = utils::dummy_opening_proof(vc_params, protocol::MERKLE_TREE_LEVELS);
+    let dummy_utxo = utils::get_dummy_utxo(crs);
 
-        let (_, vc_params, _) = utils::trusted_setup();
-        // note that circuit setup does not care about the values of witness variables
-        PaymentCircuit {
-            crs: crs.clone(),
-            prf_params: prf_params,
-            vc_params: vc_params,
-            sk: [0u8; 32],
-            input_utxo: utils::get_dummy_utxo(&crs), // doesn't matter what value the coin has
-            output_utxo: utils::get_dummy_utxo(&crs), // again, doesn't matter what value
-            unspent_coin_existence_proof: merkle_proof,
-        }
+    // note that circuit setup does not care about the values of witness variables
+    let circuit = PaymentCircuit {
+        crs,
+        prf_params,
+        vc_params,
+        sk: [0u8; 32],
+        asset_blind: [0u8; 32],
+        pool_id: statement.pool_id,
+        tree_id: 0,
+        expose_leaf_index: statement.expose_leaf_index,
+        input_utxo: &dummy_utxo, // doesn't matter what value the coin has
+        output_utxo: &dummy_utxo, // again, doesn't matter what value
+        unspent_coin_existence_proof: &merkle_proof,
     };
 
     let seed = [0u8; 32];
@@ -339,61 +441,167 @@ pub fn circuit_setup() -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
     (pk, vk)
 }
 
+// proves with fresh OS randomness, so proofs of the same statement are unlinkable;
+// the statement params must be the ones pk was set up under
 pub fn generate_groth_proof(
     pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(
+        pk, statement, input_utxo, output_utxo, unspent_coin_existence_proof, sk, tree_id, &mut OsRng
+    )
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors;
+// the asset blind is drawn from the same rng
+#[allow(clippy::too_many_arguments)]
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
     input_utxo: &JZRecord<5>,
     output_utxo: &JZRecord<5>,
     unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
-    sk: &[u8; 32]
+    sk: &[u8; 32],
+    tree_id: u32,
+    rng: &mut R,
 ) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    let mut asset_blind = [0u8; 32];
+    rng.fill_bytes(&mut asset_blind);
 
-    let (prf_params, vc_params, crs) = utils::trusted_setup();
+    generate_groth_proof_with_asset_blind(
+        pk, statement, input_utxo, output_utxo, unspent_coin_existence_proof, sk, tree_id, &asset_blind, rng
+    )
+}
 
-    let nullifier = utils::bytes_to_field::<ConstraintF, 6>(
-        &JZPRFInstance::new(
-            &prf_params,
-            input_utxo.fields[protocol::UtxoField::RHO as usize].as_slice(),
-            sk)
-        .evaluate()
-    );
+// proves under a caller-chosen asset blind, for deployments that pool by asset
+#[allow(clippy::too_many_arguments)]
+pub fn generate_groth_proof_with_asset_blind<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    asset_blind: &[u8; 32],
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+
+    let (prf_params, vc_params, crs) = utils::shared_setup();
 
     let circuit = PaymentCircuit {
-        crs: crs,
-        prf_params: prf_params,
-        vc_params: vc_params,
+        crs,
+        prf_params,
+        vc_params,
         sk: *sk,
-        input_utxo: input_utxo.clone(),
-        output_utxo: output_utxo.clone(),
-        unspent_coin_existence_proof: unspent_coin_existence_proof.clone(),
+        asset_blind: *asset_blind,
+        pool_id: statement.pool_id,
+        tree_id,
+        expose_leaf_index: statement.expose_leaf_index,
+        input_utxo,
+        output_utxo,
+        unspent_coin_existence_proof,
     };
-    
-    // arrange the public inputs based on the GrothPublicInput enum definition
-    // pub enum GrothPublicInput {
-    //     ROOT_X = 0, // merkle root for proving membership of input utxo
-    //     ROOT_Y = 1, // merkle root for proving membership of input utxo
-    //     NULLIFIER = 2, // nullifier to the input utxo
-    //     COMMITMENT_X = 3, // commitment of the output utxo
-    //     COMMITMENT_Y = 4, // commitment of the output utxo
-    // }
-    let public_inputs: Vec<ConstraintF> = vec![
-        unspent_coin_existence_proof.root.x,
-        unspent_coin_existence_proof.root.y,
-        nullifier,
-        output_utxo.commitment().into_affine().x,
-        output_utxo.commitment().into_affine().y
-    ];
 
-    let seed = [0u8; 32];
-    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+    let public_inputs = public_inputs(&circuit);
 
     let now = std::time::Instant::now();
-    let proof = Groth16::<BW6_761>::prove(&pk, circuit, &mut rng).unwrap();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
     
-    println!("payment proof generated in {}.{} secs", 
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_millis()
-    );
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "payment proof generated");
 
 
     (proof, public_inputs)
 }
+
+// arrange the public inputs based on the GrothPublicInput enum definition
+// pub enum GrothPublicInput {
+//     ROOT_X = 0, // merkle root for proving membership of input utxo
+//     ROOT_Y = 1, // merkle root for proving membership of input utxo
+//     NULLIFIER = 2, // nullifier to the input utxo
+//     COMMITMENT_X = 3, // commitment of the output utxo
+//     COMMITMENT_Y = 4, // commitment of the output utxo
+//     ASSET_TAG = 5, // blinded tag of the asset being transferred
+//     TREE_ID = 6, // the tree whose root the input utxo is proven against
+//     LEAF_INDEX = 7, // the leaf the input utxo is opened at, if the circuit exposes it
+// }
+pub fn public_inputs(circuit: &PaymentCircuit) -> Vec<ConstraintF> {
+    let nullifier = utils::bytes_to_field::<ConstraintF, 6>(
+        &nullifier(
+            circuit.prf_params,
+            &circuit.input_utxo.fields[protocol::UtxoField::RHO as usize],
+            &circuit.sk,
+            circuit.pool_id.as_ref(),
+            circuit.tree_id)
+    );
+
+    vec![
+        circuit.unspent_coin_existence_proof.root.x,
+        circuit.unspent_coin_existence_proof.root.y,
+        nullifier,
+        circuit.output_utxo.commitment().into_affine().x,
+        circuit.output_utxo.commitment().into_affine().y,
+        asset_tag::field_tag(
+            circuit.prf_params,
+            &circuit.input_utxo.fields[protocol::UtxoField::ASSETID as usize],
+            &circuit.asset_blind
+        ),
+        ConstraintF::from(circuit.tree_id),
+    ]
+    .into_iter()
+    .chain(circuit.expose_leaf_index.then(|| {
+        ConstraintF::from(circuit.unspent_coin_existence_proof.path.leaf_index as u64)
+    }))
+    .collect()
+}
+
+/// the nullifier of a coin with the given rho, spent under sk from the given
+/// tree; binding it to a pool makes the same coin's nullifiers in two pools
+/// unlinkable, and binding it to the tree keeps each tree's nullifiers apart
+pub fn nullifier(
+    prf_params: &JZPRFParams,
+    rho: &[u8],
+    sk: &[u8; 32],
+    pool_id: Option<&[u8; protocol::POOL_ID_BYTES]>,
+    tree_id: u32,
+) -> Vec<u8> {
+    JZPRFInstance::new(prf_params, &nullifier_prf_input(rho, pool_id, tree_id), sk).evaluate()
+}
+
+// rho, followed by the pool id if there is one, and then the tree id
+pub fn nullifier_prf_input(
+    rho: &[u8],
+    pool_id: Option<&[u8; protocol::POOL_ID_BYTES]>,
+    tree_id: u32
+) -> Vec<u8> {
+    let mut input = rho.to_vec();
+    if let Some(pool_id) = pool_id {
+        input.extend_from_slice(pool_id);
+    }
+    input.extend_from_slice(&tree_id.to_le_bytes());
+
+    input
+}
+
+// constrains a nullifier in the statement, when condition holds, to be the
+// PRF output: its low bytes are the output, and the rest of the field's bytes
+// are zero, or the nullifier plus 2^256 would pass for the same nullifier
+pub(crate) fn enforce_nullifier(
+    nullifier_var: &ark_bls12_377::constraints::FqVar,
+    prf_output_vars: &[UInt8<ConstraintF>],
+    condition: &Boolean<ConstraintF>,
+) -> Result<(), SynthesisError> {
+    for (i, byte_var) in nullifier_var.to_bytes()?.iter().enumerate() {
+        match prf_output_vars.get(i) {
+            Some(output_byte_var) => byte_var.conditional_enforce_equal(output_byte_var, condition)?,
+            None => byte_var.conditional_enforce_equal(&UInt8::constant(0), condition)?,
+        }
+    }
+
+    Ok(())
+}
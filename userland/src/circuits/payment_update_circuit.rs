@@ -0,0 +1,216 @@
+#[cfg(feature = "insecure-setup")]
+use rand_chacha::rand_core::SeedableRng;
+
+use ark_bw6_761::BW6_761;
+use ark_relations::lc;
+use ark_relations::r1cs::*;
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
+use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    *, config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+use lib_mpc_zexe::record_commitment::kzg::*;
+
+use super::utils;
+use super::payment_circuit::{self, PaymentCircuit};
+use super::merkle_update_circuit::{self, MerkleUpdateCircuit};
+use super::protocol::{self, NUM_PAYMENT_INPUTS};
+
+// Finite Field used to encode the zk circuit
+type ConstraintF = ark_bw6_761::Fr;
+
+/// PaymentUpdateCircuit proves a payment together with the insertion of
+/// its output commitment into the tree the input coin was opened against,
+/// so that a single proof (and a single verification) replaces the payment
+/// proof plus the sequencer's merkle update proof. Verifying it costs one
+/// pairing check over 14 public inputs, against two pairing checks over 7
+/// inputs each for the two-proof flow; the price is that the client also
+/// proves the update, roughly doubling its proving time, against the latest
+/// root rather than any recent one.
+pub struct PaymentUpdateCircuit<'a> {
+    pub payment: PaymentCircuit<'a>,
+    pub merkle_update: MerkleUpdateCircuit,
+}
+
+impl<'a> ConstraintSynthesizer<ConstraintF> for PaymentUpdateCircuit<'a> {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<()> {
+        self.payment.generate_constraints(cs.clone())?;
+        self.merkle_update.generate_constraints(cs.clone())?;
+
+        // the leaf being inserted must be the payment's output commitment;
+        // instance variable 0 is the constant one, hence the offsets by 1,
+        // and the merkle update's inputs follow the payment's
+        let payment_commitment = [
+            protocol::PaymentGrothPublicInput::COMMITMENT_X as usize,
+            protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize,
+        ];
+        let inserted_leaf = [
+            protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_X as usize,
+            protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_Y as usize,
+        ];
+
+        // and the input coin must be opened against the tree being updated,
+        // so a verifier checking the old root also checks the spent coin's root
+        let payment_root = [
+            protocol::PaymentGrothPublicInput::ROOT_X as usize,
+            protocol::PaymentGrothPublicInput::ROOT_Y as usize,
+        ];
+        let old_root = [
+            protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_X as usize,
+            protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_Y as usize,
+        ];
+
+        let bound = payment_commitment.iter().zip(inserted_leaf.iter())
+            .chain(payment_root.iter().zip(old_root.iter()));
+        for (p, u) in bound {
+            cs.enforce_constraint(
+                lc!() + Variable::Instance(1 + p),
+                lc!() + Variable::One,
+                lc!() + Variable::Instance(1 + NUM_PAYMENT_INPUTS + u),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup(statement: &protocol::StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    // create a circuit with a dummy witness
+    let merkle_proof = utils::dummy_opening_proof(vc_params, protocol::MERKLE_TREE_LEVELS);
+    let dummy_utxo = utils::get_dummy_utxo(crs);
+
+    // note that circuit setup does not care about the values of witness variables
+    let circuit = PaymentUpdateCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: [0u8; 32],
+            asset_blind: [0u8; 32],
+            pool_id: statement.pool_id,
+            tree_id: 0,
+            expose_leaf_index: false,
+            input_utxo: &dummy_utxo,
+            output_utxo: &dummy_utxo,
+            unspent_coin_existence_proof: &merkle_proof,
+        },
+        merkle_update: MerkleUpdateCircuit {
+            vc_params: utils::trusted_setup().1,
+            leaf_index: 0,
+            old_merkle_proof: merkle_proof.clone(),
+            new_merkle_proof: merkle_proof.clone(),
+        },
+    };
+
+    let seed = [0u8; 32];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+
+    let (pk, vk) = Groth16::<BW6_761>::
+        circuit_specific_setup(circuit, &mut rng)
+        .unwrap();
+
+    (pk, vk)
+}
+
+// proves with fresh OS randomness, so proofs of the same statement are unlinkable
+pub fn generate_groth_proof(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    leaf_index: usize,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(
+        pk, statement, input_utxo, output_utxo, unspent_coin_existence_proof, sk, tree_id,
+        old_merkle_proof, new_merkle_proof, leaf_index, &mut OsRng
+    )
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    leaf_index: usize,
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    let mut asset_blind = [0u8; 32];
+    rng.fill_bytes(&mut asset_blind);
+
+    let circuit = payment_update_circuit(
+        statement, input_utxo, output_utxo, unspent_coin_existence_proof, sk, tree_id, &asset_blind,
+        old_merkle_proof, new_merkle_proof, leaf_index
+    );
+
+    // the public inputs of the two statements, concatenated in order
+    let public_inputs = [
+        payment_circuit::public_inputs(&circuit.payment),
+        merkle_update_circuit::public_inputs(&circuit.merkle_update),
+    ].concat();
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
+
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "payment update proof generated");
+
+    (proof, public_inputs)
+}
+
+pub fn payment_update_circuit<'a>(
+    statement: &protocol::StatementParams,
+    input_utxo: &'a JZRecord<5>,
+    output_utxo: &'a JZRecord<5>,
+    unspent_coin_existence_proof: &'a JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    asset_blind: &[u8; 32],
+    old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    leaf_index: usize,
+) -> PaymentUpdateCircuit<'a> {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    PaymentUpdateCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: *sk,
+            asset_blind: *asset_blind,
+            pool_id: statement.pool_id,
+            tree_id,
+            expose_leaf_index: false,
+            input_utxo,
+            output_utxo,
+            unspent_coin_existence_proof,
+        },
+        merkle_update: MerkleUpdateCircuit {
+            vc_params: utils::trusted_setup().1,
+            leaf_index,
+            old_merkle_proof: old_merkle_proof.clone(),
+            new_merkle_proof: new_merkle_proof.clone(),
+        },
+    }
+}
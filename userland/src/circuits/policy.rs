@@ -0,0 +1,90 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::protocol;
+
+// domain separator prefixed to every signed policy
+const POLICY_DOMAIN: &[u8] = b"sanctum/policy/v1";
+
+/// Policy lists the commitments and nullifiers (bs58 encoded, as they
+/// appear on the wire) that the operator refuses to process
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub version: u64,
+    pub blocked_commitments: Vec<String>,
+    pub blocked_nullifiers: Vec<String>,
+}
+
+/// SignedPolicy is a policy along with the operator's (bs58 encoded)
+/// ed25519 signature over it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SignedPolicy {
+    pub policy: Policy,
+    pub signature: String,
+}
+
+impl Policy {
+    pub fn is_blocked_commitment(&self, commitment: &String) -> bool {
+        self.blocked_commitments.contains(commitment)
+    }
+
+    pub fn is_blocked_nullifier(&self, nullifier: &String) -> bool {
+        let nullifier = protocol::normalize_field_bs58(nullifier);
+        self.blocked_nullifiers.iter().any(|n| protocol::normalize_field_bs58(n) == nullifier)
+    }
+
+    fn signing_message(&self) -> Vec<u8> {
+        let mut message = POLICY_DOMAIN.to_vec();
+        message.extend_from_slice(&serde_json::to_vec(self).unwrap());
+        message
+    }
+
+    pub fn sign(&self, key: &SigningKey) -> SignedPolicy {
+        SignedPolicy {
+            policy: self.clone(),
+            signature: bs58::encode(key.sign(&self.signing_message()).to_bytes()).into_string(),
+        }
+    }
+}
+
+impl SignedPolicy {
+    // returns the policy iff it carries a valid signature by the operator key
+    pub fn verify(&self, operator_key: &VerifyingKey) -> Result<Policy, String> {
+        verify_operator_signature(operator_key, &self.policy.signing_message(), &self.signature, "policy")?;
+
+        Ok(self.policy.clone())
+    }
+}
+
+// checks a bs58 encoded signature by the operator key over message; what
+// names the signed object in errors
+pub(crate) fn verify_operator_signature(
+    operator_key: &VerifyingKey,
+    message: &[u8],
+    signature: &str,
+    what: &str
+) -> Result<(), String> {
+    let signature: [u8; 64] = bs58::decode(signature)
+        .into_vec()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| format!("{} signature must be 64 bytes", what))?;
+
+    operator_key
+        .verify(message, &Signature::from_bytes(&signature))
+        .map_err(|_| format!("invalid {} signature", what))
+}
+
+// decodes a bs58 encoded ed25519 public key, e.g. from the operator's config
+pub fn operator_key_from_bs58(key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = bs58::decode(key)
+        .into_vec()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "operator key must be 32 bytes".to_string())?;
+
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
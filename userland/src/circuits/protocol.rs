@@ -1,5 +1,8 @@
+use std::cell::RefCell;
+
 use ark_bw6_761::BW6_761;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use ark_std::io::Cursor;
 use ark_ec::pairing::*;
@@ -7,6 +10,7 @@ use ark_serialize::{CanonicalSerialize, CanonicalDeserialize};
 use ark_groth16::*;
 
 use lib_mpc_zexe::coin::*;
+use lib_mpc_zexe::record_commitment::kzg::{JZRecord, JZKZGCommitmentParams};
 use lib_mpc_zexe::collaborative_snark::*;
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
     JZVectorCommitment as JubJubVectorCommitment,
@@ -23,15 +27,72 @@ use lib_mpc_zexe::vector_commitment::bytes::sha256::{
     JZVectorCommitmentInnerDigest as Sha2VectorCommitmentInnerDigest
 };
 
+/// UtxoField indexes the fields of a coin, a KZG-committed JZRecord<5>, as
+/// used by every circuit and service; see coin_models for how they map to the
+/// fields of lib_mpc_zexe's 8-field coin, which CoinBs58 encodes
 #[derive(Clone, Copy)]
 pub enum UtxoField {
-    ENTROPY = 0, // merkle root for proving membership of input utxo
-    OWNER = 1, // merkle root for proving membership of input utxo
-    ASSETID = 2, // nullifier to the input utxo
-    AMOUNT = 3, // commitment of the output utxo
-    RHO = 4, // commitment of the output utxo
+    ENTROPY = 0, // randomness hiding the coin's commitment
+    OWNER = 1, // public key of the coin's owner, see utils::derive_pubkey
+    ASSETID = 2, // the asset the coin holds, see AssetId
+    AMOUNT = 3, // how much of the asset, see AMOUNT_BYTES
+    RHO = 4, // nonce the coin's nullifier is derived from
 }
 
+// amounts are u64s, stored little-endian in the leading bytes of the AMOUNT field;
+// all remaining bytes of the field must be zero
+pub const AMOUNT_BYTES: usize = 8;
+
+// asset ids are u64s too, stored the same way in the ASSETID field, so that
+// each asset has a single encoding
+pub const ASSET_ID_BYTES: usize = 8;
+
+// bytes held by each field of a record, i.e. what fits in one element of the
+// scalar field the record is committed over
+pub const FIELD_BYTES: usize = 31;
+
+// version of the packing of public inputs into the bytes the payment contract
+// hands its verifier; it must equal the contract's layout_version before a tx
+// is built for it, and is bumped with it whenever an input is added or reordered
+pub const PUBLIC_INPUT_LAYOUT_VERSION: u32 = 1;
+
+/// the public inputs of a payment on the contract, under
+/// PUBLIC_INPUT_LAYOUT_VERSION: root, new_coin_hash and old_coin_nullifier in
+/// turn, each split into its first and last 16 bytes, each of which is a
+/// little-endian field element; the contract packs them alike
+pub fn contract_payment_inputs(root: &[u8; 32], new_coin_hash: &[u8; 32], old_coin_nullifier: &[u8; 32]) -> Vec<[u8; 32]> {
+    [root, new_coin_hash, old_coin_nullifier]
+        .iter()
+        .flat_map(|value| value.chunks(16))
+        .map(|half| {
+            let mut input = [0u8; 32];
+            input[..16].copy_from_slice(half);
+            input
+        })
+        .collect()
+}
+
+// how many historical roots a payment may be proven against; the contract is
+// initialized with the same window, and services check they agree at startup
+pub const DEFAULT_ROOT_HISTORY_SIZE: u32 = 30;
+
+// a pool id is a u64, stored little-endian; it is appended to rho when
+// deriving the nullifiers of a deployment that sets one
+pub const POOL_ID_BYTES: usize = 8;
+
+// a tree id is a u32, stored little-endian; it is appended last when deriving
+// nullifiers, so that spends from different trees never share a nullifier domain
+pub const TREE_ID_BYTES: usize = 4;
+
+// depth of the merkle tree the circuits prove membership in; the payment
+// contract declares the same depth, since no on-chain root could ever match a
+// proof over a tree of a different depth
+pub const MERKLE_TREE_LEVELS: u32 = 8;
+
+// how many coins a single tree holds; once the active tree is full, the
+// sequencer rolls over to a fresh tree, and the full one becomes spend-only
+pub const TREE_CAPACITY: usize = 1 << MERKLE_TREE_LEVELS;
+
 type Curve = ark_bls12_377::Bls12_377;
 type F = ark_bls12_377::Fr;
 type G1Affine = <Curve as Pairing>::G1Affine;
@@ -40,7 +101,7 @@ type ConstraintPairing = ark_bw6_761::BW6_761;
 type MTEdOnBls12_377 = lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bls12_377::MerkleTreeParams;
 type MTEdOnBw6_761 = lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct VectorCommitmentOpeningProofBs58 {
     pub path_leaf_sibling_hash: String,
     pub path_auth_path: Vec<String>,
@@ -53,33 +114,30 @@ pub struct VectorCommitmentOpeningProofBs58 {
  pub fn jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
     proof: &JubJubVectorCommitmentOpeningProof<MTEdOnBw6_761, G1Affine>
  ) -> VectorCommitmentOpeningProofBs58 {
-    let mut buffer: Vec<u8> = Vec::new();
-    proof.path.leaf_sibling_hash.serialize_compressed(&mut buffer).unwrap();
-    let path_leaf_sibling_hash = bs58::encode(buffer).into_string();
-
-    let mut path_auth_path = Vec::new();
-    for inner_digest in proof.path.auth_path.iter() {
-        let mut buffer: Vec<u8> = Vec::new();
-        inner_digest.serialize_compressed(&mut buffer).unwrap();
-        let inner_digest_serialized = bs58::encode(buffer).into_string();
-
-        path_auth_path.push(inner_digest_serialized);
-    }
-
-    let mut buffer: Vec<u8> = Vec::new();
-    proof.record.serialize_compressed(&mut buffer).unwrap();
-    let record = bs58::encode(buffer).into_string();
-
-    let mut buffer: Vec<u8> = Vec::new();
-    proof.root.serialize_compressed(&mut buffer).unwrap();
-    let root = bs58::encode(buffer).into_string();
+    jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+        &proof.path,
+        &proof.record,
+        &encode_compressed_as_bs58_str(&proof.root)
+    )
+}
 
+// encodes an opening proof from borrowed parts, with the root already encoded;
+// lets the sequencer serve proofs without cloning the record or re-encoding the root
+#[allow(non_snake_case)]
+pub fn jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+    path: &JubJubVectorCommitmentPath<MTEdOnBw6_761>,
+    record: &G1Affine,
+    root_bs58: &str
+) -> VectorCommitmentOpeningProofBs58 {
     VectorCommitmentOpeningProofBs58 {
-        path_leaf_sibling_hash,
-        path_auth_path,
-        path_leaf_index: proof.path.leaf_index,
-        record,
-        root
+        path_leaf_sibling_hash: encode_compressed_as_bs58_str(&path.leaf_sibling_hash),
+        path_auth_path: path.auth_path
+            .iter()
+            .map(|inner_digest| encode_compressed_as_bs58_str(inner_digest))
+            .collect(),
+        path_leaf_index: path.leaf_index,
+        record: encode_compressed_as_bs58_str(record),
+        root: root_bs58.to_string(),
     }
 }
 
@@ -257,6 +315,53 @@ pub enum PaymentGrothPublicInput {
     NULLIFIER = 2, // nullifier to the input utxo
     COMMITMENT_X = 3, // commitment of the output utxo
     COMMITMENT_Y = 4, // commitment of the output utxo
+    ASSET_TAG = 5, // blinded tag of the asset being transferred
+    TREE_ID = 6, // the tree whose root the input utxo is proven against
+}
+
+pub const NUM_PAYMENT_INPUTS: usize = PaymentGrothPublicInput::TREE_ID as usize + 1;
+
+/// StatementParams are what a deployment fixes of its payment statements: the
+/// pool their nullifiers are bound to, if any, and whether the payment
+/// statement ends with the spent leaf's index. Both are constants of the
+/// circuits, so the setup, the provers and the verifiers must share them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatementParams {
+    pub pool_id: Option<[u8; POOL_ID_BYTES]>,
+    pub expose_leaf_index: bool,
+}
+
+impl StatementParams {
+    /// the pool id, as transfers name pools
+    pub fn pool(&self) -> Option<u64> {
+        self.pool_id.map(u64::from_le_bytes)
+    }
+
+    /// the number of public inputs of the payment statement
+    pub fn num_payment_inputs(&self) -> usize {
+        NUM_PAYMENT_INPUTS + usize::from(self.expose_leaf_index)
+    }
+}
+
+// a swap half's statement is a payment's, followed by the swap id and its role
+#[allow(non_camel_case_types)]
+pub enum SwapGrothPublicInput {
+    SWAP_ID = 7, // shared by both halves of a swap, and the entropy of each output coin
+    SWAP_ROLE = 8, // which half this is, see SwapRole
+}
+
+/// SwapRole is which half of a swap a proof is of; the swap id commits to the
+/// amount each role pays, and a half proves it pays the amount of its role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapRole {
+    Proposal = 0,
+    Acceptance = 1,
+}
+
+// a transfer's statement is a payment's, followed by the pool its output coin goes to
+#[allow(non_camel_case_types)]
+pub enum TransferGrothPublicInput {
+    DEST_POOL_ID = 7, // the pool that inserts the output coin; the input is spent in this one
 }
 
 #[allow(non_camel_case_types)]
@@ -278,6 +383,8 @@ pub enum MerkleUpdateGrothPublicInput {
     NEW_ROOT_Y = 6, // merkle tree root after the update
 }
 
+pub const NUM_MERKLE_UPDATE_INPUTS: usize = MerkleUpdateGrothPublicInput::NEW_ROOT_Y as usize + 1;
+
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldElementBs58 {
@@ -289,24 +396,570 @@ pub struct CoinBs58 {
 	pub fields: [String; NUM_FIELDS],
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// CircuitKind tags every proof with the circuit it was generated for, so that
+/// handlers never verify a proof against another circuit's verifying key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitKind {
+    Onramp,
+    // a single deposit minting several fixed-denomination notes
+    OnrampMulti,
+    Payment,
+    MerkleUpdate,
+    PaymentUpdate,
+    // one half of an atomic swap; see SwapHalfBs58
+    Swap,
+    // a payment into another pool; see TransferExportBs58
+    Transfer,
+    // a holder's balance, shown to an auditor rather than submitted to the pool
+    Balance,
+    // a circuit registered by a third-party app; see AppTxBs58
+    App,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct GrothProofBs58 {
+    pub circuit: CircuitKind,
     pub proof: String,
     pub public_inputs: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// AppRegistrationBs58 registers an app's circuit: its (bs58 compressed)
+/// verifying key, the names of its public inputs, and which of them hold
+/// the coordinates of the commitment each tx inserts into the tree
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppRegistrationBs58 {
+    pub name: String,
+    pub vk: String,
+    pub public_inputs: Vec<String>,
+    pub commitment_x_index: usize,
+    pub commitment_y_index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppTxBs58 {
+    pub app: String,
+    pub proof: GrothProofBs58,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppProofBs58 {
+    pub app_tx: AppTxBs58,
+    pub merkle_update_proof: GrothProofBs58
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct OnRampProofBs58 {
     pub on_ramp_proof: GrothProofBs58,
     pub merkle_update_proof: GrothProofBs58
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// OnRampMultiProofBs58 carries a deposit minting several notes, along with
+/// one merkle update proof per minted note, chained in insertion order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OnRampMultiProofBs58 {
+    pub on_ramp_proof: GrothProofBs58,
+    pub merkle_update_proofs: Vec<GrothProofBs58>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecordBs58 {
+    pub fields: Vec<String>,
+    pub blind: String,
+}
+
+/// PaymentWitnessBs58 carries everything needed to prove a payment, so that
+/// thin clients can delegate proving; it includes the spender's secret key.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PaymentWitnessBs58 {
+    pub input_utxo: RecordBs58,
+    pub output_utxo: RecordBs58,
+    pub unspent_coin_existence_proof: VectorCommitmentOpeningProofBs58,
+    pub sk: String,
+    // the tree the input utxo lives in, which the opening proof is against
+    #[serde(default)]
+    pub tree_id: u32,
+}
+
+// maximum size of the (encrypted) memo attached to a payment
+pub const MAX_MEMO_BYTES: usize = 512;
+
+/// PaymentTxBs58 is what the client submits to the sequencer; the memo is
+/// an opaque payload, encrypted by the client to the recipient, which is
+/// stored off-chain and never enters any public input.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PaymentTxBs58 {
+    pub payment_proof: GrothProofBs58,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct PaymentProofBs58 {
     pub payment_proof: GrothProofBs58,
     pub merkle_update_proof: GrothProofBs58
 }
 
+/// SwapHalfBs58 is one party's half of an atomic swap: a swap proof paying
+/// the counterparty, and the asset tag (under the blind both parties agreed
+/// on) of the asset the party wants in return
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwapHalfBs58 {
+    pub payment_proof: GrothProofBs58,
+    pub want_asset_tag: String,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// SwapSettlementBs58 carries both halves of a settled swap, proposal first,
+/// each with the merkle update inserting its output coin
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwapSettlementBs58 {
+    pub halves: Vec<PaymentProofBs58>,
+}
+
+/// TransferExportBs58 is a transfer the source pool's sequencer accepted,
+/// having spent its nullifier there, signed (in bs58) by that sequencer so
+/// that the destination pool can insert its output coin without verifying
+/// a proof made under another pool's keys; anyone may deliver it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransferExportBs58 {
+    pub source_pool_id: u64,
+    pub transfer_proof: GrothProofBs58,
+    pub signature: String,
+}
+
+/// TransferResponse is the source sequencer's reply to a transfer; once it is
+/// accepted, it carries the export to deliver to the destination pool
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransferResponse {
+    pub accepted: bool,
+    pub error: Option<String>,
+    pub export: Option<TransferExportBs58>,
+}
+
+impl TransferResponse {
+    pub fn accepted(export: TransferExportBs58) -> Self {
+        TransferResponse { accepted: true, error: None, export: Some(export) }
+    }
+
+    pub fn rejected(error: &str) -> Self {
+        TransferResponse { accepted: false, error: Some(error.to_string()), export: None }
+    }
+}
+
+/// TransferInProofBs58 is an imported transfer as the destination pool's
+/// verifiers see it, with the merkle update inserting its output coin
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransferInProofBs58 {
+    pub export: TransferExportBs58,
+    pub merkle_update_proof: GrothProofBs58,
+}
+
+/// TxResponse is the sequencer's reply to a submitted transaction
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TxResponse {
+    pub accepted: bool,
+    // reason for rejecting the tx, if it was rejected
+    pub error: Option<String>,
+    // time the sequencer spent verifying the proof, in milliseconds
+    pub verify_time_ms: Option<u64>,
+    // the leaf the tx inserted its coin into, when the sequencer reports it
+    #[serde(default)]
+    pub leaf_index: Option<usize>,
+}
+
+impl TxResponse {
+    pub fn accepted(verify_time_ms: u64) -> Self {
+        TxResponse { accepted: true, error: None, verify_time_ms: Some(verify_time_ms), leaf_index: None }
+    }
+
+    pub fn rejected(error: &str) -> Self {
+        TxResponse { accepted: false, error: Some(error.to_string()), verify_time_ms: None, leaf_index: None }
+    }
+
+    // the outcome recorded for a retried tx; nothing was verified this time
+    pub fn recorded(leaf_index: usize) -> Self {
+        TxResponse { accepted: true, error: None, verify_time_ms: None, leaf_index: Some(leaf_index) }
+    }
+
+    pub fn with_leaf_index(self, leaf_index: usize) -> Self {
+        TxResponse { leaf_index: Some(leaf_index), ..self }
+    }
+}
+
+/// Diagnostic is the outcome of one of the checks the sequencer runs on a tx;
+/// it only ever refers to the tx's public data
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Diagnostic {
+    pub check: String,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+/// SimulationResponse tells whether a tx would be accepted, without applying
+/// it; when a diagnosis is asked for, it lists the checks that were run, in
+/// order, up to and including the first that failed
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimulationResponse {
+    pub accepted: bool,
+    pub error: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// BatchItemStatus is the verdict on one payment of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Verified,
+    Rejected,
+    // not checked, as a payment before it was rejected
+    Skipped,
+}
+
+/// BatchItemResult reports on one payment of a batch, by its index in the
+/// batch; a streamed batch sends one per payment, in order, as each is checked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub status: BatchItemStatus,
+    pub error: Option<String>,
+}
+
+/// BatchResponse is the sequencer's reply to an unstreamed batch: the result
+/// of every payment, and whether the batch as a whole was applied
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchResponse {
+    pub items: Vec<BatchItemResult>,
+    pub outcome: TxResponse,
+}
+
+// the error a payment is rejected with when it proves membership against a root
+// that has left the root history window; the spent coin is still unspent, so
+// clients may re-prove against a fresh opening and resubmit
+pub const UNKNOWN_ROOT_ERROR: &str = "unknown merkle root";
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NullifierStatusRequestBs58 {
+    pub nullifiers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NullifierStatusBs58 {
+    // spent[i] is true iff nullifiers[i] of the request has been spent
+    pub spent: Vec<bool>,
+    // for spent nullifiers, the leaf inserted by the spending tx
+    pub leaf_indices: Vec<Option<usize>>,
+}
+
+/// NullifierAuditEntryBs58 records a spent nullifier, the root its payment
+/// was proven against, and when (unix time, in seconds) it was accepted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NullifierAuditEntryBs58 {
+    pub nullifier: String,
+    pub root_x: String,
+    pub root_y: String,
+    pub timestamp: u64,
+}
+
+/// NullifierAuditPageBs58 is one page of the audit log; next is where the
+/// following page starts, if there is one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NullifierAuditPageBs58 {
+    pub entries: Vec<NullifierAuditEntryBs58>,
+    pub next: Option<usize>,
+}
+
+/// ReceiptBs58 shows that a coin was inserted into the tree: an opening of
+/// its leaf, against a root the verifier accepted
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReceiptBs58 {
+    pub opening_proof: VectorCommitmentOpeningProofBs58,
+    pub root_x: String,
+    pub root_y: String,
+}
+
+/// OrderingProofBs58 shows which of two coins was inserted first: receipts
+/// for both against the same root, whose paths fix the leaf indices. A
+/// sequencer holding a signing key also signs the statement, in bs58
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderingProofBs58 {
+    pub first: ReceiptBs58,
+    pub second: ReceiptBs58,
+    pub first_index: usize,
+    pub second_index: usize,
+    pub signature: Option<String>,
+}
+
+/// ArchivedRootBs58 is a root the verifier accepted, numbered by the block
+/// (i.e. the merkle update) that produced it, from 0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ArchivedRootBs58 {
+    pub block: usize,
+    pub root_x: String,
+    pub root_y: String,
+}
+
+/// RootRangeBs58 is a range of archived roots; next is the block the
+/// following range starts at, if there is one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RootRangeBs58 {
+    pub roots: Vec<ArchivedRootBs58>,
+    pub next: Option<usize>,
+}
+
+/// CoinInsertedEventBs58 records a commitment inserted into the tree
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CoinInsertedEventBs58 {
+    pub leaf_index: usize,
+    pub commitment: String,
+    // unix time, in seconds, at which the sequencer inserted the coin; unset
+    // for coins replayed from a tx log written before times were logged
+    #[serde(default)]
+    pub inserted_at: Option<u64>,
+}
+
+/// ReplicationEventBs58 is one leaf of the leader's tree, as pulled by a
+/// read-only replica, along with what the payment inserting it spent
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReplicationEventBs58 {
+    pub leaf_index: usize,
+    pub commitment: String,
+    // set iff the leaf was inserted by a payment
+    pub nullifier: Option<String>,
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub inserted_at: Option<u64>,
+}
+
+/// ReplicationBatchBs58 holds the leader's leaves of one tree from some index
+/// onwards, and the root of that tree once all of them are inserted
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReplicationBatchBs58 {
+    #[serde(default)]
+    pub tree_id: u32,
+    pub events: Vec<ReplicationEventBs58>,
+    pub root: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SequencerStatus {
+    pub num_coins: usize,
+    // version of the compliance policy in force; 0 if none was loaded
+    pub policy_version: u64,
+    // proof verifications answered from the cache, and those that ran the pairing check
+    pub verify_cache_hits: u64,
+    pub verify_cache_misses: u64,
+}
+
+/// VerifierHealth is the sequencer's record of one of the verifiers it
+/// forwards txs to
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VerifierHealth {
+    pub url: String,
+    // false since the verifier last failed to answer, until it answers again
+    pub healthy: bool,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub unreachable: u64,
+    // forwarded txs on which its verdict matched the quorum's outcome, and those it did not
+    pub agreed: u64,
+    pub dissented: u64,
+    pub last_error: Option<String>,
+}
+
+/// TreeSnapshot is the sync primitive for wallets: the number of coins in the
+/// active tree, and the root of exactly those coins, read together
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TreeSnapshot {
+    // the active tree; every tree before it is full, and spend-only
+    pub tree_id: u32,
+    pub num_coins: usize,
+    // the root, as it appears in the public inputs of a payment proof
+    pub root_x: String,
+    pub root_y: String,
+    pub levels: usize,
+    // coins the active tree has room for, before the sequencer rolls over
+    pub free_leaves: usize,
+}
+
+/// LoggedTxBs58 is a tx (or app registration) the sequencer accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "tx", rename_all = "snake_case")]
+pub enum LoggedTxBs58 {
+    Onramp(GrothProofBs58),
+    OnrampMulti(GrothProofBs58),
+    Payment(PaymentTxBs58),
+    Swap { proposal: SwapHalfBs58, acceptance: SwapHalfBs58 },
+    AppRegistration(AppRegistrationBs58),
+    App(AppTxBs58),
+    // a transfer out of this pool, which spends a nullifier and inserts nothing
+    TransferOut(GrothProofBs58),
+    // a transfer into this pool from another, which inserts its output coin
+    TransferIn(TransferExportBs58),
+}
+
+/// TxLogEntryBs58 is one line of the sequencer's tx log: an accepted tx,
+/// and the (bs58 compressed) merkle root once it was applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TxLogEntryBs58 {
+    pub tx: LoggedTxBs58,
+    pub root: String,
+    // the idempotency key the tx was submitted under, if any; it is logged in
+    // the same line as the tx, so that both are recovered or neither is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<LoggedTicket>,
+    // unix time, in seconds, at which the tx was accepted; replay restores it
+    // as the insertion time of the tx's coins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggedTicket {
+    pub idempotency_key: String,
+    // unix time, in seconds, at which the tx was accepted
+    pub accepted_at: u64,
+    // the tx's moves up to being staged, which is when its line is written;
+    // logs from before they were kept have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<TxHistory>,
+}
+
+/// TxState is where a tx stands in the sequencer. It is received, validated
+/// against the state, and then staged into it under a checkpoint; a swap
+/// proposal is escrowed in between, until the half that settles it arrives.
+/// A staged tx is forwarded to the verifiers, and settled once they accept
+/// it. It may be rejected at any point before it settles, and neither a
+/// settled nor a rejected tx moves again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TxState {
+    Received,
+    Validated,
+    Escrowed,
+    Staged,
+    ForwardedToVerifiers,
+    // the leaf the tx inserted its (first) coin into, if it inserted any
+    Settled { leaf_index: Option<usize> },
+    Rejected { error: String },
+}
+
+impl TxState {
+
+    pub fn can_advance_to(&self, next: &TxState) -> bool {
+        use TxState::*;
+
+        match (self, next) {
+            (Received, Validated) => true,
+            (Validated, Escrowed) | (Validated, Staged) => true,
+            (Escrowed, Staged) => true,
+            (Staged, ForwardedToVerifiers) => true,
+            (ForwardedToVerifiers, Settled { .. }) => true,
+            (state, Rejected { .. }) => !state.is_final(),
+            _ => false,
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, TxState::Settled { .. } | TxState::Rejected { .. })
+    }
+}
+
+/// TxTransition is a tx's move into state, at unix time at, in seconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TxTransition {
+    #[serde(flatten)]
+    pub state: TxState,
+    pub at: u64,
+}
+
+/// TxHistory is every move of one tx, starting from Received. It only grows
+/// by moves TxState allows, and one read back from the log or the wire is
+/// checked the same way, so e.g. no history settles a rejected tx
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TxTransition>", into = "Vec<TxTransition>")]
+pub struct TxHistory {
+    transitions: Vec<TxTransition>,
+}
+
+impl TxHistory {
+
+    pub fn received(at: u64) -> Self {
+        TxHistory { transitions: vec![TxTransition { state: TxState::Received, at }] }
+    }
+
+    pub fn state(&self) -> &TxState {
+        &self.transitions.last().unwrap().state
+    }
+
+    pub fn transitions(&self) -> &[TxTransition] {
+        &self.transitions
+    }
+
+    // moves the tx into next, unless its current state does not lead there
+    pub fn advance(&mut self, next: TxState, at: u64) -> Result<(), String> {
+        if !self.state().can_advance_to(&next) {
+            return Err(format!("a tx cannot move from {:?} to {:?}", self.state(), next));
+        }
+
+        self.transitions.push(TxTransition { state: next, at });
+        Ok(())
+    }
+}
+
+impl TryFrom<Vec<TxTransition>> for TxHistory {
+    type Error = String;
+
+    fn try_from(transitions: Vec<TxTransition>) -> Result<Self, String> {
+        let mut transitions = transitions.into_iter();
+        let mut history = match transitions.next() {
+            Some(TxTransition { state: TxState::Received, at }) => TxHistory::received(at),
+            _ => return Err("a tx history starts with the tx being received".to_string()),
+        };
+        for transition in transitions {
+            history.advance(transition.state, transition.at)?;
+        }
+
+        Ok(history)
+    }
+}
+
+impl From<TxHistory> for Vec<TxTransition> {
+    fn from(history: TxHistory) -> Self {
+        history.transitions
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FillRatio {
+    // number of leaves holding real (non-dummy) coins
+    pub num_real_leaves: usize,
+    // total number of leaves in the tree
+    pub capacity: usize,
+    pub fill_ratio: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlonkProofBs58 {
     // commitments to input coins data structures
@@ -334,6 +987,193 @@ pub struct PlonkProofBs58 {
 }
 
 
+// the encoding used to name a coin commitment, e.g. in receipts and policies
+// the compressed encoding of a commitment, e.g. to key a map with
+pub fn commitment_to_bytes(commitment: &G1Affine) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::new();
+    commitment.serialize_compressed(&mut buffer).unwrap();
+    buffer
+}
+
+pub fn commitment_to_bs58(commitment: &G1Affine) -> String {
+    encode_compressed_as_bs58_str(commitment)
+}
+
+pub fn commitment_from_bs58(commitment: &String) -> Result<G1Affine, String> {
+    let buf = bs58::decode(commitment).into_vec().map_err(|e| e.to_string())?;
+    G1Affine::deserialize_compressed(buf.as_slice()).map_err(|e| e.to_string())
+}
+
+// rebuilds a G1 point from coordinates carried as public inputs; they cross the
+// trust boundary, so the point must lie on the curve and in the prime-order subgroup
+pub fn affine_from_xy(x: ConstraintF, y: ConstraintF) -> Result<ark_bls12_377::G1Affine, String> {
+    let point = ark_bls12_377::G1Affine::new_unchecked(x, y);
+
+    if !point.is_on_curve() {
+        return Err("point is not on the curve".to_string());
+    }
+    if !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err("point is not in the prime-order subgroup".to_string());
+    }
+
+    Ok(point)
+}
+
+thread_local! {
+    // reused by every encoding on a thread, to spare an allocation per field
+    static SERIALIZATION_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+pub fn encode_compressed_as_bs58_str<T: CanonicalSerialize>(value: &T) -> String {
+    SERIALIZATION_BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.clear();
+        value.serialize_compressed(&mut *buffer).unwrap();
+        bs58::encode(&*buffer).into_string()
+    })
+}
+
+pub fn memo_to_bs58(memo: &[u8]) -> Result<String, String> {
+    if memo.len() > MAX_MEMO_BYTES {
+        return Err(format!("memo of {} bytes exceeds the limit of {}", memo.len(), MAX_MEMO_BYTES));
+    }
+
+    Ok(bs58::encode(memo).into_string())
+}
+
+pub fn memo_from_bs58(memo: &String) -> Result<Vec<u8>, String> {
+    let buf: Vec<u8> = bs58::decode(memo)
+        .into_vec()
+        .map_err(|e| format!("memo is not valid base58: {}", e))?;
+
+    if buf.len() > MAX_MEMO_BYTES {
+        return Err(format!("memo of {} bytes exceeds the limit of {}", buf.len(), MAX_MEMO_BYTES));
+    }
+
+    Ok(buf)
+}
+
+pub fn record_to_bs58(record: &JZRecord<5>) -> RecordBs58 {
+    RecordBs58 {
+        fields: record.fields
+            .iter()
+            .map(|f| bs58::encode(f).into_string())
+            .collect(),
+        blind: bs58::encode(&record.blind).into_string(),
+    }
+}
+
+pub fn record_from_bs58(
+    crs: &JZKZGCommitmentParams<5>,
+    record: &RecordBs58
+) -> Result<JZRecord<5>, String> {
+    let fields: [Vec<u8>; 5] = record.fields
+        .iter()
+        .map(|s| bs58::decode(s).into_vec().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?
+        .try_into()
+        .map_err(|_| "a record must have exactly 5 fields".to_string())?;
+    let blind: Vec<u8> = bs58::decode(&record.blind).into_vec().map_err(|e| e.to_string())?;
+
+    validate_amount(&fields[UtxoField::AMOUNT as usize])?;
+    AssetId::from_field_bytes(&fields[UtxoField::ASSETID as usize])?;
+
+    new_record(crs, &fields, &blind)
+}
+
+// builds a record over a CRS for N fields. The commitment only binds the
+// fields the CRS has a basis element for, and each as an element of the
+// scalar field, so more fields than that, or a field past FIELD_BYTES, are
+// refused rather than committed to in part; fewer fields are zero-padded
+pub fn new_record<const N: usize>(
+    crs: &JZKZGCommitmentParams<N>,
+    fields: &[Vec<u8>],
+    blind: &[u8]
+) -> Result<JZRecord<N>, String> {
+    if fields.len() > N {
+        return Err(format!("a record of {} fields exceeds the CRS, which supports {}", fields.len(), N));
+    }
+    if let Some(i) = fields.iter().position(|f| f.len() > FIELD_BYTES) {
+        return Err(format!("field {} holds {} bytes, more than the {} a field element does",
+            i, fields[i].len(), FIELD_BYTES));
+    }
+
+    let mut padded = fields.to_vec();
+    padded.resize(N, vec![0u8; FIELD_BYTES]);
+    let fields: [Vec<u8>; N] = padded.try_into().unwrap();
+
+    Ok(JZRecord::<N>::new(crs, &fields, &blind.to_vec()))
+}
+
+// checks that an AMOUNT field encodes a u64, i.e. its high bytes are all zero
+pub fn validate_amount(amount: &[u8]) -> Result<(), String> {
+    validate_u64_field(amount, AMOUNT_BYTES, "amount")
+}
+
+// checks that a field holds a little-endian integer of width bytes, and no
+// more than a field does
+fn validate_u64_field(field: &[u8], width: usize, name: &str) -> Result<(), String> {
+    if field.len() > FIELD_BYTES {
+        return Err(format!("{} holds {} bytes, more than the {} a field element does", name, field.len(), FIELD_BYTES));
+    }
+    if field.iter().skip(width).any(|&b| b != 0) {
+        return Err(format!("{} does not fit in {} bytes", name, width));
+    }
+
+    Ok(())
+}
+
+/// AssetId names the asset a coin holds. It is a u64, encoded little-endian in
+/// the low ASSET_ID_BYTES of the ASSETID field with the rest zero; a field
+/// with any other byte set names no asset, so no asset has two encodings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AssetId(pub u64);
+
+impl AssetId {
+
+    pub fn from_field_bytes(field: &[u8]) -> Result<AssetId, String> {
+        validate_u64_field(field, ASSET_ID_BYTES, "asset id")?;
+
+        let mut bytes = [0u8; ASSET_ID_BYTES];
+        for (i, b) in field.iter().take(ASSET_ID_BYTES).enumerate() {
+            bytes[i] = *b;
+        }
+        Ok(AssetId(u64::from_le_bytes(bytes)))
+    }
+
+    // the ASSETID field of the asset's coins
+    pub fn to_field_bytes(&self) -> [u8; FIELD_BYTES] {
+        let mut field = [0u8; FIELD_BYTES];
+        field[..ASSET_ID_BYTES].copy_from_slice(&self.0.to_le_bytes());
+        field
+    }
+
+    // the asset id as a public input, e.g. the onramp's and balance's ASSET_ID
+    pub fn to_field(&self) -> ConstraintF {
+        ConstraintF::from(self.0)
+    }
+
+    pub fn of(record: &JZRecord<5>) -> Result<AssetId, String> {
+        AssetId::from_field_bytes(&record.fields[UtxoField::ASSETID as usize])
+    }
+}
+
+impl std::fmt::Display for AssetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn amount_u64(record: &JZRecord<5>) -> u64 {
+    let amount = &record.fields[UtxoField::AMOUNT as usize];
+    let mut bytes = [0u8; AMOUNT_BYTES];
+    for (i, b) in amount.iter().take(AMOUNT_BYTES).enumerate() {
+        bytes[i] = *b;
+    }
+
+    u64::from_le_bytes(bytes)
+}
+
 pub fn field_element_to_bs58(field: &F) -> FieldElementBs58 {
     FieldElementBs58 { field: encode_f_as_bs58_str(field) }
 }
@@ -503,6 +1343,7 @@ pub fn plonk_proof_to_bs58(proof: &PlonkProof) -> PlonkProofBs58 {
 }
 
 pub fn groth_proof_to_bs58(
+    circuit: CircuitKind,
     proof: &Proof<ConstraintPairing>,
     public_inputs: &Vec<ConstraintF>
 ) -> GrothProofBs58 {
@@ -516,11 +1357,35 @@ pub fn groth_proof_to_bs58(
     let proof = bs58::encode(buffer).into_string();
 
     GrothProofBs58 {
+        circuit,
         proof,
         public_inputs,
     }
 }
 
+// rejects a proof generated for a different circuit than the one expected,
+// or one carrying a different number of public inputs than that circuit has
+// under the deployment's statement params
+pub fn check_circuit_kind(
+    proof: &GrothProofBs58,
+    expected: CircuitKind,
+    statement: &StatementParams
+) -> Result<(), String> {
+    if proof.circuit != expected {
+        return Err(format!("expected a {:?} proof, got a {:?} proof", expected, proof.circuit));
+    }
+
+    if let Some(descriptor) = crate::registry::descriptor(expected) {
+        let num_public_inputs = descriptor.num_public_inputs(statement);
+        if proof.public_inputs.len() != num_public_inputs {
+            return Err(format!("expected {} public inputs for a {:?} proof, got {}",
+                num_public_inputs, expected, proof.public_inputs.len()));
+        }
+    }
+
+    Ok(())
+}
+
 pub fn groth_proof_from_bs58(proof: &GrothProofBs58) -> 
     (Proof<ConstraintPairing>, Vec<ConstraintF>) {
     let public_inputs = proof.public_inputs
@@ -534,6 +1399,103 @@ pub fn groth_proof_from_bs58(proof: &GrothProofBs58) ->
     (proof, public_inputs)
 }
 
+// a proof is canonical if it and each of its public inputs decode, and encode
+// back to exactly the strings given; groth_proof_from_bs58 assumes as much
+pub fn check_canonical_groth_proof(proof: &GrothProofBs58) -> Result<(), String> {
+    for (i, input) in proof.public_inputs.iter().enumerate() {
+        let buf: Vec<u8> = bs58::decode(input)
+            .into_vec()
+            .map_err(|e| format!("public input {} is not valid base58: {}", i, e))?;
+        let value = ConstraintF::deserialize_compressed(buf.as_slice())
+            .map_err(|e| format!("public input {} is not a field element: {}", i, e))?;
+        if encode_constraintf_as_bs58_str(&value) != *input {
+            return Err(format!("public input {} is not canonically encoded", i));
+        }
+    }
+
+    let buf: Vec<u8> = bs58::decode(&proof.proof)
+        .into_vec()
+        .map_err(|e| format!("proof is not valid base58: {}", e))?;
+    let decoded = Proof::<BW6_761>::deserialize_compressed(buf.as_slice())
+        .map_err(|e| format!("proof does not decode: {}", e))?;
+    let mut reencoded: Vec<u8> = Vec::new();
+    decoded.serialize_compressed(&mut reencoded).unwrap();
+    if reencoded != buf {
+        return Err("proof is not canonically encoded".to_string());
+    }
+
+    Ok(())
+}
+
+// the tree a payment (or swap half) proof spends from, per its TREE_ID input
+pub fn tree_id_of(proof: &GrothProofBs58) -> Result<u32, String> {
+    let tree_id = small_public_input::<TREE_ID_BYTES>(
+        proof, PaymentGrothPublicInput::TREE_ID as usize, "TREE_ID", "u32"
+    )?;
+
+    Ok(u32::from_le_bytes(tree_id))
+}
+
+// the pool a transfer proof's output coin goes to, per its DEST_POOL_ID input
+pub fn dest_pool_id_of(proof: &GrothProofBs58) -> Result<u64, String> {
+    let pool_id = small_public_input::<POOL_ID_BYTES>(
+        proof, TransferGrothPublicInput::DEST_POOL_ID as usize, "DEST_POOL_ID", "u64"
+    )?;
+
+    Ok(u64::from_le_bytes(pool_id))
+}
+
+// the asset an onramp proof, single or multi, deposits, per its ASSET_ID
+// input; an input with bytes set past ASSET_ID_BYTES names no asset
+pub fn asset_id_of(proof: &GrothProofBs58) -> Result<AssetId, String> {
+    let asset_id = small_public_input::<ASSET_ID_BYTES>(
+        proof, OnrampGrothPublicInput::ASSET_ID as usize, "ASSET_ID", "u64"
+    )?;
+
+    Ok(AssetId(u64::from_le_bytes(asset_id)))
+}
+
+// the half of a swap a swap proof is of, per its SWAP_ROLE input
+pub fn swap_role_of(proof: &GrothProofBs58) -> Result<SwapRole, String> {
+    let role = small_public_input::<1>(
+        proof, SwapGrothPublicInput::SWAP_ROLE as usize, "SWAP_ROLE", "role"
+    )?;
+
+    match role[0] {
+        0 => Ok(SwapRole::Proposal),
+        1 => Ok(SwapRole::Acceptance),
+        _ => Err("SWAP_ROLE does not fit in a role".to_string()),
+    }
+}
+
+// the low N bytes of a public input holding an integer; field elements
+// serialize little-endian, so the input is all zeros past them
+fn small_public_input<const N: usize>(
+    proof: &GrothProofBs58,
+    index: usize,
+    name: &str,
+    type_name: &str
+) -> Result<[u8; N], String> {
+    let input = proof.public_inputs
+        .get(index)
+        .ok_or(format!("missing {} public input", name))?;
+    let buf: Vec<u8> = bs58::decode(input)
+        .into_vec()
+        .map_err(|e| format!("{} is not valid base58: {}", name, e))?;
+    let value = ConstraintF::deserialize_compressed(buf.as_slice())
+        .map_err(|e| format!("{} is not a field element: {}", name, e))?;
+
+    let mut bytes: Vec<u8> = Vec::new();
+    value.serialize_compressed(&mut bytes).unwrap();
+    if bytes.iter().skip(N).any(|b| *b != 0) {
+        return Err(format!("{} does not fit in a {}", name, type_name));
+    }
+
+    let mut small = [0u8; N];
+    small.copy_from_slice(&bytes[..N]);
+    Ok(small)
+}
+
 fn decode_bs58_str_as_constraintf(msg: &String) -> ConstraintF {
     let buf: Vec<u8> = bs58::decode(msg).into_vec().unwrap();
     ConstraintF::deserialize_compressed(buf.as_slice()).unwrap()
@@ -549,7 +1511,31 @@ fn decode_bs58_str_as_g1(msg: &String) -> G1Affine {
     G1Affine::deserialize_compressed(&mut Cursor::new(decoded)).unwrap()
 }
 
-fn encode_constraintf_as_bs58_str(value: &ConstraintF) -> String {
+/// normalize_field_bs58 maps every base58 string that decodes to a field
+/// element, e.g. one carrying trailing bytes the deserializer ignores, to the
+/// single encoding this crate produces of that element; roots and nullifiers
+/// are compared as strings, so they must pass through it first. A string that
+/// is no field element is returned as is, and never matches one that is.
+pub fn normalize_field_bs58(value: &str) -> String {
+    let element = bs58::decode(value)
+        .into_vec()
+        .ok()
+        .and_then(|buf| ConstraintF::deserialize_compressed(buf.as_slice()).ok());
+
+    match element {
+        Some(element) => encode_constraintf_as_bs58_str(&element),
+        None => value.to_string(),
+    }
+}
+
+// normalizes the public inputs of a proof as it comes in over http
+pub fn normalize_public_inputs(proof: &mut GrothProofBs58) {
+    for input in proof.public_inputs.iter_mut() {
+        *input = normalize_field_bs58(input);
+    }
+}
+
+pub fn encode_constraintf_as_bs58_str(value: &ConstraintF) -> String {
     let mut buffer: Vec<u8> = Vec::new();
     value.serialize_compressed(&mut buffer).unwrap();
     bs58::encode(buffer).into_string()
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::ConstraintSystem;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use lib_mpc_zexe::vector_commitment;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    *, constraints::*,
+    config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+    config::ed_on_bw6_761::MerkleTreeParamsVar as MTParamsVar,
+};
+
+use super::protocol;
+use super::utils;
+
+type ConstraintF = ark_bw6_761::Fr;
+
+// domain separator prefixed to every signed ordering statement
+const ORDERING_DOMAIN: &[u8] = b"sanctum/ordering/v1";
+
+/// RootSource answers whether a merkle root was ever posted, e.g. by
+/// consulting the verifier's root history or the contract's `is_root_known`
+pub trait RootSource {
+    fn is_root_known(&self, root: &(String, String)) -> bool;
+}
+
+impl RootSource for Vec<(String, String)> {
+    fn is_root_known(&self, root: &(String, String)) -> bool {
+        self.contains(root)
+    }
+}
+
+/// builds the receipt for the leaf opened by the given proof
+pub fn receipt_from_opening_proof(
+    proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>
+) -> protocol::ReceiptBs58 {
+    protocol::ReceiptBs58 {
+        opening_proof: protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(proof),
+        root_x: protocol::encode_constraintf_as_bs58_str(&proof.root.x),
+        root_y: protocol::encode_constraintf_as_bs58_str(&proof.root.y),
+    }
+}
+
+/// verify checks that the receipt's opening proof is valid for its root,
+/// and that the root is known to the given source
+pub fn verify(receipt: &protocol::ReceiptBs58, roots: &impl RootSource) -> Result<(), String> {
+    let proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_from_bs58(
+        &receipt.opening_proof
+    );
+
+    // the advertised root must be the one the path opens to
+    if receipt.root_x != protocol::encode_constraintf_as_bs58_str(&proof.root.x) ||
+        receipt.root_y != protocol::encode_constraintf_as_bs58_str(&proof.root.y) {
+        return Err("receipt root does not match its opening proof".to_string());
+    }
+
+    if !is_valid_opening_proof(&proof)? {
+        return Err("receipt opening proof is invalid".to_string());
+    }
+
+    if !roots.is_root_known(&(receipt.root_x.clone(), receipt.root_y.clone())) {
+        return Err("receipt root was never posted".to_string());
+    }
+
+    Ok(())
+}
+
+/// is_valid_opening_proof checks that the proof's path opens its record to its
+/// root, natively, using the same gadget the payment circuit uses
+pub fn is_valid_opening_proof(
+    proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>
+) -> Result<bool, String> {
+    let (_, vc_params, _) = utils::trusted_setup();
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    let params_var = JZVectorCommitmentParamsVar::new_constant(cs.clone(), &vc_params)
+        .map_err(|e| e.to_string())?;
+    let proof_var = JZVectorCommitmentOpeningProofVar::<ConstraintF, MTParams, MTParamsVar>
+        ::new_witness(cs.clone(), || Ok(proof))
+        .map_err(|e| e.to_string())?;
+    vector_commitment::bytes::pedersen::constraints::generate_constraints(
+        cs.clone(), &params_var, &proof_var
+    );
+
+    cs.is_satisfied().map_err(|e| e.to_string())
+}
+
+/// builds the ordering proof for the leaves opened by the given proofs,
+/// signing it if a key is given; both must open against the same root
+pub fn ordering_proof(
+    first: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    second: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    signing_key: Option<&SigningKey>
+) -> Result<protocol::OrderingProofBs58, String> {
+    if first.root != second.root {
+        return Err("the openings are against different roots".to_string());
+    }
+
+    let mut proof = protocol::OrderingProofBs58 {
+        first: receipt_from_opening_proof(first),
+        second: receipt_from_opening_proof(second),
+        first_index: first.path.leaf_index,
+        second_index: second.path.leaf_index,
+        signature: None,
+    };
+    proof.signature = signing_key
+        .map(|key| bs58::encode(key.sign(&ordering_message(&proof)).to_bytes()).into_string());
+
+    Ok(proof)
+}
+
+/// verify_ordering checks both receipts of the proof, that they are against
+/// the same root, and that the advertised indices are the ones their paths
+/// open; if a sequencer key is given, the proof must also carry its signature.
+/// Returns how the first coin's leaf index compares to the second's, i.e.
+/// Less iff the first coin was inserted before the second
+pub fn verify_ordering(
+    proof: &protocol::OrderingProofBs58,
+    roots: &impl RootSource,
+    sequencer_key: Option<&VerifyingKey>
+) -> Result<Ordering, String> {
+    verify(&proof.first, roots)?;
+    verify(&proof.second, roots)?;
+
+    if (&proof.first.root_x, &proof.first.root_y) != (&proof.second.root_x, &proof.second.root_y) {
+        return Err("ordering receipts are against different roots".to_string());
+    }
+    if proof.first_index != proof.first.opening_proof.path_leaf_index ||
+        proof.second_index != proof.second.opening_proof.path_leaf_index {
+        return Err("ordering indices do not match the receipts' paths".to_string());
+    }
+
+    if let Some(key) = sequencer_key {
+        let signature: [u8; 64] = bs58::decode(proof.signature.as_ref().ok_or("ordering proof is not signed")?)
+            .into_vec()
+            .map_err(|e| e.to_string())?
+            .try_into()
+            .map_err(|_| "ordering signature must be 64 bytes".to_string())?;
+
+        key.verify(&ordering_message(proof), &Signature::from_bytes(&signature))
+            .map_err(|_| "invalid ordering signature".to_string())?;
+    }
+
+    Ok(proof.first_index.cmp(&proof.second_index))
+}
+
+// the signature covers everything in the proof but itself
+fn ordering_message(proof: &protocol::OrderingProofBs58) -> Vec<u8> {
+    let unsigned = protocol::OrderingProofBs58 { signature: None, ..proof.clone() };
+
+    let mut message = ORDERING_DOMAIN.to_vec();
+    message.extend_from_slice(&serde_json::to_vec(&unsigned).unwrap());
+    message
+}
+
+// decodes a bs58 encoded ed25519 secret key, e.g. the sequencer's SANCTUM_SEQUENCER_KEY
+pub fn signing_key_from_bs58(key: &str) -> Result<SigningKey, String> {
+    let bytes: [u8; 32] = bs58::decode(key)
+        .into_vec()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "signing key must be 32 bytes".to_string())?;
+
+    Ok(SigningKey::from_bytes(&bytes))
+}
@@ -0,0 +1,249 @@
+use ark_bw6_761::BW6_761;
+use ark_groth16::{ProvingKey, VerifyingKey};
+
+use crate::{
+    balance_circuit, merkle_update_circuit, onramp_circuit, onramp_multi_circuit, payment_circuit,
+    payment_update_circuit, swap_circuit, transfer_circuit,
+};
+use crate::protocol::{CircuitKind, StatementParams, NUM_MERKLE_UPDATE_INPUTS, NUM_PAYMENT_INPUTS};
+use crate::utils;
+
+// where the setup binary writes keys, overridable with SANCTUM_KEY_DIR
+pub const DEFAULT_KEY_DIR: &str = "/tmp/sanctum";
+
+// the file in a key dir where the setup binary records each circuit's keys
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+// set to true, proving keys are read without checking their points, against
+// the pk_sha256 of the manifest instead
+pub const UNCHECKED_KEYS_ENV: &str = "SANCTUM_UNCHECKED_KEYS";
+
+/// CircuitDescriptor describes one of the protocol's circuits: the id its keys
+/// and fingerprints are named by, the layout of its public inputs, and how to
+/// run its setup under a deployment's statement params
+pub struct CircuitDescriptor {
+    pub kind: CircuitKind,
+    pub id: &'static str,
+    num_public_inputs: usize,
+    #[cfg(feature = "insecure-setup")]
+    pub setup: fn(&StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>),
+    // one label per public input, in the order the circuit allocates them
+    input_labels: &'static [&'static str],
+}
+
+impl CircuitDescriptor {
+    // the payment statement ends with the spent leaf's index in deployments
+    // that expose it
+    fn exposes_leaf_index(&self, statement: &StatementParams) -> bool {
+        self.kind == CircuitKind::Payment && statement.expose_leaf_index
+    }
+
+    /// the number of public inputs of the circuit, as it is set up under statement
+    pub fn num_public_inputs(&self, statement: &StatementParams) -> usize {
+        self.num_public_inputs + usize::from(self.exposes_leaf_index(statement))
+    }
+
+    /// one label per public input, in the order the circuit allocates them
+    pub fn input_labels(&self, statement: &StatementParams) -> Vec<&'static str> {
+        let mut labels = self.input_labels.to_vec();
+        if self.exposes_leaf_index(statement) {
+            labels.push("leaf_index");
+        }
+
+        labels
+    }
+}
+
+/// every circuit of the protocol; app circuits are registered at runtime
+/// instead, see apps::AppRegistry
+pub static CIRCUITS: [CircuitDescriptor; 8] = [
+    CircuitDescriptor {
+        kind: CircuitKind::Onramp,
+        id: "onramp",
+        num_public_inputs: 4,
+        #[cfg(feature = "insecure-setup")]
+        setup: |_| onramp_circuit::circuit_setup(),
+        input_labels: &["asset_id", "amount", "commitment_x", "commitment_y"],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::OnrampMulti,
+        id: "onramp_multi",
+        num_public_inputs: 3 + 2 * onramp_multi_circuit::NUM_NOTES,
+        #[cfg(feature = "insecure-setup")]
+        setup: |_| onramp_multi_circuit::circuit_setup(),
+        input_labels: &[
+            "asset_id", "amount", "num_notes",
+            "commitment_0_x", "commitment_0_y", "commitment_1_x", "commitment_1_y",
+            "commitment_2_x", "commitment_2_y", "commitment_3_x", "commitment_3_y",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::Payment,
+        id: "payment",
+        num_public_inputs: NUM_PAYMENT_INPUTS,
+        #[cfg(feature = "insecure-setup")]
+        setup: payment_circuit::circuit_setup,
+        input_labels: &[
+            "root_x", "root_y", "nullifier", "commitment_x", "commitment_y", "asset_tag", "tree_id",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::MerkleUpdate,
+        id: "merkle_update",
+        num_public_inputs: NUM_MERKLE_UPDATE_INPUTS,
+        #[cfg(feature = "insecure-setup")]
+        setup: |_| merkle_update_circuit::circuit_setup(),
+        input_labels: &[
+            "leaf_index", "leaf_value_x", "leaf_value_y",
+            "old_root_x", "old_root_y", "new_root_x", "new_root_y",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::PaymentUpdate,
+        id: "payment_update",
+        num_public_inputs: NUM_PAYMENT_INPUTS + NUM_MERKLE_UPDATE_INPUTS,
+        #[cfg(feature = "insecure-setup")]
+        setup: payment_update_circuit::circuit_setup,
+        // the payment circuit's inputs followed by the merkle update's
+        input_labels: &[
+            "root_x", "root_y", "nullifier", "commitment_x", "commitment_y", "asset_tag", "tree_id",
+            "leaf_index", "leaf_value_x", "leaf_value_y",
+            "old_root_x", "old_root_y", "new_root_x", "new_root_y",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::Swap,
+        id: "swap",
+        num_public_inputs: 9,
+        #[cfg(feature = "insecure-setup")]
+        setup: swap_circuit::circuit_setup,
+        input_labels: &[
+            "root_x", "root_y", "nullifier", "commitment_x", "commitment_y", "asset_tag", "tree_id",
+            "swap_id", "swap_role",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::Transfer,
+        id: "transfer",
+        num_public_inputs: 8,
+        #[cfg(feature = "insecure-setup")]
+        setup: transfer_circuit::circuit_setup,
+        input_labels: &[
+            "root_x", "root_y", "nullifier", "commitment_x", "commitment_y", "asset_tag", "tree_id",
+            "dest_pool_id",
+        ],
+    },
+    CircuitDescriptor {
+        kind: CircuitKind::Balance,
+        id: "balance",
+        num_public_inputs: 5 + balance_circuit::NUM_NOTES,
+        #[cfg(feature = "insecure-setup")]
+        setup: balance_circuit::circuit_setup,
+        input_labels: &[
+            "root_x", "root_y", "tree_id", "asset_id", "threshold",
+            "nullifier_0", "nullifier_1", "nullifier_2", "nullifier_3",
+        ],
+    },
+];
+
+/// looks up the descriptor of a protocol circuit; None for app circuits
+pub fn descriptor(kind: CircuitKind) -> Option<&'static CircuitDescriptor> {
+    CIRCUITS.iter().find(|d| d.kind == kind)
+}
+
+/// runs the setup of a protocol circuit under statement, returning its proving
+/// and verifying keys; the setup's randomness is a fixed seed, so the keys are
+/// only fit for tests
+#[cfg(feature = "insecure-setup")]
+pub fn setup(kind: CircuitKind, statement: &StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    let descriptor = descriptor(kind)
+        .unwrap_or_else(|| panic!("{:?} circuits have no setup in the registry", kind));
+
+    (descriptor.setup)(statement)
+}
+
+/// the proving and verifying keys of a protocol circuit, as the services
+/// obtain them: read from SANCTUM_KEY_DIR, or DEFAULT_KEY_DIR. Builds with the
+/// insecure-setup feature instead run the setup under statement when
+/// SANCTUM_KEY_DIR is unset; keys read from a dir were set up by the setup binary.
+#[cfg_attr(not(feature = "insecure-setup"), allow(unused_variables))]
+pub fn keys(kind: CircuitKind, statement: &StatementParams) -> Result<(ProvingKey<BW6_761>, VerifyingKey<BW6_761>), String> {
+    #[cfg(feature = "insecure-setup")]
+    if std::env::var("SANCTUM_KEY_DIR").is_err() {
+        return Ok(setup(kind, statement));
+    }
+
+    read_keys(kind, &key_dir())
+}
+
+/// as keys, for services that only verify and so need not read the proving key
+#[cfg_attr(not(feature = "insecure-setup"), allow(unused_variables))]
+pub fn verifying_key(kind: CircuitKind, statement: &StatementParams) -> Result<VerifyingKey<BW6_761>, String> {
+    #[cfg(feature = "insecure-setup")]
+    if std::env::var("SANCTUM_KEY_DIR").is_err() {
+        return Ok(setup(kind, statement).1);
+    }
+
+    read_verifying_key(kind, &key_dir())
+}
+
+/// reads the keys of a protocol circuit from dir, where the setup binary
+/// writes them as e.g. payment.pk and payment.vk. With SANCTUM_UNCHECKED_KEYS
+/// set, the pk is read unchecked, see read_proving_key
+pub fn read_keys(kind: CircuitKind, dir: &str) -> Result<(ProvingKey<BW6_761>, VerifyingKey<BW6_761>), String> {
+    let unchecked = std::env::var(UNCHECKED_KEYS_ENV).map(|v| v == "true").unwrap_or(false);
+    let pk = read_proving_key(kind, dir, unchecked)?;
+    let vk = utils::read_groth_verification_key_from_file(&key_path(kind, dir, "vk")?)?;
+
+    Ok((pk, vk))
+}
+
+/// reads the pk of a protocol circuit from dir; unchecked, its points are
+/// not checked, but the file must match the pk_sha256 the manifest in dir
+/// records for the circuit
+pub fn read_proving_key(kind: CircuitKind, dir: &str, unchecked: bool) -> Result<ProvingKey<BW6_761>, String> {
+    let path = key_path(kind, dir, "pk")?;
+    if !unchecked {
+        return utils::read_groth_proving_key_from_file(&path);
+    }
+
+    utils::read_groth_proving_key_from_file_unchecked(&path, &manifest_pk_sha256(kind, dir)?)
+}
+
+// the pk_sha256 the manifest in dir records for a protocol circuit
+fn manifest_pk_sha256(kind: CircuitKind, dir: &str) -> Result<[u8; 32], String> {
+    let id = descriptor(kind)
+        .ok_or_else(|| format!("{:?} circuits have no keys in the registry", kind))?
+        .id;
+    let path = format!("{}/{}", dir, MANIFEST_FILE);
+    let manifest = std::fs::read_to_string(&path)
+        .map_err(|e| format!("unable to read {}: {}", path, e))?;
+    let manifest: Vec<serde_json::Value> = serde_json::from_str(&manifest)
+        .map_err(|e| format!("{} is not a manifest: {}", path, e))?;
+
+    let sha256 = manifest
+        .iter()
+        .find(|entry| entry["id"] == id)
+        .and_then(|entry| entry["pk_sha256"].as_str())
+        .ok_or_else(|| format!("{} records no pk_sha256 for the {} circuit", path, id))?;
+    hex::decode(sha256)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("{} records a malformed pk_sha256 for the {} circuit", path, id))
+}
+
+/// as read_keys, for the vk alone
+pub fn read_verifying_key(kind: CircuitKind, dir: &str) -> Result<VerifyingKey<BW6_761>, String> {
+    utils::read_groth_verification_key_from_file(&key_path(kind, dir, "vk")?)
+}
+
+fn key_dir() -> String {
+    std::env::var("SANCTUM_KEY_DIR").unwrap_or_else(|_| DEFAULT_KEY_DIR.to_string())
+}
+
+pub(crate) fn key_path(kind: CircuitKind, dir: &str, extension: &str) -> Result<String, String> {
+    let descriptor = descriptor(kind)
+        .ok_or_else(|| format!("{:?} circuits have no keys in the registry", kind))?;
+
+    Ok(format!("{}/{}.{}", dir, descriptor.id, extension))
+}
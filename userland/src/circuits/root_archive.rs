@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use super::protocol;
+use super::receipts::RootSource;
+
+// base58 encoded (x,y) coordinates
+type Hash = (String, String);
+
+// maximum number of roots served in a single range
+pub const MAX_ROOT_RANGE: usize = 1000;
+
+/// RootArchive keeps every root ever accepted, numbered by block, so that
+/// receipts stay verifiable after their root leaves the MerkleRootHistory
+/// window. It is an off-chain archive: the window alone decides which roots
+/// new proofs may use. With a file, each root is appended as a json line.
+pub struct RootArchive {
+    roots: Vec<Hash>,
+    known: HashSet<Hash>,
+    file: Option<std::fs::File>,
+}
+
+impl RootArchive {
+
+    // an archive held in memory only
+    pub fn new() -> Self {
+        RootArchive { roots: Vec::new(), known: HashSet::new(), file: None }
+    }
+
+    // opens the archive at path, creating it if need be. A torn last line,
+    // left by a crash in the middle of writing it, is cut off
+    pub fn open(path: &str) -> Result<Self, String> {
+        let log = match std::fs::read_to_string(path) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("unable to read root archive: {}", e)),
+        };
+        let intact = &log[..log.rfind('\n').map(|i| i + 1).unwrap_or(0)];
+
+        let roots: Vec<Hash> = intact
+            .lines()
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("malformed root archive entry: {}", e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("unable to open root archive: {}", e))?;
+        file.set_len(intact.len() as u64).map_err(|e| format!("unable to truncate root archive: {}", e))?;
+
+        let known = roots.iter().cloned().collect();
+        Ok(RootArchive { roots, known, file: Some(file) })
+    }
+
+    // persisted to SANCTUM_ROOT_ARCHIVE if it is set, and in memory otherwise
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("SANCTUM_ROOT_ARCHIVE") {
+            Ok(path) => RootArchive::open(&path),
+            Err(_) => Ok(RootArchive::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    // archives the root of the next block, returning its block number
+    pub fn append(&mut self, root: &Hash) -> Result<usize, String> {
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", serde_json::to_string(root).unwrap())
+                .and_then(|_| file.sync_data())
+                .map_err(|e| format!("unable to write root archive: {}", e))?;
+        }
+        self.roots.push(root.clone());
+        self.known.insert(root.clone());
+
+        Ok(self.roots.len() - 1)
+    }
+
+    pub fn get(&self, block: usize) -> Option<protocol::ArchivedRootBs58> {
+        self.roots.get(block).map(|root| archived(block, root))
+    }
+
+    // up to limit roots from the given block onwards
+    pub fn range(&self, from: usize, limit: usize) -> Result<protocol::RootRangeBs58, String> {
+        if limit > MAX_ROOT_RANGE {
+            return Err(format!("range of {} roots exceeds the limit of {}", limit, MAX_ROOT_RANGE));
+        }
+
+        let end = from.saturating_add(limit).min(self.roots.len());
+        let roots = (from..end).map(|block| archived(block, &self.roots[block])).collect();
+        let next = if end < self.roots.len() { Some(end) } else { None };
+
+        Ok(protocol::RootRangeBs58 { roots, next })
+    }
+}
+
+fn archived(block: usize, root: &Hash) -> protocol::ArchivedRootBs58 {
+    protocol::ArchivedRootBs58 { block, root_x: root.0.clone(), root_y: root.1.clone() }
+}
+
+impl Default for RootArchive {
+    fn default() -> Self {
+        RootArchive::new()
+    }
+}
+
+impl RootSource for RootArchive {
+    fn is_root_known(&self, root: &(String, String)) -> bool {
+        self.known.contains(root)
+    }
+}
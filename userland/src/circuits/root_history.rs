@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::protocol;
+use super::receipts::RootSource;
+
+// base58 encoded (x,y) coordinates
+type Hash = (String, String);
+
+// roots are stored, and looked up, in the one encoding normalize_field_bs58
+// gives them, so that two encodings of the same root are the same root
+fn normalize(root: &Hash) -> Hash {
+    (protocol::normalize_field_bs58(&root.0), protocol::normalize_field_bs58(&root.1))
+}
+
+/// MerkleRootHistory is the ring buffer of the most recent accepted roots;
+/// it serializes in full, so the verifier can persist its window across restarts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MerkleRootHistory {
+    pub root_history_size: u32,
+    historical_roots: HashMap<u32, Hash>,
+    next_root_index: u32,
+}
+
+impl MerkleRootHistory {
+
+    // create a new merkle tree with no leaves
+    pub fn new(root_history_size: u32) -> Self
+    {
+        MerkleRootHistory {
+            root_history_size,
+            historical_roots: HashMap::new(),
+            next_root_index: 0,
+        }
+    }
+
+    // insert a new leaf into the merkle tree
+    pub fn insert(&mut self, root: &Hash) {
+        self.historical_roots.insert(self.next_root_index, normalize(root));
+        self.next_root_index = (self.next_root_index + 1) % self.root_history_size;
+    }
+
+    // every slot holds one of the last root_history_size roots, so a root is
+    // known iff some slot holds it
+    pub fn is_known_root(&self, root: &Hash) -> bool {
+        let root = normalize(root);
+        self.historical_roots.values().any(|r| *r == root)
+    }
+
+    // how many roots were inserted after root, 0 if it is the latest; None if
+    // it has left the window
+    pub fn age_of(&self, root: &Hash) -> Option<usize> {
+        let root = normalize(root);
+        let slot = self.historical_roots
+            .iter()
+            .find(|(_, r)| **r == root)
+            .map(|(slot, _)| *slot)?;
+        let last_index = (self.next_root_index + self.root_history_size - 1) % self.root_history_size;
+
+        Some(((last_index + self.root_history_size - slot) % self.root_history_size) as usize)
+    }
+
+    pub fn get_latest_root(&self) -> Option<Hash> {
+        // the ring wraps, so the last written slot may be the final one
+        let last_index = (self.next_root_index + self.root_history_size - 1) % self.root_history_size;
+        return self.historical_roots.get(&last_index).cloned();
+    }
+}
+
+impl RootSource for MerkleRootHistory {
+    fn is_root_known(&self, root: &(String, String)) -> bool {
+        self.is_known_root(root)
+    }
+}
@@ -0,0 +1,273 @@
+#[cfg(feature = "insecure-setup")]
+use rand_chacha::rand_core::SeedableRng;
+
+use ark_ec::CurveGroup;
+use ark_bw6_761::BW6_761;
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::{AllocatedFp, FpVar};
+use ark_relations::r1cs::*;
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
+use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore, rngs::OsRng};
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    *, config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+use lib_mpc_zexe::record_commitment::kzg::{*, constraints::*};
+use lib_mpc_zexe::prf::{*, constraints::*};
+
+use super::utils;
+use super::payment_circuit::{self, PaymentCircuit};
+use super::protocol;
+
+// Finite Field used to encode the zk circuit
+type ConstraintF = ark_bw6_761::Fr;
+
+// the statement is the payment statement followed by the swap id and role
+#[allow(non_camel_case_types, unused)]
+pub enum GrothPublicInput {
+    SWAP_ID = 7,
+    SWAP_ROLE = 8,
+}
+
+/// SwapTerms are what the two parties of a swap agree on before proving:
+/// the amount each half pays, and a nonce keeping their swap id unique
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapTerms {
+    /// the amount the proposal pays, then the amount the acceptance pays
+    pub amounts: [u64; 2],
+    pub nonce: [u8; 32],
+}
+
+impl SwapTerms {
+    // the amounts, little-endian, as the PRF input the swap id commits to
+    fn prf_input(&self) -> Vec<u8> {
+        self.amounts.iter().flat_map(|amount| amount.to_le_bytes()).collect()
+    }
+
+    /// the swap id is PRF(amounts; nonce), so a half can only carry it by
+    /// paying the amount its role agreed to
+    pub fn swap_id(&self, prf_params: &JZPRFParams) -> [u8; 31] {
+        let output = JZPRFInstance::new(prf_params, &self.prf_input(), &self.nonce).evaluate();
+        output[..31].try_into().unwrap()
+    }
+}
+
+/// SwapCircuit proves one half of an atomic swap: a payment whose output
+/// coin is locked to the swap, by having the public SWAP_ID as its entropy.
+/// Both halves of a swap carry the same SWAP_ID, which the sequencer pairs
+/// them by; the output coin's rho keeps its commitment hiding. The SWAP_ID
+/// commits to the agreed amounts, and the public SWAP_ROLE says which of
+/// them this half pays.
+pub struct SwapCircuit<'a> {
+    pub payment: PaymentCircuit<'a>,
+    pub terms: SwapTerms,
+    pub role: protocol::SwapRole,
+}
+
+impl<'a> ConstraintSynthesizer<ConstraintF> for SwapCircuit<'a> {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<()> {
+        let crs = self.payment.crs;
+        let prf_params = self.payment.prf_params;
+        let output_utxo = self.payment.output_utxo;
+        let output_utxo_commitment = output_utxo.commitment().into_affine();
+
+        self.payment.generate_constraints(cs.clone())?;
+
+        let swap_id_inputvar = ark_bls12_377::constraints::FqVar::new_input(
+            ark_relations::ns!(cs, "swap_id"),
+            || Ok(utils::bytes_to_field::<ConstraintF, 6>(&self.terms.swap_id(prf_params))),
+        )?;
+
+        let swap_role_inputvar = FpVar::<ConstraintF>::new_input(
+            ark_relations::ns!(cs, "swap_role"),
+            || Ok(ConstraintF::from(self.role as u64)),
+        )?;
+
+        // the payment circuit keeps its output utxo to itself, so open the
+        // output commitment again to reach its entropy
+        let crs_var = JZKZGCommitmentParamsVar::<5>::new_constant(cs.clone(), crs)?;
+        let output_utxo_var = JZRecordVar::<5>::new_witness(
+            cs.clone(),
+            || Ok(output_utxo)
+        )?;
+        lib_mpc_zexe::record_commitment::kzg::constraints::generate_constraints(
+            cs.clone(),
+            &crs_var,
+            &output_utxo_var
+        ).unwrap();
+
+        // it must be the output commitment of the payment statement; instance
+        // variable 0 is the constant one, hence the offsets by 1
+        let commitment_var = output_utxo_var.commitment.to_affine()?;
+        let payment_input = |input: protocol::PaymentGrothPublicInput, value| FpVar::Var(
+            AllocatedFp::new(Some(value), Variable::Instance(1 + input as usize), cs.clone())
+        );
+        commitment_var.x.enforce_equal(
+            &payment_input(protocol::PaymentGrothPublicInput::COMMITMENT_X, output_utxo_commitment.x)
+        )?;
+        commitment_var.y.enforce_equal(
+            &payment_input(protocol::PaymentGrothPublicInput::COMMITMENT_Y, output_utxo_commitment.y)
+        )?;
+
+        // the output is locked to the swap: its entropy is the swap id, whose
+        // remaining high bytes are zero
+        let swap_id_bytes = swap_id_inputvar.to_bytes()?;
+        let entropy_bytes = &output_utxo_var.fields[protocol::UtxoField::ENTROPY as usize];
+        for (i, byte_var) in swap_id_bytes.iter().enumerate() {
+            match entropy_bytes.get(i) {
+                Some(entropy_byte) => byte_var.enforce_equal(entropy_byte)?,
+                None => byte_var.enforce_equal(&UInt8::constant(0))?,
+            }
+        }
+
+        // the swap id is the PRF of the agreed amounts, under the nonce
+        let prf_params_var = JZPRFParamsVar::new_constant(cs.clone(), prf_params)?;
+        let terms_prf_instance_var = JZPRFInstanceVar::new_witness(
+            cs.clone(),
+            || Ok(JZPRFInstance::new(prf_params, &self.terms.prf_input(), &self.terms.nonce))
+        )?;
+        lib_mpc_zexe::prf::constraints::generate_constraints(
+            cs.clone(),
+            &prf_params_var,
+            &terms_prf_instance_var
+        );
+        for (byte_var, output_byte_var) in swap_id_bytes.iter().zip(terms_prf_instance_var.output_var.iter()).take(31) {
+            byte_var.enforce_equal(output_byte_var)?;
+        }
+
+        // the role is 0 or 1, and picks which of the agreed amounts this half
+        // pays; the payment statement already zeroes the amount's high bytes
+        if terms_prf_instance_var.input_var.len() != 2 * protocol::AMOUNT_BYTES {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let is_acceptance = Boolean::new_witness(
+            ark_relations::ns!(cs, "is_acceptance"),
+            || Ok(self.role == protocol::SwapRole::Acceptance),
+        )?;
+        swap_role_inputvar.enforce_equal(&FpVar::from(is_acceptance.clone()))?;
+
+        let amount_var = |bytes: &[UInt8<ConstraintF>]| Boolean::le_bits_to_fp_var(&bytes.to_bits_le()?);
+        let (proposal_amount, acceptance_amount) = terms_prf_instance_var.input_var.split_at(protocol::AMOUNT_BYTES);
+        let agreed_amount = FpVar::conditionally_select(
+            &is_acceptance,
+            &amount_var(acceptance_amount)?,
+            &amount_var(proposal_amount)?,
+        )?;
+        let paid_amount = amount_var(
+            &output_utxo_var.fields[protocol::UtxoField::AMOUNT as usize][..protocol::AMOUNT_BYTES]
+        )?;
+        paid_amount.enforce_equal(&agreed_amount)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup(statement: &protocol::StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    // create a circuit with a dummy witness
+    let merkle_proof = utils::dummy_opening_proof(vc_params, protocol::MERKLE_TREE_LEVELS);
+    let dummy_utxo = utils::get_dummy_utxo(crs);
+
+    let circuit = SwapCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: [0u8; 32],
+            asset_blind: [0u8; 32],
+            pool_id: statement.pool_id,
+            tree_id: 0,
+            expose_leaf_index: false,
+            input_utxo: &dummy_utxo,
+            output_utxo: &dummy_utxo,
+            unspent_coin_existence_proof: &merkle_proof,
+        },
+        terms: SwapTerms { amounts: [0, 0], nonce: [0u8; 32] },
+        role: protocol::SwapRole::Proposal,
+    };
+
+    let seed = [0u8; 32];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+
+    let (pk, vk) = Groth16::<BW6_761>::
+        circuit_specific_setup(circuit, &mut rng)
+        .unwrap();
+
+    (pk, vk)
+}
+
+// proves one half of a swap; both halves use the terms and the asset blind
+// the parties agreed on, so that they carry the same swap id, and so that the
+// sequencer can compare their asset tags
+#[allow(clippy::too_many_arguments)]
+pub fn generate_groth_proof(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    terms: &SwapTerms,
+    role: protocol::SwapRole,
+    asset_blind: &[u8; 32],
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    generate_groth_proof_with_rng(
+        pk, statement, input_utxo, output_utxo, unspent_coin_existence_proof, sk, tree_id, terms, role, asset_blind, &mut OsRng
+    )
+}
+
+// proves with caller-supplied randomness, e.g. a seeded rng for test vectors
+#[allow(clippy::too_many_arguments)]
+pub fn generate_groth_proof_with_rng<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    terms: &SwapTerms,
+    role: protocol::SwapRole,
+    asset_blind: &[u8; 32],
+    rng: &mut R,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    let circuit = SwapCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: *sk,
+            asset_blind: *asset_blind,
+            pool_id: statement.pool_id,
+            tree_id,
+            expose_leaf_index: false,
+            input_utxo,
+            output_utxo,
+            unspent_coin_existence_proof,
+        },
+        terms: *terms,
+        role,
+    };
+
+    let mut public_inputs = payment_circuit::public_inputs(&circuit.payment);
+    public_inputs.push(utils::bytes_to_field::<ConstraintF, 6>(&terms.swap_id(prf_params)));
+    public_inputs.push(ConstraintF::from(role as u64));
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, rng).unwrap();
+
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "swap proof generated");
+
+    (proof, public_inputs)
+}
@@ -0,0 +1,2068 @@
+#![cfg(test)]
+
+use ark_bw6_761::BW6_761;
+use ark_ec::CurveGroup;
+use ark_ff::{One, PrimeField};
+use ark_groth16::{Groth16, prepare_verifying_key};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, SynthesisError};
+use ark_snark::SNARK;
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    JZVectorDB, JZVectorCommitmentOpeningProof,
+    config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+
+use crate::apps;
+use crate::balance_circuit;
+use crate::coin_models;
+use crate::asset_tag;
+use crate::dedup::{self, DedupWindow};
+use crate::key_reload::KeyReload;
+use crate::keys;
+use crate::memo;
+use crate::merkle_update_circuit;
+use crate::onramp_circuit;
+use crate::onramp_multi_circuit;
+use crate::payment_circuit::{self, PaymentCircuit};
+use crate::payment_update_circuit;
+use crate::nullifiers::*;
+use crate::policy;
+use crate::protocol;
+use crate::receipts;
+use crate::registry;
+use crate::root_history::MerkleRootHistory;
+use crate::swap_circuit;
+use crate::test_coins::test_coin;
+use crate::tickets::{Ticket, TicketStore};
+use crate::transfer_circuit::{self, TransferCircuit};
+use crate::transfers::{self, PeerPools};
+use crate::utils;
+use crate::verification_cache::VerificationCache;
+
+use crate::protocol::MERKLE_TREE_LEVELS;
+
+type ConstraintF = ark_bw6_761::Fr;
+
+// a tree filled with dummy utxos, as initialized by the sequencer
+fn dummy_db() -> JZVectorDB<MTParams, ark_bls12_377::G1Affine> {
+    let (_, vc_params, crs) = utils::trusted_setup();
+    let records: Vec<ark_bls12_377::G1Affine> = (0..(1 << MERKLE_TREE_LEVELS))
+        .map(|_| utils::get_dummy_utxo(&crs).commitment().into_affine())
+        .collect();
+
+    JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records)
+}
+
+fn opening_proof(
+    db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>,
+    index: usize
+) -> JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine> {
+    JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(index).clone(),
+        path: db.proof(index),
+    }
+}
+
+// a payment of input_utxo, opened by merkle_proof, into output_utxo, under the
+// shared parameters, a zero asset blind, no pool and tree 0; tests override
+// whichever of these they exercise
+fn payment<'a>(
+    input_utxo: &'a JZRecord<5>,
+    output_utxo: &'a JZRecord<5>,
+    merkle_proof: &'a JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: [u8; 32],
+) -> PaymentCircuit<'a> {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    PaymentCircuit {
+        crs,
+        prf_params,
+        vc_params,
+        input_utxo,
+        output_utxo,
+        sk,
+        asset_blind: [0u8; 32],
+        pool_id: None,
+        tree_id: 0,
+        expose_leaf_index: false,
+        unspent_coin_existence_proof: merkle_proof,
+    }
+}
+
+#[test]
+fn test_nullifier_status_batch() {
+    let mut store = NullifierStore::new();
+    store.insert(&"nullifier_a".to_string(), 3);
+    store.insert(&"nullifier_c".to_string(), 7);
+
+    let request = protocol::NullifierStatusRequestBs58 {
+        nullifiers: vec![
+            "nullifier_a".to_string(),
+            "nullifier_b".to_string(),
+            "nullifier_c".to_string(),
+        ]
+    };
+
+    let status = store.status(&request).unwrap();
+    assert_eq!(status.spent, vec![true, false, true]);
+    assert_eq!(status.leaf_indices, vec![Some(3), None, Some(7)]);
+
+    // batches above the cap are rejected outright
+    let request = protocol::NullifierStatusRequestBs58 {
+        nullifiers: vec!["nullifier_a".to_string(); MAX_NULLIFIER_STATUS_BATCH + 1]
+    };
+    assert!(store.status(&request).is_err());
+}
+
+#[test]
+fn test_merkle_update_binds_leaf_value_y() {
+    let mut db = dummy_db();
+    let leaf_index = 0;
+
+    let old_merkle_proof = opening_proof(&db, leaf_index);
+    db.update(leaf_index, &test_coin(10).commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db, leaf_index);
+
+    let (_, vc_params, _) = utils::trusted_setup();
+    let circuit = merkle_update_circuit::MerkleUpdateCircuit {
+        vc_params,
+        leaf_index,
+        old_merkle_proof,
+        new_merkle_proof,
+    };
+
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    // a LEAF_VALUE_Y that disagrees with the inserted leaf must not satisfy the circuit;
+    // instance variable 0 is the constant 1, so public inputs are offset by one
+    let index = 1 + merkle_update_circuit::GrothPublicInput::LEAF_VALUE_Y as usize;
+    cs.borrow_mut().unwrap().instance_assignment[index] += ConstraintF::one();
+    assert!(!cs.is_satisfied().unwrap());
+}
+
+#[test]
+fn test_vk_fingerprint() {
+    let (_, vk) = onramp_circuit::circuit_setup();
+    let (_, vk_again) = onramp_circuit::circuit_setup();
+
+    // the fingerprint is a function of the key and circuit name alone
+    let fingerprint = keys::vk_fingerprint("onramp", &vk);
+    assert_eq!(fingerprint, keys::vk_fingerprint("onramp", &vk_again));
+    assert_ne!(fingerprint, keys::vk_fingerprint("payment", &vk));
+
+    // changing any component of the key changes the fingerprint
+    let mut tampered_vk = vk.clone();
+    tampered_vk.gamma_abc_g1.swap(0, 1);
+    assert_ne!(fingerprint, keys::vk_fingerprint("onramp", &tampered_vk));
+}
+
+#[test]
+fn test_vk_diff() {
+    let (_, vk) = onramp_circuit::circuit_setup();
+    assert!(keys::vk_diff(&vk, &vk.clone()).is_empty());
+
+    // keys differing in a single component are reported as such
+    let mut tampered_vk = vk.clone();
+    tampered_vk.delta_g2 = tampered_vk.gamma_g2;
+    assert_eq!(keys::vk_diff(&vk, &tampered_vk), vec!["delta_g2 differs".to_string()]);
+}
+
+#[test]
+fn test_vk_summary() {
+    let (pk, vk) = onramp_circuit::circuit_setup();
+    let dir = std::env::temp_dir().join(format!("sanctum-keytool-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (pk_path, vk_path) = (dir.join("onramp.pk"), dir.join("onramp.vk"));
+    utils::write_groth_key_to_file(&pk, pk_path.to_str().unwrap(), &vk, vk_path.to_str().unwrap());
+
+    // a key read back from its file reports the statement of its circuit
+    let summary = keys::vk_summary(&utils::read_groth_verification_key_from_file(vk_path.to_str().unwrap()).unwrap());
+    assert_eq!(summary.num_public_inputs, protocol::OnrampGrothPublicInput::COMMITMENT_Y as usize + 1);
+    assert_eq!(summary.candidates, vec!["onramp"]);
+    assert_eq!(summary.hash, keys::vk_summary(&vk).hash);
+
+    // the proving key embeds the same verifying key
+    let pk = utils::read_groth_proving_key_from_file(pk_path.to_str().unwrap()).unwrap();
+    assert_eq!(keys::vk_summary(&pk.vk).hash, summary.hash);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_key_file_version() {
+    use ark_serialize::CanonicalSerialize;
+
+    let (pk, vk) = onramp_circuit::circuit_setup();
+    let dir = std::env::temp_dir().join(format!("sanctum-key-version-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (pk_path, vk_path) = (dir.join("onramp.pk"), dir.join("onramp.vk"));
+    let (pk_path, vk_path) = (pk_path.to_str().unwrap(), vk_path.to_str().unwrap());
+    utils::write_groth_key_to_file(&pk, pk_path, &vk, vk_path);
+    assert_eq!(utils::read_groth_verification_key_from_file(vk_path).unwrap(), vk);
+
+    // a key file from a build writing another version is refused by name
+    let mut bytes = std::fs::read(vk_path).unwrap();
+    bytes[8] ^= 0xff;
+    std::fs::write(vk_path, &bytes).unwrap();
+    let err = utils::read_groth_verification_key_from_file(vk_path).unwrap_err();
+    assert!(err.contains("incompatible key file version"), "{}", err);
+
+    // as is one written before key files had a header at all
+    let mut unversioned = Vec::new();
+    pk.serialize_uncompressed(&mut unversioned).unwrap();
+    std::fs::write(pk_path, &unversioned).unwrap();
+    let err = utils::read_groth_proving_key_from_file(pk_path).unwrap_err();
+    assert!(err.contains("is not a key file"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_registry_read_keys() {
+    let (pk, vk) = registry::setup(protocol::CircuitKind::Onramp, &protocol::StatementParams::default());
+    let dir = std::env::temp_dir().join(format!("sanctum-key-dir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+
+    // keys are found by the id of their circuit, as the setup binary names them
+    let err = registry::read_keys(protocol::CircuitKind::Onramp, dir).unwrap_err();
+    assert!(err.contains("onramp.pk"), "{}", err);
+    utils::write_groth_key_to_file(&pk, &format!("{}/onramp.pk", dir), &vk, &format!("{}/onramp.vk", dir));
+    let (read_pk, read_vk) = registry::read_keys(protocol::CircuitKind::Onramp, dir).unwrap();
+    assert_eq!(read_vk, vk);
+    assert_eq!(read_pk.vk, vk);
+
+    // unchecked, the pk is read against the manifest's pk_sha256
+    let err = registry::read_proving_key(protocol::CircuitKind::Onramp, dir, true).unwrap_err();
+    assert!(err.contains(registry::MANIFEST_FILE), "{}", err);
+    let sha256 = hex::encode(utils::key_file_sha256(&format!("{}/onramp.pk", dir)));
+    let manifest = |sha256: &str| std::fs::write(
+        format!("{}/{}", dir, registry::MANIFEST_FILE),
+        serde_json::json!([{ "id": "onramp", "pk_sha256": sha256 }]).to_string()
+    ).unwrap();
+    manifest(&sha256);
+    assert!(registry::read_proving_key(protocol::CircuitKind::Onramp, dir, true).unwrap() == pk);
+    manifest(&"00".repeat(32));
+    let err = registry::read_proving_key(protocol::CircuitKind::Onramp, dir, true).unwrap_err();
+    assert!(err.contains("does not match the checksum"), "{}", err);
+
+    // app circuits bring their own keys
+    assert!(registry::read_keys(protocol::CircuitKind::App, dir).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_constants_match_contract() {
+    // the contract is its own crate, built for wasm; its source is the one
+    // place both copies of each constant are spelled out, so read ours against it
+    let contract = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../contracts/payment/src/lib.rs"));
+    for declaration in [
+        format!("const PUBLIC_INPUT_LAYOUT_VERSION: u32 = {};", protocol::PUBLIC_INPUT_LAYOUT_VERSION),
+        format!("const MERKLE_TREE_LEVELS: u32 = {};", MERKLE_TREE_LEVELS),
+    ] {
+        assert!(
+            contract.lines().any(|line| line.trim() == declaration),
+            "the payment contract does not declare {}", declaration
+        );
+    }
+}
+
+#[test]
+fn test_payment_memo() {
+    // memos round-trip through their encoding, and are optional on the wire
+    let memo = protocol::memo_to_bs58(b"invoice #1").unwrap();
+    assert_eq!(protocol::memo_from_bs58(&memo).unwrap(), b"invoice #1".to_vec());
+
+    let tx: protocol::PaymentTxBs58 = serde_json::from_str(
+        r#"{ "payment_proof": { "circuit": "payment", "proof": "", "public_inputs": [] } }"#
+    ).unwrap();
+    assert!(tx.memo.is_none());
+
+    // over-long memos are rejected on both ends
+    let long_memo = vec![0xffu8; protocol::MAX_MEMO_BYTES + 1];
+    assert!(protocol::memo_to_bs58(&long_memo).is_err());
+    assert!(protocol::memo_from_bs58(&bs58::encode(&long_memo).into_string()).is_err());
+}
+
+#[test]
+fn test_memo_encryption() {
+    use rand_chacha::rand_core::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([3u8; 32]);
+    let (bob_sk, eve_sk) = ([25u8; 32], [26u8; 32]);
+
+    // only the recipient reads a memo, and it fits on the wire once encrypted
+    let memo = memo::encrypt(&memo::memo_pubkey(&bob_sk), b"invoice #1", &mut rng).unwrap();
+    assert_eq!(memo::decrypt(&bob_sk, &memo).unwrap(), b"invoice #1".to_vec());
+    assert!(memo::decrypt(&eve_sk, &memo).is_err());
+    assert!(!memo.windows(10).any(|w| w == b"invoice #1"));
+
+    let longest = vec![0xffu8; memo::MAX_MEMO_PLAINTEXT_BYTES];
+    let encrypted = memo::encrypt(&memo::memo_pubkey(&bob_sk), &longest, &mut rng).unwrap();
+    assert!(protocol::memo_to_bs58(&encrypted).is_ok());
+
+    // a memo that was tampered with is refused, as is one too long to send
+    let mut tampered = memo.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(memo::decrypt(&bob_sk, &tampered).is_err());
+    let too_long = vec![0xffu8; memo::MAX_MEMO_PLAINTEXT_BYTES + 1];
+    assert!(memo::encrypt(&memo::memo_pubkey(&bob_sk), &too_long, &mut rng).is_err());
+}
+
+#[test]
+fn test_prepared_vk_verification() {
+    let (pk, vk) = onramp_circuit::circuit_setup();
+    let pvk = prepare_verifying_key(&vk);
+
+    let (proof, mut public_inputs) = onramp_circuit::generate_groth_proof(&pk, &test_coin(10));
+
+    // the prepared and plain verification paths must agree, whether the proof is valid or not
+    assert!(Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+    assert!(Groth16::<BW6_761>::verify_with_processed(&pvk, &public_inputs, &proof).unwrap());
+
+    public_inputs[onramp_circuit::GrothPublicInput::AMOUNT as usize] += ConstraintF::one();
+    assert!(!Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+    assert!(!Groth16::<BW6_761>::verify_with_processed(&pvk, &public_inputs, &proof).unwrap());
+}
+
+#[test]
+fn test_unknown_fields_rejected() {
+    // a typo in a submitted field must fail loudly, naming the offending field
+    let err = serde_json::from_str::<protocol::PaymentProofBs58>(
+        r#"{
+            "payment_proof": { "circuit": "payment", "proof": "", "public_inputs": [] },
+            "merkel_update_proof": { "circuit": "merkle_update", "proof": "", "public_inputs": [] }
+        }"#
+    ).unwrap_err();
+    assert!(err.to_string().contains("merkel_update_proof"));
+}
+
+#[test]
+fn test_amount_high_bytes_rejected() {
+    let (_, _, crs) = utils::trusted_setup();
+
+    let mut coin = test_coin(10);
+    assert_eq!(protocol::amount_u64(&coin), 10);
+
+    // dirty the first byte beyond the u64 range of the amount
+    let mut fields = coin.fields.clone();
+    fields[protocol::UtxoField::AMOUNT as usize][protocol::AMOUNT_BYTES] = 1;
+    coin = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    // rejected at construction from the wire...
+    assert!(protocol::record_from_bs58(&crs, &protocol::record_to_bs58(&coin)).is_err());
+
+    // ...and by the circuit
+    let circuit = onramp_circuit::OnRampCircuit { crs, utxo: coin };
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+}
+
+#[test]
+fn test_asset_id() {
+    let (_, _, crs) = utils::trusted_setup();
+
+    // an asset id is its u64, little-endian, in the low bytes of the field
+    let asset_id = protocol::AssetId(0x0102_0304);
+    let field = asset_id.to_field_bytes();
+    assert_eq!(&field[..protocol::ASSET_ID_BYTES], &0x0102_0304u64.to_le_bytes());
+    assert!(field[protocol::ASSET_ID_BYTES..].iter().all(|&b| b == 0));
+    assert_eq!(protocol::AssetId::from_field_bytes(&field), Ok(asset_id));
+    assert_eq!(asset_id.to_field(), utils::bytes_to_field::<ConstraintF, 6>(&field));
+
+    // a short field is zero-padded, so asset 1 has the same id however it is held
+    assert_eq!(protocol::AssetId::from_field_bytes(&[1u8]), Ok(protocol::AssetId(1)));
+
+    // a field with a byte set past the u64, or longer than a field, names no asset
+    let mut dirty = field;
+    dirty[protocol::ASSET_ID_BYTES] = 1;
+    assert_eq!(protocol::AssetId::from_field_bytes(&dirty).unwrap_err(), "asset id does not fit in 8 bytes");
+    assert!(protocol::AssetId::from_field_bytes(&[0u8; 32]).is_err());
+
+    // nor does a coin of one, which is refused from the wire...
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::ASSETID as usize] = dirty.to_vec();
+    let coin = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+    assert!(protocol::AssetId::of(&coin).is_err());
+    assert!(protocol::record_from_bs58(&crs, &protocol::record_to_bs58(&coin)).is_err());
+
+    // ...and when its onramp states it, though the onramp circuit itself is satisfied
+    let circuit = onramp_circuit::OnRampCircuit { crs, utxo: coin };
+    let public_inputs = onramp_circuit::public_inputs(&circuit);
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(cs.is_satisfied().unwrap());
+
+    let tx = |public_inputs: &[ConstraintF]| protocol::GrothProofBs58 {
+        circuit: protocol::CircuitKind::Onramp,
+        proof: String::new(),
+        public_inputs: public_inputs.iter().map(protocol::encode_constraintf_as_bs58_str).collect(),
+    };
+    assert_eq!(protocol::asset_id_of(&tx(&public_inputs)).unwrap_err(), "ASSET_ID does not fit in a u64");
+
+    let mut clean_inputs = public_inputs.clone();
+    clean_inputs[protocol::OnrampGrothPublicInput::ASSET_ID as usize] = asset_id.to_field();
+    assert_eq!(protocol::asset_id_of(&tx(&clean_inputs)), Ok(asset_id));
+}
+
+#[test]
+fn test_coin_models() {
+    use coin_models::{convert_coin_5_to_8, convert_coin_8_to_5, Coin8Field, OWNED_APP_ID};
+    type F = ark_bls12_377::Fr;
+
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::ENTROPY as usize] = vec![7u8; 31];
+    fields[protocol::UtxoField::ASSETID as usize] = protocol::AssetId(3).to_field_bytes().to_vec();
+
+    // each field lands where the mapping puts it, and the coin is bound to no app
+    let coin = convert_coin_5_to_8(&fields).unwrap();
+    for (from, to) in [
+        (protocol::UtxoField::ENTROPY, Coin8Field::ENTROPY),
+        (protocol::UtxoField::OWNER, Coin8Field::OWNER),
+        (protocol::UtxoField::ASSETID, Coin8Field::ASSET_ID),
+        (protocol::UtxoField::AMOUNT, Coin8Field::AMOUNT),
+        (protocol::UtxoField::RHO, Coin8Field::RHO),
+    ] {
+        assert_eq!(coin[to as usize], F::from_le_bytes_mod_order(&fields[from as usize]));
+    }
+    assert_eq!(coin[Coin8Field::APP_INPUT_0 as usize], F::from(0u64));
+    assert_eq!(coin[Coin8Field::APP_INPUT_1 as usize], F::from(0u64));
+    assert_eq!(coin[Coin8Field::APP_ID as usize], F::from(OWNED_APP_ID));
+
+    // so it converts back to the very same fields
+    assert_eq!(convert_coin_8_to_5(&coin), Ok(fields.clone()));
+
+    // but a coin bound to an app, or holding app inputs, has no 5-field counterpart
+    let mut app_coin = coin.clone();
+    app_coin[Coin8Field::APP_ID as usize] = F::from(1u64);
+    assert_eq!(
+        convert_coin_8_to_5(&app_coin).unwrap_err(),
+        "the coin is bound to an app, which a 5-field coin cannot express"
+    );
+    let mut app_coin = coin.clone();
+    app_coin[Coin8Field::APP_INPUT_1 as usize] = F::from(1u64);
+    assert_eq!(
+        convert_coin_8_to_5(&app_coin).unwrap_err(),
+        "the coin holds app input 1, which a 5-field coin cannot express"
+    );
+
+    // nor has one whose fields don't fit one of a 5-field coin
+    let mut wide_coin = coin.clone();
+    wide_coin[Coin8Field::OWNER as usize] = -F::from(1u64);
+    assert_eq!(convert_coin_8_to_5(&wide_coin).unwrap_err(), "field 1 does not fit in 31 bytes");
+    let mut wide_coin = coin.clone();
+    wide_coin[Coin8Field::AMOUNT as usize] = F::from(u64::MAX) + F::from(1u64);
+    assert_eq!(convert_coin_8_to_5(&wide_coin).unwrap_err(), "amount does not fit in 8 bytes");
+
+    // and a 5-field coin naming no asset is not converted either
+    let mut dirty = fields.clone();
+    dirty[protocol::UtxoField::ASSETID as usize][protocol::ASSET_ID_BYTES] = 1;
+    assert_eq!(convert_coin_5_to_8(&dirty).unwrap_err(), "asset id does not fit in 8 bytes");
+}
+
+#[test]
+fn test_proving_randomness() {
+    use rand_chacha::rand_core::SeedableRng;
+
+    let (pk, vk) = onramp_circuit::circuit_setup();
+    let coin = test_coin(10);
+
+    // a seeded rng reproduces the same proof
+    let (proof_a, public_inputs) = onramp_circuit::generate_groth_proof_with_rng(
+        &pk, &coin, &mut rand_chacha::ChaCha8Rng::from_seed([7u8; 32])
+    );
+    let (proof_b, _) = onramp_circuit::generate_groth_proof_with_rng(
+        &pk, &coin, &mut rand_chacha::ChaCha8Rng::from_seed([7u8; 32])
+    );
+    assert_eq!(proof_a, proof_b);
+
+    // the default path draws fresh randomness, yet still verifies
+    let (proof_c, _) = onramp_circuit::generate_groth_proof(&pk, &coin);
+    let (proof_d, _) = onramp_circuit::generate_groth_proof(&pk, &coin);
+    assert_ne!(proof_c, proof_d);
+
+    let pvk = prepare_verifying_key(&vk);
+    for proof in [proof_a, proof_c, proof_d] {
+        assert!(Groth16::<BW6_761>::verify_with_processed(&pvk, &public_inputs, &proof).unwrap());
+    }
+}
+
+#[test]
+fn test_receipt_verification() {
+    let mut db = dummy_db();
+    db.update(0, &test_coin(10).commitment().into_affine());
+    let receipt = receipts::receipt_from_opening_proof(&opening_proof(&db, 0));
+    let root = (receipt.root_x.clone(), receipt.root_y.clone());
+
+    // a receipt verifies against a source that saw its root posted...
+    assert!(receipts::verify(&receipt, &vec![root]).is_ok());
+
+    // ...but not against one where the root was never checkpointed
+    assert!(receipts::verify(&receipt, &Vec::<(String, String)>::new()).is_err());
+}
+
+#[test]
+fn test_ordering_proof() {
+    use std::cmp::Ordering;
+
+    let mut db = dummy_db();
+    let last = (1 << MERKLE_TREE_LEVELS) - 1;
+    for (i, amount) in [(0, 10), (1, 11), (last, 12)] {
+        db.update(i, &test_coin(amount).commitment().into_affine());
+    }
+    let roots = vec![(
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().x),
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().y),
+    )];
+    let key = ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]);
+
+    // adjacent leaves, far apart ones, and the same leaf twice
+    for (first, second, expected) in [(0, 1, Ordering::Less), (last, 0, Ordering::Greater), (1, 1, Ordering::Equal)] {
+        let proof = receipts::ordering_proof(&opening_proof(&db, first), &opening_proof(&db, second), Some(&key)).unwrap();
+        assert_eq!(receipts::verify_ordering(&proof, &roots, Some(&key.verifying_key())), Ok(expected));
+    }
+
+    // swapping the indices misstates the order, and no longer matches the paths
+    let proof = receipts::ordering_proof(&opening_proof(&db, 0), &opening_proof(&db, last), None).unwrap();
+    let forged = protocol::OrderingProofBs58 { first_index: last, second_index: 0, ..proof.clone() };
+    assert!(receipts::verify_ordering(&forged, &roots, None).is_err());
+
+    // nor does a path re-labelled with the forged index open to the root
+    let mut forged = forged;
+    forged.first.opening_proof.path_leaf_index = last;
+    forged.second.opening_proof.path_leaf_index = 0;
+    assert!(receipts::verify_ordering(&forged, &roots, None).is_err());
+
+    // an unsigned proof is refused when a sequencer key is expected
+    assert!(receipts::verify_ordering(&proof, &roots, None).is_ok());
+    assert!(receipts::verify_ordering(&proof, &roots, Some(&key.verifying_key())).is_err());
+
+    // openings against different roots can't be compared
+    let old_opening = opening_proof(&db, 0);
+    db.update(2, &test_coin(13).commitment().into_affine());
+    assert!(receipts::ordering_proof(&old_opening, &opening_proof(&db, 2), None).is_err());
+}
+
+#[test]
+fn test_receipt_outlives_root_window() {
+    use crate::root_archive::RootArchive;
+
+    let mut db = dummy_db();
+    let mut window = MerkleRootHistory::new(protocol::DEFAULT_ROOT_HISTORY_SIZE);
+    let mut archive = RootArchive::new();
+    let root_of = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>| (
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().x),
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().y),
+    );
+
+    // block 0 inserts a coin, and block 1 the coin the receipt is for
+    for (block, amount) in [(0, 10), (1, 11)] {
+        db.update(block, &test_coin(amount).commitment().into_affine());
+        window.insert(&root_of(&db));
+        assert_eq!(archive.append(&root_of(&db)).unwrap(), block);
+    }
+    let receipt = receipts::receipt_from_opening_proof(&opening_proof(&db, 1));
+    assert!(receipts::verify(&receipt, &window).is_ok());
+
+    // 100 blocks later, the root has left the window but not the archive
+    for block in 2..102 {
+        let root = (format!("x{}", block), format!("y{}", block));
+        window.insert(&root);
+        archive.append(&root).unwrap();
+    }
+    assert!(receipts::verify(&receipt, &window).is_err());
+    assert!(receipts::verify(&receipt, &archive).is_ok());
+
+    assert_eq!(archive.get(1).map(|r| (r.root_x, r.root_y)), Some(root_of(&db)));
+    assert!(archive.get(102).is_none());
+    let range = archive.range(100, 10).unwrap();
+    assert_eq!((range.roots.len(), range.roots[0].block, range.next), (2, 100, None));
+    assert_eq!(archive.range(0, 10).unwrap().next, Some(10));
+}
+
+#[test]
+fn test_payment_update_binds_inserted_leaf() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+
+    // a coin owned by sk, and the coin it pays to
+    let owned_coin = |rho: u8| {
+        let mut fields = test_coin(10).fields.clone();
+        fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+        fields[protocol::UtxoField::RHO as usize] = vec![rho; 31];
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    let input_utxo = owned_coin(1);
+    let output_utxo = owned_coin(2);
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let membership_proof = opening_proof(&db, 0);
+    let old_merkle_proof = opening_proof(&db, 1);
+
+    // inserting the payment's output commitment satisfies the combined circuit,
+    // while inserting any other leaf does not, even though that update is valid
+    for (leaf, satisfied) in [(&output_utxo, true), (&input_utxo, false)] {
+        let mut db = dummy_db();
+        db.update(0, &input_utxo.commitment().into_affine());
+        db.update(1, &leaf.commitment().into_affine());
+
+        let circuit = payment_update_circuit::payment_update_circuit(
+            &protocol::StatementParams::default(), &input_utxo, &output_utxo, &membership_proof, &sk, 0, &[0u8; 32],
+            &old_merkle_proof, &opening_proof(&db, 1), 1
+        );
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+
+    // nor may the coin be spent from one tree while the output goes into another
+    let mut other_db = dummy_db();
+    other_db.update(0, &output_utxo.commitment().into_affine());
+    let other_old_merkle_proof = opening_proof(&other_db, 1);
+    other_db.update(1, &output_utxo.commitment().into_affine());
+    let circuit = payment_update_circuit::payment_update_circuit(
+        &protocol::StatementParams::default(), &input_utxo, &output_utxo, &membership_proof, &sk, 0, &[0u8; 32],
+        &other_old_merkle_proof, &opening_proof(&other_db, 1), 1
+    );
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+
+    // the combined statement is the two statements, laid out as the registry says
+    let statement = protocol::StatementParams::default();
+    assert_eq!(
+        registry::descriptor(protocol::CircuitKind::PaymentUpdate).unwrap().num_public_inputs(&statement),
+        registry::descriptor(protocol::CircuitKind::Payment).unwrap().num_public_inputs(&statement) +
+            registry::descriptor(protocol::CircuitKind::MerkleUpdate).unwrap().num_public_inputs(&statement)
+    );
+}
+
+#[test]
+fn test_policy_signature() {
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let impostor = ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]);
+
+    let policy = policy::Policy {
+        version: 1,
+        blocked_commitments: vec!["blocked".to_string()],
+        blocked_nullifiers: vec![],
+    };
+    assert!(policy.is_blocked_commitment(&"blocked".to_string()));
+    assert!(!policy.is_blocked_commitment(&"unblocked".to_string()));
+
+    let signed = policy.sign(&operator);
+    assert_eq!(signed.verify(&operator.verifying_key()).unwrap(), policy);
+
+    // signed by the wrong key, or altered after signing
+    assert!(policy.sign(&impostor).verify(&operator.verifying_key()).is_err());
+    let mut tampered = signed.clone();
+    tampered.policy.blocked_commitments.clear();
+    assert!(tampered.verify(&operator.verifying_key()).is_err());
+}
+
+#[test]
+fn test_key_reload_persisted() {
+    let path = std::env::temp_dir()
+        .join(format!("sanctum-key-reload-{}.json", std::process::id()))
+        .to_str().unwrap().to_string();
+
+    // without a file, or before any reload was applied, a service starts at version 0
+    assert_eq!(KeyReload::load_applied(None).unwrap(), KeyReload::default());
+    assert_eq!(KeyReload::load_applied(Some(&path)).unwrap(), KeyReload::default());
+
+    // a restart picks up the last reload applied, key dir and all
+    let reload = KeyReload { version: 3, key_dir: Some("/tmp/sanctum-upgraded".to_string()) };
+    reload.persist_applied(&path).unwrap();
+    assert_eq!(KeyReload::load_applied(Some(&path)).unwrap(), reload);
+    assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+    // a file that isn't a reload is refused, rather than starting over at 0
+    std::fs::write(&path, b"not a reload").unwrap();
+    assert!(KeyReload::load_applied(Some(&path)).is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+// a trivial app circuit, whose statement is just the commitment it inserts
+struct InsertCommitmentCircuit {
+    commitment: ark_bls12_377::G1Affine,
+}
+
+impl ConstraintSynthesizer<ConstraintF> for InsertCommitmentCircuit {
+    fn generate_constraints(
+        self,
+        cs: ark_relations::r1cs::ConstraintSystemRef<ConstraintF>,
+    ) -> ark_relations::r1cs::Result<()> {
+        use ark_r1cs_std::prelude::*;
+        use ark_r1cs_std::fields::fp::FpVar;
+
+        let x = FpVar::new_input(cs.clone(), || Ok(self.commitment.x))?;
+        let y = FpVar::new_input(cs.clone(), || Ok(self.commitment.y))?;
+        let xy = FpVar::new_witness(cs.clone(), || Ok(self.commitment.x * self.commitment.y))?;
+        (x * y).enforce_equal(&xy)
+    }
+}
+
+#[test]
+fn test_app_registry() {
+    use ark_serialize::CanonicalSerialize;
+    use rand_chacha::rand_core::SeedableRng;
+
+    let commitment = test_coin(10).commitment().into_affine();
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([0u8; 32]);
+    let (pk, vk) = Groth16::<BW6_761>::circuit_specific_setup(
+        InsertCommitmentCircuit { commitment }, &mut rng
+    ).unwrap();
+
+    let mut vk_bytes: Vec<u8> = Vec::new();
+    vk.serialize_compressed(&mut vk_bytes).unwrap();
+    let registration = protocol::AppRegistrationBs58 {
+        name: "insert".to_string(),
+        vk: bs58::encode(vk_bytes).into_string(),
+        public_inputs: vec!["commitment_x".to_string(), "commitment_y".to_string()],
+        commitment_x_index: 0,
+        commitment_y_index: 1,
+    };
+
+    // only the operator's signature makes a registration
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let impostor = ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]);
+    let signed = apps::sign_registration(&registration, &operator);
+    assert_eq!(signed.verify(&operator.verifying_key()).unwrap().name, registration.name);
+    assert!(apps::sign_registration(&registration, &impostor).verify(&operator.verifying_key()).is_err());
+    let mut tampered = signed.clone();
+    tampered.registration.commitment_x_index = 1;
+    assert!(tampered.verify(&operator.verifying_key()).is_err());
+
+    let mut registry = apps::AppRegistry::new();
+    registry.register(&registration).unwrap();
+    assert!(registry.register(&registration).is_err());
+
+    // a tx through the app yields the commitment it declares
+    let proof = Groth16::<BW6_761>::prove(&pk, InsertCommitmentCircuit { commitment }, &mut rng).unwrap();
+    let mut tx = protocol::AppTxBs58 {
+        app: "insert".to_string(),
+        proof: protocol::groth_proof_to_bs58(
+            protocol::CircuitKind::App, &proof, &vec![commitment.x, commitment.y]
+        ),
+    };
+    assert_eq!(registry.verify(&tx).unwrap(), commitment);
+
+    tx.app = "unknown".to_string();
+    assert!(registry.verify(&tx).is_err());
+}
+
+#[test]
+fn test_dedup_window() {
+    let digest = |n: u8| dedup::bundle_digest("/onramp", &format!("bundle{}", n));
+    assert_ne!(digest(1), dedup::bundle_digest("/payment", &"bundle1"));
+
+    let mut window = DedupWindow::new(2);
+    window.insert(digest(1), "OK");
+    window.insert(digest(2), "OK");
+    assert_eq!(window.get(&digest(1)), Some("OK"));
+
+    // at capacity, the oldest bundle leaves the window
+    window.insert(digest(3), "OK");
+    assert_eq!(window.len(), 2);
+    assert_eq!(window.get(&digest(1)), None);
+    assert_eq!(window.get(&digest(3)), Some("OK"));
+
+    // and a window of 0 remembers nothing
+    let mut window = DedupWindow::new(0);
+    window.insert(digest(1), "OK");
+    assert!(window.is_empty());
+}
+
+#[test]
+fn test_verification_cache() {
+    let proof = |n: u8| protocol::GrothProofBs58 {
+        circuit: protocol::CircuitKind::Onramp,
+        proof: format!("proof{}", n),
+        public_inputs: vec![],
+    };
+
+    let mut cache = VerificationCache::new(2, std::time::Duration::from_secs(60));
+    assert!(cache.verify(&proof(1), || true));
+    assert!(!cache.verify(&proof(2), || false));
+
+    // cached verdicts are returned without running the check again
+    assert!(cache.verify(&proof(1), || panic!("verified twice")));
+    assert!(!cache.verify(&proof(2), || panic!("verified twice")));
+    assert_eq!((cache.hits, cache.misses), (2, 2));
+
+    // at capacity, the least recently used verdict (proof 1) makes room
+    cache.verify(&proof(2), || false);
+    cache.verify(&proof(3), || true);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.verify(&proof(1), || true));
+    assert_eq!(cache.misses, 4);
+
+    // expired verdicts are checked again
+    let mut cache = VerificationCache::new(2, std::time::Duration::ZERO);
+    cache.verify(&proof(1), || true);
+    cache.verify(&proof(1), || true);
+    assert_eq!((cache.hits, cache.misses), (0, 2));
+}
+
+#[test]
+fn test_ticket_store() {
+    let ticket = |leaf_index: usize, accepted_at: u64|
+        Ticket { leaf_index, accepted_at, history: protocol::TxHistory::received(accepted_at) };
+
+    let mut store = TicketStore::new(10);
+    store.insert("a", ticket(0, 100), 100);
+    store.insert("b", ticket(1, 105), 105);
+    assert_eq!(store.get("a", 109), Some(&ticket(0, 100)));
+
+    // an expired ticket is not returned, and is compacted away by the next insert
+    assert_eq!(store.get("a", 110), None);
+    assert_eq!(store.len(), 2);
+    store.insert("c", ticket(2, 110), 110);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get("b", 110), Some(&ticket(1, 105)));
+
+    // a ticket's tx moves on in place, and only as its history allows
+    store.advance("b", protocol::TxState::Validated, 111).unwrap();
+    assert_eq!(store.get("b", 111).unwrap().history.state(), &protocol::TxState::Validated);
+    assert!(store.advance("b", protocol::TxState::Settled { leaf_index: Some(1) }, 111).is_err());
+    assert_eq!(store.advance("a", protocol::TxState::Validated, 111), Err("no ticket under a".to_string()));
+}
+
+#[test]
+fn test_tx_state() {
+    use protocol::{TxHistory, TxState::*, TxTransition};
+
+    let rejected = || Rejected { error: "no".to_string() };
+    let settled = || Settled { leaf_index: Some(0) };
+    let states = [Received, Validated, Escrowed, Staged, ForwardedToVerifiers, settled(), rejected()];
+
+    // every legal move, and none other
+    let legal = [
+        (Received, Validated),
+        (Validated, Escrowed),
+        (Validated, Staged),
+        (Escrowed, Staged),
+        (Staged, ForwardedToVerifiers),
+        (ForwardedToVerifiers, settled()),
+        (Received, rejected()),
+        (Validated, rejected()),
+        (Escrowed, rejected()),
+        (Staged, rejected()),
+        (ForwardedToVerifiers, rejected()),
+    ];
+    for from in states.iter() {
+        for to in states.iter() {
+            let expected = legal.contains(&(from.clone(), to.clone()));
+            assert_eq!(from.can_advance_to(to), expected, "{:?} to {:?}", from, to);
+        }
+    }
+    assert!(states.iter().filter(|s| s.is_final()).eq([settled(), rejected()].iter()));
+
+    // a history only grows by legal moves, and a refused move leaves it as it was
+    let mut history = TxHistory::received(1);
+    assert_eq!(
+        history.advance(Staged, 2),
+        Err("a tx cannot move from Received to Staged".to_string())
+    );
+    history.advance(Validated, 2).unwrap();
+    history.advance(rejected(), 3).unwrap();
+    assert!(history.advance(settled(), 4).is_err());
+    assert_eq!(history.state(), &rejected());
+    assert_eq!(history.transitions().len(), 3);
+
+    // it round trips through json, as a list of states tagged with their time
+    let json = serde_json::to_string(&history).unwrap();
+    assert_eq!(
+        json,
+        r#"[{"state":"received","at":1},{"state":"validated","at":2},{"state":"rejected","error":"no","at":3}]"#
+    );
+    assert_eq!(serde_json::from_str::<TxHistory>(&json).unwrap(), history);
+
+    // and one that makes an illegal move, or does not start at Received, is refused
+    let transition = |state, at| TxTransition { state, at };
+    let illegal = vec![transition(Received, 1), transition(rejected(), 2), transition(settled(), 3)];
+    assert!(serde_json::from_str::<TxHistory>(&serde_json::to_string(&illegal).unwrap()).is_err());
+    let headless = vec![transition(Validated, 1)];
+    assert!(serde_json::from_str::<TxHistory>(&serde_json::to_string(&headless).unwrap()).is_err());
+    assert!(serde_json::from_str::<TxHistory>("[]").is_err());
+}
+
+#[test]
+fn test_noncanonical_encodings_match() {
+    let encode = |i: u64| protocol::encode_constraintf_as_bs58_str(&ConstraintF::from(i));
+    // the same element, followed by a byte the deserializer never reads
+    let padded = |s: &String| {
+        let mut bytes = bs58::decode(s).into_vec().unwrap();
+        bytes.push(0);
+        bs58::encode(bytes).into_string()
+    };
+
+    let root = (encode(1), encode(2));
+    let padded_root = (padded(&root.0), padded(&root.1));
+    assert_ne!(padded_root, root);
+    assert_eq!(protocol::normalize_field_bs58(&padded_root.0), root.0);
+
+    // a root is known however it was encoded, on the way in or out
+    let mut history = MerkleRootHistory::new(3);
+    history.insert(&padded_root);
+    assert!(history.is_known_root(&root));
+    assert_eq!(history.get_latest_root(), Some(root.clone()));
+    let mut history = MerkleRootHistory::new(3);
+    history.insert(&root);
+    assert!(history.is_known_root(&padded_root));
+
+    // and so is a spent nullifier
+    let mut store = NullifierStore::new();
+    store.insert(&padded(&encode(3)), 0);
+    assert!(store.contains(&encode(3)));
+    let request = protocol::NullifierStatusRequestBs58 { nullifiers: vec![padded(&encode(3)), encode(4)] };
+    assert_eq!(store.status(&request).unwrap().spent, vec![true, false]);
+
+    // strings that are no field element are left alone
+    assert_eq!(protocol::normalize_field_bs58("x0"), "x0");
+}
+
+#[test]
+fn test_root_history_round_trip() {
+    let root = |i: u8| (format!("x{}", i), format!("y{}", i));
+
+    // more roots than the window holds, so the buffer has wrapped around
+    let mut history = MerkleRootHistory::new(3);
+    for i in 0..5 {
+        history.insert(&root(i));
+    }
+
+    let reloaded: MerkleRootHistory =
+        serde_json::from_str(&serde_json::to_string(&history).unwrap()).unwrap();
+    assert_eq!(reloaded, history);
+
+    // the reloaded window answers exactly as the original did
+    for i in 0..6 {
+        assert_eq!(reloaded.is_known_root(&root(i)), history.is_known_root(&root(i)));
+    }
+    assert!(reloaded.is_known_root(&root(4)));
+    assert!(!reloaded.is_known_root(&root(1)));
+    assert_eq!(reloaded.get_latest_root(), Some(root(4)));
+}
+
+#[test]
+fn test_affine_from_xy() {
+    let (_, _, crs) = utils::trusted_setup();
+    let com = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    assert_eq!(protocol::affine_from_xy(com.x, com.y), Ok(com));
+
+    // an off-curve pair
+    let off_curve = protocol::affine_from_xy(com.x, com.y + ConstraintF::one());
+    assert!(off_curve.unwrap_err().contains("not on the curve"));
+
+    // a point on the curve but outside the prime-order subgroup; G1 has a
+    // large cofactor, so the first point found by increasing x will do
+    let outside = (1u64..)
+        .filter_map(|x| ark_bls12_377::G1Affine::get_point_from_x_unchecked(ConstraintF::from(x), false))
+        .find(|p| !p.is_in_correct_subgroup_assuming_on_curve())
+        .unwrap();
+    let not_in_subgroup = protocol::affine_from_xy(outside.x, outside.y);
+    assert!(not_in_subgroup.unwrap_err().contains("subgroup"));
+}
+
+#[test]
+fn test_asset_tag() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let asset_id = protocol::AssetId;
+
+    // tags link payments of an asset only under a shared blind
+    let tag = asset_tag::asset_tag(&prf_params, &asset_id(1), &[5u8; 32]);
+    assert_eq!(tag, asset_tag::asset_tag(&prf_params, &asset_id(1), &[5u8; 32]));
+    assert_ne!(tag, asset_tag::asset_tag(&prf_params, &asset_id(1), &[6u8; 32]));
+    assert_ne!(tag, asset_tag::asset_tag(&prf_params, &asset_id(2), &[5u8; 32]));
+
+    // an asset's tag is that of its coins' field, which the circuit evaluates
+    assert_eq!(tag, asset_tag::field_tag(&prf_params, &asset_id(1).to_field_bytes(), &[5u8; 32]));
+
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = vec![7u8; 31];
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+
+    // the circuit is satisfied by the honest tag, but not by a tag for another asset
+    let other_tag = asset_tag::asset_tag(&prf_params, &asset_id(2), &[5u8; 32]);
+    for (tampered, satisfied) in [(false, true), (true, false)] {
+        let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 0));
+        let circuit = PaymentCircuit { asset_blind: [5u8; 32], ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) };
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        if tampered {
+            let index = 1 + payment_circuit::GrothPublicInput::ASSET_TAG as usize;
+            cs.borrow_mut().unwrap().instance_assignment[index] = other_tag;
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+
+    // nor by the honest tag plus 2^256, which agrees with it on every byte the PRF outputs
+    {
+        use ark_ff::Field;
+        use ark_r1cs_std::prelude::*;
+        use lib_mpc_zexe::prf::{JZPRFInstance, constraints::JZPRFParamsVar};
+
+        let alias = ConstraintF::from(2u64).pow([256u64]);
+        for (offset, satisfied) in [(ConstraintF::from(0u64), true), (alias, false)] {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let prf_params_var = JZPRFParamsVar::new_constant(cs.clone(), &prf_params).unwrap();
+            let asset_id_vars = UInt8::new_witness_vec(cs.clone(), &asset_id(1).to_field_bytes()).unwrap();
+            let tag_var = ark_bls12_377::constraints::FqVar::new_input(cs.clone(), || Ok(tag + offset)).unwrap();
+
+            asset_tag::generate_constraints(
+                cs.clone(),
+                &prf_params_var,
+                JZPRFInstance::new(&prf_params, &asset_id(1).to_field_bytes(), &[5u8; 32]),
+                &asset_id_vars,
+                &tag_var
+            ).unwrap();
+            assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+        }
+    }
+}
+
+#[test]
+fn test_pool_nullifiers() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let rho = vec![7u8; 31];
+    let (pool_a, pool_b) = (1u64.to_le_bytes(), 2u64.to_le_bytes());
+
+    // the same coin, spent under the same key, has a different nullifier in each pool
+    let single_pool = payment_circuit::nullifier(&prf_params, &rho, &sk, None, 0);
+    let in_pool_a = payment_circuit::nullifier(&prf_params, &rho, &sk, Some(&pool_a), 0);
+    let in_pool_b = payment_circuit::nullifier(&prf_params, &rho, &sk, Some(&pool_b), 0);
+    assert_ne!(in_pool_a, in_pool_b);
+    assert_ne!(in_pool_a, single_pool);
+    assert_ne!(in_pool_b, single_pool);
+
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = rho;
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+
+    // a circuit for pool a declares pool a's nullifier, and is not satisfied by pool b's
+    for (tampered, satisfied) in [(false, true), (true, false)] {
+        let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 0));
+        let circuit = PaymentCircuit { pool_id: Some(pool_a), ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) };
+        let index = payment_circuit::GrothPublicInput::NULLIFIER as usize;
+        assert_eq!(payment_circuit::public_inputs(&circuit)[index], utils::bytes_to_field::<ConstraintF, 6>(&in_pool_a));
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        if tampered {
+            cs.borrow_mut().unwrap().instance_assignment[1 + index] = utils::bytes_to_field::<ConstraintF, 6>(&in_pool_b);
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+}
+
+#[test]
+fn test_pool_keys() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+    let output_utxo = test_coin(10);
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let merkle_proof = opening_proof(&db, 0);
+
+    // a payment proved under pool 1's keys verifies under them alone; the pool
+    // id is a constant of the circuit, so neither pool 2's keys nor those of a
+    // deployment without a pool id take it
+    let pool_1 = protocol::StatementParams { pool_id: Some(1u64.to_le_bytes()), ..Default::default() };
+    let pool_2 = protocol::StatementParams { pool_id: Some(2u64.to_le_bytes()), ..Default::default() };
+    let (pk, vk) = payment_circuit::circuit_setup(&pool_1);
+    let (proof, public_inputs) = payment_circuit::generate_groth_proof(
+        &pk, &pool_1, &input_utxo, &output_utxo, &merkle_proof, &sk, 0
+    );
+    assert!(Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+
+    for other in [pool_2, protocol::StatementParams::default()] {
+        let (_, other_vk) = payment_circuit::circuit_setup(&other);
+        assert!(!Groth16::<BW6_761>::verify(&other_vk, &public_inputs, &proof).unwrap());
+    }
+}
+
+#[test]
+fn test_transfer_export() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+    let output_utxo = test_coin(11);
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let merkle_proof = opening_proof(&db, 0);
+
+    // a transfer from pool 1 to pool 2 states its destination after the
+    // payment's inputs, and a destination that is no u64 does not satisfy the circuit
+    let statement = protocol::StatementParams { pool_id: Some(1u64.to_le_bytes()), ..Default::default() };
+    let index = protocol::TransferGrothPublicInput::DEST_POOL_ID as usize;
+    for (tampered, satisfied) in [(false, true), (true, false)] {
+        let circuit = TransferCircuit {
+            payment: PaymentCircuit { pool_id: statement.pool_id, ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) },
+            dest_pool_id: 2,
+        };
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.borrow().unwrap().instance_assignment[1 + index], ConstraintF::from(2u64));
+
+        if tampered {
+            cs.borrow_mut().unwrap().instance_assignment[1 + index] = ConstraintF::from(u64::MAX) + ConstraintF::one();
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+
+    // pool 1 accepted the transfer, and signs its export to pool 2
+    let (pk, vk) = registry::setup(protocol::CircuitKind::Transfer, &statement);
+    let (proof, public_inputs) = transfer_circuit::generate_groth_proof(
+        &pk, &statement, &input_utxo, &output_utxo, &merkle_proof, &sk, 0, 2
+    );
+    assert!(Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Transfer, &proof, &public_inputs);
+    assert_eq!(protocol::dest_pool_id_of(&tx), Ok(2));
+
+    let key = ed25519_dalek::SigningKey::from_bytes(&[5u8; 32]);
+    let peers = PeerPools::parse(&format!("1={}", bs58::encode(key.verifying_key().to_bytes()).into_string())).unwrap();
+    let export = transfers::sign_export(1, &tx, &key);
+
+    // pool 2 inserts the output coin; no other pool takes it
+    assert_eq!(transfers::verify_export(&export, &peers, 2), Ok(output_utxo.commitment().into_affine()));
+    assert_eq!(transfers::verify_export(&export, &peers, 3).unwrap_err(), "transfer is to pool 2, not to pool 3");
+
+    // an export from a pool that isn't a peer, or altered after signing, is refused
+    let unknown = protocol::TransferExportBs58 { source_pool_id: 4, ..export.clone() };
+    assert_eq!(transfers::verify_export(&unknown, &peers, 2).unwrap_err(), "pool 4 is not a peer");
+    let mut altered = export.clone();
+    altered.transfer_proof.public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize] =
+        protocol::encode_constraintf_as_bs58_str(&test_coin(12).commitment().into_affine().x);
+    altered.transfer_proof.public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize] =
+        protocol::encode_constraintf_as_bs58_str(&test_coin(12).commitment().into_affine().y);
+    assert_eq!(transfers::verify_export(&altered, &peers, 2).unwrap_err(), "invalid transfer signature");
+
+    // the spent nullifier is exported, at no leaf, until the transfer is rolled back
+    let nullifier = tx.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+    let mut store = NullifierStore::new();
+    store.export(&nullifier);
+    assert!(store.contains(&nullifier));
+    let status = store.status(&protocol::NullifierStatusRequestBs58 { nullifiers: vec![nullifier.clone()] }).unwrap();
+    assert_eq!((status.spent, status.leaf_indices), (vec![true], vec![None]));
+    store.truncate_exported(0);
+    assert!(!store.contains(&nullifier));
+
+    assert!(PeerPools::parse("1").is_err());
+    assert!(PeerPools::parse("x=key").is_err());
+}
+
+#[test]
+fn test_balance_circuit() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let params = protocol::StatementParams::default();
+    let owned = |amount: u8| {
+        let mut fields = test_coin(amount).fields.clone();
+        fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    let (note_a, note_b, not_owned) = (owned(10), owned(15), test_coin(50));
+
+    let mut db = dummy_db();
+    for (i, note) in [&note_a, &note_b, &not_owned].iter().enumerate() {
+        db.update(i, &note.commitment().into_affine());
+    }
+    let (opening_a, opening_b, opening_not_owned) = (opening_proof(&db, 0), opening_proof(&db, 1), opening_proof(&db, 2));
+
+    let satisfied = |notes: &[(&JZRecord<5>, &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>)], threshold: u64| {
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        balance_circuit::circuit(&params, notes, &sk, 0, threshold).generate_constraints(cs.clone()).unwrap();
+        cs.is_satisfied().unwrap()
+    };
+
+    // exactly the threshold, or less, across the notes counted
+    assert!(satisfied(&[(&note_a, &opening_a), (&note_b, &opening_b)], 25));
+    assert!(!satisfied(&[(&note_a, &opening_a), (&note_b, &opening_b)], 26));
+    assert!(satisfied(&[(&note_b, &opening_b)], 15));
+
+    // a note counted twice, or one owned by another key, adds nothing
+    assert!(!satisfied(&[(&note_a, &opening_a), (&note_a, &opening_a)], 20));
+    assert!(!satisfied(&[(&note_a, &opening_a), (&not_owned, &opening_not_owned)], 10));
+
+    // nor does a note counted again under its nullifier plus 2^256, which is
+    // distinct from its nullifier, but agrees with it on every byte the PRF outputs
+    {
+        use ark_ff::Field;
+        use ark_r1cs_std::prelude::*;
+
+        let prf_output = payment_circuit::nullifier(&prf_params, &note_a.fields[protocol::UtxoField::RHO as usize], &sk, None, 0);
+        let nullifier = utils::bytes_to_field::<ConstraintF, 6>(&prf_output);
+        let alias = ConstraintF::from(2u64).pow([256u64]);
+        for (offset, satisfied) in [(ConstraintF::from(0u64), true), (alias, false)] {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let output_vars = UInt8::new_witness_vec(cs.clone(), &prf_output).unwrap();
+            let nullifier_var = ark_bls12_377::constraints::FqVar::new_input(cs.clone(), || Ok(nullifier + offset)).unwrap();
+
+            payment_circuit::enforce_nullifier(&nullifier_var, &output_vars, &Boolean::TRUE).unwrap();
+            assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+        }
+    }
+
+    // a circuit short of notes is refused with an error, where it used to panic
+    let mut short = balance_circuit::circuit(&params, &[(&note_a, &opening_a)], &sk, 0, 10);
+    short.notes.pop();
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    assert!(matches!(short.generate_constraints(cs), Err(SynthesisError::Unsatisfiable)));
+
+    // the auditor learns the root and the counted notes' nullifiers, to check
+    // against the pool, and the proof holds for its asset and threshold alone
+    let (pk, vk) = registry::setup(protocol::CircuitKind::Balance, &params);
+    let (proof, public_inputs) = balance_circuit::generate_groth_proof(
+        &pk, &params, &[(&note_a, &opening_a), (&note_b, &opening_b)], &sk, 0, 25
+    );
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Balance, &proof, &public_inputs);
+    let statement = balance_circuit::verify_balance_proof(&vk, &tx, &protocol::AssetId(0), 25).unwrap();
+    assert_eq!(statement.root_x, protocol::encode_constraintf_as_bs58_str(&db.commitment().x));
+    assert_eq!(statement.tree_id, 0);
+    let nullifier = |note: &JZRecord<5>| protocol::encode_constraintf_as_bs58_str(
+        &utils::bytes_to_field::<ConstraintF, 6>(&payment_circuit::nullifier(
+            &prf_params, &note.fields[protocol::UtxoField::RHO as usize], &sk, params.pool_id.as_ref(), 0
+        ))
+    );
+    assert_eq!(statement.nullifiers, vec![nullifier(&note_a), nullifier(&note_b)]);
+
+    assert!(balance_circuit::verify_balance_proof(&vk, &tx, &protocol::AssetId(0), 24).is_err());
+    assert!(balance_circuit::verify_balance_proof(&vk, &tx, &protocol::AssetId(1), 25).is_err());
+}
+
+#[test]
+fn test_tree_nullifiers() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let rho = vec![7u8; 31];
+
+    // the same coin, spent under the same key, has a different nullifier in each tree
+    let in_tree_0 = payment_circuit::nullifier(&prf_params, &rho, &sk, None, 0);
+    let in_tree_1 = payment_circuit::nullifier(&prf_params, &rho, &sk, None, 1);
+    assert_ne!(in_tree_0, in_tree_1);
+
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = rho;
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+
+    // a spend from tree 1 declares tree 1 and its nullifier; claiming another
+    // tree under the same nullifier does not satisfy the circuit
+    for (tampered, satisfied) in [(false, true), (true, false)] {
+        let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 0));
+        let circuit = PaymentCircuit { tree_id: 1, ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) };
+        let public_inputs = payment_circuit::public_inputs(&circuit);
+        assert_eq!(public_inputs[payment_circuit::GrothPublicInput::NULLIFIER as usize],
+            utils::bytes_to_field::<ConstraintF, 6>(&in_tree_1));
+        assert_eq!(public_inputs[payment_circuit::GrothPublicInput::TREE_ID as usize], ConstraintF::from(1u32));
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        if tampered {
+            let index = 1 + payment_circuit::GrothPublicInput::TREE_ID as usize;
+            cs.borrow_mut().unwrap().instance_assignment[index] = ConstraintF::from(2u32);
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+}
+
+#[test]
+fn test_swap_binds_agreed_amounts() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let terms = swap_circuit::SwapTerms { amounts: [10, 7], nonce: [9u8; 32] };
+
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let merkle_proof = opening_proof(&db, 0);
+
+    // a half paying 10 proves as the proposal of terms agreeing on 10 from the
+    // proposal, but neither as their acceptance, nor under terms agreeing on less
+    let cheaper = swap_circuit::SwapTerms { amounts: [9, 7], ..terms };
+    for (terms, role, satisfied) in [
+        (terms, protocol::SwapRole::Proposal, true),
+        (terms, protocol::SwapRole::Acceptance, false),
+        (cheaper, protocol::SwapRole::Proposal, false),
+    ] {
+        // the output is locked to the swap of these terms either way
+        fields[protocol::UtxoField::ENTROPY as usize] = terms.swap_id(&prf_params).to_vec();
+        let output_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+        let circuit = swap_circuit::SwapCircuit {
+            payment: payment(&input_utxo, &output_utxo, &merkle_proof, sk),
+            terms,
+            role,
+        };
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied, "{:?}", role);
+    }
+}
+
+#[test]
+fn test_circuit_registry() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+    let output_utxo = test_coin(20);
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let membership_proof = opening_proof(&db, 0);
+    let old_merkle_proof = opening_proof(&db, 1);
+    db.update(1, &output_utxo.commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db, 1);
+
+    // each descriptor's layout agrees with its keys, and with the inputs its circuit proves
+    let statement = protocol::StatementParams::default();
+    for descriptor in registry::CIRCUITS.iter() {
+        let (pk, vk) = (descriptor.setup)(&statement);
+        assert_eq!(descriptor.input_labels(&statement).len(), descriptor.num_public_inputs(&statement), "{}", descriptor.id);
+        assert_eq!(vk.gamma_abc_g1.len(), descriptor.num_public_inputs(&statement) + 1, "{}", descriptor.id);
+
+        let (_, public_inputs) = match descriptor.kind {
+            protocol::CircuitKind::Onramp =>
+                onramp_circuit::generate_groth_proof(&pk, &output_utxo),
+            protocol::CircuitKind::OnrampMulti =>
+                onramp_multi_circuit::generate_groth_proof(&pk, &[output_utxo.clone()]),
+            protocol::CircuitKind::Payment =>
+                payment_circuit::generate_groth_proof(
+                    &pk, &statement, &input_utxo, &output_utxo, &membership_proof, &sk, 0
+                ),
+            protocol::CircuitKind::MerkleUpdate =>
+                merkle_update_circuit::generate_groth_proof(
+                    &pk, &old_merkle_proof, &new_merkle_proof, 1
+                ),
+            protocol::CircuitKind::PaymentUpdate =>
+                payment_update_circuit::generate_groth_proof(
+                    &pk, &statement, &input_utxo, &output_utxo, &membership_proof, &sk, 0,
+                    &old_merkle_proof, &new_merkle_proof, 1
+                ),
+            protocol::CircuitKind::Swap =>
+                swap_circuit::generate_groth_proof(
+                    &pk, &statement, &input_utxo, &output_utxo, &membership_proof, &sk, 0,
+                    &swap_circuit::SwapTerms { amounts: [20, 0], nonce: [0u8; 32] },
+                    protocol::SwapRole::Proposal, &[0u8; 32]
+                ),
+            protocol::CircuitKind::Transfer =>
+                transfer_circuit::generate_groth_proof(
+                    &pk, &statement, &input_utxo, &output_utxo, &membership_proof, &sk, 0, 1
+                ),
+            protocol::CircuitKind::Balance =>
+                balance_circuit::generate_groth_proof(
+                    &pk, &statement, &[(&input_utxo, &membership_proof)], &sk, 0, 10
+                ),
+            protocol::CircuitKind::App => unreachable!("app circuits are not in the registry"),
+        };
+        assert_eq!(public_inputs.len(), descriptor.num_public_inputs(&statement), "{}", descriptor.id);
+        assert!(std::ptr::eq(registry::descriptor(descriptor.kind).unwrap(), descriptor));
+    }
+    assert!(registry::descriptor(protocol::CircuitKind::App).is_none());
+}
+
+#[test]
+fn test_onramp_multi_denominations() {
+    use onramp_multi_circuit::{decompose, DENOMINATIONS, NUM_NOTES};
+
+    // exact decompositions, down to a single note
+    assert_eq!(decompose(35, &DENOMINATIONS, NUM_NOTES).unwrap(), vec![10, 10, 10, 5]);
+    assert_eq!(decompose(10, &DENOMINATIONS, NUM_NOTES).unwrap(), vec![10]);
+
+    // a remainder no denomination represents, nothing at all, or too many notes
+    assert!(decompose(37, &DENOMINATIONS, NUM_NOTES).is_err());
+    assert!(decompose(0, &DENOMINATIONS, NUM_NOTES).is_err());
+    assert!(decompose(50, &DENOMINATIONS, NUM_NOTES).is_err());
+
+    let (_, _, crs) = utils::trusted_setup();
+    let note = |amount: u64| {
+        let mut fields = test_coin(0).fields.clone();
+        fields[protocol::UtxoField::AMOUNT as usize][..protocol::AMOUNT_BYTES]
+            .copy_from_slice(&amount.to_le_bytes());
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    let circuit = |amounts: &[u64]| {
+        let notes: Vec<JZRecord<5>> = amounts.iter().map(|a| note(*a)).collect();
+        let mut padded = notes.clone();
+        padded.resize_with(NUM_NOTES, || onramp_multi_circuit::padding_note(
+            &crs, &notes[0].fields[protocol::UtxoField::ASSETID as usize]
+        ));
+        onramp_multi_circuit::OnRampMultiCircuit { crs: crs.clone(), notes: padded, num_notes: notes.len() }
+    };
+
+    // the planned notes satisfy the circuit, including the single-note case,
+    // while a note outside the denominations does not
+    for (amounts, satisfied) in [(vec![10, 10, 10, 5], true), (vec![10], true), (vec![7], false)] {
+        let circuit = circuit(&amounts);
+        let public_inputs = onramp_multi_circuit::public_inputs(&circuit);
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+
+        if satisfied {
+            assert_eq!(onramp_multi_circuit::minted_commitments(&public_inputs).unwrap().len(), amounts.len());
+
+            // claiming more minted notes than the proof carries does not satisfy it
+            let index = 1 + onramp_multi_circuit::GrothPublicInput::NUM_NOTES as usize;
+            cs.borrow_mut().unwrap().instance_assignment[index] += ConstraintF::one();
+            assert!(!cs.is_satisfied().unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_payment_binds_full_leaf() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = vec![7u8; 31];
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    // the negated commitment shares the coin's x-coordinate, and differs only
+    // in the y bytes, which follow x in the serialized leaf
+    let mut db = dummy_db();
+    db.update(0, &(-input_utxo.commitment().into_affine()));
+
+    let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 0));
+    let circuit = payment(&input_utxo, &output_utxo, &merkle_proof, sk);
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+}
+
+#[test]
+fn test_payment_binds_nullifier() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let rho = vec![7u8; 31];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = rho.clone();
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+
+    // the nullifier the circuit must declare is PRF(rho; sk); any other value,
+    // including the nullifier of another coin or of another key, is refused
+    let index = payment_circuit::GrothPublicInput::NULLIFIER as usize;
+    let nullifier = utils::bytes_to_field::<ConstraintF, 6>(
+        &payment_circuit::nullifier(&prf_params, &rho, &sk, None, 0)
+    );
+    let forged = [
+        None,
+        Some(nullifier + ConstraintF::one()),
+        Some(utils::bytes_to_field::<ConstraintF, 6>(&payment_circuit::nullifier(&prf_params, &[8u8; 31], &sk, None, 0))),
+        Some(utils::bytes_to_field::<ConstraintF, 6>(&payment_circuit::nullifier(&prf_params, &rho, &[4u8; 32], None, 0))),
+    ];
+
+    for declared in forged {
+        let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 0));
+        let circuit = payment(&input_utxo, &output_utxo, &merkle_proof, sk);
+        assert_eq!(payment_circuit::public_inputs(&circuit)[index], nullifier);
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        if let Some(declared) = declared {
+            assert_ne!(declared, nullifier);
+            cs.borrow_mut().unwrap().instance_assignment[1 + index] = declared;
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), declared.is_none());
+    }
+
+    // nor is the nullifier plus 2^256, which agrees with it on every byte the
+    // PRF outputs; its bit witnesses are fixed at synthesis, so it is declared
+    // against the gadget binding the nullifier rather than a synthesized circuit
+    {
+        use ark_ff::Field;
+        use ark_r1cs_std::prelude::*;
+
+        let prf_output = payment_circuit::nullifier(&prf_params, &rho, &sk, None, 0);
+        let alias = ConstraintF::from(2u64).pow([256u64]);
+        for (offset, satisfied) in [(ConstraintF::from(0u64), true), (alias, false)] {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let output_vars = UInt8::new_witness_vec(cs.clone(), &prf_output).unwrap();
+            let nullifier_var = ark_bls12_377::constraints::FqVar::new_input(cs.clone(), || Ok(nullifier + offset)).unwrap();
+
+            payment_circuit::enforce_nullifier(&nullifier_var, &output_vars, &Boolean::TRUE).unwrap();
+            assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+        }
+    }
+}
+
+#[test]
+fn test_payment_exposes_leaf_index() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = vec![7u8; 31];
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(3, &input_utxo.commitment().into_affine());
+    let (output_utxo, merkle_proof) = (test_coin(10), opening_proof(&db, 3));
+
+    // without the flag, the statement is as it always was
+    let index = payment_circuit::GrothPublicInput::LEAF_INDEX as usize;
+    for expose_leaf_index in [false, true] {
+        let circuit = PaymentCircuit { expose_leaf_index, ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) };
+        let public_inputs = payment_circuit::public_inputs(&circuit);
+        assert_eq!(public_inputs.len(), if expose_leaf_index { index + 1 } else { index });
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        assert_eq!(cs.num_instance_variables(), 1 + public_inputs.len());
+    }
+
+    // the declared index must be the one the path opens, neither another
+    // leaf's nor one that agrees with it only on the path's bits
+    let forged = [
+        None,
+        Some(ConstraintF::from(2u64)),
+        Some(ConstraintF::from(3u64 + (1u64 << MERKLE_TREE_LEVELS))),
+    ];
+    for declared in forged {
+        let circuit = PaymentCircuit { expose_leaf_index: true, ..payment(&input_utxo, &output_utxo, &merkle_proof, sk) };
+        assert_eq!(payment_circuit::public_inputs(&circuit)[index], ConstraintF::from(3u64));
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        if let Some(declared) = declared {
+            cs.borrow_mut().unwrap().instance_assignment[1 + index] = declared;
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), declared.is_none());
+    }
+
+    // keys are set up for one statement or the other, as the params ask, and
+    // the registry describes the statement under the same params
+    let descriptor = registry::descriptor(protocol::CircuitKind::Payment).unwrap();
+    for expose_leaf_index in [false, true] {
+        let statement = protocol::StatementParams { expose_leaf_index, ..Default::default() };
+        let (_, vk) = payment_circuit::circuit_setup(&statement);
+        assert_eq!(vk.gamma_abc_g1.len(), 1 + index + usize::from(expose_leaf_index));
+        assert_eq!(descriptor.num_public_inputs(&statement), vk.gamma_abc_g1.len() - 1);
+        assert_eq!(statement.num_payment_inputs(), vk.gamma_abc_g1.len() - 1);
+        assert_eq!(descriptor.input_labels(&statement).last() == Some(&"leaf_index"), expose_leaf_index);
+    }
+}
+
+#[test]
+fn test_dummy_leaf_unspendable() {
+    let (_, _, crs) = utils::trusted_setup();
+
+    // every unoccupied leaf holds the dummy coin; an empty leaf is opened
+    // against a tree whose other leaves hold real coins
+    let mut db = dummy_db();
+    db.update(0, &test_coin(10).commitment().into_affine());
+    let dummy_utxo = utils::get_dummy_utxo(&crs);
+    assert_eq!(db.get_record(5), &dummy_utxo.commitment().into_affine());
+
+    let merkle_proof = opening_proof(&db, 5);
+    let circuit = payment(&dummy_utxo, &dummy_utxo, &merkle_proof, [0u8; 32]);
+    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+    circuit.generate_constraints(cs.clone()).unwrap();
+    assert!(!cs.is_satisfied().unwrap());
+}
+
+#[test]
+fn test_dummy_opening_proof() {
+    let (prf_params, vc_params, crs) = utils::trusted_setup();
+
+    // the opening built from the dummy subtrees' roots is the one a full tree gives
+    let fast = utils::dummy_opening_proof(&vc_params, MERKLE_TREE_LEVELS);
+    assert!(receipts::is_valid_opening_proof(&fast).unwrap());
+    let fast = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&fast);
+    let full = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&opening_proof(&dummy_db(), 0));
+    assert_eq!(fast.root, full.root);
+    assert_eq!(fast.record, full.record);
+    assert_eq!(fast.path_leaf_sibling_hash, full.path_leaf_sibling_hash);
+    assert_eq!(fast.path_auth_path, full.path_auth_path);
+    assert_eq!(fast.path_leaf_index, 0);
+
+    // it costs a hash per level, so a depth whose tree could never be built is quick
+    let start = std::time::Instant::now();
+    let deep = utils::dummy_opening_proof(&vc_params, 40);
+    assert_eq!(deep.path.auth_path.len(), 39);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    // keys set up over it verify a payment, and a merkle update, proven against a real tree
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = vec![7u8; 31];
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+    let output_utxo = test_coin(10);
+
+    let mut db = dummy_db();
+    db.update(0, &test_coin(20).commitment().into_affine());
+    db.update(3, &input_utxo.commitment().into_affine());
+    let membership_proof = opening_proof(&db, 3);
+
+    let statement = protocol::StatementParams::default();
+    let (pk, vk) = payment_circuit::circuit_setup(&statement);
+    let (proof, public_inputs) = payment_circuit::generate_groth_proof(
+        &pk, &statement, &input_utxo, &output_utxo, &membership_proof, &sk, 0
+    );
+    assert!(Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+
+    let old_merkle_proof = opening_proof(&db, 4);
+    db.update(4, &output_utxo.commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db, 4);
+    let (pk, vk) = merkle_update_circuit::circuit_setup();
+    let (proof, public_inputs) = merkle_update_circuit::generate_groth_proof(
+        &pk, &old_merkle_proof, &new_merkle_proof, 4
+    );
+    assert!(Groth16::<BW6_761>::verify(&vk, &public_inputs, &proof).unwrap());
+}
+
+#[test]
+fn test_payment_fresh_rho() {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let sk = [3u8; 32];
+    let mut fields = test_coin(10).fields.clone();
+    fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, &sk).to_vec();
+    fields[protocol::UtxoField::RHO as usize] = vec![7u8; 31];
+    let input_utxo = JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec());
+
+    let mut db = dummy_db();
+    db.update(0, &input_utxo.commitment().into_affine());
+    let merkle_proof = opening_proof(&db, 0);
+
+    // the same payment proves with a fresh rho, but not with the input's
+    let output_utxo = |rho: u8| {
+        let mut fields = test_coin(10).fields.clone();
+        fields[protocol::UtxoField::RHO as usize] = vec![rho; 31];
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    for (rho, satisfied) in [(8u8, true), (7u8, false)] {
+        let output_utxo = output_utxo(rho);
+        let circuit = payment(&input_utxo, &output_utxo, &merkle_proof, sk);
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+}
+
+#[test]
+fn test_record_exceeding_crs() {
+    use rand_chacha::rand_core::SeedableRng;
+    use lib_mpc_zexe::record_commitment::kzg::JZKZGCommitmentParams;
+
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([0u8; 32]);
+    let small_crs = JZKZGCommitmentParams::<2>::trusted_setup(&mut rng);
+    let blind = [0u8; 31];
+
+    // a utxo's five fields cannot be committed to under a CRS for two
+    let fields = vec![vec![1u8; protocol::FIELD_BYTES]; 5];
+    assert_eq!(
+        protocol::new_record(&small_crs, &fields, &blind).err().as_deref(),
+        Some("a record of 5 fields exceeds the CRS, which supports 2")
+    );
+
+    // nor can a field past the size of a field element
+    let oversized = vec![vec![1u8; protocol::FIELD_BYTES + 1]];
+    assert!(protocol::new_record(&small_crs, &oversized, &blind).is_err());
+
+    // fewer fields are padded with zeros, as if they had been given
+    let record = protocol::new_record(&small_crs, &fields[..1], &blind).unwrap();
+    let padded = protocol::new_record(&small_crs, &[fields[0].clone(), vec![0u8; protocol::FIELD_BYTES]], &blind).unwrap();
+    assert_eq!(record.commitment(), padded.commitment());
+}
+
+#[test]
+fn test_asset_amounts() {
+    use crate::assets::{AssetRegistry, parse_amount, format_amount, MAX_DECIMALS};
+
+    // digits past the asset's precision are refused, never rounded
+    assert_eq!(parse_amount("1.5", 6), Ok(1_500_000));
+    assert_eq!(parse_amount("0.000001", 6), Ok(1));
+    assert_eq!(parse_amount("007", 2), Ok(700));
+    assert!(parse_amount("0.0000001", 6).is_err());
+    assert!(parse_amount("1.50", 1).is_err());
+    assert_eq!(parse_amount("12", 0), Ok(12));
+    assert!(parse_amount("1.0", 0).is_err());
+    for malformed in ["", ".5", "1.", "-1", "+1", "1e3", "1,5", " 1", "1.2.3"] {
+        assert!(parse_amount(malformed, 6).is_err(), "{:?}", malformed);
+    }
+
+    // amounts must fit in the u64 the circuits enforce
+    assert_eq!(parse_amount("18446744073709551615", 0), Ok(u64::MAX));
+    assert!(parse_amount("18446744073709551616", 0).is_err());
+    assert_eq!(parse_amount("18446744073709.551615", 6), Ok(u64::MAX));
+    assert!(parse_amount("18446744073709.551616", 6).is_err());
+    assert!(parse_amount("18446744073710", 6).is_err());
+
+    for (amount, decimals, formatted) in [
+        (1_500_000, 6, "1.5"), (1, 6, "0.000001"), (2_000_000, 6, "2"), (0, 6, "0"), (u64::MAX, MAX_DECIMALS, "1.8446744073709551615"),
+    ] {
+        assert_eq!(format_amount(amount, decimals), formatted);
+        assert_eq!(parse_amount(formatted, decimals), Ok(amount));
+    }
+
+    let asset_id = bs58::encode(protocol::AssetId(7).to_field_bytes()).into_string();
+    let registry = AssetRegistry::from_json(&format!(
+        r#"[{{"symbol": "USDC", "asset_id": "{}", "decimals": 6}}]"#, asset_id
+    )).unwrap();
+    assert_eq!(registry.parse("1.5", "USDC"), Ok((protocol::AssetId(7), 1_500_000)));
+    assert!(registry.parse("1.5", "EURC").is_err());
+    assert_eq!(registry.format(1_500_000, &protocol::AssetId(7)), "1.5 USDC");
+    assert_eq!(registry.format(15, &protocol::AssetId(1)), "15 of asset 1");
+
+    // a registry with more decimals than a u64 holds, a short asset id, or one
+    // with a byte set past the u64, is refused
+    assert!(AssetRegistry::from_json(&format!(
+        r#"[{{"symbol": "USDC", "asset_id": "{}", "decimals": 20}}]"#, asset_id
+    )).is_err());
+    assert!(AssetRegistry::from_json(r#"[{"symbol": "USDC", "asset_id": "2", "decimals": 6}]"#).is_err());
+    assert_eq!(AssetRegistry::from_json(&format!(
+        r#"[{{"symbol": "USDC", "asset_id": "{}", "decimals": 6}}]"#, bs58::encode([7u8; 31]).into_string()
+    )).unwrap_err(), "asset id does not fit in 8 bytes");
+}
+
+#[test]
+fn test_nullifier_audit_log() {
+    use std::io::Write;
+    use crate::audit::{NullifierAuditLog, MAX_AUDIT_PAGE};
+
+    let path = std::env::temp_dir().join(format!("sanctum-audit-{}.jsonl", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    // every accepted payment appends its nullifier, root and time
+    let root = ("rx".to_string(), "ry".to_string());
+    let mut log = NullifierAuditLog::open(path).unwrap();
+    for (i, nullifier) in ["n0", "n1", "n2"].iter().enumerate() {
+        log.append(nullifier, &root, 1000 + i as u64).unwrap();
+    }
+    assert_eq!(log.len(), 3);
+
+    // the log outlives a restart, minus a line torn by a crash
+    drop(log);
+    std::fs::OpenOptions::new().append(true).open(path).unwrap().write_all(b"{\"nullif").unwrap();
+    let mut log = NullifierAuditLog::open(path).unwrap();
+    log.append("n3", &root, 1003).unwrap();
+
+    let page = log.page(0, 2).unwrap();
+    assert_eq!(page.entries.iter().map(|e| e.nullifier.as_str()).collect::<Vec<_>>(), vec!["n0", "n1"]);
+    assert_eq!(page.entries[1].timestamp, 1001);
+    assert_eq!((page.entries[1].root_x.as_str(), page.next), ("rx", Some(2)));
+    let page = log.page(2, 2).unwrap();
+    assert_eq!(page.entries.iter().map(|e| e.nullifier.as_str()).collect::<Vec<_>>(), vec!["n2", "n3"]);
+    assert_eq!(page.next, None);
+    assert!(log.page(10, 2).unwrap().entries.is_empty());
+    assert!(log.page(0, MAX_AUDIT_PAGE + 1).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_log_config_args() {
+    use crate::logging::{self, LogConfig};
+
+    let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+
+    // the logging flags are taken out, wherever they sit among the service's own
+    let mut argv = args("sequencer --log-level debug --replay tx.log --log-format json");
+    let config = LogConfig::from_args(&mut argv).unwrap();
+    assert_eq!(config, LogConfig { filter: "debug".to_string(), json: true });
+    assert_eq!(argv, args("sequencer --replay tx.log"));
+
+    let mut argv = args("verifier --log-format text");
+    let config = LogConfig::from_args(&mut argv).unwrap();
+    assert_eq!(config, LogConfig::from_env(logging::DEFAULT_LEVEL));
+    assert_eq!(argv, args("verifier"));
+
+    assert!(LogConfig::from_args(&mut args("prover --log-format xml")).is_err());
+    assert!(LogConfig::from_args(&mut args("prover --log-level")).is_err());
+    assert!(logging::init(&LogConfig { filter: "lib_sanctum=loud".to_string(), json: false }).is_err());
+}
+
+#[test]
+fn test_config_precedence() {
+    use std::collections::HashMap;
+    use crate::config::{self, List, SequencerConfig};
+
+    let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+    let path = std::env::temp_dir().join(format!("sanctum-config-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    std::fs::write(&path, r#"
+        root_history_size = 10
+        pool_id = 1
+        verifier_urls = ["http://127.0.0.1:8081", "http://127.0.0.1:9081"]
+        log_format = "json"
+    "#).unwrap();
+
+    // the file is overridden by env, which is overridden by the flags
+    let env: HashMap<&str, &str> = [
+        (config::CONFIG_ENV, "/nonexistent/sanctum.toml"),
+        ("SANCTUM_ROOT_HISTORY_SIZE", "20"),
+        ("SANCTUM_POOL_ID", "2"),
+    ].into_iter().collect();
+    let lookup = |var: &str| env.get(var).map(|v| v.to_string());
+
+    let mut argv = args(&format!("sequencer --replay tx.log --pool-id 3 --config {}", path));
+    let config = config::load::<SequencerConfig>(&mut argv, lookup).unwrap();
+    assert_eq!(config.root_history_size, Some(20));
+    assert_eq!(config.pool_id, Some(3));
+    assert_eq!(config.verifier_urls, Some(List(vec!["http://127.0.0.1:8081".to_string(), "http://127.0.0.1:9081".to_string()])));
+    assert_eq!(config.verifier_quorum, None);
+    assert!(config::log_config(&config).json);
+
+    // the service's own arguments are left where it expects them
+    assert_eq!(argv, args("sequencer --replay tx.log"));
+
+    // a list set in env replaces the file's, in the form the service reads
+    let urls_env = |var: &str| (var == "SANCTUM_VERIFIER_URLS").then(|| "http://a:1, http://b:2/".to_string());
+    let mut argv = args(&format!("sequencer --config {}", path));
+    let config = config::load::<SequencerConfig>(&mut argv, urls_env).unwrap();
+    assert_eq!(config.verifier_urls, Some(List(vec!["http://a:1".to_string(), "http://b:2/".to_string()])));
+
+    // the config file named in env is read when no flag names one
+    assert!(config::load::<SequencerConfig>(&mut args("sequencer"), lookup).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_config_reports_every_error() {
+    use crate::config::{self, ProverConfig, SequencerConfig, ServiceConfig, VerifierConfig};
+
+    let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+    let path = std::env::temp_dir().join(format!("sanctum-config-errors-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    std::fs::write(&path, r#"
+        root_history_size = 0
+        verifier_urls = ["ftp://127.0.0.1:8081"]
+        policy_file = "/nonexistent/policy.json"
+        log_format = "xml"
+    "#).unwrap();
+
+    let env = |var: &str| (var == "SANCTUM_PROOF_BODY_LIMIT").then(|| "lots".to_string());
+    let mut argv = args(&format!("sequencer --config {} --verifier-quorum 2 --ticket-ttl-secs", path));
+    let errors = config::load::<SequencerConfig>(&mut argv, env).unwrap_err();
+
+    for expected in [
+        "--ticket-ttl-secs needs a value",
+        "SANCTUM_PROOF_BODY_LIMIT: lots is not a usize",
+        "root_history_size: must not be zero",
+        "verifier_urls: ftp://127.0.0.1:8081 is not an http(s) url",
+        "policy_file: /nonexistent/policy.json is not a file",
+        "policy_file: a policy is only accepted alongside the operator_key that signed it",
+        "verifier_quorum: a quorum of 2 is not between 1 and the 1 verifiers",
+        "log_format: xml is neither text nor json",
+    ] {
+        assert!(errors.iter().any(|e| e == expected), "{} not in {:?}", expected, errors);
+    }
+    assert_eq!(errors.len(), 8);
+
+    // a file naming a setting the service doesn't have is refused
+    std::fs::write(&path, "tx_log = \"tx.log\"\n").unwrap();
+    assert!(config::load::<VerifierConfig>(&mut args(&format!("verifier --config {}", path)), |_| None).is_err());
+    assert!(config::load::<SequencerConfig>(&mut args(&format!("sequencer --config {}", path)), |_| None).is_ok());
+    let _ = std::fs::remove_file(&path);
+
+    // the sample config sets every setting once uncommented, with values of the right types
+    fn uncommented<C: ServiceConfig>() -> C {
+        let toml: String = config::sample::<C>()
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| C::settings().iter().any(|s| line.starts_with(&format!("{} = ", s.key))))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        config::parse::<C>(&toml).unwrap()
+    }
+    let sequencer = uncommented::<SequencerConfig>();
+    assert!(SequencerConfig::settings().iter().all(|s| sequencer.get(s.key).is_some()));
+    let verifier = uncommented::<VerifierConfig>();
+    assert!(VerifierConfig::settings().iter().all(|s| verifier.get(s.key).is_some()));
+    let prover = uncommented::<ProverConfig>();
+    assert!(ProverConfig::settings().iter().all(|s| prover.get(s.key).is_some()));
+}
+
+// commitments of fixed records, pinned to the values in testdata; every coin
+// in a pool is committed this way, so any change to these values (from a
+// dependency bump, or a reordering of the fields) invalidates existing pools
+// and must be deliberate. The test never writes the file: a missing or stale
+// file fails it, printing the current values to review and commit by hand
+#[test]
+fn test_record_commitments_are_pinned() {
+    let (_, _, crs) = utils::trusted_setup();
+    let mut amount = vec![0u8; 31];
+    amount[..protocol::AMOUNT_BYTES].copy_from_slice(&1_000_000u64.to_le_bytes());
+
+    let fields: [Vec<u8>; 5] = [vec![1u8; 31], vec![2u8; 31], vec![3u8; 31], amount, vec![4u8; 31]];
+    let records = [
+        ("fixed", JZRecord::<5>::new(&crs, &fields, &[5u8; 31].to_vec())),
+        ("dummy", utils::get_dummy_utxo(&crs)),
+    ];
+    let commitments: std::collections::BTreeMap<&str, String> = records
+        .iter()
+        .map(|(name, record)| (*name, protocol::commitment_to_bs58(&record.commitment().into_affine())))
+        .collect();
+
+    let current = serde_json::to_string_pretty(&commitments).unwrap();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/circuits/testdata/record_commitments.json");
+    let golden = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("unable to read {}: {}; the current values are\n{}", path, e, current));
+
+    let golden: std::collections::BTreeMap<String, String> = serde_json::from_str(&golden).unwrap();
+    for (name, commitment) in commitments.iter() {
+        assert_eq!(
+            golden.get(*name), Some(commitment),
+            "the commitment of the {} record changed; the current values are\n{}", name, current
+        );
+    }
+}
+
+// differential tests: for randomized witnesses, the public inputs a circuit
+// assigns must be exactly those computed natively for its proofs, element by
+// element. PROPTEST_CASES sets how many cases run; the default is 256
+mod differential {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    // a coin of the given owner, holding amount of asset, with the given rho
+    fn coin(owner: &[u8], asset: &[u8; 31], amount: u64, entropy: &[u8; 31], rho: &[u8; 31]) -> JZRecord<5> {
+        let (_, _, crs) = utils::trusted_setup();
+        let mut amount_bytes = vec![0u8; 31];
+        amount_bytes[..protocol::AMOUNT_BYTES].copy_from_slice(&amount.to_le_bytes());
+
+        let fields: [Vec<u8>; 5] = [entropy.to_vec(), owner.to_vec(), asset.to_vec(), amount_bytes, rho.to_vec()];
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    }
+
+    // the assigned public inputs, past the constant one, of a satisfied circuit
+    fn assigned_inputs(circuit: impl ConstraintSynthesizer<ConstraintF>) -> Vec<ConstraintF> {
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let assignment = cs.borrow().unwrap().instance_assignment[1..].to_vec();
+        assignment
+    }
+
+    // a tree holding a few random coins besides the dummy ones
+    fn tree(leaves: &[(usize, u8)]) -> JZVectorDB<MTParams, ark_bls12_377::G1Affine> {
+        let mut db = dummy_db();
+        for (index, amount) in leaves {
+            db.update(*index, &test_coin(*amount).commitment().into_affine());
+        }
+        db
+    }
+
+    fn leaves() -> impl Strategy<Value = Vec<(usize, u8)>> {
+        proptest::collection::vec((0..protocol::TREE_CAPACITY, any::<u8>()), 0..4)
+    }
+
+    proptest! {
+        #[test]
+        fn payment_inputs_match(
+            sk in any::<[u8; 32]>(),
+            asset in any::<[u8; 31]>(),
+            amount in any::<u64>(),
+            entropy in any::<[u8; 31]>(),
+            rho in any::<[u8; 31]>(),
+            asset_blind in any::<[u8; 32]>(),
+            pool_id in proptest::option::of(any::<u64>()),
+            tree_id in any::<u32>(),
+            index in 0..protocol::TREE_CAPACITY,
+            others in leaves(),
+        ) {
+            let (prf_params, _, _) = utils::trusted_setup();
+            let owner = utils::derive_pubkey(&prf_params, &sk);
+            let input_utxo = coin(&owner, &asset, amount, &entropy, &rho);
+
+            let mut db = tree(&others);
+            db.update(index, &input_utxo.commitment().into_affine());
+
+            let output_utxo = coin(&[9u8; 31], &asset, amount, &rho, &entropy);
+            let merkle_proof = opening_proof(&db, index);
+            let circuit = PaymentCircuit {
+                asset_blind,
+                pool_id: pool_id.map(|id| id.to_le_bytes()),
+                tree_id,
+                ..payment(&input_utxo, &output_utxo, &merkle_proof, sk)
+            };
+            let native = payment_circuit::public_inputs(&circuit);
+            prop_assert_eq!(native.len(), registry::descriptor(protocol::CircuitKind::Payment).unwrap().num_public_inputs(&protocol::StatementParams::default()));
+            prop_assert_eq!(assigned_inputs(circuit), native);
+        }
+
+        #[test]
+        fn onramp_inputs_match(
+            owner in any::<[u8; 31]>(),
+            asset in any::<[u8; 31]>(),
+            amount in any::<u64>(),
+            entropy in any::<[u8; 31]>(),
+            rho in any::<[u8; 31]>(),
+        ) {
+            let (_, _, crs) = utils::trusted_setup();
+            let circuit = onramp_circuit::OnRampCircuit { crs, utxo: coin(&owner, &asset, amount, &entropy, &rho) };
+
+            let native = onramp_circuit::public_inputs(&circuit);
+            prop_assert_eq!(assigned_inputs(circuit), native);
+        }
+
+        #[test]
+        fn merkle_update_inputs_match(
+            index in 0..protocol::TREE_CAPACITY,
+            amount in any::<u8>(),
+            others in leaves(),
+        ) {
+            let mut db = tree(&others);
+            let old_merkle_proof = opening_proof(&db, index);
+            db.update(index, &test_coin(amount).commitment().into_affine());
+            let new_merkle_proof = opening_proof(&db, index);
+
+            let (_, vc_params, _) = utils::trusted_setup();
+            let circuit = merkle_update_circuit::MerkleUpdateCircuit {
+                vc_params,
+                leaf_index: index,
+                old_merkle_proof,
+                new_merkle_proof,
+            };
+
+            let native = merkle_update_circuit::public_inputs(&circuit);
+            prop_assert_eq!(assigned_inputs(circuit), native);
+        }
+    }
+}
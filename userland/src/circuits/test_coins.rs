@@ -0,0 +1,32 @@
+// coins shared by the tests of the circuits, the services and the integration
+// tests. They are committed to under the insecure setup's crs, so they are
+// only built alongside it
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+
+use super::utils;
+
+/// a coin of the zero asset holding amount, owned by owner, whose rho is rho
+/// repeated; its entropy and blinding are zero, so it is the same on every call
+pub fn coin(owner: &[u8; 31], amount: u8, rho: u8) -> JZRecord<5> {
+    let (_, _, crs) = utils::trusted_setup();
+    let mut amount_bytes = vec![0u8; 31];
+    amount_bytes[0] = amount;
+
+    let fields: [Vec<u8>; 5] =
+    [
+        vec![0u8; 31], //entropy
+        owner.to_vec(), //owner
+        vec![0u8; 31], //asset id
+        amount_bytes, //amount
+        vec![rho; 31], //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+}
+
+/// a coin owned by a fixed key, distinguishable from the dummy coin, and
+/// from coins of other amounts, by its amount
+pub fn test_coin(amount: u8) -> JZRecord<5> {
+    coin(&[1u8; 31], amount, amount)
+}
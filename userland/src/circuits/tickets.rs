@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use super::protocol;
+
+// default, overridable with SANCTUM_TICKET_TTL_SECS
+pub const DEFAULT_TICKET_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Ticket is the recorded outcome of a tx submitted under an idempotency key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ticket {
+    // the leaf the tx inserted its (first) coin into
+    pub leaf_index: usize,
+    // unix time, in seconds, at which the tx was accepted
+    pub accepted_at: u64,
+    // every move of the tx, up to where it stands now
+    pub history: protocol::TxHistory,
+}
+
+/// TicketStore maps idempotency keys to the outcome of the tx first submitted
+/// under them, so that a client retrying a tx gets that outcome back rather
+/// than applying the tx twice. It holds no state of its own worth persisting:
+/// every ticket is written to the tx log along with its tx, and rebuilt when
+/// the log is replayed.
+pub struct TicketStore {
+    ttl_secs: u64,
+    tickets: HashMap<String, Ticket>,
+}
+
+impl TicketStore {
+
+    pub fn new(ttl_secs: u64) -> Self {
+        TicketStore { ttl_secs, tickets: HashMap::new() }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl_secs = match std::env::var("SANCTUM_TICKET_TTL_SECS") {
+            Ok(t) => t.parse::<u64>().expect("SANCTUM_TICKET_TTL_SECS must be a u64"),
+            Err(_) => DEFAULT_TICKET_TTL_SECS,
+        };
+
+        TicketStore::new(ttl_secs)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tickets.is_empty()
+    }
+
+    // an expired ticket is as good as none, even before it is compacted away
+    pub fn get(&self, key: &str, now: u64) -> Option<&Ticket> {
+        self.tickets
+            .get(key)
+            .filter(|ticket| !self.is_expired(ticket, now))
+    }
+
+    pub fn insert(&mut self, key: &str, ticket: Ticket, now: u64) {
+        self.compact(now);
+        self.tickets.insert(key.to_string(), ticket);
+    }
+
+    // moves the tx recorded under key on, e.g. once the verifiers accept it
+    pub fn advance(&mut self, key: &str, next: protocol::TxState, at: u64) -> Result<(), String> {
+        self.tickets
+            .get_mut(key)
+            .ok_or_else(|| format!("no ticket under {}", key))?
+            .history
+            .advance(next, at)
+    }
+
+    // forgets the outcome of a tx that was rolled back, so a retry applies it afresh
+    pub fn remove(&mut self, key: &str) {
+        self.tickets.remove(key);
+    }
+
+    // drops every expired ticket, which keeps the store bounded by the txs
+    // accepted within one ttl
+    pub fn compact(&mut self, now: u64) {
+        let ttl_secs = self.ttl_secs;
+        self.tickets.retain(|_, ticket| ticket.accepted_at.saturating_add(ttl_secs) > now);
+    }
+
+    fn is_expired(&self, ticket: &Ticket, now: u64) -> bool {
+        ticket.accepted_at.saturating_add(self.ttl_secs) <= now
+    }
+}
+
+// seconds since the unix epoch; tickets must outlive restarts, so they cannot
+// be timed with Instant
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
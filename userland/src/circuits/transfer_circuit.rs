@@ -0,0 +1,139 @@
+#[cfg(feature = "insecure-setup")]
+use rand_chacha::rand_core::SeedableRng;
+
+use ark_bw6_761::BW6_761;
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::*;
+use ark_groth16::{Groth16, Proof, ProvingKey};
+#[cfg(feature = "insecure-setup")]
+use ark_groth16::VerifyingKey;
+use ark_snark::SNARK;
+use ark_std::rand::{RngCore, rngs::OsRng};
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    *, config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+use lib_mpc_zexe::record_commitment::kzg::*;
+
+use super::utils;
+use super::payment_circuit::{self, PaymentCircuit};
+use super::protocol;
+
+// Finite Field used to encode the zk circuit
+type ConstraintF = ark_bw6_761::Fr;
+
+/// TransferCircuit proves a transfer to another pool: a payment whose input
+/// is spent here, and whose output coin is inserted into the pool named by
+/// the public DEST_POOL_ID instead. The payment statement already exposes the
+/// nullifier and the output commitment, so the statement as a whole is what
+/// the two pools agree on: this pool consumes its nullifier, and the
+/// destination inserts its commitment.
+pub struct TransferCircuit<'a> {
+    pub payment: PaymentCircuit<'a>,
+    pub dest_pool_id: u64,
+}
+
+impl<'a> ConstraintSynthesizer<ConstraintF> for TransferCircuit<'a> {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<()> {
+        self.payment.generate_constraints(cs.clone())?;
+
+        let dest_pool_id_inputvar = FpVar::<ConstraintF>::new_input(
+            ark_relations::ns!(cs, "dest_pool_id"),
+            || Ok(ConstraintF::from(self.dest_pool_id)),
+        )?;
+
+        // the destination is a pool id, so its bytes past the first
+        // POOL_ID_BYTES are zero
+        let dest_pool_id_bytes = dest_pool_id_inputvar.to_bytes()?;
+        for byte_var in dest_pool_id_bytes.iter().skip(protocol::POOL_ID_BYTES) {
+            byte_var.enforce_equal(&UInt8::constant(0))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "insecure-setup")]
+pub fn circuit_setup(statement: &protocol::StatementParams) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    // create a circuit with a dummy witness
+    let merkle_proof = utils::dummy_opening_proof(vc_params, protocol::MERKLE_TREE_LEVELS);
+    let dummy_utxo = utils::get_dummy_utxo(crs);
+
+    let circuit = TransferCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: [0u8; 32],
+            asset_blind: [0u8; 32],
+            pool_id: statement.pool_id,
+            tree_id: 0,
+            expose_leaf_index: false,
+            input_utxo: &dummy_utxo,
+            output_utxo: &dummy_utxo,
+            unspent_coin_existence_proof: &merkle_proof,
+        },
+        dest_pool_id: 0,
+    };
+
+    let seed = [0u8; 32];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(seed);
+
+    let (pk, vk) = Groth16::<BW6_761>::
+        circuit_specific_setup(circuit, &mut rng)
+        .unwrap();
+
+    (pk, vk)
+}
+
+// proves a transfer of the input utxo, as the output utxo, to the destination pool
+#[allow(clippy::too_many_arguments)]
+pub fn generate_groth_proof(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    input_utxo: &JZRecord<5>,
+    output_utxo: &JZRecord<5>,
+    unspent_coin_existence_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    sk: &[u8; 32],
+    tree_id: u32,
+    dest_pool_id: u64,
+) -> (Proof<BW6_761>, Vec<ConstraintF>) {
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+
+    // the asset tag is fresh, as a payment's is, so transfers stay unlinkable
+    let mut asset_blind = [0u8; 32];
+    OsRng.fill_bytes(&mut asset_blind);
+
+    let circuit = TransferCircuit {
+        payment: PaymentCircuit {
+            crs,
+            prf_params,
+            vc_params,
+            sk: *sk,
+            asset_blind,
+            pool_id: statement.pool_id,
+            tree_id,
+            expose_leaf_index: false,
+            input_utxo,
+            output_utxo,
+            unspent_coin_existence_proof,
+        },
+        dest_pool_id,
+    };
+
+    let mut public_inputs = payment_circuit::public_inputs(&circuit.payment);
+    public_inputs.push(ConstraintF::from(dest_pool_id));
+
+    let now = std::time::Instant::now();
+    let proof = Groth16::<BW6_761>::prove(&pk, circuit, &mut OsRng).unwrap();
+
+    tracing::info!(elapsed_ms = now.elapsed().as_millis() as u64, "transfer proof generated");
+
+    (proof, public_inputs)
+}
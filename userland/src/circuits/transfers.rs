@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::policy;
+use super::protocol;
+
+// domain separator prefixed to every signed transfer export
+const EXPORT_DOMAIN: &[u8] = b"sanctum/transfer/v1";
+
+/// PeerPools are the pools this one exchanges transfers with, each by its
+/// pool id, along with the key its sequencer signs transfer exports with
+pub struct PeerPools {
+    keys: HashMap<u64, VerifyingKey>,
+}
+
+impl PeerPools {
+
+    // create a set with no peers, which takes no transfers
+    pub fn new() -> Self {
+        PeerPools { keys: HashMap::new() }
+    }
+
+    // reads SANCTUM_PEER_POOLS, a comma separated list of <pool id>=<bs58 key>
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("SANCTUM_PEER_POOLS") {
+            Ok(peers) => Self::parse(&peers),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    pub fn parse(peers: &str) -> Result<Self, String> {
+        let mut pools = Self::new();
+
+        for peer in peers.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (pool_id, key) = peer
+                .split_once('=')
+                .ok_or_else(|| format!("peer pool {} is not <pool id>=<bs58 key>", peer))?;
+            let pool_id = pool_id
+                .parse::<u64>()
+                .map_err(|_| format!("peer pool id {} is not a u64", pool_id))?;
+
+            let key = policy::operator_key_from_bs58(key)
+                .map_err(|e| format!("peer pool {}: {}", pool_id, e))?;
+            pools.insert(pool_id, key);
+        }
+
+        Ok(pools)
+    }
+
+    pub fn insert(&mut self, pool_id: u64, key: VerifyingKey) {
+        self.keys.insert(pool_id, key);
+    }
+
+    pub fn key(&self, pool_id: u64) -> Option<&VerifyingKey> {
+        self.keys.get(&pool_id)
+    }
+}
+
+/// signs a transfer the source pool accepted, for delivery to its destination
+pub fn sign_export(
+    source_pool_id: u64,
+    transfer_proof: &protocol::GrothProofBs58,
+    signing_key: &SigningKey
+) -> protocol::TransferExportBs58 {
+    let mut export = protocol::TransferExportBs58 {
+        source_pool_id,
+        transfer_proof: transfer_proof.clone(),
+        signature: String::new(),
+    };
+    export.signature = bs58::encode(signing_key.sign(&export_message(&export)).to_bytes()).into_string();
+
+    export
+}
+
+/// verify_export checks that the export is a transfer into the given pool,
+/// signed by the sequencer of a peer pool, and returns the commitment of the
+/// coin to insert. The proof itself is not verified: it was made under the
+/// source pool's keys, which verified it before signing.
+pub fn verify_export(
+    export: &protocol::TransferExportBs58,
+    peers: &PeerPools,
+    pool_id: u64
+) -> Result<ark_bls12_377::G1Affine, String> {
+    let proof = &export.transfer_proof;
+    // the transfer statement has the same inputs under any pool's params
+    protocol::check_circuit_kind(proof, protocol::CircuitKind::Transfer, &protocol::StatementParams::default())?;
+    protocol::check_canonical_groth_proof(proof)?;
+
+    let dest_pool_id = protocol::dest_pool_id_of(proof)?;
+    if dest_pool_id != pool_id {
+        return Err(format!("transfer is to pool {}, not to pool {}", dest_pool_id, pool_id));
+    }
+
+    let key = peers
+        .key(export.source_pool_id)
+        .ok_or_else(|| format!("pool {} is not a peer", export.source_pool_id))?;
+    let signature: [u8; 64] = bs58::decode(&export.signature)
+        .into_vec()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "transfer signature must be 64 bytes".to_string())?;
+    key.verify(&export_message(export), &Signature::from_bytes(&signature))
+        .map_err(|_| "invalid transfer signature".to_string())?;
+
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(proof);
+    protocol::affine_from_xy(
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+    )
+}
+
+/// identifies a transfer across pools: a nullifier is spent once in its
+/// source pool, so the destination imports each one at most once
+pub fn export_id(export: &protocol::TransferExportBs58) -> String {
+    let nullifier = &export.transfer_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+
+    format!("{}/{}", export.source_pool_id, protocol::normalize_field_bs58(nullifier))
+}
+
+// the signature covers everything in the export but itself
+fn export_message(export: &protocol::TransferExportBs58) -> Vec<u8> {
+    let unsigned = protocol::TransferExportBs58 { signature: String::new(), ..export.clone() };
+
+    let mut message = EXPORT_DOMAIN.to_vec();
+    message.extend_from_slice(&serde_json::to_vec(&unsigned).unwrap());
+    message
+}
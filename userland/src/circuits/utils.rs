@@ -1,9 +1,13 @@
 use std::fs::*;
-use std::io::Read;
+use std::io::{BufReader, Read};
 use rand::SeedableRng;
 
 use ark_serialize::*;
+use sha2::{Digest, Sha256};
+use ark_crypto_primitives::crh::TwoToOneCRHScheme;
+use ark_crypto_primitives::merkle_tree::Config as MerkleConfig;
 use ark_groth16::*;
+use ark_ec::CurveGroup;
 use ark_bw6_761::{*};
 use ark_ff::{
     PrimeField,
@@ -11,65 +15,161 @@ use ark_ff::{
     BigInteger
 };
 
-use lib_mpc_zexe::prf::JZPRFParams;
+use lib_mpc_zexe::prf::{JZPRFParams, JZPRFInstance};
 use lib_mpc_zexe::record_commitment::kzg::{JZRecord, JZKZGCommitmentParams};
-use lib_mpc_zexe::vector_commitment::bytes::pedersen::JZVectorCommitmentParams;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    JZVectorCommitmentOpeningProof, JZVectorCommitmentParams, JZVectorCommitmentPath, JZVectorDB,
+};
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
 
+// every key file starts with this magic and the version of the encoding that
+// follows it, so that a file written by an incompatible build is refused by
+// name rather than deserialized into garbage. Bump the version whenever the
+// encoding changes, e.g. with an arkworks upgrade
+const KEY_FILE_MAGIC: &[u8; 8] = b"sanctum\0";
+pub const KEY_FILE_VERSION: u32 = 1;
+
 pub fn write_groth_key_to_file(
     pk: &ProvingKey<BW6_761>,
     pk_file_path: &str,
     vk: &VerifyingKey<BW6_761>,
     vk_file_path: &str
 ) {
-    let mut serialized_pk = Vec::new();
+    let mut serialized_pk = key_file_header();
     pk.serialize_uncompressed(&mut serialized_pk).unwrap();
 
-    let mut serialized_vk = Vec::new();
-    vk.serialize_uncompressed(&mut serialized_vk).unwrap();
-
     let mut file = File::create(pk_file_path).unwrap();
     file.write_all(&serialized_pk).unwrap();
-    println!("wrote {} bytes to {}", serialized_pk.len(), pk_file_path);
+    tracing::info!(bytes = serialized_pk.len(), path = pk_file_path, "wrote pk");
+
+    write_groth_verification_key_to_file(vk, vk_file_path);
+}
+
+pub fn write_groth_verification_key_to_file(
+    vk: &VerifyingKey<BW6_761>,
+    vk_file_path: &str
+) {
+    let mut serialized_vk = key_file_header();
+    vk.serialize_uncompressed(&mut serialized_vk).unwrap();
 
     let mut file = File::create(vk_file_path).unwrap();
     file.write_all(&serialized_vk).unwrap();
-    println!("wrote {} bytes to {}", serialized_vk.len(), vk_file_path);
-
+    tracing::info!(bytes = serialized_vk.len(), path = vk_file_path, "wrote vk");
 }
 
 pub fn read_groth_proving_key_from_file(
     pk_file_path: &str
-) -> ProvingKey<BW6_761> {
+) -> Result<ProvingKey<BW6_761>, String> {
 
+    // streamed from the file rather than read into memory whole first: a
+    // payment pk is several GB, and holding the file's bytes alongside the
+    // key would double the prover's peak memory
     let pk = ProvingKey::<BW6_761>::deserialize_uncompressed(
-        get_file_as_byte_vec(&pk_file_path).as_slice()
-    ).unwrap();
-    println!("read pk from {}", pk_file_path);
+        read_key_file(pk_file_path)?
+    ).map_err(|e| format!("unable to deserialize pk from {}: {}", pk_file_path, e))?;
+    tracing::debug!(path = pk_file_path, "read pk");
+
+    Ok(pk)
+}
+
+/// as read_groth_proving_key_from_file, but without checking that every point
+/// of the key is on the curve and in the subgroup, which dominates the time to
+/// read a large key. The file is instead checked against the sha256 that setup
+/// recorded for it in the manifest, hashed as it is read
+pub fn read_groth_proving_key_from_file_unchecked(
+    pk_file_path: &str,
+    expected_sha256: &[u8; 32]
+) -> Result<ProvingKey<BW6_761>, String> {
+
+    let mut reader = HashingReader { inner: open_key_file(pk_file_path)?, hasher: Sha256::new() };
+    check_key_file_header(&mut reader, pk_file_path)?;
+    let pk = ProvingKey::<BW6_761>::deserialize_uncompressed_unchecked(&mut reader)
+        .map_err(|e| format!("unable to deserialize pk from {}: {}", pk_file_path, e))?;
 
-    pk
+    // the checksum covers the whole file, including anything after the key
+    std::io::copy(&mut reader, &mut std::io::sink())
+        .map_err(|e| format!("unable to read {}: {}", pk_file_path, e))?;
+    if reader.hasher.finalize().as_slice() != expected_sha256 {
+        return Err(format!("{} does not match the checksum in the manifest", pk_file_path));
+    }
+    tracing::debug!(path = pk_file_path, "read pk without checking its points");
+
+    Ok(pk)
+}
+
+// the sha256 of a key file, as setup records it in the manifest
+pub fn key_file_sha256(filename: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut open_key_file(filename).unwrap(), &mut hasher).expect("unable to read key file");
+
+    hasher.finalize().into()
 }
 
 pub fn read_groth_verification_key_from_file(
     vk_file_path: &str
-) -> VerifyingKey<BW6_761> {
+) -> Result<VerifyingKey<BW6_761>, String> {
 
     let vk = VerifyingKey::<BW6_761>::deserialize_uncompressed(
-        get_file_as_byte_vec(&vk_file_path).as_slice()
-    ).unwrap();
-    println!("read vk from {}", vk_file_path);
+        read_key_file(vk_file_path)?
+    ).map_err(|e| format!("unable to deserialize vk from {}: {}", vk_file_path, e))?;
+    tracing::debug!(path = vk_file_path, "read vk");
 
-    vk
+    Ok(vk)
 }
 
-fn get_file_as_byte_vec(filename: &str) -> Vec<u8> {
-    let mut f = File::open(&filename).expect("no file found");
-    let metadata = std::fs::metadata(&filename).expect("unable to read metadata");
-    let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer).expect("buffer overflow");
-    println!("read bytes from {}", filename);
+fn key_file_header() -> Vec<u8> {
+    let mut header = KEY_FILE_MAGIC.to_vec();
+    header.extend_from_slice(&KEY_FILE_VERSION.to_le_bytes());
+
+    header
+}
+
+fn open_key_file(filename: &str) -> Result<BufReader<File>, String> {
+    let file = File::open(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+
+    Ok(BufReader::new(file))
+}
+
+// a key file, positioned at its key bytes once its header has been checked
+fn read_key_file(filename: &str) -> Result<BufReader<File>, String> {
+    let mut reader = open_key_file(filename)?;
+    check_key_file_header(&mut reader, filename)?;
+    tracing::debug!(path = filename, "read key file");
+
+    Ok(reader)
+}
+
+fn check_key_file_header<R: Read>(reader: &mut R, filename: &str) -> Result<(), String> {
+    let mut header = [0u8; KEY_FILE_MAGIC.len() + 4];
+    if reader.read_exact(&mut header).is_err() || &header[..KEY_FILE_MAGIC.len()] != KEY_FILE_MAGIC {
+        return Err(format!("{} is not a key file, or was written before key files were versioned", filename));
+    }
+
+    let version = u32::from_le_bytes(header[KEY_FILE_MAGIC.len()..].try_into().unwrap());
+    if version != KEY_FILE_VERSION {
+        return Err(format!(
+            "{}: incompatible key file version {}, this build reads version {}",
+            filename, version, KEY_FILE_VERSION
+        ));
+    }
 
-    buffer
+    Ok(())
+}
+
+// hashes the bytes deserialized through it, so that checking a key file
+// against its checksum takes no second pass over the file
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
 }
 
 pub fn trusted_setup() -> (JZPRFParams, JZVectorCommitmentParams<MTParams>, JZKZGCommitmentParams<5>) {
@@ -85,6 +185,21 @@ pub fn trusted_setup() -> (JZPRFParams, JZVectorCommitmentParams<MTParams>, JZKZ
     (prf_params, vc_params, crs)
 }
 
+// the parameters of trusted_setup, sampled once per process; circuits borrow
+// them, rather than sampling their own copy for every proof
+pub fn shared_setup() -> &'static (JZPRFParams, JZVectorCommitmentParams<MTParams>, JZKZGCommitmentParams<5>) {
+    static SETUP: std::sync::OnceLock<(JZPRFParams, JZVectorCommitmentParams<MTParams>, JZKZGCommitmentParams<5>)> =
+        std::sync::OnceLock::new();
+
+    SETUP.get_or_init(trusted_setup)
+}
+
+// derives the public key owning a coin, as in zCash: pk = PRF(0; sk)
+pub fn derive_pubkey(prf_params: &JZPRFParams, sk: &[u8; 32]) -> [u8; 31] {
+    let output = JZPRFInstance::new(prf_params, &[0u8; 32], sk).evaluate();
+    output[..31].try_into().unwrap()
+}
+
 pub fn bytes_to_field<F, const N: usize>(bytes: &[u8]) -> F 
     where F: PrimeField + From<BigInt<N>>
 {
@@ -102,6 +217,44 @@ fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
     bits
 }
 
+// the root history window, configurable via SANCTUM_ROOT_HISTORY_SIZE; it
+// must match the window the contract was initialized with
+pub fn root_history_size() -> u32 {
+    match std::env::var("SANCTUM_ROOT_HISTORY_SIZE") {
+        Ok(size) => size.parse::<u32>().expect("SANCTUM_ROOT_HISTORY_SIZE must be a u32"),
+        Err(_) => super::protocol::DEFAULT_ROOT_HISTORY_SIZE,
+    }
+}
+
+// the statement params a service or binary was configured with: the pool
+// nullifiers are bound to, via SANCTUM_POOL_ID, and whether payment statements
+// expose the spent leaf's index, via SANCTUM_EXPOSE_LEAF_INDEX=true. Unset,
+// nullifiers are PRF(rho || tree_id; sk) as in a single pool deployment, and
+// the index stays hidden. Read once at startup; the circuits take the params
+// explicitly, and keys must be set up under the same values
+pub fn statement_params() -> super::protocol::StatementParams {
+    super::protocol::StatementParams {
+        pool_id: std::env::var("SANCTUM_POOL_ID")
+            .ok()
+            .map(|id| id.parse::<u64>().expect("SANCTUM_POOL_ID must be a u64").to_le_bytes()),
+        expose_leaf_index: std::env::var("SANCTUM_EXPOSE_LEAF_INDEX").map(|v| v == "true").unwrap_or(false),
+    }
+}
+
+// default limits, in bytes, on the json bodies accepted by the services:
+// proof submissions carry proofs and keys, while queries are small
+pub const DEFAULT_PROOF_BODY_LIMIT: usize = 1 << 20;
+pub const DEFAULT_BATCH_BODY_LIMIT: usize = 16 << 20;
+pub const DEFAULT_QUERY_BODY_LIMIT: usize = 64 << 10;
+
+// reads a body limit from the environment, falling back to the default
+pub fn body_limit(var: &str, default: usize) -> usize {
+    match std::env::var(var) {
+        Ok(limit) => limit.parse::<usize>().unwrap_or_else(|_| panic!("{} must be a usize", var)),
+        Err(_) => default,
+    }
+}
+
 pub fn get_dummy_utxo(crs: &JZKZGCommitmentParams<5>) -> JZRecord<5> {
     let fields: [Vec<u8>; 5] = 
     [
@@ -113,4 +266,56 @@ pub fn get_dummy_utxo(crs: &JZKZGCommitmentParams<5>) -> JZRecord<5> {
     ];
 
     JZRecord::<5>::new(crs, &fields, &[0u8; 31].into())
-}
\ No newline at end of file
+}
+
+// a tree of dummy coins, as every tree starts out; its root is the one a
+// fresh tree's first merkle update extends
+pub fn empty_tree() -> JZVectorDB<MTParams, ark_bls12_377::G1Affine> {
+    let (_, vc_params, crs) = trusted_setup();
+    let records: Vec<ark_bls12_377::G1Affine> = (0..super::protocol::TREE_CAPACITY)
+        .map(|_| get_dummy_utxo(&crs).commitment().into_affine())
+        .collect();
+
+    JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records)
+}
+
+// an opening of leaf 0 of a tree of 2^depth dummy coins, as circuit setups
+// need one, in O(depth) hashes rather than the O(2^depth) of empty_tree:
+// dummy subtrees of the same height share their root, so every node of the
+// path is the root of a dummy subtree, hashed from the one a level below.
+// The lowest is hashed by a tree of two dummy coins, under trusted_setup's
+// parameters, so vc_params must be those too, as every tree's are
+pub fn dummy_opening_proof(
+    vc_params: &JZVectorCommitmentParams<MTParams>,
+    depth: u32
+) -> JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine> {
+    assert!(depth >= 1, "a tree has at least two leaves");
+
+    let (_, pair_params, crs) = trusted_setup();
+    let dummy = get_dummy_utxo(&crs).commitment().into_affine();
+    let pair = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(pair_params, &vec![dummy; 2]);
+
+    // subtrees[h - 1] is the root of a dummy subtree of 2^h leaves
+    let mut subtrees = vec![pair.commitment()];
+    for _ in 1..depth {
+        let below = subtrees.last().unwrap();
+        let above = <<MTParams as MerkleConfig>::TwoToOneHash as TwoToOneCRHScheme>::compress(
+            &vc_params.two_to_one_crh_params, below, below
+        ).unwrap();
+        subtrees.push(above);
+    }
+
+    // the tallest is the root, and the rest are the auth path, from the top down
+    let root = subtrees.pop().unwrap();
+    subtrees.reverse();
+
+    JZVectorCommitmentOpeningProof {
+        root,
+        record: dummy,
+        path: JZVectorCommitmentPath {
+            leaf_sibling_hash: pair.proof(0).leaf_sibling_hash,
+            auth_path: subtrees,
+            leaf_index: 0,
+        },
+    }
+}
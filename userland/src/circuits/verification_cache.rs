@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use super::protocol;
+
+// defaults, overridable with SANCTUM_VERIFY_CACHE_CAPACITY and SANCTUM_VERIFY_CACHE_TTL_SECS
+pub const DEFAULT_VERIFY_CACHE_CAPACITY: usize = 1024;
+pub const DEFAULT_VERIFY_CACHE_TTL_SECS: u64 = 60;
+
+struct CachedVerdict {
+    valid: bool,
+    verified_at: Instant,
+    // value of the cache's clock when the verdict was last read or written
+    last_used: u64,
+}
+
+/// VerificationCache remembers the outcome of recent Groth16 verifications,
+/// keyed by a digest of the proof and its public inputs, so that a tx that
+/// is submitted twice only pays for one pairing check. It only ever stands
+/// in for the pairing computation: callers must still run every nullifier,
+/// root and state check on a cache hit.
+pub struct VerificationCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<[u8; 32], CachedVerdict>,
+    clock: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl VerificationCache {
+
+    // a capacity of 0 disables the cache
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        VerificationCache {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = match std::env::var("SANCTUM_VERIFY_CACHE_CAPACITY") {
+            Ok(c) => c.parse::<usize>().expect("SANCTUM_VERIFY_CACHE_CAPACITY must be a usize"),
+            Err(_) => DEFAULT_VERIFY_CACHE_CAPACITY,
+        };
+        let ttl_secs = match std::env::var("SANCTUM_VERIFY_CACHE_TTL_SECS") {
+            Ok(t) => t.parse::<u64>().expect("SANCTUM_VERIFY_CACHE_TTL_SECS must be a u64"),
+            Err(_) => DEFAULT_VERIFY_CACHE_TTL_SECS,
+        };
+
+        VerificationCache::new(capacity, Duration::from_secs(ttl_secs))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // returns the cached verdict for the proof if there is a fresh one, and
+    // otherwise runs verify and caches its verdict
+    pub fn verify<F: FnOnce() -> bool>(&mut self, proof: &protocol::GrothProofBs58, verify: F) -> bool {
+        let key = proof_digest(proof);
+        self.clock += 1;
+
+        if let Some(entry) = self.entries.get_mut(&key) {
+            if entry.verified_at.elapsed() < self.ttl {
+                entry.last_used = self.clock;
+                self.hits += 1;
+                return entry.valid;
+            }
+        }
+
+        self.misses += 1;
+        let valid = verify();
+
+        if self.capacity > 0 {
+            if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+                self.evict_least_recently_used();
+            }
+            self.entries.insert(key, CachedVerdict {
+                valid,
+                verified_at: Instant::now(),
+                last_used: self.clock,
+            });
+        }
+
+        valid
+    }
+
+    // forgets every verdict, e.g. once the keys they were reached with are replaced
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| *key);
+
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+// the digest covers the circuit tag too, so a proof is never looked up
+// under another circuit's verdict
+fn proof_digest(proof: &protocol::GrothProofBs58) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    let circuit = format!("{:?}", proof.circuit);
+    for part in [&circuit, &proof.proof].into_iter().chain(proof.public_inputs.iter()) {
+        // length-prefix each part so that adjacent ones can't run together
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+
+    hasher.finalize().into()
+}
@@ -0,0 +1,123 @@
+use super::protocol;
+
+// where txs are forwarded when SANCTUM_VERIFIER_URLS is not set
+pub const DEFAULT_VERIFIER_URL: &str = "http://127.0.0.1:8081";
+
+/// Verdict is one verifier's answer to a forwarded tx
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Accepted,
+    // the verifier answered with this status, rather than a success
+    Rejected(u16),
+    // the verifier gave no answer at all
+    Unreachable(String),
+}
+
+/// VerifierSet is the verifiers a sequencer forwards every accepted tx to, and
+/// the quorum of them that must accept it before the sequencer does; a tx
+/// short of the quorum is rolled back. It keeps the health of each verifier,
+/// and how often its verdict agreed with the outcome.
+pub struct VerifierSet {
+    quorum: usize,
+    verifiers: Vec<protocol::VerifierHealth>,
+}
+
+impl VerifierSet {
+
+    pub fn new(urls: Vec<String>, quorum: usize) -> Result<Self, String> {
+        if quorum == 0 || quorum > urls.len() {
+            return Err(format!("a quorum of {} is not between 1 and the {} verifiers", quorum, urls.len()));
+        }
+
+        let verifiers = urls
+            .into_iter()
+            .map(|url| protocol::VerifierHealth {
+                url,
+                healthy: true,
+                accepted: 0,
+                rejected: 0,
+                unreachable: 0,
+                agreed: 0,
+                dissented: 0,
+                last_error: None,
+            })
+            .collect();
+
+        Ok(VerifierSet { quorum, verifiers })
+    }
+
+    // SANCTUM_VERIFIER_URLS is a comma separated list of verifiers, of which
+    // SANCTUM_VERIFIER_QUORUM must accept a tx; by default, all of them
+    pub fn from_env() -> Self {
+        let urls: Vec<String> = match std::env::var("SANCTUM_VERIFIER_URLS") {
+            Ok(urls) => urls
+                .split(',')
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty())
+                .collect(),
+            Err(_) => vec![DEFAULT_VERIFIER_URL.to_string()],
+        };
+        let quorum = match std::env::var("SANCTUM_VERIFIER_QUORUM") {
+            Ok(q) => q.parse::<usize>().expect("SANCTUM_VERIFIER_QUORUM must be a usize"),
+            Err(_) => urls.len(),
+        };
+
+        VerifierSet::new(urls, quorum).expect("invalid verifier configuration")
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        self.verifiers.iter().map(|v| v.url.clone()).collect()
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    pub fn health(&self) -> &[protocol::VerifierHealth] {
+        &self.verifiers
+    }
+
+    // records one verdict per verifier, in the order of urls(), and checks
+    // them against the quorum; returns the verifiers that went against the
+    // outcome, which is an error naming them when the quorum is not reached
+    pub fn tally(&mut self, verdicts: &[Verdict]) -> Result<Vec<String>, String> {
+        if verdicts.len() != self.verifiers.len() {
+            return Err(format!("{} verdicts for {} verifiers", verdicts.len(), self.verifiers.len()));
+        }
+
+        let accepted = verdicts.iter().filter(|v| **v == Verdict::Accepted).count();
+        let reached = accepted >= self.quorum;
+
+        let mut dissenting = Vec::new();
+        for (verifier, verdict) in self.verifiers.iter_mut().zip(verdicts) {
+            match verdict {
+                Verdict::Accepted => verifier.accepted += 1,
+                Verdict::Rejected(status) => {
+                    verifier.rejected += 1;
+                    verifier.last_error = Some(format!("rejected with status {}", status));
+                },
+                Verdict::Unreachable(e) => {
+                    verifier.unreachable += 1;
+                    verifier.last_error = Some(e.clone());
+                },
+            }
+            verifier.healthy = !matches!(verdict, Verdict::Unreachable(_));
+
+            if (*verdict == Verdict::Accepted) == reached {
+                verifier.agreed += 1;
+            } else {
+                verifier.dissented += 1;
+                dissenting.push(verifier.url.clone());
+            }
+        }
+
+        if reached {
+            Ok(dissenting)
+        } else {
+            Err(format!(
+                "{} of {} verifiers accepted, short of the quorum of {}; dissenting: {}",
+                accepted, self.verifiers.len(), self.quorum, dissenting.join(", ")
+            ))
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use reqwest::Client;
+use std::future::Future;
+use std::time::{Duration, Instant};
 
 use ark_ff::{*};
+use ark_std::rand::{RngCore, rngs::OsRng};
+use ark_bw6_761::BW6_761;
+use ark_groth16::{ProvingKey, VerifyingKey};
 
 use lib_mpc_zexe::record_commitment::kzg::*;
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
@@ -8,12 +13,37 @@ use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
     config::ed_on_bw6_761::MerkleTreeParams as MTParams,
 };
 
-use lib_sanctum::{payment_circuit, onramp_circuit, utils, protocol};
+use lib_sanctum::{balance_circuit, logging, memo, merkle_delta, payment_circuit, onramp_circuit, onramp_multi_circuit, receipts, registry, utils, protocol};
+use lib_sanctum::assets::AssetRegistry;
+use lib_sanctum::nullifiers::MAX_NULLIFIER_STATUS_BATCH;
 
-async fn request_merkle_proof(index: usize)
--> reqwest::Result<JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>> {
+const DEFAULT_SEQUENCER_URL: &str = "http://127.0.0.1:8080";
+const DEFAULT_VERIFIER_URL: &str = "http://127.0.0.1:8081";
+
+// how many times a payment is re-proven against a fresh root, unless --max-reproofs says otherwise
+const DEFAULT_MAX_REPROOFS: usize = 3;
+
+type MerkleProof = JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>;
+
+/// ProgressSink receives progress updates from client flows that may take a
+/// while, such as a payment that has to be re-proven
+trait ProgressSink {
+    fn progress(&mut self, message: &str);
+}
+
+// the command line client just prints progress
+struct StdoutProgress;
+
+impl ProgressSink for StdoutProgress {
+    fn progress(&mut self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+// opens a leaf of the given tree; a coin must be spent from the tree it lives in
+async fn request_merkle_proof(sequencer_url: &str, tree_id: u32, index: usize) -> reqwest::Result<MerkleProof> {
     let client = Client::new();
-    let response = client.get("http://127.0.0.1:8080/merkle")
+    let response = client.get(format!("{}/merkle?tree={}", sequencer_url, tree_id))
         .json(&index)
         .send()
         .await?
@@ -25,80 +55,765 @@ async fn request_merkle_proof(index: usize)
     )
 }
 
-async fn submit_onramp_transaction(item: crate::protocol::GrothProofBs58) -> reqwest::Result<()> {
+// brings an opening we hold up to the tree's current root by fetching only the
+// nodes of its path that changed, and checks the patched opening before it is
+// proven with. The whole opening is fetched instead if the sequencer no longer
+// knows our root, or if the patched opening doesn't check out
+async fn refresh_merkle_proof(sync_url: &str, tree_id: u32, cached: &MerkleProof) -> reqwest::Result<MerkleProof> {
+    let request = merkle_delta::MerkleDeltaRequestBs58 {
+        index: cached.path.leaf_index,
+        known_root_x: protocol::encode_constraintf_as_bs58_str(&cached.root.x),
+        known_root_y: protocol::encode_constraintf_as_bs58_str(&cached.root.y),
+    };
+    let response = Client::new()
+        .post(format!("{}/merkle_delta?tree={}", sync_url, tree_id))
+        .json(&request)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let delta: merkle_delta::MerkleDeltaBs58 = response.json().await?;
+        let cached = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(cached);
+        let patched = merkle_delta::apply(&cached, &delta)
+            .map(|proof| protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_from_bs58(&proof));
+
+        match patched {
+            Ok(proof) if receipts::is_valid_opening_proof(&proof) == Ok(true) => return Ok(proof),
+            _ => tracing::warn!(leaf_index = request.index, "patched merkle path is invalid; fetching it in full"),
+        }
+    }
+
+    request_merkle_proof(sync_url, tree_id, request.index).await
+}
+
+// every root the verifier has ever accepted, fetched range by range; unlike
+// its window, the archive still holds the roots of old receipts
+async fn request_root_archive(verifier_url: &str) -> reqwest::Result<Vec<(String, String)>> {
+    let client = Client::new();
+    let mut roots = Vec::new();
+    let mut from = Some(0);
+
+    while let Some(block) = from {
+        let range: protocol::RootRangeBs58 = client
+            .get(format!("{}/root/history?from={}", verifier_url, block))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        roots.extend(range.roots.into_iter().map(|root| (root.root_x, root.root_y)));
+        from = range.next;
+    }
+
+    Ok(roots)
+}
+
+// queries the spent status of each nullifier, chunked to respect the server's cap
+async fn request_nullifier_status(sync_url: &str, nullifiers: &[String]) -> reqwest::Result<Vec<bool>> {
+    let client = Client::new();
+    let mut spent = Vec::with_capacity(nullifiers.len());
+
+    for chunk in nullifiers.chunks(MAX_NULLIFIER_STATUS_BATCH) {
+        let request = protocol::NullifierStatusRequestBs58 { nullifiers: chunk.to_vec() };
+        let response: protocol::NullifierStatusBs58 = client
+            .post(format!("{}/nullifiers/status", sync_url))
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        spent.extend(response.spent);
+    }
+
+    Ok(spent)
+}
+
+// fetches the (encrypted) memo attached to the payment that created the coin at index
+async fn request_memo(sequencer_url: &str, index: usize) -> reqwest::Result<Option<String>> {
+    let client = Client::new();
+    client.get(format!("{}/memo", sequencer_url))
+        .json(&index)
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+// delegates payment proving to a prover service; note that the prover learns the secret key
+async fn request_payment_proof(
+    prover_url: &str,
+    witness: &protocol::PaymentWitnessBs58
+) -> reqwest::Result<protocol::GrothProofBs58> {
+    let client = Client::new();
+    client.post(format!("{}/prove/payment", prover_url))
+        .json(witness)
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+// catches a payment proof whose statement contradicts the opening it was proven
+// against, or that no verifier could decode, before it costs a round trip and
+// a verification only to be rejected
+fn precheck_payment(
+    proof: &protocol::GrothProofBs58,
+    merkle_proof: &MerkleProof,
+    statement: &protocol::StatementParams
+) -> Result<(), String> {
+    protocol::check_circuit_kind(proof, protocol::CircuitKind::Payment, statement)?;
+    protocol::check_canonical_groth_proof(proof)?;
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(proof);
+
+    let root = (
+        public_inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize],
+    );
+    if root != (merkle_proof.root.x, merkle_proof.root.y) {
+        return Err("the proof's root is not the root of the merkle proof it spends against".to_string());
+    }
+
+    protocol::affine_from_xy(
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+    ).map_err(|e| format!("output commitment: {}", e))?;
+
+    Ok(())
+}
+
+/// TimingReport breaks down where the latency of a submitted tx was spent
+#[derive(Debug)]
+struct TimingReport {
+    proof_generation: Duration,
+    // time from sending the tx to receiving the sequencer's response
+    round_trip: Duration,
+    // proof verification time, as reported by the sequencer
+    server_verification: Option<Duration>,
+}
+
+fn timing_report(
+    proof_generation: Duration,
+    round_trip: Duration,
+    response: Option<&protocol::TxResponse>
+) -> TimingReport {
+    TimingReport {
+        proof_generation,
+        round_trip,
+        server_verification: response
+            .and_then(|r| r.verify_time_ms)
+            .map(Duration::from_millis),
+    }
+}
+
+async fn submit_onramp_transaction(
+    sequencer_url: &str,
+    item: crate::protocol::GrothProofBs58,
+    proof_generation: Duration
+) -> reqwest::Result<TimingReport> {
+    // a deposit minting several notes has its own endpoint
+    let endpoint = match item.circuit {
+        protocol::CircuitKind::OnrampMulti => "onramp_multi",
+        _ => "onramp",
+    };
+
     let client = Client::new();
-    let response = client.post("http://127.0.0.1:8080/onramp")
+    let now = Instant::now();
+    let response = client.post(format!("{}/{}", sequencer_url, endpoint))
         .json(&item)
         .send()
         .await?;
 
-    if response.status().is_success() {
+    let tx_response = if response.status().is_success() {
+        let tx_response: protocol::TxResponse = response.json().await?;
         println!("successfully processed onramp tx");
+        Some(tx_response)
     } else {
         println!("Failed to create item: {:?}", response.status());
-    }
+        None
+    };
 
-    Ok(())
+    Ok(timing_report(proof_generation, now.elapsed(), tx_response.as_ref()))
 }
 
-async fn submit_payment_transaction(item: crate::protocol::GrothProofBs58) -> reqwest::Result<()> {
+async fn submit_payment_transaction(
+    sequencer_url: &str,
+    item: &crate::protocol::PaymentTxBs58,
+    proof_generation: Duration
+) -> reqwest::Result<(Option<protocol::TxResponse>, TimingReport)> {
     let client = Client::new();
-    let response = client.post("http://127.0.0.1:8080/payment")
-        .json(&item)
+    let now = Instant::now();
+    let response = client.post(format!("{}/payment", sequencer_url))
+        .json(item)
         .send()
         .await?;
     
-    if response.status().is_success() {
+    let tx_response = if response.status().is_success() {
+        let tx_response: protocol::TxResponse = response.json().await?;
         println!("successfully processed payment tx");
+        Some(tx_response)
     } else {
         println!("Failed to create item: {:?}", response.status());
-    }
+        None
+    };
     
-    Ok(())
+    let timing = timing_report(proof_generation, now.elapsed(), tx_response.as_ref());
+    Ok((tx_response, timing))
+}
+
+// reads a payment proven offline, as the json body of the sequencer's /payment
+fn read_proof_bundle(path: &str) -> Result<protocol::PaymentTxBs58, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("{} is not a payment bundle: {}", path, e))
+}
+
+// submits a bundle file as is, so that a payment proven on an air-gapped
+// machine is relayed without re-deriving anything from it
+async fn submit_bundle_file(path: &str, sequencer_url: &str) -> Result<protocol::TxResponse, String> {
+    let tx = read_proof_bundle(path)?;
+
+    let (response, _) = submit_payment_transaction(sequencer_url, &tx, Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    response.ok_or_else(|| "the sequencer answered with an error status".to_string())
+}
+
+/// PaymentOutcome describes the last submission made by pay
+#[derive(Debug)]
+struct PaymentOutcome {
+    tx: protocol::PaymentTxBs58,
+    // None if the sequencer answered with an error status
+    response: Option<protocol::TxResponse>,
+    timing: TimingReport,
+    // how many times the payment was re-proven against a fresh root
+    reproofs: usize,
+}
+
+fn is_stale_root_rejection(response: &Option<protocol::TxResponse>) -> bool {
+    response.as_ref().is_some_and(|r| {
+        !r.accepted && r.error.as_deref() == Some(protocol::UNKNOWN_ROOT_ERROR)
+    })
+}
+
+// proves and submits a payment spending the coin at leaf_index of tree_id. If the tree
+// moved on far enough while we were proving that the sequencer no longer
+// accepts our root, the coin is still unspent: bring its opening up to date
+// and re-prove, up to max_reproofs times. Openings come from sync_url, which
+// may be a read-only replica of the sequencer
+#[allow(clippy::too_many_arguments)]
+async fn pay<P, F, S>(
+    sequencer_url: &str,
+    sync_url: &str,
+    tree_id: u32,
+    leaf_index: usize,
+    memo: Option<String>,
+    max_reproofs: usize,
+    mut prove: P,
+    progress: &mut S,
+) -> reqwest::Result<PaymentOutcome>
+where
+    P: FnMut(MerkleProof) -> F,
+    F: Future<Output = reqwest::Result<protocol::GrothProofBs58>>,
+    S: ProgressSink,
+{
+    let mut reproofs = 0;
+    let mut cached: Option<MerkleProof> = None;
+
+    loop {
+        progress.progress("requesting merkle path...");
+        let merkle_proof = match cached.as_ref() {
+            Some(cached) => refresh_merkle_proof(sync_url, tree_id, cached).await?,
+            None => request_merkle_proof(sync_url, tree_id, leaf_index).await?,
+        };
+        cached = Some(merkle_proof.clone());
+
+        progress.progress("generating payment proof...");
+        let now = Instant::now();
+        let tx = protocol::PaymentTxBs58 {
+            payment_proof: prove(merkle_proof).await?,
+            memo: memo.clone(),
+        };
+        let proof_generation = now.elapsed();
+
+        progress.progress("submitting payment tx...");
+        let (response, timing) = submit_payment_transaction(sequencer_url, &tx, proof_generation).await?;
+
+        if !is_stale_root_rejection(&response) || reproofs == max_reproofs {
+            return Ok(PaymentOutcome { tx, response, timing, reproofs });
+        }
+
+        reproofs += 1;
+        progress.progress(&format!(
+            "payment was proven against a stale root; re-proving ({} of {})", reproofs, max_reproofs
+        ));
+    }
+}
+
+/// ClientArgs are the client's command line options
+#[derive(Debug, PartialEq)]
+struct ClientArgs {
+    // where txs are submitted
+    sequencer_url: String,
+    // where wallet sync queries go; defaults to the sequencer, but may be a read-only replica
+    sync_url: String,
+    max_reproofs: usize,
+    // if set, the client only deposits this amount, split into denominations
+    onramp_amount: Option<u64>,
+    // the asset registry, a json file mapping symbols to asset ids and decimals
+    assets: Option<String>,
+    // what alice pays bob, e.g. 1.5 of USDC, resolved against the registry
+    amount: Option<String>,
+    asset: Option<String>,
+    // where receipts are checked against the archive of accepted roots
+    verifier_url: String,
+    // if set, the client only verifies the receipt in this json file
+    verify_receipt: Option<String>,
+    // if set, the client only submits the payment bundle in this json file
+    submit_file: Option<String>,
+    // if set, the client only proves alice holds at least this much of the asset, for an auditor
+    prove_balance: Option<String>,
+}
+
+// `client [--sequencer-url <url>] [--sync-url <url>] [--max-reproofs <n>] [--onramp-amount <n>]
+//     [--assets <file> --amount <decimal> --asset <symbol>] [--verifier-url <url>] [--verify-receipt <file>]
+//     [--submit-file <file>] [--prove-balance <decimal>]`
+fn parse_args(args: &[String]) -> Result<ClientArgs, String> {
+    let value = |flag: &str| -> Result<Option<&String>, String> {
+        match args.iter().position(|a| a == flag) {
+            Some(i) => args.get(i + 1).map(Some).ok_or_else(|| format!("{} expects a value", flag)),
+            None => Ok(None),
+        }
+    };
+
+    let sequencer_url = value("--sequencer-url")?
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_SEQUENCER_URL.to_string());
+    let sync_url = value("--sync-url")?
+        .cloned()
+        .unwrap_or_else(|| sequencer_url.clone());
+    let max_reproofs = match value("--max-reproofs")? {
+        Some(n) => n.parse::<usize>().map_err(|_| "--max-reproofs expects a number".to_string())?,
+        None => DEFAULT_MAX_REPROOFS,
+    };
+    let onramp_amount = match value("--onramp-amount")? {
+        Some(n) => Some(n.parse::<u64>().map_err(|_| "--onramp-amount expects a number".to_string())?),
+        None => None,
+    };
+
+    let assets = value("--assets")?.cloned();
+    let amount = value("--amount")?.cloned();
+    let asset = value("--asset")?.cloned();
+    if amount.is_some() != asset.is_some() {
+        return Err("--amount and --asset go together".to_string());
+    }
+
+    let verifier_url = value("--verifier-url")?
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_VERIFIER_URL.to_string());
+    let verify_receipt = value("--verify-receipt")?.cloned();
+    let submit_file = value("--submit-file")?.cloned();
+    let prove_balance = value("--prove-balance")?.cloned();
+
+    Ok(ClientArgs {
+        sequencer_url, sync_url, max_reproofs, onramp_amount, assets, amount, asset, verifier_url, verify_receipt,
+        submit_file, prove_balance
+    })
+}
+
+// the asset id and integer amount alice pays; without --amount, 10 of asset 1
+fn resolve_payment(args: &ClientArgs, registry: &AssetRegistry) -> Result<(protocol::AssetId, u64), String> {
+    match (args.amount.as_ref(), args.asset.as_ref()) {
+        (Some(amount), Some(asset)) => registry.parse(amount, asset),
+        _ => Ok((protocol::AssetId(1), 10)),
+    }
+}
+
+// the least balance alice proves, in the units of --asset if there is one
+fn resolve_threshold(args: &ClientArgs, registry: &AssetRegistry, threshold: &str) -> Result<u64, String> {
+    match args.asset.as_ref() {
+        Some(asset) => registry.parse(threshold, asset).map(|(_, threshold)| threshold),
+        None => threshold.parse::<u64>().map_err(|_| "--prove-balance expects a number".to_string()),
+    }
+}
+
+// the keys the sequencer's verifier was set up with, under the statement
+// params the client is configured with, see registry::keys
+fn load_keys(kind: protocol::CircuitKind) -> (ProvingKey<BW6_761>, VerifyingKey<BW6_761>) {
+    registry::keys(kind, &utils::statement_params()).unwrap_or_else(|e| {
+        eprintln!("unable to load the {:?} keys: {}", kind, e);
+        std::process::exit(2);
+    })
 }
 
 #[tokio::main]
 async fn main() -> reqwest::Result<()> {
-    // let (onramp_pk, _) = utils::read_groth_key_from_file(
-    //     "/tmp/sanctum/onramp.pk",
-    //     "/tmp/sanctum/onramp.vk"
-    // );
+    let args: Vec<String> = std::env::args().collect();
+
+    // `client keygen [--out <file>]` creates a fresh identity, and exits
+    if args.get(1).map(String::as_str) == Some("keygen") {
+        if let Err(e) = keygen(&args[2..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
 
-    // let (payment_pk, _) = utils::read_groth_key_from_file(
-    //     "/tmp/sanctum/payment.pk",
-    //     "/tmp/sanctum/payment.vk"
-    // );
+    let args = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
 
-    let (onramp_pk, _onramp_vk) = onramp_circuit::circuit_setup();
-    let (payment_pk, _payment_vk) = payment_circuit::circuit_setup();
+    // proof timings are logged, at info unless RUST_LOG says otherwise
+    logging::init(&logging::LogConfig::from_env(logging::DEFAULT_LEVEL)).unwrap();
+
+    if let Some(amount) = args.onramp_amount {
+        return onramp_in_denominations(&args.sequencer_url, amount).await;
+    }
+    if let Some(path) = args.verify_receipt.as_ref() {
+        return verify_receipt(&args.verifier_url, path).await;
+    }
+    if let Some(path) = args.submit_file.as_ref() {
+        match submit_bundle_file(path, &args.sequencer_url).await {
+            Ok(response) if response.accepted => println!("bundle accepted"),
+            Ok(response) => {
+                eprintln!("bundle rejected: {}", response.error.unwrap_or_default());
+                std::process::exit(1);
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            },
+        }
+        return Ok(());
+    }
+
+    let registry = match args.assets.as_ref() {
+        Some(path) => AssetRegistry::load(path),
+        None => Ok(AssetRegistry::default()),
+    };
+    let (registry, (asset_id, amount)) = registry
+        .and_then(|registry| resolve_payment(&args, &registry).map(|payment| (registry, payment)))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        });
+
+    if let Some(threshold) = args.prove_balance.as_ref() {
+        let threshold = resolve_threshold(&args, &registry, threshold).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        });
+        return prove_balance(&args, &asset_id, amount, threshold).await;
+    }
+
+    let statement = utils::statement_params();
+    let (payment_pk, _payment_vk) = load_keys(protocol::CircuitKind::Payment);
+
+    onramp_alice_coin(&args.sequencer_url, &asset_id, amount).await?;
+
+    println!("paying bob {}...", registry.format(amount, &asset_id));
+    // thin clients may offload proving to a (trusted, self-hosted) prover service
+    let prover_url = std::env::var("SANCTUM_PROVER_URL").ok();
+    let (payment_pk, statement) = (&payment_pk, &statement);
+    // alice's coin is the first onramped into the sequencer's first tree
+    let (alice_tree_id, alice_leaf_index) = (0, 0);
+    let prove = |alice_merkle_proof: MerkleProof| {
+        let prover_url = prover_url.clone();
+        async move {
+            let proof = match prover_url {
+                Some(prover_url) => {
+                    let witness = protocol::PaymentWitnessBs58 {
+                        input_utxo: protocol::record_to_bs58(&alice_input_coin(&asset_id, amount)),
+                        output_utxo: protocol::record_to_bs58(&alice_output_coin(&asset_id, amount)),
+                        unspent_coin_existence_proof:
+                            protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&alice_merkle_proof),
+                        sk: bs58::encode(&alice_key().0).into_string(),
+                        tree_id: alice_tree_id,
+                    };
+                    request_payment_proof(&prover_url, &witness).await
+                },
+                None => {
+                    let groth_proof = payment_circuit::generate_groth_proof(
+                        payment_pk,
+                        statement,
+                        &alice_input_coin(&asset_id, amount),
+                        &alice_output_coin(&asset_id, amount),
+                        &alice_merkle_proof,
+                        &alice_key().0,
+                        alice_tree_id
+                    );
+                    Ok(crate::protocol::groth_proof_to_bs58(
+                        protocol::CircuitKind::Payment, &groth_proof.0, &groth_proof.1
+                    ))
+                }
+            };
+
+            if let Ok(proof) = &proof {
+                if let Err(e) = precheck_payment(proof, &alice_merkle_proof, statement) {
+                    eprintln!("not submitting an inconsistent payment proof: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            proof
+        }
+    };
+    let outcome = pay(
+        &args.sequencer_url,
+        &args.sync_url,
+        alice_tree_id,
+        alice_leaf_index,
+        Some(protocol::memo_to_bs58(
+            &memo::encrypt(&memo::memo_pubkey(&bob_key().0), b"invoice #1", &mut OsRng).unwrap()
+        ).unwrap()),
+        args.max_reproofs,
+        prove,
+        &mut StdoutProgress
+    ).await?;
+    println!("payment timing: {:?} after {} re-proofs", outcome.timing, outcome.reproofs);
+    let nullifier = outcome.tx
+        .payment_proof
+        .public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize]
+        .clone();
+
+    println!("syncing nullifier status...");
+    let spent = request_nullifier_status(&args.sync_url, &[nullifier]).await?;
+    println!("alice's input coin spent: {}", spent[0]);
+    println!("bob's balance: {}", registry.format(amount, &asset_id));
+    if let Some(leaf_index) = outcome.response.as_ref().and_then(|r| r.leaf_index) {
+        match request_memo(&args.sequencer_url, leaf_index).await? {
+            Some(memo) => match protocol::memo_from_bs58(&memo).and_then(|m| memo::decrypt(&bob_key().0, &m)) {
+                Ok(memo) => println!("bob's memo: {}", String::from_utf8_lossy(&memo)),
+                Err(e) => eprintln!("unable to read bob's memo: {}", e),
+            },
+            None => println!("bob's coin carries no memo"),
+        }
+    }
+
+    Ok(())
+}
+
+// checks a receipt (as served by the sequencer's /receipt) against every root
+// the verifier ever accepted, so that it verifies long after its root has left
+// the window new proofs are checked against
+async fn verify_receipt(verifier_url: &str, path: &str) -> reqwest::Result<()> {
+    let receipt: protocol::ReceiptBs58 = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("unable to read receipt from {}: {}", path, e);
+            std::process::exit(2);
+        });
+
+    let roots = request_root_archive(verifier_url).await?;
+    match receipts::verify(&receipt, &roots) {
+        Ok(()) => println!("receipt verified against {} archived roots", roots.len()),
+        Err(e) => {
+            eprintln!("receipt rejected: {}", e);
+            std::process::exit(1);
+        },
+    }
+
+    Ok(())
+}
+
+// deposits the coin alice goes on to pay bob, or to prove her balance with
+async fn onramp_alice_coin(sequencer_url: &str, asset_id: &protocol::AssetId, amount: u64) -> reqwest::Result<()> {
+    let (onramp_pk, _onramp_vk) = load_keys(protocol::CircuitKind::Onramp);
 
     println!("submitting on-ramp tx...");
-    submit_onramp_transaction( {
+    let now = Instant::now();
+    let onramp_proof = {
         let groth_proof = onramp_circuit::generate_groth_proof(
             &onramp_pk,
-            &alice_on_ramp_coin()
+            &alice_on_ramp_coin(asset_id, amount)
         );
-        crate::protocol::groth_proof_to_bs58(&groth_proof.0, &groth_proof.1)
-    }).await?;
+        crate::protocol::groth_proof_to_bs58(
+            protocol::CircuitKind::Onramp, &groth_proof.0, &groth_proof.1
+        )
+    };
+    let timing = submit_onramp_transaction(sequencer_url, onramp_proof, now.elapsed()).await?;
+    println!("on-ramp timing: {:?}", timing);
+
+    Ok(())
+}
+
+// onramps alice's coin, and proves that she holds at least threshold of its
+// asset without revealing the leaf it landed in. The proof is printed for an
+// auditor, who checks it with balance_circuit::verify_balance_proof, and then
+// checks its root and nullifiers against the pool
+async fn prove_balance(args: &ClientArgs, asset_id: &protocol::AssetId, amount: u64, threshold: u64) -> reqwest::Result<()> {
+    if amount < threshold {
+        eprintln!("alice's coin of {} does not cover a balance of {}", amount, threshold);
+        std::process::exit(2);
+    }
 
+    onramp_alice_coin(&args.sequencer_url, asset_id, amount).await?;
+
+    // alice's coin is the first onramped into the sequencer's first tree
+    let (alice_tree_id, alice_leaf_index) = (0, 0);
     println!("requesting merkle path...");
-    let alice_merkle_proof = request_merkle_proof(0).await?;
-
-    println!("submitting payment tx...");
-    submit_payment_transaction( {
-        let groth_proof = payment_circuit::generate_groth_proof(
-            &payment_pk,
-            &alice_input_coin(),
-            &alice_output_coin(),
-            &alice_merkle_proof,
-            &alice_key().0
-        );
-        crate::protocol::groth_proof_to_bs58(&groth_proof.0, &groth_proof.1)
-    }).await?;
+    let merkle_proof = request_merkle_proof(&args.sync_url, alice_tree_id, alice_leaf_index).await?;
+
+    println!("generating balance proof...");
+    let (balance_pk, balance_vk) = load_keys(protocol::CircuitKind::Balance);
+    let note = alice_input_coin(asset_id, amount);
+    let (proof, public_inputs) = balance_circuit::generate_groth_proof(
+        &balance_pk, &utils::statement_params(), &[(&note, &merkle_proof)], &alice_key().0, alice_tree_id, threshold
+    );
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Balance, &proof, &public_inputs);
+
+    // check it as the auditor will before handing it over
+    if let Err(e) = balance_circuit::verify_balance_proof(&balance_vk, &tx, asset_id, threshold) {
+        eprintln!("balance proof does not verify: {}", e);
+        std::process::exit(1);
+    }
+    println!("{}", serde_json::to_string_pretty(&tx).unwrap());
+
+    Ok(())
+}
+
+// deposits amount as notes of the pool's denominations, all minted by a single
+// proof; prints the plan first, and refuses amounts the denominations can't represent
+async fn onramp_in_denominations(sequencer_url: &str, amount: u64) -> reqwest::Result<()> {
+    let plan = onramp_multi_circuit::decompose(
+        amount, &onramp_multi_circuit::DENOMINATIONS, onramp_multi_circuit::NUM_NOTES
+    ).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    println!("onramp plan: {} = {}",
+        amount, plan.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(" + "));
+
+    let (onramp_multi_pk, _) = load_keys(protocol::CircuitKind::OnrampMulti);
+    let notes: Vec<JZRecord<5>> = plan.iter().map(|value| alice_note(*value)).collect();
+
+    println!("submitting on-ramp tx minting {} notes...", notes.len());
+    let now = Instant::now();
+    let (proof, public_inputs) = onramp_multi_circuit::generate_groth_proof(&onramp_multi_pk, &notes);
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::OnrampMulti, &proof, &public_inputs);
+    let timing = submit_onramp_transaction(sequencer_url, tx, now.elapsed()).await?;
+    println!("on-ramp timing: {:?}", timing);
 
     Ok(())
 }
 
+// splits input_coin into a coin paying amount to the recipient and a change
+// coin, owned by sk, holding whatever remains after the fee; both outputs get
+// fresh entropy and rho. The payment circuit is still 1-in-1-out, so nothing
+// spends these outputs yet
+#[allow(dead_code)]
+fn build_payment(
+    input_coin: &JZRecord<5>,
+    recipient_pubkey: &[u8; 31],
+    amount: u64,
+    fee: u64,
+    sk: &[u8; 32]
+) -> Result<(JZRecord<5>, JZRecord<5>), String> {
+    let (prf_params, _, crs) = utils::trusted_setup();
+
+    let sender_pubkey = utils::derive_pubkey(&prf_params, sk);
+    if input_coin.fields[protocol::UtxoField::OWNER as usize] != sender_pubkey.to_vec() {
+        return Err("input coin is not owned by sk".to_string());
+    }
+
+    let input_amount = protocol::amount_u64(input_coin);
+    let change = amount
+        .checked_add(fee)
+        .and_then(|spent| input_amount.checked_sub(spent))
+        .ok_or(format!("input of {} cannot cover {} plus a fee of {}", input_amount, amount, fee))?;
+
+    let asset_id = protocol::AssetId::of(input_coin)?;
+    let output_coin = |owner: &[u8; 31], value: u64| {
+        let fields: [Vec<u8>; 5] =
+        [
+            random_array().to_vec(), //entropy
+            owner.to_vec(), //owner
+            asset_id.to_field_bytes().to_vec(), //asset id
+            amount_field(value), //amount
+            random_array().to_vec(), //rho
+        ];
+
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+
+    Ok((output_coin(recipient_pubkey, amount), output_coin(&sender_pubkey, change)))
+}
+
+fn random_array() -> [u8; 31] {
+    let mut arr = [0u8; 31];
+    OsRng.fill_bytes(&mut arr);
+    arr
+}
+
+// samples a fresh secret key, and derives the public key that coins it
+// spends are owned by, as the payment circuit checks it: pk = PRF(0; sk)
+fn generate_keypair() -> ([u8; 32], [u8; 31]) {
+    let (prf_params, _, _) = utils::trusted_setup();
+    let mut sk = [0u8; 32];
+    OsRng.fill_bytes(&mut sk);
+
+    (sk, utils::derive_pubkey(&prf_params, &sk))
+}
+
+// a coin owned by pk, ready to be onramped, with fresh entropy and rho
+fn onramp_coin_for(pk: &[u8; 31], asset_id: &protocol::AssetId, amount: u64) -> JZRecord<5> {
+    let (_, _, crs) = utils::trusted_setup();
+    let fields: [Vec<u8>; 5] =
+    [
+        random_array().to_vec(), //entropy
+        pk.to_vec(), //owner
+        asset_id.to_field_bytes().to_vec(), //asset id
+        amount_field(amount), //amount
+        random_array().to_vec(), //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+}
+
+// `client keygen [--out <file>]` prints a fresh identity, with the key memos
+// to it are encrypted to, and a coin of 10 of asset 1 owned by it to onramp;
+// --out also writes the identity to a new file that only its owner can read
+fn keygen(args: &[String]) -> Result<(), String> {
+    let out = match args {
+        [] => None,
+        [flag, path] if flag == "--out" => Some(path),
+        _ => return Err("usage: client keygen [--out <file>]".to_string()),
+    };
+
+    let (sk, pk) = generate_keypair();
+    let identity = serde_json::to_string_pretty(&serde_json::json!({
+        "sk": bs58::encode(&sk).into_string(),
+        "pk": bs58::encode(&pk).into_string(),
+        "memo_pk": bs58::encode(memo::memo_pubkey(&sk)).into_string(),
+        "onramp_coin": protocol::record_to_bs58(&onramp_coin_for(&pk, &protocol::AssetId(1), 10)),
+    })).unwrap();
+
+    eprintln!("warning: the sk spends every coin owned by the pk, so keep it secret; \
+        nobody can recover coins owned by a lost sk, so back it up");
+    if let Some(path) = out {
+        write_secret_file(path, &identity)?;
+        eprintln!("wrote the identity to {}", path);
+    }
+    println!("{}", identity);
+
+    Ok(())
+}
+
+// creates a file only its owner can read, refusing to overwrite one
+fn write_secret_file(path: &str, contents: &str) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| format!("unable to write {}: {}", path, e))
+}
+
 fn alice_key() -> ([u8; 32], [u8; 31]) {
     let privkey = [20u8; 32];
     let pubkey =
@@ -125,41 +840,59 @@ fn bob_key() -> ([u8; 32], [u8; 31]) {
     (privkey, pubkey)
 }
 
-// Anonymous function to generate an array
-fn create_array(input: u8) -> [u8; 31] {
-    let mut arr = [0; 31];
-    arr[0] = input;
-    arr
+// the little-endian amount field of a coin holding amount
+fn amount_field(amount: u64) -> Vec<u8> {
+    let mut amount_bytes = vec![0u8; 31];
+    amount_bytes[..protocol::AMOUNT_BYTES].copy_from_slice(&amount.to_le_bytes());
+    amount_bytes
 }
 
-fn alice_on_ramp_coin() -> JZRecord<5> {
+fn alice_on_ramp_coin(asset_id: &protocol::AssetId, amount: u64) -> JZRecord<5> {
     let (_, _, crs) = utils::trusted_setup();
     let fields: [Vec<u8>; 5] = 
     [
         vec![0u8; 31], //entropy
         alice_key().1.to_vec(), //owner
-        create_array(1u8).to_vec(), //asset id
-        create_array(10u8).to_vec(), //amount
+        asset_id.to_field_bytes().to_vec(), //asset id
+        amount_field(amount), //amount
         vec![0u8; 31],
     ];
 
     JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
 }
 
-fn alice_input_coin() -> JZRecord<5> {
-    alice_on_ramp_coin()
+// a note of asset 1 owned by alice, with fresh entropy and rho
+fn alice_note(amount: u64) -> JZRecord<5> {
+    let (_, _, crs) = utils::trusted_setup();
+    let fields: [Vec<u8>; 5] =
+    [
+        random_array().to_vec(), //entropy
+        alice_key().1.to_vec(), //owner
+        protocol::AssetId(1).to_field_bytes().to_vec(), //asset id
+        amount_field(amount), //amount
+        random_array().to_vec(), //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
 }
 
-fn alice_output_coin() -> JZRecord<5> {
+fn alice_input_coin(asset_id: &protocol::AssetId, amount: u64) -> JZRecord<5> {
+    alice_on_ramp_coin(asset_id, amount)
+}
+
+// the payment circuit is 1-in-1-out, so bob gets all of alice's coin
+fn alice_output_coin(asset_id: &protocol::AssetId, amount: u64) -> JZRecord<5> {
     let (_, _, crs) = utils::trusted_setup();
     let fields: [Vec<u8>; 5] = 
     [
         vec![0u8; 31], //entropy
         bob_key().1.to_vec(), //owner
-        create_array(1u8).to_vec(), //asset id
-        create_array(10u8).to_vec(), //amount
+        asset_id.to_field_bytes().to_vec(), //asset id
+        amount_field(amount), //amount
         vec![0u8; 31], //rho
     ];
 
     JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
 }
+
+mod test;
@@ -0,0 +1,415 @@
+#![cfg(test)]
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use ark_ec::CurveGroup;
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::JZVectorDB;
+
+use lib_sanctum::protocol::MERKLE_TREE_LEVELS;
+
+use super::*;
+
+/// FakeSequencer stands in for a sequencer whose tree advances between the
+/// client fetching an opening and submitting its payment: it serves a stale
+/// opening first and a fresh one after, and only accepts payments proven
+/// against the fresh root
+struct FakeSequencer {
+    openings: [protocol::VectorCommitmentOpeningProofBs58; 2],
+    fresh_root: (String, String),
+    num_served: Mutex<usize>,
+}
+
+async fn fake_merkle(state: web::Data<FakeSequencer>) -> HttpResponse {
+    let mut num_served = state.num_served.lock().unwrap();
+    let opening = &state.openings[(*num_served).min(1)];
+    *num_served += 1;
+
+    HttpResponse::Ok().json(opening)
+}
+
+// the fake keeps no root history, so every opening is refreshed in full
+async fn fake_merkle_delta() -> HttpResponse {
+    HttpResponse::Gone().finish()
+}
+
+async fn fake_payment(
+    state: web::Data<FakeSequencer>,
+    tx: web::Json<protocol::PaymentTxBs58>
+) -> HttpResponse {
+    let inputs = &tx.payment_proof.public_inputs;
+    let root = (
+        inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize].clone(),
+        inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize].clone(),
+    );
+
+    if root == state.fresh_root {
+        HttpResponse::Ok().json(protocol::TxResponse::accepted(0))
+    } else {
+        HttpResponse::Ok().json(protocol::TxResponse::rejected(protocol::UNKNOWN_ROOT_ERROR))
+    }
+}
+
+// starts a fake sequencer on a free port and returns its url
+fn spawn_fake_sequencer() -> String {
+    let (_, vc_params, crs) = utils::trusted_setup();
+    let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    let mut db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &vec![dummy; 1 << MERKLE_TREE_LEVELS]);
+
+    let opening = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>| {
+        protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&JZVectorCommitmentOpeningProof {
+            root: db.commitment(),
+            record: db.get_record(0).clone(),
+            path: db.proof(0),
+        })
+    };
+
+    db.update(0, &alice_input_coin(&protocol::AssetId(1), 10).commitment().into_affine());
+    let stale = opening(&db);
+
+    // someone else's coin lands while the client is proving
+    db.update(1, &alice_output_coin(&protocol::AssetId(1), 10).commitment().into_affine());
+    let fresh = opening(&db);
+    let fresh_root = (
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().x),
+        protocol::encode_constraintf_as_bs58_str(&db.commitment().y),
+    );
+
+    let state = web::Data::new(FakeSequencer {
+        openings: [stale, fresh],
+        fresh_root,
+        num_served: Mutex::new(0),
+    });
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .route("/merkle", web::get().to(fake_merkle))
+            .route("/merkle_delta", web::post().to(fake_merkle_delta))
+            .route("/payment", web::post().to(fake_payment))
+    })
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    url
+}
+
+// a stand-in for the prover, which commits to the root it was given
+fn fake_payment_proof(merkle_proof: &MerkleProof) -> protocol::GrothProofBs58 {
+    protocol::GrothProofBs58 {
+        circuit: protocol::CircuitKind::Payment,
+        proof: String::new(),
+        public_inputs: vec![
+            protocol::encode_constraintf_as_bs58_str(&merkle_proof.root.x),
+            protocol::encode_constraintf_as_bs58_str(&merkle_proof.root.y),
+        ],
+    }
+}
+
+#[derive(Default)]
+struct RecordedProgress(Vec<String>);
+
+impl ProgressSink for RecordedProgress {
+    fn progress(&mut self, message: &str) {
+        self.0.push(message.to_string());
+    }
+}
+
+#[test]
+fn test_timing_report() {
+    let response = protocol::TxResponse::accepted(250);
+    let report = timing_report(
+        Duration::from_secs(3),
+        Duration::from_millis(400),
+        Some(&response)
+    );
+
+    assert_eq!(report.proof_generation, Duration::from_secs(3));
+    assert_eq!(report.round_trip, Duration::from_millis(400));
+    assert_eq!(report.server_verification, Some(Duration::from_millis(250)));
+
+    // a rejected tx carries no server-side timing
+    let response = protocol::TxResponse::rejected("duplicate nullifier");
+    let report = timing_report(Duration::ZERO, Duration::ZERO, Some(&response));
+    assert!(report.server_verification.is_none());
+}
+
+#[test]
+fn test_build_payment_conserves_value() {
+    let (prf_params, _, _) = utils::trusted_setup();
+    let (sk, _) = alice_key();
+
+    let input_coin = alice_input_coin(&protocol::AssetId(1), 10);
+    let (recipient_coin, change_coin) = build_payment(&input_coin, &bob_key().1, 6, 1, &sk).unwrap();
+
+    // input == amount + change + fee
+    assert_eq!(protocol::amount_u64(&recipient_coin), 6);
+    assert_eq!(protocol::amount_u64(&change_coin), 3);
+    assert_eq!(
+        protocol::amount_u64(&input_coin),
+        protocol::amount_u64(&recipient_coin) + protocol::amount_u64(&change_coin) + 1
+    );
+
+    // change goes back to the sender, and each output gets its own rho
+    let owner = protocol::UtxoField::OWNER as usize;
+    assert_eq!(recipient_coin.fields[owner], bob_key().1.to_vec());
+    assert_eq!(change_coin.fields[owner], utils::derive_pubkey(&prf_params, &sk).to_vec());
+    let rho = protocol::UtxoField::RHO as usize;
+    assert_ne!(recipient_coin.fields[rho], change_coin.fields[rho]);
+
+    // spending more than the input holds is refused
+    assert!(build_payment(&input_coin, &bob_key().1, 10, 1, &sk).is_err());
+    assert!(build_payment(&input_coin, &bob_key().1, u64::MAX, 1, &sk).is_err());
+
+    // as is spending a coin whose asset id has a byte set past the u64, so
+    // that the outputs carry the asset under its one encoding
+    let (_, _, crs) = utils::trusted_setup();
+    let mut fields = input_coin.fields.clone();
+    fields[protocol::UtxoField::ASSETID as usize][protocol::ASSET_ID_BYTES] = 1;
+    let dirty_coin = JZRecord::<5>::new(&crs, &fields, &input_coin.blind);
+    assert_eq!(build_payment(&dirty_coin, &bob_key().1, 6, 1, &sk).unwrap_err(), "asset id does not fit in 8 bytes");
+}
+
+#[actix_web::test]
+async fn test_pay_reproves_on_stale_root() {
+    let url = spawn_fake_sequencer();
+
+    let mut proven_roots = Vec::new();
+    let mut progress = RecordedProgress::default();
+    let outcome = pay(&url, &url, 0, 0, None, 2, |merkle_proof| {
+        proven_roots.push(merkle_proof.root);
+        let proof = fake_payment_proof(&merkle_proof);
+        async move { Ok(proof) }
+    }, &mut progress).await.unwrap();
+
+    // rejected against the stale root, then accepted against the fresh one
+    assert!(outcome.response.unwrap().accepted);
+    assert_eq!(outcome.reproofs, 1);
+    assert_eq!(proven_roots.len(), 2);
+    assert_ne!(proven_roots[0], proven_roots[1]);
+    assert_eq!(progress.0.iter().filter(|m| m.contains("re-proving")).count(), 1);
+
+    // with no re-proofs allowed, the stale-root rejection is surfaced as is
+    let url = spawn_fake_sequencer();
+    let outcome = pay(&url, &url, 0, 0, None, 0, |merkle_proof| {
+        let proof = fake_payment_proof(&merkle_proof);
+        async move { Ok(proof) }
+    }, &mut RecordedProgress::default()).await.unwrap();
+    assert!(is_stale_root_rejection(&outcome.response));
+    assert_eq!(outcome.reproofs, 0);
+}
+
+#[actix_web::test]
+async fn test_submit_bundle_file() {
+    let url = spawn_fake_sequencer();
+    let stale = request_merkle_proof(&url, 0, 0).await.unwrap();
+    let fresh = request_merkle_proof(&url, 0, 0).await.unwrap();
+
+    // a payment proven offline travels as a file, and is submitted as it is
+    let path = std::env::temp_dir().join(format!("sanctum-bundle-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let bundle = |merkle_proof: &MerkleProof| protocol::PaymentTxBs58 {
+        payment_proof: fake_payment_proof(merkle_proof),
+        memo: None,
+    };
+
+    std::fs::write(path, serde_json::to_string(&bundle(&fresh)).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&read_proof_bundle(path).unwrap()).unwrap(),
+        serde_json::to_string(&bundle(&fresh)).unwrap()
+    );
+    assert!(submit_bundle_file(path, &url).await.unwrap().accepted);
+
+    // the sequencer's verdict on a bundle comes back unchanged
+    std::fs::write(path, serde_json::to_string(&bundle(&stale)).unwrap()).unwrap();
+    let response = submit_bundle_file(path, &url).await.unwrap();
+    assert_eq!(response.error.as_deref(), Some(protocol::UNKNOWN_ROOT_ERROR));
+
+    // a file that isn't a bundle is refused before anything is sent
+    std::fs::write(path, "{}").unwrap();
+    assert!(submit_bundle_file(path, &url).await.unwrap_err().contains("not a payment bundle"));
+    std::fs::remove_file(path).unwrap();
+    assert!(submit_bundle_file(path, &url).await.is_err());
+}
+
+#[test]
+fn test_precheck_payment() {
+    let (_, vc_params, crs) = utils::trusted_setup();
+    let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    let mut db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &vec![dummy; 1 << MERKLE_TREE_LEVELS]);
+    let opening = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>| JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(0).clone(),
+        path: db.proof(0),
+    };
+
+    db.update(0, &alice_input_coin(&protocol::AssetId(1), 10).commitment().into_affine());
+    let merkle_proof = opening(&db);
+    db.update(1, &alice_output_coin(&protocol::AssetId(1), 10).commitment().into_affine());
+    let other_merkle_proof = opening(&db);
+
+    // a statement laid out as the payment circuit's, around a placeholder proof
+    let commitment = alice_output_coin(&protocol::AssetId(1), 10).commitment().into_affine();
+    let public_inputs = vec![
+        merkle_proof.root.x,
+        merkle_proof.root.y,
+        ark_bw6_761::Fr::from(7u64),
+        commitment.x,
+        commitment.y,
+        ark_bw6_761::Fr::from(0u64),
+        ark_bw6_761::Fr::from(0u64),
+    ];
+    let payment = |inputs: &Vec<ark_bw6_761::Fr>| protocol::groth_proof_to_bs58(
+        protocol::CircuitKind::Payment, &ark_groth16::Proof::<BW6_761>::default(), inputs
+    );
+    let statement = protocol::StatementParams::default();
+    assert!(precheck_payment(&payment(&public_inputs), &merkle_proof, &statement).is_ok());
+
+    // proven against a different root than the opening's
+    let error = precheck_payment(&payment(&public_inputs), &other_merkle_proof, &statement).unwrap_err();
+    assert!(error.contains("root"));
+
+    // an output commitment that is not a curve point
+    let mut off_curve = public_inputs.clone();
+    off_curve[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize] += ark_bw6_761::Fr::from(1u64);
+    let error = precheck_payment(&payment(&off_curve), &merkle_proof, &statement).unwrap_err();
+    assert!(error.contains("not on the curve"));
+
+    // too few public inputs, also for a statement exposing the leaf index, or another circuit's
+    let error = precheck_payment(&payment(&public_inputs[..6].to_vec()), &merkle_proof, &statement).unwrap_err();
+    assert!(error.contains("public inputs"));
+    let exposing = protocol::StatementParams { expose_leaf_index: true, ..statement };
+    let error = precheck_payment(&payment(&public_inputs), &merkle_proof, &exposing).unwrap_err();
+    assert!(error.contains("public inputs"));
+    let mut swap = payment(&public_inputs);
+    swap.circuit = protocol::CircuitKind::Swap;
+    assert!(precheck_payment(&swap, &merkle_proof, &statement).is_err());
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    // sync queries follow the sequencer unless pointed elsewhere
+    let parsed = parse_args(&args(&["client"])).unwrap();
+    assert_eq!(parsed, ClientArgs {
+        sequencer_url: DEFAULT_SEQUENCER_URL.to_string(),
+        sync_url: DEFAULT_SEQUENCER_URL.to_string(),
+        max_reproofs: DEFAULT_MAX_REPROOFS,
+        onramp_amount: None,
+        assets: None,
+        amount: None,
+        asset: None,
+        verifier_url: DEFAULT_VERIFIER_URL.to_string(),
+        verify_receipt: None,
+        submit_file: None,
+        prove_balance: None,
+    });
+    let parsed = parse_args(&args(&["client", "--sequencer-url", "http://leader"])).unwrap();
+    assert_eq!(parsed.sync_url, "http://leader");
+
+    let parsed = parse_args(&args(&[
+        "client", "--sync-url", "http://replica", "--sequencer-url", "http://leader", "--max-reproofs", "5"
+    ])).unwrap();
+    assert_eq!(parsed, ClientArgs {
+        sequencer_url: "http://leader".to_string(),
+        sync_url: "http://replica".to_string(),
+        max_reproofs: 5,
+        onramp_amount: None,
+        assets: None,
+        amount: None,
+        asset: None,
+        verifier_url: DEFAULT_VERIFIER_URL.to_string(),
+        verify_receipt: None,
+        submit_file: None,
+        prove_balance: None,
+    });
+    let parsed = parse_args(&args(&["client", "--onramp-amount", "35"])).unwrap();
+    assert_eq!(parsed.onramp_amount, Some(35));
+    let parsed = parse_args(&args(&["client", "--submit-file", "payment.json"])).unwrap();
+    assert_eq!(parsed.submit_file.as_deref(), Some("payment.json"));
+
+    assert!(parse_args(&args(&["client", "--sync-url"])).is_err());
+    assert!(parse_args(&args(&["client", "--max-reproofs"])).is_err());
+    assert!(parse_args(&args(&["client", "--max-reproofs", "many"])).is_err());
+    assert!(parse_args(&args(&["client", "--onramp-amount", "-5"])).is_err());
+
+    // a decimal amount is resolved against the registry, and needs an asset to do so
+    let parsed = parse_args(&args(&["client", "--assets", "assets.json", "--amount", "1.5", "--asset", "USDC"])).unwrap();
+    let registry = lib_sanctum::assets::AssetRegistry::from_json(&format!(
+        r#"[{{"symbol": "USDC", "asset_id": "{}", "decimals": 6}}]"#, bs58::encode(protocol::AssetId(7).to_field_bytes()).into_string()
+    )).unwrap();
+    assert_eq!(resolve_payment(&parsed, &registry), Ok((protocol::AssetId(7), 1_500_000)));
+    assert_eq!(resolve_payment(&parse_args(&args(&["client"])).unwrap(), &registry), Ok((protocol::AssetId(1), 10)));
+    assert!(parse_args(&args(&["client", "--amount", "1.5"])).is_err());
+
+    // a balance to prove is resolved the same way, or taken as is without an asset
+    let parsed = parse_args(&args(&[
+        "client", "--assets", "assets.json", "--amount", "2", "--asset", "USDC", "--prove-balance", "1.25"
+    ])).unwrap();
+    assert_eq!(resolve_threshold(&parsed, &registry, parsed.prove_balance.as_ref().unwrap()), Ok(1_250_000));
+    let parsed = parse_args(&args(&["client", "--prove-balance", "7"])).unwrap();
+    assert_eq!(resolve_threshold(&parsed, &registry, "7"), Ok(7));
+    assert!(resolve_threshold(&parsed, &registry, "7.5").is_err());
+}
+
+#[test]
+fn test_keygen() {
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+
+    let (prf_params, vc_params, crs) = utils::trusted_setup();
+    let (sk, pk) = generate_keypair();
+    assert_eq!(pk, utils::derive_pubkey(&prf_params, &sk));
+    assert_ne!(generate_keypair().0, sk);
+
+    // the coin to onramp is owned by the pk, and the payment circuit lets the
+    // sk, and no other key, spend it
+    let coin = onramp_coin_for(&pk, &protocol::AssetId(1), 10);
+    assert_eq!(coin.fields[protocol::UtxoField::OWNER as usize], pk.to_vec());
+
+    let (_, db_params, _) = utils::trusted_setup();
+    let dummy = utils::get_dummy_utxo(&crs).commitment().into_affine();
+    let mut db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(db_params, &vec![dummy; 1 << MERKLE_TREE_LEVELS]);
+    db.update(0, &coin.commitment().into_affine());
+    let merkle_proof = JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(0).clone(),
+        path: db.proof(0),
+    };
+
+    let output_coin = alice_output_coin(&protocol::AssetId(1), 10);
+    for (spending_key, satisfied) in [(sk, true), (generate_keypair().0, false)] {
+        let circuit = payment_circuit::PaymentCircuit {
+            crs: &crs,
+            prf_params: &prf_params,
+            vc_params: &vc_params,
+            input_utxo: &coin,
+            output_utxo: &output_coin,
+            sk: spending_key,
+            asset_blind: [0u8; 32],
+            pool_id: None,
+            tree_id: 0,
+            expose_leaf_index: false,
+            unspent_coin_existence_proof: &merkle_proof,
+        };
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.is_satisfied().unwrap(), satisfied);
+    }
+
+    // the identity file is readable by its owner alone, and never overwritten
+    let path = std::env::temp_dir().join(format!("sanctum-keygen-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    write_secret_file(path, "{}").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    assert!(write_secret_file(path, "{}").is_err());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,95 @@
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+// the services listen on fixed local ports
+const VERIFIER_ADDR: &str = "127.0.0.1:8081";
+const SEQUENCER_ADDR: &str = "127.0.0.1:8080";
+
+// the setup, and the services reading the proving keys, take a while
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(600);
+
+const STATE_DIR: &str = "/tmp/sanctum";
+
+/// devnet runs the setup once, brings up a local verifier and sequencer
+/// sharing the keys it wrote, waits for them to come up, and then drives
+/// the client through an on-ramp and a payment so that the client's alice
+/// and bob are left holding funded notes. The services keep running until
+/// the devnet is interrupted.
+///
+/// usage: devnet [--reset]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--reset") {
+        println!("wiping {}...", STATE_DIR);
+        let _ = std::fs::remove_dir_all(STATE_DIR);
+    }
+    std::fs::create_dir_all(STATE_DIR).unwrap();
+
+    // the keys are set up once, and every component reads them from there
+    // rather than each running its own setup
+    if !Path::new(STATE_DIR).join("manifest.json").exists() {
+        let status = sibling("setup").status().expect("failed to run setup");
+        assert!(status.success(), "setup exited with {}", status);
+    }
+
+    // the verifier must be up before the sequencer forwards anything to it
+    let mut verifier = spawn_sibling("verifier");
+    wait_for_listener(VERIFIER_ADDR);
+
+    let mut sequencer = spawn_sibling("sequencer");
+    wait_for_listener(SEQUENCER_ADDR);
+
+    println!("running client to fund a test wallet...");
+    let status = sibling("client")
+        .status()
+        .expect("failed to run client");
+
+    if !status.success() {
+        let _ = sequencer.kill();
+        let _ = verifier.kill();
+        panic!("client exited with {}", status);
+    }
+
+    println!("devnet is up:");
+    println!("  sequencer: http://{}", SEQUENCER_ADDR);
+    println!("  verifier:  http://{}", VERIFIER_ADDR);
+    println!("  state:     {}", STATE_DIR);
+    println!("  wallets:   the client's alice and bob");
+
+    // block on the services; they exit when the devnet is interrupted
+    let _ = sequencer.wait();
+    let _ = verifier.wait();
+}
+
+// the service binaries are built alongside this one
+fn sibling_path(name: &str) -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.set_file_name(name);
+    path
+}
+
+// a sibling binary, reading the keys setup wrote into the state dir
+fn sibling(name: &str) -> Command {
+    let mut command = Command::new(sibling_path(name));
+    command.env("SANCTUM_KEY_DIR", STATE_DIR);
+    command
+}
+
+fn spawn_sibling(name: &str) -> Child {
+    println!("starting {}...", name);
+    sibling(name)
+        .spawn()
+        .unwrap_or_else(|e| panic!("failed to start {}: {}", name, e))
+}
+
+fn wait_for_listener(addr: &str) {
+    let start = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        if start.elapsed() > STARTUP_TIMEOUT {
+            panic!("timed out waiting for {}", addr);
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,62 @@
+use lib_sanctum::{keys, utils};
+
+// usage:
+//     keytool inspect <file.pk | file.vk>
+//     keytool compare <a.vk> <b.vk>
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.iter().skip(1).map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["inspect", path] => inspect(path),
+        ["compare", a, b] => compare(a, b),
+        _ => {
+            eprintln!("usage: {} inspect <file.pk | file.vk>", args[0]);
+            eprintln!("       {} compare <a.vk> <b.vk>", args[0]);
+            std::process::exit(2);
+        }
+    }
+}
+
+// a proving key embeds its verifying key, which is all there is to report
+fn inspect(path: &str) {
+    let vk = if path.ends_with(".pk") {
+        read_or_exit(utils::read_groth_proving_key_from_file(path)).vk
+    } else {
+        read_or_exit(utils::read_groth_verification_key_from_file(path))
+    };
+    let summary = keys::vk_summary(&vk);
+
+    println!("curve: {}", summary.curve);
+    println!("gamma_abc_g1 elements: {}", vk.gamma_abc_g1.len());
+    println!("public inputs: {}", summary.num_public_inputs);
+    println!("sha256: {}", hex::encode(summary.hash));
+    if summary.candidates.is_empty() {
+        println!("circuit: none of the protocol circuits has {} public inputs", summary.num_public_inputs);
+    } else {
+        println!("circuit: {}", summary.candidates.join(" or "));
+    }
+}
+
+fn compare(a: &str, b: &str) {
+    let diffs = keys::vk_diff(
+        &read_or_exit(utils::read_groth_verification_key_from_file(a)),
+        &read_or_exit(utils::read_groth_verification_key_from_file(b))
+    );
+    if diffs.is_empty() {
+        println!("match");
+        return;
+    }
+
+    println!("mismatch");
+    for diff in diffs.iter() {
+        println!("{}", diff);
+    }
+    std::process::exit(1);
+}
+
+fn read_or_exit<K>(key: Result<K, String>) -> K {
+    key.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    })
+}
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+
+use lib_sanctum::corpus::{self, CorpusBs58};
+use lib_sanctum::protocol;
+use lib_sanctum::utils;
+
+const SEQUENCER_URL: &str = "http://127.0.0.1:8080";
+
+// generated corpora are cached here, one file per size
+const STATE_DIR: &str = "/tmp/sanctum";
+
+/// sanctum-loadgen funds n coins on a running (fresh) sequencer, then submits
+/// n payments spending them, `concurrency` at a time, and reports payment
+/// latency percentiles and the acceptance rate. The proofs are generated
+/// once per n and cached on disk. They are valid in submission order, so a
+/// concurrency above 1 may see payments land out of order and get rejected.
+///
+/// usage: sanctum-loadgen <n> <concurrency>
+#[tokio::main]
+async fn main() -> reqwest::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let parsed = if args.len() == 3 {
+        args[1].parse::<usize>().ok().zip(args[2].parse::<usize>().ok().filter(|&c| c > 0))
+    } else {
+        None
+    };
+    let (n, concurrency) = match parsed {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("usage: {} <n> <concurrency>", args[0]);
+            std::process::exit(2);
+        }
+    };
+
+    let corpus = load_or_generate_corpus(n);
+    let client = Client::new();
+
+    // funding is setup rather than load, so it goes through one tx at a time
+    println!("funding {} coins...", n);
+    for tx in corpus.onramps.iter() {
+        let (_, accepted) = submit(&client, "onramp", tx).await?;
+        if !accepted {
+            eprintln!("onramp rejected; is the sequencer fresh?");
+            std::process::exit(1);
+        }
+    }
+
+    println!("submitting {} payments, {} at a time...", n, concurrency);
+    let now = Instant::now();
+    let mut latencies = Vec::with_capacity(n);
+    let mut num_accepted = 0;
+    for batch in corpus.payments.chunks(concurrency) {
+        let handles: Vec<_> = batch
+            .iter()
+            .cloned()
+            .map(|tx| {
+                let client = client.clone();
+                tokio::spawn(async move { submit(&client, "payment", &tx).await })
+            })
+            .collect();
+
+        for handle in handles {
+            let (latency, accepted) = handle.await.unwrap()?;
+            latencies.push(latency);
+            if accepted { num_accepted += 1; }
+        }
+    }
+    let elapsed = now.elapsed();
+
+    latencies.sort();
+    println!("throughput: {:.2} tx/s", n as f64 / elapsed.as_secs_f64());
+    println!("accepted: {}/{} ({:.1}%)", num_accepted, n, 100.0 * num_accepted as f64 / n.max(1) as f64);
+    for p in [50.0, 90.0, 99.0] {
+        println!("p{}: {:?}", p, percentile(&latencies, p));
+    }
+
+    Ok(())
+}
+
+async fn submit<T: serde::Serialize>(
+    client: &Client,
+    route: &str,
+    tx: &T
+) -> reqwest::Result<(Duration, bool)> {
+    let now = Instant::now();
+    let response = client.post(format!("{}/{}", SEQUENCER_URL, route))
+        .json(tx)
+        .send()
+        .await?;
+    let latency = now.elapsed();
+
+    let accepted = response.status().is_success() &&
+        response.json::<protocol::TxResponse>().await?.accepted;
+
+    Ok((latency, accepted))
+}
+
+fn load_or_generate_corpus(n: usize) -> CorpusBs58 {
+    let path = PathBuf::from(STATE_DIR).join(format!("corpus-{}.json", n));
+
+    if let Ok(bytes) = std::fs::read(&path) {
+        println!("loading corpus from {}", path.display());
+        return serde_json::from_slice(&bytes).expect("corrupt corpus file");
+    }
+
+    println!("generating a corpus of {} payments; this takes a while...", n);
+    // proven under the params the sequencer is configured with, as its keys were
+    let corpus = corpus::generate(n, &utils::statement_params()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+
+    std::fs::create_dir_all(STATE_DIR).unwrap();
+    std::fs::write(&path, serde_json::to_vec(&corpus).unwrap()).unwrap();
+    println!("cached corpus at {}", path.display());
+
+    corpus
+}
+
+// nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -0,0 +1,97 @@
+//! The prover service generates payment proofs on behalf of thin clients
+//! (e.g. mobile or browser wallets) that cannot run the BW6_761 prover.
+//!
+//! Trust implications: the prover necessarily sees the entire witness --
+//! both coins, the merkle path, and the spender's secret key -- so whoever
+//! operates it can spend the client's coins and link its transactions.
+//! Only delegate to a prover you control (ideally self-hosted), and only
+//! reach it over TLS; this service binds to localhost, so TLS should be
+//! terminated by a proxy in front of it. The client submits the returned
+//! proof to the sequencer itself.
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use tracing::info;
+
+use ark_bw6_761::BW6_761;
+use ark_groth16::*;
+
+use lib_sanctum::config::{self, ProverConfig};
+use lib_sanctum::{payment_circuit, protocol, registry, utils};
+
+pub struct AppStateType {
+    payment_pk: ProvingKey<BW6_761>,
+    // the params payment_pk was set up under
+    statement: protocol::StatementParams,
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    config::init_from_args::<ProverConfig>(&mut std::env::args().collect());
+
+    let app_state = web::Data::new(initialize_state());
+    info!("zkBricks prover listening for requests...");
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().limit(
+                utils::body_limit("SANCTUM_PROOF_BODY_LIMIT", utils::DEFAULT_PROOF_BODY_LIMIT)
+            ))
+            .route("/prove/payment", web::post().to(serve_payment_proof_request))
+    })
+    .bind(("127.0.0.1", 8082))?
+    .run()
+    .await
+}
+
+async fn serve_payment_proof_request(
+    state: web::Data<AppStateType>,
+    witness: web::Json<protocol::PaymentWitnessBs58>
+) -> HttpResponse {
+    // proving is CPU-bound, so keep it off the async workers
+    let proof = web::block(move || prove_payment(&state.payment_pk, &state.statement, &witness.into_inner())).await;
+
+    match proof {
+        Ok(Ok(proof)) => HttpResponse::Ok().json(proof),
+        Ok(Err(e)) => HttpResponse::BadRequest().body(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn prove_payment(
+    pk: &ProvingKey<BW6_761>,
+    statement: &protocol::StatementParams,
+    witness: &protocol::PaymentWitnessBs58
+) -> Result<protocol::GrothProofBs58, String> {
+    let (_, _, crs) = utils::trusted_setup();
+
+    let sk: [u8; 32] = bs58::decode(&witness.sk)
+        .into_vec()
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "secret key must be 32 bytes".to_string())?;
+
+    let (proof, public_inputs) = payment_circuit::generate_groth_proof(
+        pk,
+        statement,
+        &protocol::record_from_bs58(&crs, &witness.input_utxo)?,
+        &protocol::record_from_bs58(&crs, &witness.output_utxo)?,
+        &protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_from_bs58(
+            &witness.unspent_coin_existence_proof
+        ),
+        &sk,
+        witness.tree_id
+    );
+
+    Ok(protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs))
+}
+
+fn initialize_state() -> AppStateType {
+    let statement = utils::statement_params();
+    let (payment_pk, _) = registry::keys(protocol::CircuitKind::Payment, &statement)
+        .expect("unable to load the payment keys");
+
+    AppStateType { payment_pk, statement }
+}
+
+mod test;
@@ -0,0 +1,51 @@
+#![cfg(test)]
+
+use ark_ec::CurveGroup;
+use ark_snark::SNARK;
+
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    JZVectorDB, JZVectorCommitmentOpeningProof,
+    config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+
+use lib_sanctum::protocol::MERKLE_TREE_LEVELS;
+use lib_sanctum::test_coins;
+
+use super::*;
+
+#[test]
+fn test_prove_then_submit() {
+    let (prf_params, vc_params, crs) = utils::trusted_setup();
+    let sk = [20u8; 32];
+    let pk = utils::derive_pubkey(&prf_params, &sk);
+
+    // the sender's coin sits at leaf 0 of an otherwise dummy tree
+    let input_utxo = test_coins::coin(&pk, 10, 1);
+    let output_utxo = test_coins::coin(&[7u8; 31], 10, 2);
+    let mut records: Vec<ark_bls12_377::G1Affine> = (0..(1 << MERKLE_TREE_LEVELS))
+        .map(|_| utils::get_dummy_utxo(&crs).commitment().into_affine())
+        .collect();
+    records[0] = input_utxo.commitment().into_affine();
+    let db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records);
+    let merkle_proof = JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(0).clone(),
+        path: db.proof(0),
+    };
+
+    let witness = protocol::PaymentWitnessBs58 {
+        input_utxo: protocol::record_to_bs58(&input_utxo),
+        output_utxo: protocol::record_to_bs58(&output_utxo),
+        unspent_coin_existence_proof:
+            protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&merkle_proof),
+        sk: bs58::encode(&sk).into_string(),
+        tree_id: 0,
+    };
+
+    let state = initialize_state();
+    let proof = prove_payment(&state.payment_pk, &state.statement, &witness).unwrap();
+
+    // the returned proof is exactly what the sequencer would verify on submission
+    let (proof, public_inputs) = protocol::groth_proof_from_bs58(&proof);
+    assert!(Groth16::<BW6_761>::verify(&state.payment_pk.vk, &public_inputs, &proof).unwrap());
+}
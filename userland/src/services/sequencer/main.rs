@@ -1,252 +1,2909 @@
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::error::JsonPayloadError;
+use futures::StreamExt;
+use futures::channel::mpsc::UnboundedSender;
+use utoipa::OpenApi;
 use reqwest::Client;
+use serde::Deserialize;
+use tracing::{debug, error, info, warn};
 
-use ark_ec::CurveGroup;
 use ark_bw6_761::BW6_761;
+use ark_ec::CurveGroup;
 use ark_groth16::*;
 use ark_snark::SNARK;
 
 use std::borrow::BorrowMut;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::sync::Mutex;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use lib_sanctum::protocol;
 
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::*;
 use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
 
+use lib_sanctum::apps::{self, AppRegistry};
+use lib_sanctum::config::{self, SequencerConfig};
+use lib_sanctum::key_reload::{self, KeyReload, VkFingerprint};
+use lib_sanctum::keys;
+use lib_sanctum::merkle_delta::{self, MerkleDeltaBs58, MerkleDeltaRequestBs58};
 use lib_sanctum::merkle_update_circuit;
+use lib_sanctum::multiproof::{self, MultiProofBs58};
+use lib_sanctum::onramp_multi_circuit;
+use lib_sanctum::nullifiers::NullifierStore;
+use lib_sanctum::policy;
+use lib_sanctum::receipts;
+use lib_sanctum::registry;
+use lib_sanctum::root_history::MerkleRootHistory;
+use lib_sanctum::tickets::{self, TicketStore};
+use lib_sanctum::transfers::{self, PeerPools};
 use lib_sanctum::utils;
+use lib_sanctum::verification_cache::VerificationCache;
+use lib_sanctum::verifiers::{Verdict, VerifierSet};
+use lib_sanctum::protocol::MERKLE_TREE_LEVELS;
 
-// define the depth of the merkle tree as a constant
-const MERKLE_TREE_LEVELS: u32 = 8;
+// where a read-only replica listens, so it can share a host with its leader
+const REPLICA_PORT: u16 = 8083;
 
+// how often a read-only replica pulls new leaves from its leader
+const REPLICATION_INTERVAL: Duration = Duration::from_secs(1);
 
-pub struct AppStateType {
-    onramp_vk: VerifyingKey<BW6_761>,
-    payment_vk: VerifyingKey<BW6_761>,
+// how many leaves one multiproof may open, so that a single request can't
+// hold the lock while the sequencer opens the whole tree
+const MAX_MULTIPROOF_LEAVES: usize = 256;
+
+// how long a proposed swap stays in escrow, waiting for its matching half
+const SWAP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// SequencerKeys verify submitted txs and prove tree updates; a read-only
+/// replica takes no txs, and never loads them
+pub struct SequencerKeys {
+    // verifying keys are preprocessed once, rather than on every verification
+    onramp_pvk: PreparedVerifyingKey<BW6_761>,
+    onramp_multi_pvk: PreparedVerifyingKey<BW6_761>,
+    payment_pvk: PreparedVerifyingKey<BW6_761>,
+    swap_pvk: PreparedVerifyingKey<BW6_761>,
+    transfer_pvk: PreparedVerifyingKey<BW6_761>,
     merkle_update_pk: ProvingKey<BW6_761>,
+}
+
+impl SequencerKeys {
+    fn fingerprints(&self) -> Vec<VkFingerprint> {
+        vec![
+            key_reload::fingerprint(protocol::CircuitKind::Onramp, &self.onramp_pvk.vk),
+            key_reload::fingerprint(protocol::CircuitKind::OnrampMulti, &self.onramp_multi_pvk.vk),
+            key_reload::fingerprint(protocol::CircuitKind::Payment, &self.payment_pvk.vk),
+            key_reload::fingerprint(protocol::CircuitKind::Swap, &self.swap_pvk.vk),
+            key_reload::fingerprint(protocol::CircuitKind::Transfer, &self.transfer_pvk.vk),
+            key_reload::fingerprint(protocol::CircuitKind::MerkleUpdate, &self.merkle_update_pk.vk),
+        ]
+    }
+}
+
+pub struct AppStateType {
+    // None on a read-only replica
+    keys: Option<SequencerKeys>,
+    // version of the last key reload applied, kept across restarts if
+    // SANCTUM_KEY_RELOAD_FILE is set; 0 until one is applied
+    keys_version: u64,
+    // set on a read-only replica, whose write routes point clients here
+    leader_url: Option<String>,
+
+    // the active tree, into which coins are inserted; every tree before it is
+    // retired, and kept in retired_trees so that its coins remain spendable
+    tree_id: u32,
+    retired_trees: Vec<RetiredTree>,
 
-    db: JZVectorDB<MTParams, ark_bls12_377::G1Affine>, //leaves of sha256 hashes
+    db: JZVectorDB<MTParams, ark_bls12_377::G1Affine>, //leaves are coin commitments
+    // bs58 encoding of db.commitment(), refreshed whenever the db changes
+    root_bs58: String,
+    // roots a payment may prove membership against, over the same window as the verifier
+    root_history: MerkleRootHistory,
     //merkle_tree_frontier: FrontierMerkleTreeWithHistory,
     num_coins: usize,
+    // is_real_leaf[i] is true iff leaf i holds a coin rather than a dummy
+    is_real_leaf: Vec<bool>,
+    // inserted_at[i] is the unix time, in seconds, at which coin i was inserted
+    inserted_at: Vec<u64>,
+    // the (first) leaf holding each coin, keyed by its compressed commitment;
+    // kept by insert_leaf, so it is rebuilt along with the tree on recovery
+    leaf_indices: HashMap<Vec<u8>, usize>,
+    nullifiers: NullifierStore,
+    // payment memos, keyed by the index of the leaf created by the payment
+    memos: HashMap<usize, String>,
+    // compliance policy, and the operator key that policy updates and app
+    // registrations must be signed by
+    policy: policy::Policy,
+    operator_key: Option<ed25519_dalek::VerifyingKey>,
+    // the sequencer's own key, which it signs ordering proofs with if set;
+    // transfers out of this pool are refused without it, as their exports are signed
+    signing_key: Option<ed25519_dalek::SigningKey>,
+    // the params this pool's keys were set up under; its pool id is the one
+    // transfers name as their destination, and unset, the pool takes no
+    // transfers in or out
+    statement: protocol::StatementParams,
+    // the pools transfers are taken from and sent to
+    peers: PeerPools,
+    // the tree and leaf each imported transfer inserted its coin at, by
+    // transfers::export_id, so that no transfer is imported twice
+    imports: HashMap<String, (u32, usize)>,
+    // circuits registered by third-party apps
+    apps: AppRegistry,
+    // if SANCTUM_TX_LOG is set, every accepted tx is appended to it as a json line
+    tx_log: Option<std::fs::File>,
+    // recent verification verdicts, so a resubmitted proof is only paired once
+    verification_cache: VerificationCache,
+    // proposed swaps awaiting their matching half, keyed by swap id
+    swaps: HashMap<String, PendingSwap>,
+    // outcomes of txs submitted under an idempotency key, rebuilt from the tx log
+    tickets: TicketStore,
+    // the verifiers every accepted tx is forwarded to, a quorum of which must accept it
+    verifiers: VerifierSet,
+}
+
+// a swap half held in escrow; nothing of it is applied until the swap settles
+struct PendingSwap {
+    proposal: protocol::SwapHalfBs58,
+    expires_at: Instant,
+    // the proposal's moves, up to being escrowed
+    history: protocol::TxHistory,
+}
+
+/// RetiredTree is a tree the sequencer has rolled over from: it takes no more
+/// coins, but payments may still spend its coins against its roots
+struct RetiredTree {
+    db: JZVectorDB<MTParams, ark_bls12_377::G1Affine>,
+    root_bs58: String,
+    root_history: MerkleRootHistory,
+    num_coins: usize,
+    inserted_at: Vec<u64>,
+    // the nullifiers and memos of the payments that inserted its leaves
+    spent_at: HashMap<usize, String>,
+    memos: HashMap<usize, String>,
 }
 
 struct GlobalAppState {
     state: Mutex<AppStateType>, // <- Mutex is necessary to mutate safely across threads
+    // held from applying a tx until the verifiers have answered, so that a tx
+    // they refuse is still the last one applied, and can be rolled back
+    forwarding: futures::lock::Mutex<()>,
+    // a copy of the state's statement params, which never change, so that
+    // proofs are checked against them before the lock is taken
+    statement: protocol::StatementParams,
+}
+
+impl GlobalAppState {
+    fn new(state: AppStateType) -> Self {
+        GlobalAppState {
+            statement: state.statement,
+            state: Mutex::new(state),
+            forwarding: futures::lock::Mutex::new(()),
+        }
+    }
+}
+
+/// Checkpoint is the tail of the state before a tx was applied, from which the
+/// tx is rolled back if the verifiers do not reach a quorum on it
+struct Checkpoint {
+    tree_id: u32,
+    num_coins: usize,
+    root_history: MerkleRootHistory,
+    // how many nullifiers transfers out of this pool had spent
+    num_exported: usize,
+    // the length of the tx log, if any
+    log_len: Option<u64>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zkBricks sequencer", version = "1"),
+    paths(
+        process_onramp_tx,
+        process_onramp_multi_tx,
+        process_payment_tx,
+        process_payment_batch,
+        simulate_payment_tx,
+        process_swap_proposal,
+        process_swap_acceptance,
+        process_transfer_tx,
+        process_transfer_import,
+        serve_merkle_proof_request,
+        serve_merkle_multiproof_request,
+        serve_merkle_delta_request,
+        serve_nullifier_status_request,
+        serve_fill_ratio_request,
+        serve_memo_request,
+        serve_receipt_request,
+        serve_ordering_request,
+        serve_status_request,
+        serve_verifiers_request,
+        serve_tx_status_request,
+        serve_tree_request,
+        serve_coin_events_request,
+        serve_replication_request,
+        process_policy_update,
+        process_key_reload,
+        process_app_registration,
+        process_app_tx,
+    ),
+    components(schemas(
+        protocol::GrothProofBs58,
+        protocol::PaymentTxBs58,
+        protocol::SwapHalfBs58,
+        protocol::TransferExportBs58,
+        protocol::TransferResponse,
+        protocol::TxResponse,
+        protocol::TxState,
+        protocol::TxTransition,
+        protocol::BatchItemStatus,
+        protocol::BatchItemResult,
+        protocol::BatchResponse,
+        protocol::SimulationResponse,
+        protocol::Diagnostic,
+        protocol::VectorCommitmentOpeningProofBs58,
+        multiproof::MultiProofBs58,
+        multiproof::MultiProofOpeningBs58,
+        merkle_delta::MerkleDeltaRequestBs58,
+        merkle_delta::MerkleDeltaBs58,
+        merkle_delta::AuthPathNodeBs58,
+        protocol::NullifierStatusRequestBs58,
+        protocol::NullifierStatusBs58,
+        protocol::FillRatio,
+        protocol::ReceiptBs58,
+        protocol::OrderingProofBs58,
+        protocol::SequencerStatus,
+        protocol::VerifierHealth,
+        protocol::TreeSnapshot,
+        protocol::CoinInsertedEventBs58,
+        protocol::ReplicationEventBs58,
+        protocol::ReplicationBatchBs58,
+        policy::SignedPolicy,
+        policy::Policy,
+        key_reload::SignedKeyReload,
+        key_reload::KeyReload,
+        key_reload::KeysReloaded,
+        key_reload::VkFingerprint,
+        apps::SignedAppRegistration,
+        protocol::AppRegistrationBs58,
+        protocol::AppTxBs58,
+    ))
+)]
+struct ApiDoc;
+
+// rejects malformed json (including unknown fields) with a 400 naming the problem,
+// and bodies over the limit with a 413
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit).error_handler(|err, _req| {
+        let message = err.to_string();
+        let response = match &err {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } =>
+                HttpResponse::PayloadTooLarge().body(message),
+            _ => HttpResponse::BadRequest().body(message),
+        };
+        actix_web::error::InternalError::from_response(err, response).into()
+    })
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Note: web::Data created _outside_ HttpServer::new closure
-    let app_state = web::Data::new(
-        GlobalAppState {
-            state: Mutex::new(initialize_state()),
+    // --config, and the flags of the config's settings, may accompany any of the modes below
+    let mut args: Vec<String> = std::env::args().collect();
+    config::init_from_args::<SequencerConfig>(&mut args);
+
+    // `sequencer --replay <log>` checks a tx log against a fresh sequencer, then exits
+    if args.len() == 3 && args[1] == "--replay" {
+        let log = std::fs::read_to_string(&args[2])?;
+        let mut state = initialize_state();
+        state.tx_log = None;
+        match replay_tx_log(&mut state, &log) {
+            Ok(n) => println!("replayed {} txs; final root {}", n, state.root_bs58),
+            Err(e) => {
+                eprintln!("replay failed: {}", e);
+                std::process::exit(1);
+            }
         }
-    );
-    println!("zkBricks sequencer listening for transactions...");
+        return Ok(());
+    }
+
+    // `sequencer --verify-state <log> [<tree snapshot>]` is a dry run of the
+    // same replay, which also checks the rebuilt tree against the live one,
+    // as served at /tree and saved to a file
+    if (args.len() == 3 || args.len() == 4) && args[1] == "--verify-state" {
+        let log = std::fs::read_to_string(&args[2])?;
+        let live: Option<protocol::TreeSnapshot> = match args.get(3) {
+            Some(path) => Some(serde_json::from_slice(&std::fs::read(path)?)?),
+            None => None,
+        };
+        let mut state = initialize_state();
+        state.tx_log = None;
+        match verify_state(&mut state, &log, live.as_ref()) {
+            Ok(n) => println!("verified {} txs; final root {}", n, state.root_bs58),
+            Err(e) => {
+                eprintln!("state diverges: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // `sequencer --read-only <leader url>` serves wallet sync from a replica of the leader
+    if args.len() == 3 && args[1] == "--read-only" {
+        return run_replica(&args[2]).await;
+    }
+
+    // Note: web::Data created _outside_ HttpServer::new closure
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+    info!("zkBricks sequencer listening for transactions...");
 
     HttpServer::new(move || {
         // move counter into the closure
         App::new()
             .app_data(app_state.clone()) // <- register the created data
-            .route("/onramp", web::post().to(process_onramp_tx))
-            .route("/payment", web::post().to(process_payment_tx))
-            .route("/merkle", web::get().to(serve_merkle_proof_request))
+            .configure(configure_routes)
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
 }
 
+// a replica serves the read routes off a copy of the leader's state, which a
+// background task keeps pulling from the leader
+async fn run_replica(leader_url: &str) -> std::io::Result<()> {
+    let app_state = web::Data::new(GlobalAppState::new(initialize_replica_state(leader_url)));
+    actix_web::rt::spawn(follow_leader(app_state.clone(), leader_url.to_string()));
+    info!(leader = leader_url, "zkBricks sequencer replica serving reads...");
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .configure(configure_replica_routes)
+    })
+    .bind(("127.0.0.1", REPLICA_PORT))?
+    .run()
+    .await
+}
+
+// the replication pull loop: asks the leader for every leaf past the ones we
+// hold, applies them, and checks we then agree with the leader's root
+async fn follow_leader(global_state: web::Data<GlobalAppState>, leader_url: String) {
+    let client = Client::new();
+
+    loop {
+        let (tree_id, from) = {
+            let state = global_state.state.lock().unwrap();
+            ((*state).tree_id, (*state).num_coins)
+        };
+        let batch = client.get(format!("{}/replication?tree={}", leader_url, tree_id))
+            .json(&from)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match batch {
+            Ok(response) => match response.json::<protocol::ReplicationBatchBs58>().await {
+                Ok(batch) => {
+                    let mut state = global_state.state.lock().unwrap();
+                    if let Err(e) = apply_replication_batch((*state).borrow_mut(), &batch) {
+                        // serving a tree that has diverged from the leader is worse than not serving
+                        eprintln!("replica diverged from leader: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+                Err(e) => warn!(error = %e, "malformed replication batch from leader"),
+            },
+            Err(e) => warn!(error = %e, "unable to reach leader"),
+        }
+
+        actix_web::rt::time::sleep(REPLICATION_INTERVAL).await;
+    }
+}
+
+// proof submissions get a larger body limit than queries; the defaults can be
+// overridden with SANCTUM_PROOF_BODY_LIMIT and SANCTUM_QUERY_BODY_LIMIT
+fn configure_routes(cfg: &mut web::ServiceConfig) {
+    let proof_limit = utils::body_limit("SANCTUM_PROOF_BODY_LIMIT", utils::DEFAULT_PROOF_BODY_LIMIT);
+    let batch_limit = utils::body_limit("SANCTUM_BATCH_BODY_LIMIT", utils::DEFAULT_BATCH_BODY_LIMIT);
+
+    configure_read_routes(cfg);
+    cfg
+        .service(web::resource("/onramp")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_onramp_tx)))
+        .service(web::resource("/onramp_multi")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_onramp_multi_tx)))
+        .service(web::resource("/payment")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_payment_tx)))
+        .service(web::resource("/payment/batch")
+            .app_data(json_config(batch_limit))
+            .route(web::post().to(process_payment_batch)))
+        .service(web::resource("/simulate/payment")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(simulate_payment_tx)))
+        .service(web::resource("/swap/propose")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_swap_proposal)))
+        .service(web::resource("/swap/accept")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_swap_acceptance)))
+        .service(web::resource("/transfer")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_transfer_tx)))
+        .service(web::resource("/transfer/in")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_transfer_import)))
+        .service(web::resource("/admin/policy")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_policy_update)))
+        .service(web::resource("/admin/reload-keys")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_key_reload)))
+        .service(web::resource("/apps/register")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_app_registration)))
+        .service(web::resource("/app_tx")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_app_tx)))
+        .route("/verifiers", web::get().to(serve_verifiers_request))
+        .route("/tx/{id}", web::get().to(serve_tx_status_request));
+}
+
+// a read-only replica answers every write route with a 405 naming the leader
+fn configure_replica_routes(cfg: &mut web::ServiceConfig) {
+    configure_read_routes(cfg);
+    for path in WRITE_ROUTES {
+        cfg.route(path, web::post().to(reject_write_on_replica));
+    }
+}
+
+const WRITE_ROUTES: [&str; 13] = ["/onramp", "/onramp_multi", "/payment", "/payment/batch", "/simulate/payment", "/swap/propose", "/swap/accept", "/transfer", "/transfer/in", "/admin/policy", "/admin/reload-keys", "/apps/register", "/app_tx"];
+
+fn configure_read_routes(cfg: &mut web::ServiceConfig) {
+    let query_limit = utils::body_limit("SANCTUM_QUERY_BODY_LIMIT", utils::DEFAULT_QUERY_BODY_LIMIT);
+
+    cfg
+        .app_data(json_config(query_limit))
+        .route("/openapi.json", web::get().to(serve_openapi_request))
+        .route("/merkle", web::get().to(serve_merkle_proof_request))
+        .route("/merkle/multi", web::get().to(serve_merkle_multiproof_request))
+        .route("/merkle_delta", web::post().to(serve_merkle_delta_request))
+        .route("/nullifiers/status", web::post().to(serve_nullifier_status_request))
+        .route("/fill-ratio", web::get().to(serve_fill_ratio_request))
+        .route("/memo", web::get().to(serve_memo_request))
+        .route("/receipt/{commitment}", web::get().to(serve_receipt_request))
+        .route("/ordering", web::get().to(serve_ordering_request))
+        .route("/status", web::get().to(serve_status_request))
+        .route("/tree", web::get().to(serve_tree_request))
+        .route("/events/coins", web::get().to(serve_coin_events_request))
+        .route("/replication", web::get().to(serve_replication_request));
+}
+
+async fn reject_write_on_replica(global_state: web::Data<GlobalAppState>) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let leader_url = (*state).leader_url.clone().unwrap_or_default();
+
+    HttpResponse::MethodNotAllowed().body(format!("read-only replica; submit txs to the leader at {}", leader_url))
+}
+
+async fn serve_openapi_request() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[derive(Deserialize)]
+struct TreeQuery {
+    // the tree a read is about; the active tree if unset
+    tree: Option<u32>,
+}
+
 // queries the merkle opening proof, as the L1 contract only stores the frontier merkle tree
+#[utoipa::path(get, path = "/merkle", request_body = usize,
+    params(("tree" = Option<u32>, Query, description = "the tree to open the leaf of; the active tree if unset")),
+    responses((status = 200, body = protocol::VectorCommitmentOpeningProofBs58), (status = 400)))]
 async fn serve_merkle_proof_request(
     global_state: web::Data<GlobalAppState>,
+    query: web::Query<TreeQuery>,
     index: web::Json<usize>
-) -> String {
+) -> HttpResponse {
     let state = global_state.state.lock().unwrap();
     let index: usize = index.into_inner();
 
-    let merkle_proof = 
-        JZVectorCommitmentOpeningProof::<MTParams, ark_bls12_377::G1Affine> {
-            root: (*state).db.commitment(),
-            record: (*state).db.get_record(index).clone(),
-            path: (*state).db.proof(index),
-        };
+    let tree_id = query.tree.unwrap_or((*state).tree_id);
+    let (db, root_bs58, _) = match tree_of(&state, tree_id) {
+        Some(tree) => tree,
+        None => return HttpResponse::BadRequest().body(format!("unknown tree {}", tree_id)),
+    };
+    if let Err(e) = check_leaf_index(&state, tree_id, index) {
+        return HttpResponse::BadRequest().body(e);
+    }
 
+    // encode straight from the db, reusing the cached root encoding
     let merkle_proof_bs58 = 
-        protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
-            &merkle_proof
+        protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+            &db.proof(index),
+            db.get_record(index),
+            root_bs58
         );
 
     drop(state);
 
-    serde_json::to_string(&merkle_proof_bs58).unwrap()
+    HttpResponse::Ok().json(merkle_proof_bs58)
 }
 
-async fn process_onramp_tx(
+// opens several leaves of one tree at once, e.g. every coin of a wallet, sending
+// the nodes their paths share once
+#[utoipa::path(get, path = "/merkle/multi", request_body = Vec<usize>,
+    params(("tree" = Option<u32>, Query, description = "the tree to open the leaves of; the active tree if unset")),
+    responses((status = 200, body = multiproof::MultiProofBs58), (status = 400)))]
+async fn serve_merkle_multiproof_request(
     global_state: web::Data<GlobalAppState>,
-    input: web::Json<protocol::GrothProofBs58>
-) -> String {
+    query: web::Query<TreeQuery>,
+    indices: web::Json<Vec<usize>>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let tree_id = query.tree.unwrap_or((*state).tree_id);
 
-    let mut state = global_state.state.lock().unwrap();
+    let multiproof = assemble_multiproof(&state, tree_id, &indices);
+    drop(state);
 
-    let now = Instant::now();
+    match multiproof {
+        Ok(multiproof) => HttpResponse::Ok().json(multiproof),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
 
-    // instead of blindly forwarding the proof to the verifier, let's verify it here first
-    let (proof, public_inputs) = 
-        protocol::groth_proof_from_bs58(&input.clone());
+// the nodes of a leaf's path that changed since a root the wallet holds an
+// opening against; once that root has left the window, the wallet is told
+// to fetch the whole opening again
+#[utoipa::path(post, path = "/merkle_delta", request_body = merkle_delta::MerkleDeltaRequestBs58,
+    params(("tree" = Option<u32>, Query, description = "the tree the leaf is in; the active tree if unset")),
+    responses((status = 200, body = merkle_delta::MerkleDeltaBs58), (status = 400), (status = 410)))]
+async fn serve_merkle_delta_request(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<TreeQuery>,
+    request: web::Json<MerkleDeltaRequestBs58>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let tree_id = query.tree.unwrap_or((*state).tree_id);
 
-    assert!(Groth16::<BW6_761>::verify(&(*state).onramp_vk, &public_inputs, &proof).unwrap());
+    let delta = assemble_merkle_delta(&state, tree_id, &request);
+    drop(state);
 
-    println!("on-ramp proof verified in {}.{} secs", 
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_millis()
-    );
+    match delta {
+        Ok(Some(delta)) => HttpResponse::Ok().json(delta),
+        Ok(None) => HttpResponse::Gone().body("the known root has left the history; fetch the full opening"),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
 
-    // let's grab the utxo commitment being created by this tx
-    let utxo_com = ark_bls12_377::G1Affine::new(
-        public_inputs[protocol::OnrampGrothPublicInput::COMMITMENT_X as usize],
-        public_inputs[protocol::OnrampGrothPublicInput::COMMITMENT_Y as usize]
-    );
+// answers whether each of a batch of nullifiers has been spent
+#[utoipa::path(post, path = "/nullifiers/status", request_body = protocol::NullifierStatusRequestBs58,
+    responses((status = 200, body = protocol::NullifierStatusBs58), (status = 400)))]
+async fn serve_nullifier_status_request(
+    global_state: web::Data<GlobalAppState>,
+    request: web::Json<protocol::NullifierStatusRequestBs58>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let status = (*state).nullifiers.status(&request.into_inner());
+    drop(state);
 
-    // add utxo to state
-    let merkle_update_proof = add_coin_to_state((*state).borrow_mut(), &utxo_com);
+    match status {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
 
+// reports how much of the tree is occupied by real coins, as opposed to dummies
+#[utoipa::path(get, path = "/fill-ratio", responses((status = 200, body = protocol::FillRatio)))]
+async fn serve_fill_ratio_request(
+    global_state: web::Data<GlobalAppState>
+) -> String {
+    let state = global_state.state.lock().unwrap();
+    let fill_ratio = compute_fill_ratio(&state);
     drop(state);
 
-    // let's forward the request to the verifier
-    let output = protocol::OnRampProofBs58 {
-        on_ramp_proof: input.clone(),
-        merkle_update_proof: merkle_update_proof,
-    };
+    serde_json::to_string(&fill_ratio).unwrap()
+}
 
-    // HTTP request to transmit the output to the verifier
-    let client = Client::new();
-    let response = client.post("http://127.0.0.1:8081/onramp")
-        .json(&output)
-        .send()
-        .await
-        .unwrap();
-
-    if response.status().is_success() {
-        println!("verifier successfully processed onramp tx\n");
-        return "OK".to_string(); // TODO: this should be protocol-ized
-    } else {
-        println!("verifier failed to process onramp tx {:?}", response.status());
-        return "FAILED".to_string(); // TODO: protocol-ize
+// queries the memo attached to the payment that created the coin at index
+#[utoipa::path(get, path = "/memo", request_body = usize,
+    responses((status = 200, body = Option<String>)))]
+async fn serve_memo_request(
+    global_state: web::Data<GlobalAppState>,
+    index: web::Json<usize>
+) -> String {
+    let state = global_state.state.lock().unwrap();
+    let memo: Option<String> = (*state).memos.get(&index.into_inner()).cloned();
+    drop(state);
+
+    serde_json::to_string(&memo).unwrap()
+}
+
+// serves every move of a tx, so far: one submitted under idempotency key id,
+// for as long as its ticket is kept, or a swap proposal escrowed under swap id id
+#[utoipa::path(get, path = "/tx/{id}",
+    params(("id" = String, Path, description = "the tx's idempotency key, or the bs58 encoded swap id of an escrowed proposal")),
+    responses((status = 200, body = Vec<protocol::TxTransition>), (status = 404)))]
+async fn serve_tx_status_request(
+    global_state: web::Data<GlobalAppState>,
+    id: web::Path<String>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+
+    let history = (*state).tickets
+        .get(&id, tickets::unix_time())
+        .map(|ticket| &ticket.history)
+        .or_else(|| (*state).swaps.get(id.as_str()).map(|pending| &pending.history));
+
+    match history {
+        Some(history) => HttpResponse::Ok().json(history.transitions()),
+        None => HttpResponse::NotFound().body(format!("no tx under {}", id)),
     }
 }
 
-// mirrors the logic on L1 contract, but stores the entire state (rather than frontier)
-async fn process_payment_tx(
+// serves an inclusion receipt for a coin, given its bs58 (compressed) commitment
+#[utoipa::path(get, path = "/receipt/{commitment}",
+    params(("commitment" = String, Path, description = "bs58 encoded coin commitment")),
+    responses((status = 200, body = protocol::ReceiptBs58), (status = 404)))]
+async fn serve_receipt_request(
     global_state: web::Data<GlobalAppState>,
-    tx: web::Json<protocol::GrothProofBs58>
-) -> String {
+    commitment: web::Path<String>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
 
-    let mut state = global_state.state.lock().unwrap();
+    let index = protocol::commitment_from_bs58(&commitment)
+        .ok()
+        .and_then(|com| leaf_index_of(&state, &com));
 
-    let now = Instant::now();
+    match index {
+        Some(i) => {
+            // i < num_coins, so the index has been written and is in range
+            let receipt = receipts::receipt_from_opening_proof(&assemble_merkle_proof(&state, i).unwrap());
+            HttpResponse::Ok().json(receipt)
+        },
+        None => HttpResponse::NotFound().body("unknown commitment"),
+    }
+}
 
-    // instead of blindly forwarding the proof to the verifier, let's verify it here first
-    let (proof, public_inputs) = 
-        protocol::groth_proof_from_bs58(&tx.clone());
+#[derive(Deserialize)]
+struct OrderingQuery {
+    first: String,
+    second: String,
+}
 
-    assert!(Groth16::<BW6_761>::verify(&(*state).payment_vk, &public_inputs, &proof).unwrap());
+// serves a proof of which of two coins, given their bs58 (compressed)
+// commitments, was inserted into the active tree first
+#[utoipa::path(get, path = "/ordering",
+    params(
+        ("first" = String, Query, description = "bs58 encoded coin commitment"),
+        ("second" = String, Query, description = "bs58 encoded coin commitment"),
+    ),
+    responses((status = 200, body = protocol::OrderingProofBs58), (status = 404)))]
+async fn serve_ordering_request(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<OrderingQuery>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
 
-    println!("payment proof verified in {}.{} secs", 
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_millis()
-    );
+    let mut openings = Vec::with_capacity(2);
+    for commitment in [&query.first, &query.second] {
+        let index = protocol::commitment_from_bs58(commitment)
+            .ok()
+            .and_then(|com| leaf_index_of(&state, &com));
 
-    // let's grab the utxo commitment being created by this tx
-    let utxo_com = ark_bls12_377::G1Affine::new(
-        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
-        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
-    );
+        match index {
+            // i < num_coins, so the index has been written and is in range
+            Some(i) => openings.push(assemble_merkle_proof(&state, i).unwrap()),
+            None => return HttpResponse::NotFound().body(format!("unknown commitment {}", commitment)),
+        }
+    }
 
-    // add utxo to state
-    let merkle_update_proof = add_coin_to_state((*state).borrow_mut(), &utxo_com);
+    // both openings are taken under the lock, hence against the same root
+    match receipts::ordering_proof(&openings[0], &openings[1], (*state).signing_key.as_ref()) {
+        Ok(proof) => HttpResponse::Ok().json(proof),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
 
+// lists coin_inserted events from the given leaf index onwards, from which
+// clients can rebuild the tree themselves
+#[utoipa::path(get, path = "/events/coins", request_body = usize,
+    responses((status = 200, body = Vec<protocol::CoinInsertedEventBs58>)))]
+async fn serve_coin_events_request(
+    global_state: web::Data<GlobalAppState>,
+    from: web::Json<usize>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let events = coin_events(&state, from.into_inner());
     drop(state);
 
-    // let's forward the request to the verifier
-    let output = protocol::PaymentProofBs58 {
-        payment_proof: tx.clone(),
-        merkle_update_proof: merkle_update_proof,
-    };
+    HttpResponse::Ok().json(events)
+}
 
-    // HTTP request to transmit the output to the verifier
-    let client = Client::new();
-    let response = client.post("http://127.0.0.1:8081/payment")
-        .json(&output)
-        .send()
-        .await
-        .unwrap();
-
-    if response.status().is_success() {
-        println!("verifier successfully processed payment tx\n");
-        return "OK".to_string(); // TODO: this should be protocol-ized
-    } else {
-        println!("verifier failed to process payment tx {:?}", response.status());
-        return "FAILED".to_string(); // TODO: protocol-ize
+// lists every leaf of a tree from the given index onwards, with the nullifier
+// and memo of the payment that inserted it, for read-only replicas to mirror
+#[utoipa::path(get, path = "/replication", request_body = usize,
+    params(("tree" = Option<u32>, Query, description = "the tree the replica is at; the active tree if unset")),
+    responses((status = 200, body = protocol::ReplicationBatchBs58), (status = 400)))]
+async fn serve_replication_request(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<TreeQuery>,
+    from: web::Json<usize>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let tree_id = query.tree.unwrap_or((*state).tree_id);
+    let batch = replication_batch(&state, tree_id, from.into_inner());
+    drop(state);
+
+    match batch {
+        Ok(batch) => HttpResponse::Ok().json(batch),
+        Err(e) => HttpResponse::BadRequest().body(e),
     }
 }
 
-fn initialize_state() -> AppStateType {
+// a replica holding every leaf of a retired tree is moved on to the next tree,
+// so that it follows the leader's rollovers in order
+fn replication_batch(
+    state: &AppStateType,
+    tree_id: u32,
+    from: usize
+) -> Result<protocol::ReplicationBatchBs58, String> {
+    let (mut tree_id, mut from) = (tree_id, from);
+    while let Some(tree) = state.retired_trees.get(tree_id as usize) {
+        if from < tree.num_coins {
+            break;
+        }
+        tree_id += 1;
+        from = 0;
+    }
+
+    let event = |i: usize, commitment: &ark_bls12_377::G1Affine, nullifier: Option<&String>, memo: Option<&String>, inserted_at: Option<&u64>|
+        protocol::ReplicationEventBs58 {
+            leaf_index: i,
+            commitment: protocol::commitment_to_bs58(commitment),
+            nullifier: nullifier.cloned(),
+            memo: memo.cloned(),
+            inserted_at: inserted_at.copied(),
+        };
 
-    let (_, vc_params, crs) = utils::trusted_setup();
+    if let Some(tree) = state.retired_trees.get(tree_id as usize) {
+        let events = (from..tree.num_coins)
+            .map(|i| event(i, tree.db.get_record(i), tree.spent_at.get(&i), tree.memos.get(&i), tree.inserted_at.get(i)))
+            .collect();
+        return Ok(protocol::ReplicationBatchBs58 { tree_id, events, root: tree.root_bs58.clone() });
+    }
+    if tree_id != state.tree_id {
+        return Err(format!("unknown tree {}", tree_id));
+    }
 
-    let records: Vec<ark_bls12_377::G1Affine> = (0..(1 << MERKLE_TREE_LEVELS))
-        .map(|_| utils::get_dummy_utxo(&crs).commitment().into_affine())
+    let events = (from..state.num_coins)
+        .map(|i| event(i, state.db.get_record(i), state.nullifiers.spent_at(i), state.memos.get(&i), state.inserted_at.get(i)))
         .collect();
 
-    let db = JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records);
+    Ok(protocol::ReplicationBatchBs58 { tree_id, events, root: state.root_bs58.clone() })
+}
 
+// applies a batch pulled from the leader; the leaves must pick up where ours
+// leave off, and must bring us to the leader's root. A batch of the next tree
+// means the leader rolled over, once we held every leaf of our tree
+fn apply_replication_batch(
+    state: &mut AppStateType,
+    batch: &protocol::ReplicationBatchBs58
+) -> Result<(), String> {
+    if batch.tree_id == (*state).tree_id + 1 {
+        start_new_tree(state);
+    }
+    if batch.tree_id != (*state).tree_id {
+        return Err(format!("expected tree {}, got tree {}", (*state).tree_id, batch.tree_id));
+    }
 
-    let (_, onramp_vk) = lib_sanctum::onramp_circuit::circuit_setup();
-    let (_, payment_vk) = lib_sanctum::payment_circuit::circuit_setup();
-    let (merkle_update_pk, _) = lib_sanctum::merkle_update_circuit::circuit_setup();
+    for event in batch.events.iter() {
+        if event.leaf_index != (*state).num_coins {
+            return Err(format!("expected leaf {}, got leaf {}", (*state).num_coins, event.leaf_index));
+        }
 
-    AppStateType {
-        onramp_vk,
-        payment_vk,
-        merkle_update_pk,
-        db,
-        num_coins: 0 
+        insert_leaf(state, &protocol::commitment_from_bs58(&event.commitment)?)?;
+
+        if let Some(nullifier) = event.nullifier.as_ref() {
+            (*state).nullifiers.insert(nullifier, event.leaf_index);
+        }
+        if let Some(memo) = event.memo.as_ref() {
+            (*state).memos.insert(event.leaf_index, memo.clone());
+        }
+        if let Some(inserted_at) = event.inserted_at {
+            (*state).inserted_at[event.leaf_index] = inserted_at;
+        }
+    }
+
+    if (*state).root_bs58 != batch.root {
+        return Err(format!("root {} does not match leader root {}", (*state).root_bs58, batch.root));
     }
+
+    Ok(())
 }
 
-fn add_coin_to_state(state: &mut AppStateType, com: &ark_bls12_377::G1Affine) -> protocol::GrothProofBs58 {
+fn coin_events(state: &AppStateType, from: usize) -> Vec<protocol::CoinInsertedEventBs58> {
+    (from..state.num_coins)
+        .map(|i| protocol::CoinInsertedEventBs58 {
+            leaf_index: i,
+            commitment: protocol::commitment_to_bs58(state.db.get_record(i)),
+            inserted_at: state.inserted_at.get(i).copied(),
+        })
+        .collect()
+}
 
-    let leaf_index = (*state).num_coins;
+#[utoipa::path(get, path = "/status", responses((status = 200, body = protocol::SequencerStatus)))]
+async fn serve_status_request(global_state: web::Data<GlobalAppState>) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
 
-    let old_merkle_proof = assemble_merkle_proof(state, leaf_index);
+    HttpResponse::Ok().json(protocol::SequencerStatus {
+        num_coins: (*state).num_coins,
+        policy_version: (*state).policy.version,
+        verify_cache_hits: (*state).verification_cache.hits,
+        verify_cache_misses: (*state).verification_cache.misses,
+    })
+}
 
-    // add it to the vector db
-    (*state).db.update(leaf_index as usize, &com);
-    (*state).num_coins += 1;
+// the health of each verifier txs are forwarded to, and how often it agreed
+// with the quorum
+#[utoipa::path(get, path = "/verifiers", responses((status = 200, body = Vec<protocol::VerifierHealth>)))]
+async fn serve_verifiers_request(global_state: web::Data<GlobalAppState>) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
 
-    let new_merkle_proof = assemble_merkle_proof(state, leaf_index);
+    HttpResponse::Ok().json((*state).verifiers.health())
+}
 
-    let (proof, public_inputs) = merkle_update_circuit::generate_groth_proof(
-        &(*state).merkle_update_pk,
-        &old_merkle_proof,
-        &new_merkle_proof,
-        leaf_index
-    );
+// the leaf count and the root are read under one lock, so a syncing wallet
+// never sees a root that does not match the leaves it scans
+#[utoipa::path(get, path = "/tree", responses((status = 200, body = protocol::TreeSnapshot)))]
+async fn serve_tree_request(global_state: web::Data<GlobalAppState>) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let snapshot = compute_tree_snapshot(&state);
+    drop(state);
 
-    crate::protocol::groth_proof_to_bs58(&proof, &public_inputs)
+    HttpResponse::Ok().json(snapshot)
 }
 
+// replaces the compliance policy with a newer one signed by the operator
+#[utoipa::path(post, path = "/admin/policy", request_body = policy::SignedPolicy,
+    responses((status = 200), (status = 400), (status = 403), (status = 409)))]
+async fn process_policy_update(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<policy::SignedPolicy>
+) -> HttpResponse {
+    let mut state = global_state.state.lock().unwrap();
 
-fn assemble_merkle_proof(
-    state: &AppStateType,
-    index: usize
-) -> JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine> {
-    JZVectorCommitmentOpeningProof::<MTParams, ark_bls12_377::G1Affine> {
-        root: state.db.commitment(),
-        record: state.db.get_record(index).clone(),
-        path: state.db.proof(index),
+    let operator_key = match (*state).operator_key.as_ref() {
+        Some(key) => key,
+        None => return HttpResponse::Forbidden().body("no operator key is configured"),
+    };
+
+    let policy = match input.verify(operator_key) {
+        Ok(policy) => policy,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    // never roll back to an older policy
+    if policy.version <= (*state).policy.version {
+        return HttpResponse::Conflict().body("policy version must increase");
+    }
+
+    info!(version = policy.version, "installing policy");
+    (*state).policy = policy;
+
+    HttpResponse::Ok().finish()
+}
+
+// swaps in the keys a reload signed by the operator points to, e.g. after a
+// circuit upgrade. The keys are read outside the lock, so txs keep being
+// verified meanwhile, and swapped in whole under it, so that every tx is
+// verified either with the old keys or with the new ones
+#[utoipa::path(post, path = "/admin/reload-keys", request_body = key_reload::SignedKeyReload,
+    responses((status = 200, body = key_reload::KeysReloaded), (status = 400), (status = 403), (status = 409), (status = 500)))]
+async fn process_key_reload(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<key_reload::SignedKeyReload>
+) -> HttpResponse {
+    let reload = {
+        let state = global_state.state.lock().unwrap();
+
+        let operator_key = match (*state).operator_key.as_ref() {
+            Some(key) => key,
+            None => return HttpResponse::Forbidden().body("no operator key is configured"),
+        };
+        let reload = match input.verify(operator_key) {
+            Ok(reload) => reload,
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        };
+        // refused before any key is read; the check that counts is the one
+        // made under the lock the keys are swapped in under
+        if reload.version <= (*state).keys_version {
+            return HttpResponse::Conflict().body("key reload version must increase");
+        }
+        reload
+    };
+
+    // reading and preparing the keys takes a while, so it runs off the async workers
+    let (blocking_reload, statement) = (reload.clone(), global_state.statement);
+    let keys = match web::block(move || load_keys(&blocking_reload, &statement)).await {
+        Ok(Ok(keys)) => keys,
+        Ok(Err(e)) => return HttpResponse::BadRequest().body(e),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    // the version is checked, the reload persisted and the keys swapped in
+    // under one lock, so that no other reload lands in between
+    let mut state = global_state.state.lock().unwrap();
+    if reload.version <= (*state).keys_version {
+        return HttpResponse::Conflict().body("key reload version must increase");
+    }
+    // kept before it is applied, so that a restart never falls back to older keys
+    if let Ok(path) = std::env::var("SANCTUM_KEY_RELOAD_FILE") {
+        if let Err(e) = reload.persist_applied(&path) {
+            return HttpResponse::InternalServerError().body(e);
+        }
+    }
+
+    info!(version = reload.version, key_dir = ?reload.key_dir, "reloaded keys");
+    let fingerprints = keys.fingerprints();
+    (*state).keys = Some(keys);
+    (*state).keys_version = reload.version;
+    // the cached verdicts were reached with the old keys
+    (*state).verification_cache.clear();
+
+    HttpResponse::Ok().json(key_reload::KeysReloaded { version: reload.version, fingerprints })
+}
+
+// posts a tx to every verifier at once, and tallies their verdicts against
+// the quorum; verifiers that split on the tx raise an alert naming the ones
+// that went against the outcome
+async fn forward_to_verifiers<T: serde::Serialize>(
+    global_state: &GlobalAppState,
+    route: &str,
+    body: &T
+) -> Result<(), String> {
+    let urls = global_state.state.lock().unwrap().verifiers.urls();
+
+    let client = Client::new();
+    let responses = futures::future::join_all(
+        urls.iter().map(|url| client.post(format!("{}{}", url, route)).json(body).send())
+    ).await;
+    let verdicts: Vec<Verdict> = responses
+        .into_iter()
+        .map(|response| match response {
+            Ok(response) if response.status().is_success() => Verdict::Accepted,
+            Ok(response) => Verdict::Rejected(response.status().as_u16()),
+            Err(e) => Verdict::Unreachable(e.to_string()),
+        })
+        .collect();
+
+    let mut state = global_state.state.lock().unwrap();
+    let tally = (*state).verifiers.tally(&verdicts);
+    let split = verdicts.iter().any(|v| *v == Verdict::Accepted) && verdicts.iter().any(|v| *v != Verdict::Accepted);
+    match &tally {
+        Ok(dissenting) if split =>
+            error!(route, dissenting = %dissenting.join(", "), "ALERT: verifiers split on a tx, which reached quorum"),
+        Err(e) if split =>
+            error!(route, error = %e, "ALERT: verifiers split on a tx, which is rolled back"),
+        _ => (),
     }
-}
\ No newline at end of file
+
+    tally.map(|_| ())
+}
+
+// rolls back a tx the verifiers did not reach a quorum on
+fn roll_back_tx(global_state: &GlobalAppState, checkpoint: Checkpoint) {
+    let mut state = global_state.state.lock().unwrap();
+    if let Err(e) = rollback((*state).borrow_mut(), checkpoint) {
+        error!(error = %e, "ALERT: unable to roll back a tx the verifiers refused");
+    }
+}
+
+// moves the tx submitted under idempotency_key, if any, on; its ticket was
+// recorded when it was staged, so a move it cannot make is a bug
+fn advance_ticket(global_state: &GlobalAppState, idempotency_key: Option<&str>, next: protocol::TxState) {
+    if let Some(key) = idempotency_key {
+        let mut state = global_state.state.lock().unwrap();
+        if let Err(e) = (*state).tickets.advance(key, next, tickets::unix_time()) {
+            error!(error = %e, "ALERT: unable to record the state of a ticketed tx");
+        }
+    }
+}
+
+// registers an app's circuit, signed by the operator, here and with the verifier
+#[utoipa::path(post, path = "/apps/register", request_body = apps::SignedAppRegistration,
+    responses((status = 200), (status = 400), (status = 403), (status = 502)))]
+async fn process_app_registration(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<apps::SignedAppRegistration>
+) -> HttpResponse {
+    let signed = input.into_inner();
+
+    let mut state = global_state.state.lock().unwrap();
+    let operator_key = match (*state).operator_key.as_ref() {
+        Some(key) => key,
+        None => return HttpResponse::Forbidden().body("no operator key is configured"),
+    };
+    let registration = match signed.verify(operator_key) {
+        Ok(registration) => registration,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    if let Err(e) = (*state).apps.register(&registration) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::AppRegistration(registration.clone()));
+    drop(state);
+
+    info!(app = %registration.name, "registered app");
+
+    // a registration is not rolled back: an app the verifiers refused has its
+    // txs refused by them too
+    match forward_to_verifiers(&global_state, "/apps/register", &signed).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            warn!(app = %registration.name, error = %e, "verifiers failed to register app");
+            HttpResponse::BadGateway().body("verifier rejected app registration")
+        }
+    }
+}
+
+// verifies a tx against its app's registered circuit, and inserts the
+// commitment the app declares into the tree
+#[utoipa::path(post, path = "/app_tx", request_body = protocol::AppTxBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_app_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::AppTxBs58>
+) -> HttpResponse {
+    let app_tx = input.into_inner();
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+    let commitment = match (*state).apps.verify(&app_tx) {
+        Ok(commitment) => commitment,
+        Err(e) => {
+            info!(error = %e, "rejecting app tx");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+
+    let merkle_update_proof = match add_coin_to_state((*state).borrow_mut(), &commitment) {
+        Ok(proof) => proof,
+        Err(e) => {
+            info!(error = %e, "rejecting app tx");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::App(app_tx.clone()));
+    drop(state);
+
+    let output = protocol::AppProofBs58 {
+        app_tx,
+        merkle_update_proof,
+    };
+
+    match forward_to_verifiers(&global_state, "/app_tx", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed app tx");
+            HttpResponse::Ok().json(protocol::TxResponse::accepted(verify_time_ms))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process app tx");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TxResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+#[utoipa::path(post, path = "/onramp", request_body = protocol::GrothProofBs58,
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "retrying an onramp under the same key returns its recorded outcome")),
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_onramp_tx(
+    global_state: web::Data<GlobalAppState>,
+    req: actix_web::HttpRequest,
+    input: web::Json<protocol::GrothProofBs58>
+) -> String {
+
+    // never let a proof for another circuit reach the onramp vk, nor one
+    // minting coins of an asset id with a byte set past its u64
+    if let Err(e) = protocol::check_circuit_kind(&input, protocol::CircuitKind::Onramp, &global_state.statement)
+        .and_then(|_| protocol::asset_id_of(&input).map(|_| ())) {
+        info!(error = %e, "rejecting onramp tx");
+        return serde_json::to_string(&protocol::TxResponse::rejected(&e)).unwrap();
+    }
+
+    // a client retrying an onramp, e.g. after a crash, sends the same key again
+    let idempotency_key = req.headers()
+        .get("Idempotency-Key")
+        .and_then(|key| key.to_str().ok())
+        .map(|key| key.to_string());
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+
+    // instead of blindly forwarding the proof to the verifier, let's verify it here first
+    let (leaf_index, merkle_update_proof) =
+        match apply_onramp_tx((*state).borrow_mut(), &input, idempotency_key.as_deref()) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                info!(error = %e, "rejecting onramp tx");
+                return serde_json::to_string(&protocol::TxResponse::rejected(&e)).unwrap();
+            }
+        };
+    drop(state);
+
+    let merkle_update_proof = match merkle_update_proof {
+        Some(proof) => proof,
+        None => {
+            debug!(leaf_index, "onramp tx was already accepted");
+            return serde_json::to_string(&protocol::TxResponse::recorded(leaf_index)).unwrap();
+        }
+    };
+    advance_ticket(&global_state, idempotency_key.as_deref(), protocol::TxState::ForwardedToVerifiers);
+
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+    info!(elapsed_ms = verify_time_ms, "on-ramp proof verified");
+
+    // let's forward the request to the verifier
+    let output = protocol::OnRampProofBs58 {
+        on_ramp_proof: input.clone(),
+        merkle_update_proof: merkle_update_proof,
+    };
+
+    // HTTP request to transmit the output to the verifiers
+    match forward_to_verifiers(&global_state, "/onramp", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed onramp tx");
+            advance_ticket(&global_state, idempotency_key.as_deref(), protocol::TxState::Settled { leaf_index: Some(leaf_index) });
+            serde_json::to_string(&protocol::TxResponse::accepted(verify_time_ms).with_leaf_index(leaf_index)).unwrap()
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process onramp tx");
+            roll_back_tx(&global_state, checkpoint);
+            // a retry under the same key is applied afresh, rather than
+            // answered with the leaf just rolled back
+            if let Some(key) = idempotency_key.as_deref() {
+                global_state.state.lock().unwrap().tickets.remove(key);
+            }
+            serde_json::to_string(&protocol::TxResponse::rejected("verifier rejected tx")).unwrap()
+        }
+    }
+}
+
+// escrows one half of a swap until its matching half arrives, or it expires
+#[utoipa::path(post, path = "/swap/propose", request_body = protocol::SwapHalfBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_swap_proposal(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::SwapHalfBs58>
+) -> HttpResponse {
+    let mut proposal = input.into_inner();
+    protocol::normalize_public_inputs(&mut proposal.payment_proof);
+    proposal.want_asset_tag = protocol::normalize_field_bs58(&proposal.want_asset_tag);
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let now = Instant::now();
+    match propose_swap((*state).borrow_mut(), proposal, now) {
+        Ok(()) => HttpResponse::Ok().json(protocol::TxResponse::accepted(now.elapsed().as_millis() as u64)),
+        Err(e) => {
+            info!(error = %e, "rejecting swap proposal");
+            HttpResponse::Ok().json(protocol::TxResponse::rejected(&e))
+        }
+    }
+}
+
+// settles an escrowed swap with its matching half, applying both as one batch
+#[utoipa::path(post, path = "/swap/accept", request_body = protocol::SwapHalfBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_swap_acceptance(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::SwapHalfBs58>
+) -> HttpResponse {
+    let mut acceptance = input.into_inner();
+    protocol::normalize_public_inputs(&mut acceptance.payment_proof);
+    acceptance.want_asset_tag = protocol::normalize_field_bs58(&acceptance.want_asset_tag);
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+    let (proposal, merkle_update_proofs) = match accept_swap((*state).borrow_mut(), &acceptance, now) {
+        Ok(settlement) => settlement,
+        Err(e) => {
+            info!(error = %e, "rejecting swap acceptance");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::Swap {
+        proposal: proposal.clone(),
+        acceptance: acceptance.clone(),
+    });
+    drop(state);
+
+    let [proposal_update, acceptance_update] = merkle_update_proofs;
+    let output = protocol::SwapSettlementBs58 {
+        halves: vec![
+            protocol::PaymentProofBs58 {
+                payment_proof: proposal.payment_proof,
+                merkle_update_proof: proposal_update,
+            },
+            protocol::PaymentProofBs58 {
+                payment_proof: acceptance.payment_proof,
+                merkle_update_proof: acceptance_update,
+            },
+        ],
+    };
+
+    // a rolled back swap leaves escrow too, so both halves are to be resubmitted
+    match forward_to_verifiers(&global_state, "/swap", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed swap");
+            HttpResponse::Ok().json(protocol::TxResponse::accepted(verify_time_ms))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process swap");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TxResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+// spends a coin here, in a transfer to another pool, and returns the signed
+// export for the destination pool to insert its output coin from. Replicas
+// learn of spent nullifiers leaf by leaf, so they do not see exported ones
+#[utoipa::path(post, path = "/transfer", request_body = protocol::GrothProofBs58,
+    responses((status = 200, body = protocol::TransferResponse), (status = 400)))]
+async fn process_transfer_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::GrothProofBs58>
+) -> HttpResponse {
+    let mut tx = input.into_inner();
+    protocol::normalize_public_inputs(&mut tx);
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let export = match export_transfer((*state).borrow_mut(), &tx) {
+        Ok(export) => export,
+        Err(e) => {
+            info!(error = %e, "rejecting transfer tx");
+            return HttpResponse::Ok().json(protocol::TransferResponse::rejected(&e));
+        }
+    };
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::TransferOut(tx.clone()));
+    drop(state);
+
+    // the export is only handed out once the verifiers have spent the nullifier
+    // too, so that the coin is never spendable in both pools
+    match forward_to_verifiers(&global_state, "/transfer/out", &tx).await {
+        Ok(()) => {
+            debug!("verifier successfully processed transfer tx");
+            HttpResponse::Ok().json(protocol::TransferResponse::accepted(export))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process transfer tx");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TransferResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+// inserts the output coin of a transfer another pool exported to this one
+#[utoipa::path(post, path = "/transfer/in", request_body = protocol::TransferExportBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_transfer_import(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::TransferExportBs58>
+) -> HttpResponse {
+    let mut export = input.into_inner();
+    protocol::normalize_public_inputs(&mut export.transfer_proof);
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+    let (leaf_index, merkle_update_proof) = match import_transfer((*state).borrow_mut(), &export) {
+        Ok(imported) => imported,
+        Err(e) => {
+            info!(error = %e, "rejecting transfer import");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::TransferIn(export.clone()));
+    drop(state);
+
+    let output = protocol::TransferInProofBs58 { export, merkle_update_proof };
+
+    // a rolled back import may be delivered again
+    match forward_to_verifiers(&global_state, "/transfer/in", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed transfer import");
+            HttpResponse::Ok().json(protocol::TxResponse::accepted(verify_time_ms).with_leaf_index(leaf_index))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process transfer import");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TxResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+// a deposit minting several notes is inserted as one batch, so either every
+// minted note lands in the tree or none does
+#[utoipa::path(post, path = "/onramp_multi", request_body = protocol::GrothProofBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400)))]
+async fn process_onramp_multi_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::GrothProofBs58>
+) -> HttpResponse {
+
+    // nor one that doesn't decode, which would panic under the lock, or that
+    // mints coins of an asset id with a byte set past its u64
+    if let Err(e) = protocol::check_circuit_kind(&input, protocol::CircuitKind::OnrampMulti, &global_state.statement)
+        .and_then(|_| protocol::check_canonical_groth_proof(&input))
+        .and_then(|_| protocol::asset_id_of(&input).map(|_| ())) {
+        info!(error = %e, "rejecting onramp multi tx");
+        return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+    }
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+    let commitments = match verify_onramp_multi_tx((*state).borrow_mut(), &input) {
+        Ok(commitments) => commitments,
+        Err(e) => {
+            info!(error = %e, "rejecting onramp multi tx");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+
+    let merkle_update_proofs = match add_coins_to_state((*state).borrow_mut(), &commitments) {
+        Ok(proofs) => proofs,
+        Err(e) => {
+            info!(error = %e, "rejecting onramp multi tx");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::OnrampMulti(input.clone()));
+    drop(state);
+
+    let output = protocol::OnRampMultiProofBs58 {
+        on_ramp_proof: input.into_inner(),
+        merkle_update_proofs,
+    };
+
+    match forward_to_verifiers(&global_state, "/onramp_multi", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed onramp multi tx");
+            HttpResponse::Ok().json(protocol::TxResponse::accepted(verify_time_ms))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process onramp multi tx");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TxResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+// mirrors the logic on L1 contract, but stores the entire state (rather than frontier)
+#[utoipa::path(post, path = "/payment", request_body = protocol::PaymentTxBs58,
+    responses((status = 200, body = protocol::TxResponse), (status = 400),
+        (status = 451, body = protocol::TxResponse)))]
+async fn process_payment_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::PaymentTxBs58>
+) -> HttpResponse {
+
+    let mut input = input.into_inner();
+    protocol::normalize_public_inputs(&mut input.payment_proof);
+    let tx = &input.payment_proof;
+
+    let _forwarding = global_state.forwarding.lock().await;
+    let mut state = global_state.state.lock().unwrap();
+    let checkpoint = checkpoint(&state);
+
+    let now = Instant::now();
+
+    // instead of blindly forwarding the proof to the verifier, let's check it here first
+    let submission = PaymentSubmission { tx: &input, vk_fingerprint: None };
+    if let Some(failed) = run_payment_validators((*state).borrow_mut(), &submission).pop().filter(|d| !d.passed) {
+        let error = failed.error.unwrap_or_default();
+
+        // refuse to process anything the operator's policy blocks
+        if failed.check == "policy" {
+            info!(policy_version = (*state).policy.version, "rejecting payment tx blocked by policy");
+            return HttpResponse::build(actix_web::http::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+                .json(protocol::TxResponse::rejected(&error));
+        }
+
+        info!(error = %error, "rejecting payment tx");
+        return HttpResponse::Ok().json(protocol::TxResponse::rejected(&error));
+    }
+
+    let utxo_com = payment_commitment(&input).unwrap();
+
+    let verify_time_ms = now.elapsed().as_millis() as u64;
+    info!(elapsed_ms = verify_time_ms, "payment proof verified");
+
+    let merkle_update_proof = match apply_payment_tx((*state).borrow_mut(), &input, &utxo_com) {
+        Ok(proof) => proof,
+        Err(e) => {
+            info!(error = %e, "rejecting payment tx");
+            return HttpResponse::Ok().json(protocol::TxResponse::rejected(&e));
+        }
+    };
+
+    log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::Payment(input.clone()));
+    drop(state);
+
+    // let's forward the request to the verifier
+    let output = protocol::PaymentProofBs58 {
+        payment_proof: tx.clone(),
+        merkle_update_proof: merkle_update_proof,
+    };
+
+    // HTTP request to transmit the output to the verifiers
+    match forward_to_verifiers(&global_state, "/payment", &output).await {
+        Ok(()) => {
+            debug!("verifier successfully processed payment tx");
+            HttpResponse::Ok().json(protocol::TxResponse::accepted(verify_time_ms))
+        },
+        Err(e) => {
+            warn!(error = %e, "verifiers failed to process payment tx");
+            roll_back_tx(&global_state, checkpoint);
+            HttpResponse::Ok().json(protocol::TxResponse::rejected("verifier rejected tx"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchQuery {
+    // send each payment's result as it is reached, rather than all at the end
+    #[serde(default)]
+    stream: bool,
+}
+
+// verifies a batch of payments, and applies them only if every one verifies;
+// a client submitting many payments learns which one failed, and streaming
+// learns it as soon as the sequencer does. The streamed response is json
+// lines: one BatchItemResult per payment, in order, then the batch's TxResponse
+#[utoipa::path(post, path = "/payment/batch", request_body = Vec<protocol::PaymentTxBs58>,
+    params(("stream" = Option<bool>, Query, description = "stream each payment's result as a json line")),
+    responses((status = 200, body = protocol::BatchResponse), (status = 400)))]
+async fn process_payment_batch(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<BatchQuery>,
+    input: web::Json<Vec<protocol::PaymentTxBs58>>
+) -> HttpResponse {
+    let mut batch = input.into_inner();
+    for tx in batch.iter_mut() {
+        protocol::normalize_public_inputs(&mut tx.payment_proof);
+    }
+
+    let (sender, receiver) = futures::channel::mpsc::unbounded();
+
+    // the batch is settled on its own task, which sends each result as it is
+    // reached, and closes the channel once the batch is checked
+    let settled = actix_web::rt::spawn(settle_payment_batch(global_state, batch, sender));
+    let outcome = async move {
+        settled.await.unwrap_or_else(|e| protocol::TxResponse::rejected(&e.to_string()))
+    };
+
+    if query.stream {
+        let lines = receiver
+            .map(|item| json_line(&item))
+            .chain(futures::stream::once(outcome).map(|outcome| json_line(&outcome)));
+        return HttpResponse::Ok().content_type("application/x-ndjson").streaming(lines);
+    }
+
+    let items = receiver.collect::<Vec<_>>().await;
+    HttpResponse::Ok().json(protocol::BatchResponse { items, outcome: outcome.await })
+}
+
+// one line of a json lines response
+fn json_line<T: serde::Serialize>(value: &T) -> Result<web::Bytes, actix_web::Error> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+
+    Ok(web::Bytes::from(line))
+}
+
+// checks and applies a batch under the state lock, reporting each payment's
+// result through sender, and then forwards the applied payments to the verifier
+async fn settle_payment_batch(
+    global_state: web::Data<GlobalAppState>,
+    batch: Vec<protocol::PaymentTxBs58>,
+    sender: UnboundedSender<protocol::BatchItemResult>
+) -> protocol::TxResponse {
+    let _forwarding = global_state.forwarding.lock().await;
+
+    // pairings take a while, so they run off the async workers
+    let blocking_state = global_state.clone();
+    let applied = web::block(move || {
+        let mut state = blocking_state.state.lock().unwrap();
+        let checkpoint = checkpoint(&state);
+
+        let now = Instant::now();
+        let mut report = move |result| { let _ = sender.unbounded_send(result); };
+        let commitments = verify_payment_batch((*state).borrow_mut(), &batch, &mut report)?;
+        let verify_time_ms = now.elapsed().as_millis() as u64;
+        // every result is in, so the channel can close
+        drop(report);
+
+        let merkle_update_proofs = apply_payment_batch((*state).borrow_mut(), &batch, &commitments)?;
+        for tx in batch.iter() {
+            log_tx((*state).borrow_mut(), protocol::LoggedTxBs58::Payment(tx.clone()));
+        }
+
+        let outputs: Vec<protocol::PaymentProofBs58> = batch
+            .into_iter()
+            .zip(merkle_update_proofs)
+            .map(|(tx, merkle_update_proof)| protocol::PaymentProofBs58 {
+                payment_proof: tx.payment_proof,
+                merkle_update_proof,
+            })
+            .collect();
+        Ok::<_, String>((outputs, verify_time_ms, checkpoint))
+    }).await;
+
+    let (outputs, verify_time_ms, checkpoint) = match applied.map_err(|e| e.to_string()).and_then(|applied| applied) {
+        Ok(applied) => applied,
+        Err(e) => {
+            info!(error = %e, "rejecting payment batch");
+            return protocol::TxResponse::rejected(&e);
+        }
+    };
+
+    // the verifiers take the payments one at a time, in the order they were
+    // applied; the batch is rolled back whole, though verifiers may by then
+    // hold the payments before the one refused
+    for (index, output) in outputs.iter().enumerate() {
+        if let Err(e) = forward_to_verifiers(&global_state, "/payment", output).await {
+            warn!(index, error = %e, "verifiers failed to process payment of batch");
+            if index > 0 {
+                error!(accepted = index, "ALERT: rolling back a batch whose first payments the verifiers accepted");
+            }
+            roll_back_tx(&global_state, checkpoint);
+            return protocol::TxResponse::rejected(&format!("verifier rejected payment {}", index));
+        }
+    }
+
+    debug!(payments = outputs.len(), "verifier successfully processed batch");
+    protocol::TxResponse::accepted(verify_time_ms)
+}
+
+#[derive(Deserialize)]
+struct SimulateQuery {
+    // list the checks that were run, rather than only the verdict
+    #[serde(default)]
+    diagnose: bool,
+    // hex fingerprint of the vk the client proved with, if it wants it compared
+    vk_fingerprint: Option<String>,
+}
+
+// checks a payment exactly as /payment would, without applying it
+#[utoipa::path(post, path = "/simulate/payment", request_body = protocol::PaymentTxBs58,
+    params(
+        ("diagnose" = Option<bool>, Query, description = "list the checks run, up to the first that failed"),
+        ("vk_fingerprint" = Option<String>, Query, description = "hex fingerprint of the vk the proof was made with"),
+    ),
+    responses((status = 200, body = protocol::SimulationResponse), (status = 400)))]
+async fn simulate_payment_tx(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<SimulateQuery>,
+    input: web::Json<protocol::PaymentTxBs58>
+) -> HttpResponse {
+    let mut input = input.into_inner();
+    protocol::normalize_public_inputs(&mut input.payment_proof);
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let submission = PaymentSubmission {
+        tx: &input,
+        vk_fingerprint: query.vk_fingerprint.as_deref(),
+    };
+    let diagnostics = run_payment_validators((*state).borrow_mut(), &submission);
+    drop(state);
+
+    let error = diagnostics.last().filter(|d| !d.passed).and_then(|d| d.error.clone());
+    HttpResponse::Ok().json(protocol::SimulationResponse {
+        accepted: error.is_none(),
+        error,
+        diagnostics: if query.diagnose { diagnostics } else { vec![] },
+    })
+}
+
+fn initialize_state() -> AppStateType {
+    // the keys loaded at startup are those of the last reload applied, or
+    // those a reload without a key dir would load if none was
+    let applied = KeyReload::load_applied(std::env::var("SANCTUM_KEY_RELOAD_FILE").ok().as_deref())
+        .unwrap_or_else(|e| panic!("{}", e));
+    let statement = utils::statement_params();
+    let keys = load_keys(&applied, &statement)
+        .unwrap_or_else(|e| panic!("unable to load the sequencer keys: {}", e));
+
+    let (policy, operator_key) = load_policy();
+
+    let mut state = empty_state(Some(keys));
+    state.keys_version = applied.version;
+    state.policy = policy;
+    state.operator_key = operator_key;
+    state.signing_key = std::env::var("SANCTUM_SEQUENCER_KEY")
+        .ok()
+        .map(|key| receipts::signing_key_from_bs58(&key).unwrap());
+    state.statement = statement;
+    state.peers = PeerPools::from_env().expect("unable to parse SANCTUM_PEER_POOLS");
+
+    // pick up where the last run left off, and keep logging to the same file
+    if let Ok(path) = std::env::var("SANCTUM_TX_LOG") {
+        info!(path = %path, "logging accepted txs");
+        let num_txs = recover_from_tx_log(&mut state, &path).expect("unable to recover from tx log");
+        info!(txs = num_txs, "recovered from the tx log");
+    }
+
+    state
+}
+
+// the sequencer verifies onramp, payment, swap and transfer proofs, and proves
+// merkle updates; every vk is read, and prepared, before any is swapped in
+fn load_keys(reload: &KeyReload, statement: &protocol::StatementParams) -> Result<SequencerKeys, String> {
+    let [onramp_vk, onramp_multi_vk, payment_vk, swap_vk, transfer_vk] = [
+        protocol::CircuitKind::Onramp,
+        protocol::CircuitKind::OnrampMulti,
+        protocol::CircuitKind::Payment,
+        protocol::CircuitKind::Swap,
+        protocol::CircuitKind::Transfer,
+    ].map(|kind| reload.verifying_key(kind, statement).map_err(|e| format!("unable to load the {:?} vk: {}", kind, e)));
+    let (merkle_update_pk, _) = reload.keys(protocol::CircuitKind::MerkleUpdate, statement)
+        .map_err(|e| format!("unable to load the MerkleUpdate keys: {}", e))?;
+
+    let keys = SequencerKeys {
+        onramp_pvk: prepare_verifying_key(&onramp_vk?),
+        onramp_multi_pvk: prepare_verifying_key(&onramp_multi_vk?),
+        payment_pvk: prepare_verifying_key(&payment_vk?),
+        swap_pvk: prepare_verifying_key(&swap_vk?),
+        transfer_pvk: prepare_verifying_key(&transfer_vk?),
+        merkle_update_pk,
+    };
+    for VkFingerprint { circuit, fingerprint } in keys.fingerprints() {
+        info!(circuit = %circuit, fingerprint = %fingerprint, "vk fingerprint");
+    }
+
+    Ok(keys)
+}
+
+// a replica loads no keys, and neither logs nor polices txs, as it takes none
+fn initialize_replica_state(leader_url: &str) -> AppStateType {
+    let mut state = empty_state(None);
+    state.leader_url = Some(leader_url.to_string());
+
+    state
+}
+
+// a tree of dummy coins, with nothing spent, no policy and no tx log
+fn empty_state(keys: Option<SequencerKeys>) -> AppStateType {
+    let (db, root_bs58, root_history) = empty_tree();
+
+    AppStateType {
+        keys,
+        keys_version: 0,
+        leader_url: None,
+        tree_id: 0,
+        retired_trees: Vec::new(),
+        db,
+        root_bs58,
+        root_history,
+        num_coins: 0,
+        is_real_leaf: vec![false; protocol::TREE_CAPACITY],
+        inserted_at: Vec::new(),
+        leaf_indices: HashMap::new(),
+        nullifiers: NullifierStore::new(),
+        memos: HashMap::new(),
+        policy: policy::Policy::default(),
+        operator_key: None,
+        signing_key: None,
+        statement: protocol::StatementParams::default(),
+        peers: PeerPools::new(),
+        imports: HashMap::new(),
+        apps: AppRegistry::new(),
+        tx_log: None,
+        verification_cache: VerificationCache::from_env(),
+        swaps: HashMap::new(),
+        tickets: TicketStore::from_env(),
+        verifiers: VerifierSet::from_env(),
+    }
+}
+
+// a tree of dummy coins, with its root encoded, and a root history holding that root
+fn empty_tree() -> (JZVectorDB<MTParams, ark_bls12_377::G1Affine>, String, MerkleRootHistory) {
+    let db = utils::empty_tree();
+
+    let root_bs58 = protocol::encode_compressed_as_bs58_str(&db.commitment());
+    let mut root_history = MerkleRootHistory::new(utils::root_history_size());
+    root_history.insert(&root_xy_bs58(&db.commitment()));
+
+    (db, root_bs58, root_history)
+}
+
+// retires the active tree, coins and all, and opens a fresh one: a new root
+// lineage, whose leaves are numbered from 0 again. Nullifiers stay global, and
+// the retired tree's roots remain known to payments that name it
+fn start_new_tree(state: &mut AppStateType) {
+    let (db, root_bs58, root_history) = empty_tree();
+
+    let retired = RetiredTree {
+        db: std::mem::replace(&mut (*state).db, db),
+        root_bs58: std::mem::replace(&mut (*state).root_bs58, root_bs58),
+        root_history: std::mem::replace(&mut (*state).root_history, root_history),
+        num_coins: std::mem::replace(&mut (*state).num_coins, 0),
+        inserted_at: std::mem::take(&mut (*state).inserted_at),
+        spent_at: (*state).nullifiers.start_new_tree(),
+        memos: std::mem::take(&mut (*state).memos),
+    };
+    (*state).retired_trees.push(retired);
+    (*state).tree_id += 1;
+    (*state).is_real_leaf = vec![false; protocol::TREE_CAPACITY];
+    (*state).leaf_indices.clear();
+
+    info!(from = (*state).tree_id - 1, to = (*state).tree_id, "rolled over to a new tree");
+}
+
+// rolls over to a fresh tree unless the active one has room for n more coins,
+// so that the coins of one tx always land in the same tree
+fn make_room(state: &mut AppStateType, n: usize) -> Result<(), String> {
+    if n > protocol::TREE_CAPACITY {
+        return Err(format!("a tree holds {} coins, not {}", protocol::TREE_CAPACITY, n));
+    }
+    if (*state).num_coins + n > protocol::TREE_CAPACITY {
+        start_new_tree(state);
+    }
+
+    Ok(())
+}
+
+// the db, root encoding and number of coins of a tree, active or retired
+fn tree_of(
+    state: &AppStateType,
+    tree_id: u32
+) -> Option<(&JZVectorDB<MTParams, ark_bls12_377::G1Affine>, &String, usize)> {
+    if tree_id == state.tree_id {
+        return Some((&state.db, &state.root_bs58, state.num_coins));
+    }
+
+    state.retired_trees
+        .get(tree_id as usize)
+        .map(|tree| (&tree.db, &tree.root_bs58, tree.num_coins))
+}
+
+// rebuilds the state from the tx log at path, if there is one, and then keeps
+// appending to it. A torn last line, left by a crash in the middle of writing
+// it, is cut off: its tx was never acknowledged, so its client will retry it.
+fn recover_from_tx_log(state: &mut AppStateType, path: &str) -> Result<usize, String> {
+    let log = match std::fs::read_to_string(path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("unable to read tx log: {}", e)),
+    };
+
+    let intact = &log[..log.rfind('\n').map(|i| i + 1).unwrap_or(0)];
+    if intact.len() < log.len() {
+        warn!(bytes = log.len() - intact.len(), "cutting off a torn entry at the end of the tx log");
+    }
+
+    (*state).tx_log = None;
+    let num_txs = replay_tx_log(state, intact)?;
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("unable to open tx log: {}", e))?;
+    file.set_len(intact.len() as u64).map_err(|e| format!("unable to truncate tx log: {}", e))?;
+    (*state).tx_log = Some(file);
+
+    Ok(num_txs)
+}
+
+// appends an accepted tx, along with the root it produced, to the tx log
+fn log_tx(state: &mut AppStateType, tx: protocol::LoggedTxBs58) {
+    log_ticketed_tx(state, tx, None);
+}
+
+// the entry is synced to disk before this returns, so that a tx is never
+// acknowledged before it, and its ticket, would survive a crash
+fn log_ticketed_tx(state: &mut AppStateType, tx: protocol::LoggedTxBs58, ticket: Option<protocol::LoggedTicket>) {
+    let entry = protocol::TxLogEntryBs58 {
+        tx,
+        root: (*state).root_bs58.clone(),
+        ticket,
+        timestamp: Some(tickets::unix_time()),
+    };
+    if let Some(log) = (*state).tx_log.as_mut() {
+        writeln!(log, "{}", serde_json::to_string(&entry).unwrap()).expect("unable to write tx log");
+        log.sync_data().expect("unable to sync tx log");
+    }
+}
+
+// the tail of the state, as it stands before a tx is applied
+fn checkpoint(state: &AppStateType) -> Checkpoint {
+    Checkpoint {
+        tree_id: state.tree_id,
+        num_coins: state.num_coins,
+        root_history: state.root_history.clone(),
+        num_exported: state.nullifiers.num_exported(),
+        log_len: state.tx_log.as_ref().and_then(|log| log.metadata().ok()).map(|m| m.len()),
+    }
+}
+
+// undoes every tx applied since the checkpoint: its coins leave the tree, its
+// nullifiers, memos and imports are forgotten, and its tx log entries are cut off.
+// Txs are applied and forwarded one at a time, so these are the refused tx's
+// alone; if it rolled the tree over, the rollover is undone with it
+fn rollback(state: &mut AppStateType, checkpoint: Checkpoint) -> Result<(), String> {
+    // make_room rolls over at most once per tx, before any of its coins is inserted
+    if (*state).tree_id == checkpoint.tree_id + 1 {
+        undo_rollover(state)?;
+    } else if (*state).tree_id != checkpoint.tree_id {
+        return Err(format!("tree {} was rolled over more than once", checkpoint.tree_id));
+    }
+
+    let dummy_com = utils::get_dummy_utxo(&utils::shared_setup().2).commitment().into_affine();
+    for leaf_index in (checkpoint.num_coins..(*state).num_coins).rev() {
+        (*state).db.update(leaf_index, &dummy_com);
+        (*state).is_real_leaf[leaf_index] = false;
+        (*state).inserted_at.pop();
+        (*state).leaf_indices.retain(|_, index| *index != leaf_index);
+        (*state).memos.remove(&leaf_index);
+        (*state).nullifiers.remove(leaf_index);
+        let tree_id = (*state).tree_id;
+        (*state).imports.retain(|_, at| *at != (tree_id, leaf_index));
+    }
+    (*state).nullifiers.truncate_exported(checkpoint.num_exported);
+    (*state).num_coins = checkpoint.num_coins;
+    (*state).root_bs58 = protocol::encode_compressed_as_bs58_str(&(*state).db.commitment());
+    (*state).root_history = checkpoint.root_history;
+
+    if let (Some(log), Some(log_len)) = ((*state).tx_log.as_mut(), checkpoint.log_len) {
+        log.set_len(log_len).map_err(|e| format!("unable to truncate tx log: {}", e))?;
+        log.sync_data().map_err(|e| format!("unable to sync tx log: {}", e))?;
+    }
+
+    Ok(())
+}
+
+// drops the active tree, and every coin inserted into it, and makes the tree
+// it retired active again, as it stood when it was retired
+fn undo_rollover(state: &mut AppStateType) -> Result<(), String> {
+    let retired = (*state).retired_trees
+        .pop()
+        .ok_or_else(|| "no tree was rolled over".to_string())?;
+
+    let tree_id = (*state).tree_id;
+    for leaf_index in 0..(*state).num_coins {
+        (*state).nullifiers.remove(leaf_index);
+        (*state).imports.retain(|_, at| *at != (tree_id, leaf_index));
+    }
+    (*state).nullifiers.restore_tree(retired.spent_at);
+
+    (*state).tree_id -= 1;
+    (*state).db = retired.db;
+    (*state).root_bs58 = retired.root_bs58;
+    (*state).root_history = retired.root_history;
+    (*state).num_coins = retired.num_coins;
+    (*state).inserted_at = retired.inserted_at;
+    (*state).memos = retired.memos;
+
+    // neither is kept for a retired tree; coins are inserted from leaf 0 on,
+    // and each commitment is indexed at its first leaf, as insert_leaf does
+    (*state).is_real_leaf = (0..protocol::TREE_CAPACITY).map(|i| i < retired.num_coins).collect();
+    (*state).leaf_indices.clear();
+    for leaf_index in 0..retired.num_coins {
+        let com_bytes = protocol::commitment_to_bytes((*state).db.get_record(leaf_index));
+        (*state).leaf_indices.entry(com_bytes).or_insert(leaf_index);
+    }
+
+    info!(from = tree_id, to = tree_id - 1, "undid a rollover");
+    Ok(())
+}
+
+// applies an onramp, unless one was already accepted under its idempotency key;
+// returns the leaf of its coin, along with the merkle update proof to forward
+// if the onramp is new
+fn apply_onramp_tx(
+    state: &mut AppStateType,
+    tx: &protocol::GrothProofBs58,
+    idempotency_key: Option<&str>
+) -> Result<(usize, Option<protocol::GrothProofBs58>), String> {
+    let now = tickets::unix_time();
+    if let Some(ticket) = idempotency_key.and_then(|key| (*state).tickets.get(key, now)) {
+        return Ok((ticket.leaf_index, None));
+    }
+
+    let mut history = protocol::TxHistory::received(now);
+    let utxo_com = verify_onramp_tx(state, tx)?;
+    history.advance(protocol::TxState::Validated, tickets::unix_time())?;
+    make_room(state, 1)?;
+    let leaf_index = (*state).num_coins;
+    let merkle_update_proof = add_coin_to_state(state, &utxo_com)?;
+    history.advance(protocol::TxState::Staged, tickets::unix_time())?;
+
+    let ticket = idempotency_key.map(|key| protocol::LoggedTicket {
+        idempotency_key: key.to_string(),
+        accepted_at: now,
+        history: Some(history.clone()),
+    });
+    log_ticketed_tx(state, protocol::LoggedTxBs58::Onramp(tx.clone()), ticket.clone());
+    if let Some(ticket) = ticket {
+        (*state).tickets.insert(&ticket.idempotency_key, tickets::Ticket { leaf_index, accepted_at: now, history }, now);
+    }
+
+    Ok((leaf_index, Some(merkle_update_proof)))
+}
+
+// verifies an onramp proof, and returns the commitment of the coin it creates
+fn verify_onramp_tx(
+    state: &mut AppStateType,
+    tx: &protocol::GrothProofBs58
+) -> Result<ark_bls12_377::G1Affine, String> {
+    if !verify_proof(state, tx)? {
+        return Err("onramp proof does not verify".to_string());
+    }
+
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(tx);
+
+    protocol::affine_from_xy(
+        public_inputs[protocol::OnrampGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::OnrampGrothPublicInput::COMMITMENT_Y as usize]
+    )
+}
+
+// verifies a deposit minting several notes, and returns the commitments of the
+// minted notes, in the order they are to be inserted
+fn verify_onramp_multi_tx(
+    state: &mut AppStateType,
+    tx: &protocol::GrothProofBs58
+) -> Result<Vec<ark_bls12_377::G1Affine>, String> {
+    if !verify_proof(state, tx)? {
+        return Err("invalid onramp multi proof".to_string());
+    }
+
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(tx);
+    onramp_multi_circuit::minted_commitments(&public_inputs)
+}
+
+// runs the pairing check for a proof tagged onramp (multi), payment or swap, unless the cache
+// holds a fresh verdict for it; a cached verdict never skips any state checks. A proof
+// that doesn't decode is an error, as decoding it would panic under the lock, and one
+// the pairing check errors on is refused like one it fails
+fn verify_proof(state: &mut AppStateType, tx: &protocol::GrothProofBs58) -> Result<bool, String> {
+    protocol::check_canonical_groth_proof(tx)?;
+
+    let keys = match state.keys.as_ref() {
+        Some(keys) => keys,
+        None => return Ok(false),
+    };
+    let pvk = match tx.circuit {
+        protocol::CircuitKind::Onramp => &keys.onramp_pvk,
+        protocol::CircuitKind::OnrampMulti => &keys.onramp_multi_pvk,
+        protocol::CircuitKind::Payment => &keys.payment_pvk,
+        protocol::CircuitKind::Swap => &keys.swap_pvk,
+        protocol::CircuitKind::Transfer => &keys.transfer_pvk,
+        _ => return Ok(false),
+    };
+
+    Ok(state.verification_cache.verify(tx, || {
+        let (proof, public_inputs) = protocol::groth_proof_from_bs58(tx);
+        Groth16::<BW6_761>::verify_with_processed(pvk, &public_inputs, &proof).unwrap_or(false)
+    }))
+}
+
+/// PaymentSubmission is a payment as the validators see it
+struct PaymentSubmission<'a> {
+    tx: &'a protocol::PaymentTxBs58,
+    // hex fingerprint of the vk the client claims to have proved with
+    vk_fingerprint: Option<&'a str>,
+}
+
+type PaymentValidator = fn(&mut AppStateType, &PaymentSubmission) -> Result<(), String>;
+
+// the checks a payment must pass before it is applied, in the order they run;
+// both /payment and /simulate/payment go through this list, so a diagnosis
+// names the very check a submission fails. Every later check may assume the
+// ones before it passed, e.g. that the encoding decodes.
+const PAYMENT_VALIDATORS: [(&str, PaymentValidator); 9] = [
+    ("circuit_kind", validate_payment_kind),
+    ("encoding", validate_payment_encoding),
+    ("memo", validate_payment_memo),
+    ("known_root", validate_payment_root),
+    ("commitment", validate_payment_commitment),
+    ("nullifier_unspent", validate_payment_nullifier),
+    ("policy", validate_payment_policy),
+    ("vk_fingerprint", validate_payment_vk_fingerprint),
+    ("pairing", validate_payment_pairing),
+];
+
+// runs the validators in order, up to and including the first that fails
+fn run_payment_validators(state: &mut AppStateType, submission: &PaymentSubmission) -> Vec<protocol::Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (check, validator) in PAYMENT_VALIDATORS.iter() {
+        let error = validator(state, submission).err();
+        let passed = error.is_none();
+        diagnostics.push(protocol::Diagnostic { check: check.to_string(), passed, error });
+
+        if !passed {
+            break;
+        }
+    }
+
+    diagnostics
+}
+
+// never let a proof for another circuit, or with another number of inputs, reach the payment vk
+fn validate_payment_kind(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    protocol::check_circuit_kind(&submission.tx.payment_proof, protocol::CircuitKind::Payment, &(*state).statement)
+}
+
+fn validate_payment_encoding(_: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    protocol::check_canonical_groth_proof(&submission.tx.payment_proof)
+}
+
+// the memo is opaque to us, but we won't store arbitrarily large ones
+fn validate_payment_memo(_: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    match submission.tx.memo.as_ref() {
+        Some(memo) => protocol::memo_from_bs58(memo).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+// a proof against a root that has left the window is cheap to reject, and
+// the client can recover from it by re-proving against a fresh opening
+fn validate_payment_root(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    check_known_root(state, &submission.tx.payment_proof)
+}
+
+// a payment (or swap half) proves membership against a root of the tree its
+// TREE_ID names, so only that tree's window is searched
+fn check_known_root(state: &AppStateType, tx: &protocol::GrothProofBs58) -> Result<(), String> {
+    let tree_id = protocol::tree_id_of(tx)?;
+    let root_history = if tree_id == state.tree_id {
+        &state.root_history
+    } else {
+        &state.retired_trees.get(tree_id as usize).ok_or_else(|| format!("unknown tree {}", tree_id))?.root_history
+    };
+
+    let claimed_root = (
+        tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize].clone(),
+        tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize].clone(),
+    );
+    if !root_history.is_known_root(&claimed_root) {
+        return Err(protocol::UNKNOWN_ROOT_ERROR.to_string());
+    }
+
+    Ok(())
+}
+
+fn validate_payment_commitment(_: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    payment_commitment(submission.tx).map(|_| ())
+}
+
+fn validate_payment_nullifier(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    let nullifier = &submission.tx.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    if (*state).nullifiers.contains(nullifier) {
+        return Err("duplicate nullifier".to_string());
+    }
+
+    Ok(())
+}
+
+fn validate_payment_policy(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    let nullifier = &submission.tx.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    let utxo_com = payment_commitment(submission.tx)?;
+    if (*state).policy.is_blocked_nullifier(nullifier) ||
+        (*state).policy.is_blocked_commitment(&protocol::commitment_to_bs58(&utxo_com)) {
+        return Err("blocked by policy".to_string());
+    }
+
+    Ok(())
+}
+
+// a proof made with another setup fails the pairing check too, but this names the cause
+fn validate_payment_vk_fingerprint(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    let claimed = match submission.vk_fingerprint {
+        Some(claimed) => claimed,
+        None => return Ok(()),
+    };
+    let sequencer_keys = (*state).keys.as_ref().ok_or("a read-only replica holds no verifying keys")?;
+
+    let id = registry::descriptor(protocol::CircuitKind::Payment).unwrap().id;
+    let fingerprint = hex::encode(keys::vk_fingerprint(id, &sequencer_keys.payment_pvk.vk));
+    if !claimed.eq_ignore_ascii_case(&fingerprint) {
+        return Err(format!("proof was made for vk {}, but the sequencer verifies with vk {}", claimed, fingerprint));
+    }
+
+    Ok(())
+}
+
+fn validate_payment_pairing(state: &mut AppStateType, submission: &PaymentSubmission) -> Result<(), String> {
+    if !verify_proof(state, &submission.tx.payment_proof)? {
+        return Err("invalid payment proof".to_string());
+    }
+
+    Ok(())
+}
+
+// the commitment of the coin a payment creates
+fn payment_commitment(input: &protocol::PaymentTxBs58) -> Result<ark_bls12_377::G1Affine, String> {
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(&input.payment_proof);
+
+    protocol::affine_from_xy(
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+    )
+}
+
+// applies a verified payment: consumes its nullifier and inserts the coin it creates
+fn apply_payment_tx(
+    state: &mut AppStateType,
+    input: &protocol::PaymentTxBs58,
+    utxo_com: &ark_bls12_377::G1Affine
+) -> Result<protocol::GrothProofBs58, String> {
+    let nullifier = &input.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+
+    // reject any attempt to double spend
+    if (*state).nullifiers.contains(nullifier) {
+        return Err("duplicate nullifier".to_string());
+    }
+
+    // the spending tx is identified by the leaf it is about to insert
+    make_room(state, 1)?;
+    let leaf_index = (*state).num_coins;
+
+    // add utxo to state; the nullifier is only consumed once that succeeds
+    let merkle_update_proof = add_coin_to_state(state, utxo_com)?;
+
+    (*state).nullifiers.insert(nullifier, leaf_index);
+
+    if let Some(memo) = input.memo.as_ref() {
+        (*state).memos.insert(leaf_index, memo.clone());
+    }
+
+    Ok(merkle_update_proof)
+}
+
+// checks every payment of a batch, as /payment would, against the state as
+// it stands: a payment cannot prove against a root that an earlier payment of
+// the same batch creates. Each result is reported as soon as it is reached,
+// and once a payment is rejected the rest are skipped. Returns the commitments
+// of the coins the payments create, in order
+fn verify_payment_batch(
+    state: &mut AppStateType,
+    batch: &[protocol::PaymentTxBs58],
+    report: &mut dyn FnMut(protocol::BatchItemResult)
+) -> Result<Vec<ark_bls12_377::G1Affine>, String> {
+    if batch.is_empty() || batch.len() > protocol::TREE_CAPACITY {
+        return Err(format!("a batch holds between 1 and {} payments, not {}", protocol::TREE_CAPACITY, batch.len()));
+    }
+
+    let mut commitments = Vec::with_capacity(batch.len());
+    let mut nullifiers = HashSet::new();
+    let mut rejection = None;
+
+    for (index, tx) in batch.iter().enumerate() {
+        if rejection.is_some() {
+            report(protocol::BatchItemResult { index, status: protocol::BatchItemStatus::Skipped, error: None });
+            continue;
+        }
+
+        match check_batch_payment(state, tx, &mut nullifiers) {
+            Ok(utxo_com) => {
+                commitments.push(utxo_com);
+                report(protocol::BatchItemResult { index, status: protocol::BatchItemStatus::Verified, error: None });
+            },
+            Err(e) => {
+                rejection = Some(format!("payment {}: {}", index, e));
+                report(protocol::BatchItemResult { index, status: protocol::BatchItemStatus::Rejected, error: Some(e) });
+            },
+        }
+    }
+
+    match rejection {
+        Some(e) => Err(e),
+        None => Ok(commitments),
+    }
+}
+
+// runs the payment validators on one payment of a batch, and makes sure no
+// earlier payment of the batch spends the same coin
+fn check_batch_payment(
+    state: &mut AppStateType,
+    tx: &protocol::PaymentTxBs58,
+    nullifiers: &mut HashSet<String>
+) -> Result<ark_bls12_377::G1Affine, String> {
+    let submission = PaymentSubmission { tx, vk_fingerprint: None };
+    if let Some(failed) = run_payment_validators(state, &submission).pop().filter(|d| !d.passed) {
+        return Err(failed.error.unwrap_or_default());
+    }
+
+    let nullifier = &tx.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    if !nullifiers.insert(nullifier.clone()) {
+        return Err("duplicate nullifier".to_string());
+    }
+
+    payment_commitment(tx)
+}
+
+// applies a verified batch, all of whose coins land in the same tree; returns
+// one merkle update proof per payment, in order
+fn apply_payment_batch(
+    state: &mut AppStateType,
+    batch: &[protocol::PaymentTxBs58],
+    commitments: &[ark_bls12_377::G1Affine]
+) -> Result<Vec<protocol::GrothProofBs58>, String> {
+    make_room(state, batch.len())?;
+
+    batch
+        .iter()
+        .zip(commitments)
+        .map(|(tx, utxo_com)| apply_payment_tx(state, tx, utxo_com))
+        .collect()
+}
+
+// checks a swap half as a payment is checked, short of consuming its nullifier;
+// returns the commitment of the coin it creates
+fn check_swap_half(
+    state: &mut AppStateType,
+    half: &protocol::SwapHalfBs58
+) -> Result<ark_bls12_377::G1Affine, String> {
+    let tx = &half.payment_proof;
+    protocol::check_circuit_kind(tx, protocol::CircuitKind::Swap, &(*state).statement)?;
+    // decoding a malformed proof would panic under the lock, and poison it
+    protocol::check_canonical_groth_proof(tx)?;
+
+    if let Some(memo) = half.memo.as_ref() {
+        protocol::memo_from_bs58(memo)?;
+    }
+
+    check_known_root(state, tx)?;
+
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(tx);
+    let utxo_com = protocol::affine_from_xy(
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+    )?;
+
+    let nullifier = &tx.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    if (*state).nullifiers.contains(nullifier) {
+        return Err("duplicate nullifier".to_string());
+    }
+    if (*state).policy.is_blocked_nullifier(nullifier) ||
+        (*state).policy.is_blocked_commitment(&protocol::commitment_to_bs58(&utxo_com)) {
+        return Err("blocked by policy".to_string());
+    }
+
+    if !verify_proof(state, tx)? {
+        return Err("invalid swap proof".to_string());
+    }
+
+    Ok(utxo_com)
+}
+
+// drops every proposal whose timeout has passed, returning the escrowed halves;
+// nothing of an expired proposal was applied, so its coin remains spendable
+fn expire_swaps(state: &mut AppStateType, now: Instant) -> Vec<protocol::SwapHalfBs58> {
+    let expired: Vec<String> = (*state).swaps
+        .iter()
+        .filter(|(_, pending)| pending.expires_at <= now)
+        .map(|(swap_id, _)| swap_id.clone())
+        .collect();
+
+    expired
+        .iter()
+        .filter_map(|swap_id| (*state).swaps.remove(swap_id))
+        .map(|pending| pending.proposal)
+        .collect()
+}
+
+fn propose_swap(
+    state: &mut AppStateType,
+    proposal: protocol::SwapHalfBs58,
+    now: Instant
+) -> Result<(), String> {
+    let mut history = protocol::TxHistory::received(tickets::unix_time());
+    expire_swaps(state, now);
+    check_swap_half(state, &proposal)?;
+    if protocol::swap_role_of(&proposal.payment_proof)? != protocol::SwapRole::Proposal {
+        return Err("not a swap proposal".to_string());
+    }
+
+    let inputs = &proposal.payment_proof.public_inputs;
+    let swap_id = inputs[protocol::SwapGrothPublicInput::SWAP_ID as usize].clone();
+    if (*state).swaps.contains_key(&swap_id) {
+        return Err("swap already proposed".to_string());
+    }
+
+    // a coin may back at most one pending swap
+    let nullifier = &inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    if (*state).swaps.values().any(|pending|
+        &pending.proposal.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize] == nullifier
+    ) {
+        return Err("nullifier already escrowed".to_string());
+    }
+
+    history.advance(protocol::TxState::Validated, tickets::unix_time())?;
+    history.advance(protocol::TxState::Escrowed, tickets::unix_time())?;
+    (*state).swaps.insert(swap_id, PendingSwap { proposal, expires_at: now + SWAP_TIMEOUT, history });
+
+    Ok(())
+}
+
+// settles a pending swap with its matching half; either both halves are
+// applied or neither is. Returns the proposal, and the merkle update proofs
+// of the proposal's and the acceptance's output coins, in that order
+fn accept_swap(
+    state: &mut AppStateType,
+    acceptance: &protocol::SwapHalfBs58,
+    now: Instant
+) -> Result<(protocol::SwapHalfBs58, [protocol::GrothProofBs58; 2]), String> {
+    expire_swaps(state, now);
+    let acceptance_com = check_swap_half(state, acceptance)?;
+    if protocol::swap_role_of(&acceptance.payment_proof)? != protocol::SwapRole::Acceptance {
+        return Err("not a swap acceptance".to_string());
+    }
+
+    let input = |half: &protocol::SwapHalfBs58, index: usize| half.payment_proof.public_inputs[index].clone();
+    let swap_id = input(acceptance, protocol::SwapGrothPublicInput::SWAP_ID as usize);
+    let pending = (*state).swaps.get(&swap_id).ok_or("no pending swap with this id")?;
+    if !pending.history.state().can_advance_to(&protocol::TxState::Staged) {
+        return Err(format!("swap proposal is {:?}, not escrowed", pending.history.state()));
+    }
+    let proposal = pending.proposal.clone();
+
+    // each half must pay the asset the other half wants
+    let asset_tag = protocol::PaymentGrothPublicInput::ASSET_TAG as usize;
+    if input(acceptance, asset_tag) != proposal.want_asset_tag ||
+        input(&proposal, asset_tag) != acceptance.want_asset_tag {
+        return Err("mismatched asset".to_string());
+    }
+
+    // the proposal was checked when escrowed, but its root may since have left
+    // the window, or its coin have been spent by this very acceptance
+    let proposal_com = check_swap_half(state, &proposal)?;
+    let nullifier = protocol::PaymentGrothPublicInput::NULLIFIER as usize;
+    if input(acceptance, nullifier) == input(&proposal, nullifier) {
+        return Err("duplicate nullifier".to_string());
+    }
+
+    // both output coins land in the same tree
+    make_room(state, 2)?;
+
+    // should the second half fail to apply, the first is undone, and the
+    // proposal stays escrowed
+    let before = checkpoint(state);
+    let as_payment = |half: &protocol::SwapHalfBs58| protocol::PaymentTxBs58 {
+        payment_proof: half.payment_proof.clone(),
+        memo: half.memo.clone(),
+    };
+    let updates = apply_payment_tx(state, &as_payment(&proposal), &proposal_com)
+        .and_then(|proposal_update| {
+            let acceptance_update = apply_payment_tx(state, &as_payment(acceptance), &acceptance_com)?;
+            Ok([proposal_update, acceptance_update])
+        });
+    let updates = match updates {
+        Ok(updates) => updates,
+        Err(e) => {
+            rollback(state, before)?;
+            return Err(e);
+        }
+    };
+
+    (*state).swaps.remove(&swap_id);
+
+    Ok((proposal, updates))
+}
+
+// applies a transfer out of this pool, to a peer, and signs its export
+fn export_transfer(
+    state: &mut AppStateType,
+    tx: &protocol::GrothProofBs58
+) -> Result<protocol::TransferExportBs58, String> {
+    let pool_id = (*state).statement.pool().ok_or("this pool has no SANCTUM_POOL_ID to transfer from")?;
+    if (*state).signing_key.is_none() {
+        return Err("this sequencer has no SANCTUM_SEQUENCER_KEY to sign transfers with".to_string());
+    }
+
+    // the destination only takes exports from pools it knows, and vice versa
+    protocol::check_circuit_kind(tx, protocol::CircuitKind::Transfer, &(*state).statement)?;
+    let dest_pool_id = protocol::dest_pool_id_of(tx)?;
+    if (*state).peers.key(dest_pool_id).is_none() {
+        return Err(format!("pool {} is not a peer", dest_pool_id));
+    }
+
+    apply_transfer_out(state, tx)?;
+
+    Ok(transfers::sign_export(pool_id, tx, (*state).signing_key.as_ref().unwrap()))
+}
+
+// checks a transfer out of this pool as a payment is checked, then consumes
+// its nullifier; its output coin is inserted by the destination pool instead
+fn apply_transfer_out(state: &mut AppStateType, tx: &protocol::GrothProofBs58) -> Result<(), String> {
+    protocol::check_circuit_kind(tx, protocol::CircuitKind::Transfer, &(*state).statement)?;
+    protocol::check_canonical_groth_proof(tx)?;
+    check_known_root(state, tx)?;
+
+    let dest_pool_id = protocol::dest_pool_id_of(tx)?;
+    if Some(dest_pool_id) == (*state).statement.pool() {
+        return Err("a transfer to this pool is a payment".to_string());
+    }
+
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(tx);
+    let utxo_com = protocol::affine_from_xy(
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+        public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+    )?;
+
+    let nullifier = &tx.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize];
+    if (*state).nullifiers.contains(nullifier) {
+        return Err("duplicate nullifier".to_string());
+    }
+    if (*state).policy.is_blocked_nullifier(nullifier) ||
+        (*state).policy.is_blocked_commitment(&protocol::commitment_to_bs58(&utxo_com)) {
+        return Err("blocked by policy".to_string());
+    }
+
+    if !verify_proof(state, tx)? {
+        return Err("invalid transfer proof".to_string());
+    }
+
+    (*state).nullifiers.export(nullifier);
+
+    Ok(())
+}
+
+// inserts the output coin of a transfer a peer exported to this pool; returns
+// its leaf, along with the merkle update proof to forward
+fn import_transfer(
+    state: &mut AppStateType,
+    export: &protocol::TransferExportBs58
+) -> Result<(usize, protocol::GrothProofBs58), String> {
+    let pool_id = (*state).statement.pool().ok_or("this pool has no SANCTUM_POOL_ID to transfer to")?;
+    let utxo_com = transfers::verify_export(export, &(*state).peers, pool_id)?;
+
+    let id = transfers::export_id(export);
+    if (*state).imports.contains_key(&id) {
+        return Err("transfer was already imported".to_string());
+    }
+    if (*state).policy.is_blocked_commitment(&protocol::commitment_to_bs58(&utxo_com)) {
+        return Err("blocked by policy".to_string());
+    }
+
+    make_room(state, 1)?;
+    let leaf_index = (*state).num_coins;
+    let merkle_update_proof = add_coin_to_state(state, &utxo_com)?;
+    (*state).imports.insert(id, ((*state).tree_id, leaf_index));
+
+    Ok((leaf_index, merkle_update_proof))
+}
+
+// feeds a tx log into the (fresh) state, checking after each tx that the
+// root matches the logged one; returns the number of txs replayed
+fn replay_tx_log(state: &mut AppStateType, log: &str) -> Result<usize, String> {
+    let mut num_txs = 0;
+
+    for (line_number, line) in log.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let entry: protocol::TxLogEntryBs58 = serde_json::from_str(line)
+            .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        let (tree_id, num_coins) = ((*state).tree_id, (*state).num_coins);
+        replay_tx(state, &entry.tx).map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+
+        if (*state).root_bs58 != entry.root {
+            return Err(format!(
+                "line {}: root {} does not match logged root {}",
+                line_number + 1, (*state).root_bs58, entry.root
+            ));
+        }
+
+        // the tx's coins were inserted when it was first accepted, not now;
+        // if it rolled the tree over, they are all of the new tree's coins
+        if let Some(timestamp) = entry.timestamp {
+            let first = if (*state).tree_id == tree_id { num_coins } else { 0 };
+            (*state).inserted_at[first..].fill(timestamp);
+        }
+
+        // only onramps are ticketed, and an onramp's coin is the last one inserted
+        if let Some(ticket) = entry.ticket.as_ref() {
+            let leaf_index = (*state).num_coins - 1;
+            let history = settled_history(ticket, leaf_index).map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            let recorded = tickets::Ticket { leaf_index, accepted_at: ticket.accepted_at, history };
+            (*state).tickets.insert(&ticket.idempotency_key, recorded, tickets::unix_time());
+        }
+        num_txs += 1;
+    }
+
+    Ok(num_txs)
+}
+
+// the history of a logged ticketed tx, which settled, or it would have been cut
+// from the log when it was rolled back. Its line is written once it is staged,
+// so its later moves are dated to that; a line from before histories were
+// logged dates every move to the tx's acceptance
+fn settled_history(ticket: &protocol::LoggedTicket, leaf_index: usize) -> Result<protocol::TxHistory, String> {
+    let mut history = match ticket.history.clone() {
+        Some(history) => history,
+        None => {
+            let mut history = protocol::TxHistory::received(ticket.accepted_at);
+            history.advance(protocol::TxState::Validated, ticket.accepted_at)?;
+            history.advance(protocol::TxState::Staged, ticket.accepted_at)?;
+            history
+        }
+    };
+
+    let staged_at = history.transitions().last().unwrap().at;
+    history.advance(protocol::TxState::ForwardedToVerifiers, staged_at)?;
+    history.advance(protocol::TxState::Settled { leaf_index: Some(leaf_index) }, staged_at)?;
+    Ok(history)
+}
+
+// applies a logged tx to the state, verifying its proofs again
+fn replay_tx(state: &mut AppStateType, tx: &protocol::LoggedTxBs58) -> Result<(), String> {
+    match tx {
+        protocol::LoggedTxBs58::Onramp(tx) => {
+            let utxo_com = verify_onramp_tx(state, tx)?;
+            add_coin_to_state(state, &utxo_com)?;
+        },
+        protocol::LoggedTxBs58::OnrampMulti(tx) => {
+            let commitments = verify_onramp_multi_tx(state, tx)?;
+            add_coins_to_state(state, &commitments)?;
+        },
+        protocol::LoggedTxBs58::Payment(input) => {
+            if !verify_proof(state, &input.payment_proof)? {
+                return Err("payment proof does not verify".to_string());
+            }
+
+            let (_, public_inputs) = protocol::groth_proof_from_bs58(&input.payment_proof);
+
+            let utxo_com = protocol::affine_from_xy(
+                public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+                public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+            )?;
+            apply_payment_tx(state, input, &utxo_com)?;
+        },
+        protocol::LoggedTxBs58::Swap { proposal, acceptance } => {
+            let now = Instant::now();
+            propose_swap(state, proposal.clone(), now)?;
+            accept_swap(state, acceptance, now)?;
+        },
+        protocol::LoggedTxBs58::AppRegistration(registration) => {
+            (*state).apps.register(registration)?;
+        },
+        protocol::LoggedTxBs58::App(app_tx) => {
+            let commitment = (*state).apps.verify(app_tx)?;
+            add_coin_to_state(state, &commitment)?;
+        },
+        protocol::LoggedTxBs58::TransferOut(tx) => {
+            apply_transfer_out(state, tx)?;
+        },
+        protocol::LoggedTxBs58::TransferIn(export) => {
+            import_transfer(state, export)?;
+        },
+    }
+
+    Ok(())
+}
+
+// replays a tx log into the (fresh) state as replay_tx_log does, but as a
+// check of the log rather than a recovery from it: a torn last entry is an
+// error here, not something to cut off. Given the live tree, the rebuilt one
+// must match it too, which catches a log that lost its last entries whole
+fn verify_state(
+    state: &mut AppStateType,
+    log: &str,
+    live: Option<&protocol::TreeSnapshot>
+) -> Result<usize, String> {
+    if !log.is_empty() && !log.ends_with('\n') {
+        return Err(format!("line {}: the last entry is torn", log.lines().count()));
+    }
+
+    let num_txs = replay_tx_log(state, log)?;
+
+    if let Some(live) = live {
+        let rebuilt = compute_tree_snapshot(state);
+        if (rebuilt.tree_id, rebuilt.num_coins) != (live.tree_id, live.num_coins) {
+            return Err(format!(
+                "the log rebuilds {} coins in tree {}, but the live state has {} coins in tree {}",
+                rebuilt.num_coins, rebuilt.tree_id, live.num_coins, live.tree_id
+            ));
+        }
+        if (&rebuilt.root_x, &rebuilt.root_y) != (&live.root_x, &live.root_y) {
+            return Err(format!(
+                "the log rebuilds root ({}, {}), but the live root is ({}, {})",
+                rebuilt.root_x, rebuilt.root_y, live.root_x, live.root_y
+            ));
+        }
+    }
+
+    Ok(num_txs)
+}
+
+// the operator key (bs58) and an initial signed policy file are optional;
+// a policy file is only accepted alongside the key that signed it
+fn load_policy() -> (policy::Policy, Option<ed25519_dalek::VerifyingKey>) {
+    let operator_key = std::env::var("SANCTUM_OPERATOR_KEY")
+        .ok()
+        .map(|key| policy::operator_key_from_bs58(&key).unwrap());
+
+    let policy = match std::env::var("SANCTUM_POLICY_FILE") {
+        Ok(path) => {
+            let signed: policy::SignedPolicy = serde_json::from_slice(
+                &std::fs::read(&path).expect("unable to read policy file")
+            ).unwrap();
+            let key = operator_key.as_ref().expect("SANCTUM_POLICY_FILE requires SANCTUM_OPERATOR_KEY");
+            signed.verify(key).unwrap()
+        },
+        Err(_) => policy::Policy::default(),
+    };
+    info!(version = policy.version, "policy loaded");
+
+    (policy, operator_key)
+}
+
+fn add_coin_to_state(
+    state: &mut AppStateType,
+    com: &ark_bls12_377::G1Affine
+) -> Result<protocol::GrothProofBs58, String> {
+    if (*state).keys.is_none() {
+        return Err("a read-only replica cannot prove tree updates".to_string());
+    }
+
+    make_room(state, 1)?;
+    let leaf_index = (*state).num_coins;
+    let (old_merkle_proof, new_merkle_proof) = insert_leaf(state, com)?;
+
+    let (proof, public_inputs) = merkle_update_circuit::generate_groth_proof(
+        &(*state).keys.as_ref().unwrap().merkle_update_pk,
+        &old_merkle_proof,
+        &new_merkle_proof,
+        leaf_index
+    );
+
+    Ok(crate::protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &proof, &public_inputs))
+}
+
+// inserts several coins as one batch, into a single tree; returns one merkle
+// update proof per coin, in order
+fn add_coins_to_state(
+    state: &mut AppStateType,
+    coms: &[ark_bls12_377::G1Affine]
+) -> Result<Vec<protocol::GrothProofBs58>, String> {
+    make_room(state, coms.len())?;
+
+    coms.iter().map(|com| add_coin_to_state(state, com)).collect()
+}
+
+// inserts a coin into the next free leaf, without proving the update; returns
+// the opening proofs of that leaf before and after the insertion
+fn insert_leaf(
+    state: &mut AppStateType,
+    com: &ark_bls12_377::G1Affine
+) -> Result<(
+    JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>
+), String> {
+
+    let leaf_index = (*state).num_coins;
+
+    // validates leaf_index before anything is mutated
+    let old_merkle_proof = assemble_merkle_proof(state, leaf_index)?;
+
+    // add it to the vector db
+    (*state).db.update(leaf_index as usize, &com);
+    (*state).root_bs58 = protocol::encode_compressed_as_bs58_str(&(*state).db.commitment());
+    (*state).is_real_leaf[leaf_index] = true;
+    (*state).inserted_at.push(tickets::unix_time());
+    (*state).num_coins += 1;
+    let com_bytes = protocol::commitment_to_bytes(com);
+    let is_first_copy = !(*state).leaf_indices.contains_key(&com_bytes);
+    if is_first_copy {
+        (*state).leaf_indices.insert(com_bytes.clone(), leaf_index);
+    }
+
+    let new_merkle_proof = assemble_merkle_proof(state, leaf_index)?;
+
+    // the update circuit requires both proofs to open the same leaf along the
+    // same path; catch a mismatch here rather than as an unsatisfiable statement
+    if let Err(e) = check_same_path(&old_merkle_proof, &new_merkle_proof, leaf_index) {
+        (*state).db.update(leaf_index, &old_merkle_proof.record);
+        (*state).root_bs58 = protocol::encode_compressed_as_bs58_str(&(*state).db.commitment());
+        (*state).is_real_leaf[leaf_index] = false;
+        (*state).inserted_at.pop();
+        (*state).num_coins -= 1;
+        if is_first_copy {
+            (*state).leaf_indices.remove(&com_bytes);
+        }
+        return Err(e);
+    }
+
+    let new_root = root_xy_bs58(&(*state).db.commitment());
+    (*state).root_history.insert(&new_root);
+
+    Ok((old_merkle_proof, new_merkle_proof))
+}
+
+
+// the leaf holding a coin, found without scanning the tree; if the same coin
+// was inserted more than once, the first leaf holding it
+fn leaf_index_of(state: &AppStateType, com: &ark_bls12_377::G1Affine) -> Option<usize> {
+    state.leaf_indices.get(&protocol::commitment_to_bytes(com)).copied()
+}
+
+// the (x, y) encoding of a root, as it appears in the public inputs of a payment proof
+fn root_xy_bs58(root: &ark_bls12_377::G1Affine) -> (String, String) {
+    (protocol::encode_constraintf_as_bs58_str(&root.x), protocol::encode_constraintf_as_bs58_str(&root.y))
+}
+
+fn compute_tree_snapshot(state: &AppStateType) -> protocol::TreeSnapshot {
+    let (root_x, root_y) = root_xy_bs58(&state.db.commitment());
+
+    protocol::TreeSnapshot {
+        tree_id: state.tree_id,
+        num_coins: state.num_coins,
+        root_x,
+        root_y,
+        levels: MERKLE_TREE_LEVELS as usize,
+        free_leaves: protocol::TREE_CAPACITY - state.num_coins,
+    }
+}
+
+fn compute_fill_ratio(state: &AppStateType) -> protocol::FillRatio {
+    let num_real_leaves = state.is_real_leaf.iter().filter(|&&real| real).count();
+    let capacity = state.is_real_leaf.len();
+
+    protocol::FillRatio {
+        num_real_leaves,
+        capacity,
+        fill_ratio: num_real_leaves as f64 / capacity as f64,
+    }
+}
+
+fn check_same_path(
+    old_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    new_merkle_proof: &JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>,
+    leaf_index: usize
+) -> Result<(), String> {
+    if old_merkle_proof.path.leaf_index != leaf_index || new_merkle_proof.path.leaf_index != leaf_index {
+        return Err(format!(
+            "merkle proofs open leaves {} and {}, expected {}",
+            old_merkle_proof.path.leaf_index, new_merkle_proof.path.leaf_index, leaf_index
+        ));
+    }
+
+    if old_merkle_proof.path.auth_path.len() != new_merkle_proof.path.auth_path.len() {
+        return Err(format!(
+            "merkle proofs have auth paths of length {} and {}",
+            old_merkle_proof.path.auth_path.len(), new_merkle_proof.path.auth_path.len()
+        ));
+    }
+
+    Ok(())
+}
+
+// checks index against the size of tree tree_id, which the caller has resolved;
+// a retired tree was as large as the active tree when it filled up
+fn check_leaf_index(state: &AppStateType, tree_id: u32, index: usize) -> Result<(), String> {
+    let capacity = if tree_id == state.tree_id { state.is_real_leaf.len() } else { protocol::TREE_CAPACITY };
+    if index >= capacity {
+        return Err(format!("leaf index {} is out of range for a tree of {} leaves", index, capacity));
+    }
+
+    Ok(())
+}
+
+// the db panics on out of range indices, so check them against its size first
+fn assemble_merkle_proof(
+    state: &AppStateType,
+    index: usize
+) -> Result<JZVectorCommitmentOpeningProof<MTParams, ark_bls12_377::G1Affine>, String> {
+    check_leaf_index(state, state.tree_id, index)?;
+
+    Ok(JZVectorCommitmentOpeningProof::<MTParams, ark_bls12_377::G1Affine> {
+        root: state.db.commitment(),
+        record: state.db.get_record(index).clone(),
+        path: state.db.proof(index),
+    })
+}
+
+// the multiproof of the given leaves of a tree, against its current root
+fn assemble_multiproof(state: &AppStateType, tree_id: u32, indices: &[usize]) -> Result<MultiProofBs58, String> {
+    if indices.len() > MAX_MULTIPROOF_LEAVES {
+        return Err(format!("a multiproof opens at most {} leaves", MAX_MULTIPROOF_LEAVES));
+    }
+
+    let (db, root_bs58, _) = tree_of(state, tree_id).ok_or_else(|| format!("unknown tree {}", tree_id))?;
+    let proofs = indices
+        .iter()
+        .map(|&index| {
+            check_leaf_index(state, tree_id, index)?;
+            Ok(protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+                &db.proof(index),
+                db.get_record(index),
+                root_bs58
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    multiproof::compress(&proofs)
+}
+
+// the delta from the opening of a leaf against a known root to its opening
+// against the tree's current root, or None if the known root has left the
+// window. Every insert adds one root, so the known root's age in the window
+// is how many leaves were inserted since
+fn assemble_merkle_delta(
+    state: &AppStateType,
+    tree_id: u32,
+    request: &MerkleDeltaRequestBs58
+) -> Result<Option<MerkleDeltaBs58>, String> {
+    let (db, root_bs58, num_coins) = tree_of(state, tree_id).ok_or_else(|| format!("unknown tree {}", tree_id))?;
+    check_leaf_index(state, tree_id, request.index)?;
+    let root_history = match state.retired_trees.get(tree_id as usize) {
+        Some(tree) if tree_id != state.tree_id => &tree.root_history,
+        _ => &state.root_history,
+    };
+
+    let known_root = (request.known_root_x.clone(), request.known_root_y.clone());
+    let age = match root_history.age_of(&known_root) {
+        Some(age) => age,
+        None => return Ok(None),
+    };
+    let known_num_coins = num_coins
+        .checked_sub(age)
+        .ok_or("the known root is older than the tree")?;
+    if request.index >= known_num_coins {
+        return Err(format!("leaf {} was inserted after the known root", request.index));
+    }
+
+    let path = db.proof(request.index);
+    let levels = path.auth_path.len() as u32 + 1;
+    let (leaf_sibling_changed, positions) =
+        merkle_delta::changed_nodes(request.index, levels, known_num_coins, num_coins);
+
+    Ok(Some(MerkleDeltaBs58 {
+        index: request.index,
+        root: root_bs58.clone(),
+        leaf_sibling_hash: leaf_sibling_changed
+            .then(|| protocol::encode_compressed_as_bs58_str(&path.leaf_sibling_hash)),
+        auth_path: positions
+            .into_iter()
+            .map(|position| merkle_delta::AuthPathNodeBs58 {
+                position,
+                node: protocol::encode_compressed_as_bs58_str(&path.auth_path[position]),
+            })
+            .collect(),
+    }))
+}
+
+mod test;
@@ -0,0 +1,1449 @@
+#![cfg(test)]
+
+use ark_ec::CurveGroup;
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+
+use lib_sanctum::utils;
+
+use lib_sanctum::test_coins::test_coin;
+
+use super::*;
+
+#[test]
+fn test_fill_ratio() {
+    let mut state = initialize_state();
+    assert_eq!(compute_fill_ratio(&state).num_real_leaves, 0);
+
+    let mut previous_ratio = compute_fill_ratio(&state).fill_ratio;
+    for amount in 1..4u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+
+        let fill_ratio = compute_fill_ratio(&state);
+        assert_eq!(fill_ratio.num_real_leaves, amount as usize);
+        assert!(fill_ratio.fill_ratio > previous_ratio);
+        previous_ratio = fill_ratio.fill_ratio;
+    }
+}
+
+#[test]
+fn test_tree_snapshot() {
+    let mut state = initialize_state();
+
+    for amount in 1..3u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+
+        let snapshot = compute_tree_snapshot(&state);
+        assert_eq!(snapshot.num_coins, amount as usize);
+        assert_eq!(snapshot.levels, MERKLE_TREE_LEVELS as usize);
+        assert_eq!((snapshot.root_x.clone(), snapshot.root_y.clone()), root_xy_bs58(&state.db.commitment()));
+
+        // a wallet rebuilding the tree from exactly num_coins leaves reaches the same root
+        let mut tree = lib_sanctum::incremental_tree::IncrementalTree::new(MERKLE_TREE_LEVELS);
+        for event in coin_events(&state, 0).iter().take(snapshot.num_coins) {
+            tree.ingest(event).unwrap();
+        }
+        let root = tree.opening_proof(0).unwrap().root;
+        assert_eq!((snapshot.root_x, snapshot.root_y), root_xy_bs58(&root));
+    }
+}
+
+#[test]
+fn test_leaf_indices_match_scan() {
+    let mut state = initialize_state();
+
+    // the same coin twice, among others
+    for amount in [1u8, 2, 1, 3] {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+    }
+
+    // every coin is found at the first leaf a full scan finds it at
+    for i in 0..state.num_coins {
+        let com = state.db.get_record(i).clone();
+        let scanned = (0..state.num_coins).find(|&j| *state.db.get_record(j) == com);
+        assert_eq!(leaf_index_of(&state, &com), scanned);
+    }
+    assert_eq!(state.leaf_indices.len(), 3);
+    assert_eq!(leaf_index_of(&state, &test_coin(4).commitment().into_affine()), None);
+}
+
+#[test]
+fn test_out_of_range_leaf_index() {
+    let mut state = initialize_state();
+    let capacity = state.is_real_leaf.len();
+    assert!(assemble_merkle_proof(&state, capacity - 1).is_ok());
+    assert!(assemble_merkle_proof(&state, capacity).is_err());
+
+    // a corrupted coin count surfaces as an error, leaving the state untouched
+    state.num_coins = capacity;
+    let com = test_coin(1).commitment().into_affine();
+    assert!(add_coin_to_state(&mut state, &com).is_err());
+    assert_eq!(state.num_coins, capacity);
+}
+
+#[test]
+fn test_check_same_path() {
+    let state = initialize_state();
+    let proof_0 = assemble_merkle_proof(&state, 0).unwrap();
+    let proof_1 = assemble_merkle_proof(&state, 1).unwrap();
+
+    assert!(check_same_path(&proof_0, &proof_0, 0).is_ok());
+    assert!(check_same_path(&proof_0, &proof_1, 0).unwrap_err().contains("leaves 0 and 1"));
+
+    let mut truncated = proof_0.clone();
+    truncated.path.auth_path.pop();
+    assert!(check_same_path(&proof_0, &truncated, 0).unwrap_err().contains("auth paths"));
+}
+
+#[test]
+fn test_multiproof() {
+    let mut state = initialize_state();
+    for amount in 1..4u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+    }
+
+    // the multiproof verifies, and opens each leaf as its own proof does
+    let multiproof = assemble_multiproof(&state, state.tree_id, &[0, 1, 2]).unwrap();
+    let openings = multiproof::verify(&multiproof).unwrap();
+    let separate: Vec<_> = (0..3)
+        .map(|i| protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(&assemble_merkle_proof(&state, i).unwrap()))
+        .collect();
+    for (opening, proof) in openings.iter().zip(separate.iter()) {
+        let opening = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(opening);
+        assert_eq!(serde_json::to_string(&opening).unwrap(), serde_json::to_string(proof).unwrap());
+    }
+
+    // and is smaller than the three proofs it stands in for
+    let multiproof_size = serde_json::to_vec(&multiproof).unwrap().len();
+    let separate_size: usize = separate.iter().map(|proof| serde_json::to_vec(proof).unwrap().len()).sum();
+    assert!(multiproof_size < separate_size, "{} >= {}", multiproof_size, separate_size);
+
+    // a path pointing at another node, or past the end of the list, is refused
+    let mut tampered = multiproof.clone();
+    tampered.openings[0].auth_path.swap(0, 1);
+    assert!(multiproof::verify(&tampered).is_err());
+    tampered.openings[0].auth_path[0] = tampered.inner_nodes.len();
+    assert!(multiproof::verify(&tampered).is_err());
+}
+
+#[test]
+fn test_merkle_delta() {
+    let mut state = initialize_state();
+    add_coin_to_state(&mut state, &test_coin(1).commitment().into_affine()).unwrap();
+
+    let opening = |state: &AppStateType| protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+        &assemble_merkle_proof(state, 0).unwrap()
+    );
+    let request = |state: &AppStateType| {
+        let (known_root_x, known_root_y) = root_xy_bs58(&state.db.commitment());
+        MerkleDeltaRequestBs58 { index: 0, known_root_x, known_root_y }
+    };
+    let json = |proof: &protocol::VectorCommitmentOpeningProofBs58| serde_json::to_string(proof).unwrap();
+
+    // after one insertion, only the leaf's sibling changed
+    let (cached, known) = (opening(&state), request(&state));
+    add_coin_to_state(&mut state, &test_coin(2).commitment().into_affine()).unwrap();
+    let delta = assemble_merkle_delta(&state, state.tree_id, &known).unwrap().unwrap();
+    assert!(delta.leaf_sibling_hash.is_some());
+    assert!(delta.auth_path.is_empty());
+    assert_eq!(json(&merkle_delta::apply(&cached, &delta).unwrap()), json(&opening(&state)));
+
+    // after many, the nodes over leaves 2..=8 changed, and nothing over the dummy leaves
+    let (cached, known) = (opening(&state), request(&state));
+    for amount in 3..10u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+    }
+    let delta = assemble_merkle_delta(&state, state.tree_id, &known).unwrap().unwrap();
+    assert!(delta.leaf_sibling_hash.is_none());
+    assert_eq!(delta.auth_path.len(), 3);
+    let patched = merkle_delta::apply(&cached, &delta).unwrap();
+    assert_eq!(json(&patched), json(&opening(&state)));
+    assert_eq!(receipts::is_valid_opening_proof(
+        &protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_from_bs58(&patched)
+    ), Ok(true));
+
+    // a leaf inserted after the known root has no opening to patch
+    let mut later = known.clone();
+    later.index = 5;
+    assert!(assemble_merkle_delta(&state, state.tree_id, &later).is_err());
+
+    // once the known root has left the window, the wallet falls back to a full fetch
+    state.root_history = MerkleRootHistory::new(2);
+    state.root_history.insert(&root_xy_bs58(&state.db.commitment()));
+    add_coin_to_state(&mut state, &test_coin(10).commitment().into_affine()).unwrap();
+    assert_eq!(assemble_merkle_delta(&state, state.tree_id, &known), Ok(None));
+}
+
+#[test]
+fn test_root_cache_tracks_inserts() {
+    let mut state = initialize_state();
+    add_coin_to_state(&mut state, &test_coin(1).commitment().into_affine()).unwrap();
+
+    // serving from borrowed parts and the cached root matches a full encoding
+    let expected = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+        &assemble_merkle_proof(&state, 0).unwrap()
+    );
+    let served = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_parts_to_bs58(
+        &state.db.proof(0), state.db.get_record(0), &state.root_bs58
+    );
+    assert_eq!(serde_json::to_string(&served).unwrap(), serde_json::to_string(&expected).unwrap());
+}
+
+#[test]
+fn test_client_rebuilt_tree_matches_sequencer() {
+    let mut state = initialize_state();
+    for amount in 1..4u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+    }
+
+    let mut tree = lib_sanctum::incremental_tree::IncrementalTree::new(MERKLE_TREE_LEVELS);
+    for event in coin_events(&state, 0).iter() {
+        tree.ingest(event).unwrap();
+    }
+
+    // a replayed event is a gap in the other direction, and is refused
+    assert!(tree.ingest(&coin_events(&state, 0)[0]).is_err());
+
+    for i in 0..state.num_coins {
+        let client_proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+            &tree.opening_proof(i).unwrap()
+        );
+        let served_proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+            &assemble_merkle_proof(&state, i).unwrap()
+        );
+        assert_eq!(serde_json::to_string(&client_proof).unwrap(), serde_json::to_string(&served_proof).unwrap());
+    }
+}
+
+#[test]
+fn test_merkle_update_new_root_follows_from_insertion() {
+    use protocol::MerkleUpdateGrothPublicInput as Input;
+
+    let mut state = initialize_state();
+    let coms = [test_coin(1).commitment().into_affine(), test_coin(2).commitment().into_affine()];
+    add_coin_to_state(&mut state, &coms[0]).unwrap();
+    let update = add_coin_to_state(&mut state, &coms[1]).unwrap();
+
+    // the update verifies, as the verifier checks it
+    let pvk = prepare_verifying_key(&registry::verifying_key(protocol::CircuitKind::MerkleUpdate, &state.statement).unwrap());
+    let (proof, public_inputs) = protocol::groth_proof_from_bs58(&update);
+    assert!(Groth16::<BW6_761>::verify_with_processed(&pvk, &public_inputs, &proof).unwrap());
+
+    // the root of a tree built from scratch, holding the given coins at the given leaves
+    let root_of = |leaves: &[(usize, ark_bls12_377::G1Affine)]| {
+        let (_, vc_params, crs) = utils::trusted_setup();
+        let mut records: Vec<ark_bls12_377::G1Affine> = (0..protocol::TREE_CAPACITY)
+            .map(|_| utils::get_dummy_utxo(&crs).commitment().into_affine())
+            .collect();
+        for (i, com) in leaves {
+            records[*i] = *com;
+        }
+        JZVectorDB::<MTParams, ark_bls12_377::G1Affine>::new(vc_params, &records).commitment()
+    };
+
+    // the update's NEW_ROOT is the root inserting its leaf at its index leads to
+    let expected_root = root_of(&[(0, coms[0]), (1, coms[1])]);
+    assert_eq!(public_inputs[Input::LEAF_INDEX as usize], ark_bw6_761::Fr::from(1u64));
+    assert_eq!(public_inputs[Input::LEAF_VALUE_X as usize], coms[1].x);
+    assert_eq!(public_inputs[Input::LEAF_VALUE_Y as usize], coms[1].y);
+    assert_eq!(public_inputs[Input::NEW_ROOT_X as usize], expected_root.x);
+    assert_eq!(public_inputs[Input::NEW_ROOT_Y as usize], expected_root.y);
+
+    // and the proof holds for no other NEW_ROOT, e.g. that of the leaf inserted
+    // one index further, or of the insertion not happening at all
+    for wrong_root in [root_of(&[(0, coms[0]), (2, coms[1])]), root_of(&[(0, coms[0])])] {
+        let mut forged_inputs = public_inputs.clone();
+        forged_inputs[Input::NEW_ROOT_X as usize] = wrong_root.x;
+        forged_inputs[Input::NEW_ROOT_Y as usize] = wrong_root.y;
+        assert!(!Groth16::<BW6_761>::verify_with_processed(&pvk, &forged_inputs, &proof).unwrap());
+    }
+}
+
+#[test]
+fn test_openapi_covers_routes() {
+    let doc = ApiDoc::openapi();
+    for route in ["/onramp", "/onramp_multi", "/payment", "/simulate/payment", "/merkle", "/merkle/multi", "/merkle_delta", "/nullifiers/status", "/fill-ratio", "/memo",
+        "/receipt/{commitment}", "/status", "/tree", "/admin/policy", "/admin/reload-keys",
+        "/events/coins", "/replication", "/swap/propose", "/swap/accept", "/payment/batch", "/verifiers", "/ordering", "/transfer", "/transfer/in", "/tx/{id}"] {
+        assert!(doc.paths.paths.contains_key(route), "missing route {}", route);
+    }
+}
+
+#[actix_web::test]
+async fn test_read_only_replica() {
+    use actix_web::{http::StatusCode, test};
+
+    // a leader holding an onramped coin, and the coin of a payment with a memo
+    let mut leader = initialize_state();
+    leader.tx_log = None;
+    add_coin_to_state(&mut leader, &test_coin(1).commitment().into_affine()).unwrap();
+    let nullifier = "nullifier".to_string();
+    let payment = protocol::PaymentTxBs58 {
+        payment_proof: protocol::GrothProofBs58 {
+            circuit: protocol::CircuitKind::Payment,
+            proof: String::new(),
+            public_inputs: vec![String::new(), String::new(), nullifier.clone()],
+        },
+        memo: Some(protocol::memo_to_bs58(b"invoice #1").unwrap()),
+    };
+    apply_payment_tx(&mut leader, &payment, &test_coin(2).commitment().into_affine()).unwrap();
+
+    // a replica loads no keys, yet syncs to the leader's tree, nullifiers and memos
+    let mut replica = initialize_replica_state("http://leader:8080");
+    assert!(replica.keys.is_none());
+    apply_replication_batch(&mut replica, &replication_batch(&leader, 0, 0).unwrap()).unwrap();
+
+    assert_eq!(replica.root_bs58, leader.root_bs58);
+    for i in 0..leader.num_coins {
+        let leader_proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+            &assemble_merkle_proof(&leader, i).unwrap()
+        );
+        let replica_proof = protocol::jubjub_vector_commitment_opening_proof_MTEdOnBw6_761_to_bs58(
+            &assemble_merkle_proof(&replica, i).unwrap()
+        );
+        assert_eq!(serde_json::to_string(&leader_proof).unwrap(), serde_json::to_string(&replica_proof).unwrap());
+    }
+    assert_eq!(replica.nullifiers.spent_at(1), Some(&nullifier));
+    assert_eq!(replica.memos.get(&1), leader.memos.get(&1));
+
+    // later batches carry only what is new
+    add_coin_to_state(&mut leader, &test_coin(3).commitment().into_affine()).unwrap();
+    let batch = replication_batch(&leader, 0, replica.num_coins).unwrap();
+    assert_eq!(batch.events.len(), 1);
+    apply_replication_batch(&mut replica, &batch).unwrap();
+    assert_eq!(replica.root_bs58, leader.root_bs58);
+
+    // a batch that doesn't pick up where the replica left off, or that leads
+    // to another root than the leader's, is refused
+    assert!(apply_replication_batch(&mut replica, &replication_batch(&leader, 0, 0).unwrap()).is_err());
+    let mut batch = replication_batch(&leader, 0, replica.num_coins).unwrap();
+    batch.root = protocol::encode_compressed_as_bs58_str(&test_coin(4).commitment().into_affine());
+    assert!(apply_replication_batch(&mut replica, &batch).is_err());
+
+    // without keys, the replica can't prove tree updates of its own
+    assert!(add_coin_to_state(&mut replica, &test_coin(4).commitment().into_affine()).is_err());
+
+    // writes are refused with a pointer to the leader, while reads are served
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(GlobalAppState::new(replica)))
+            .configure(configure_replica_routes)
+    ).await;
+    for route in WRITE_ROUTES {
+        let request = test::TestRequest::post().uri(route).set_json(0usize).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("http://leader:8080"));
+    }
+    let request = test::TestRequest::get().uri("/merkle").set_json(0usize).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_ordering_request() {
+    use actix_web::{http::StatusCode, test};
+    use std::cmp::Ordering;
+
+    let mut state = initialize_state();
+    state.signing_key = Some(ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]));
+    let sequencer_key = state.signing_key.as_ref().unwrap().verifying_key();
+    for amount in 1..4u8 {
+        add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+    }
+    let roots = vec![root_xy_bs58(&state.db.commitment())];
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(GlobalAppState::new(state)))
+            .configure(configure_read_routes)
+    ).await;
+    let commitment = |amount: u8| protocol::encode_compressed_as_bs58_str(&test_coin(amount).commitment().into_affine());
+    let uri = |first: &str, second: &str| format!("/ordering?first={}&second={}", first, second);
+
+    // coins come back at the leaves they were inserted at, in either order
+    for (first, second, expected) in [(1, 2, Ordering::Less), (3, 1, Ordering::Greater)] {
+        let request = test::TestRequest::get().uri(&uri(&commitment(first), &commitment(second))).to_request();
+        let proof: protocol::OrderingProofBs58 = test::call_and_read_body_json(&app, request).await;
+        assert_eq!((proof.first_index, proof.second_index), (first as usize - 1, second as usize - 1));
+        assert_eq!(receipts::verify_ordering(&proof, &roots, Some(&sequencer_key)), Ok(expected));
+    }
+
+    // a coin that isn't in the tree is named in the 404
+    let request = test::TestRequest::get().uri(&uri(&commitment(1), &commitment(4))).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = test::read_body(response).await;
+    assert!(String::from_utf8(body.to_vec()).unwrap().contains(&commitment(4)));
+}
+
+#[actix_web::test]
+async fn test_onramp_proof_rejected_by_payment_endpoint() {
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) =
+        lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+
+    // the payment handler must refuse the onramp proof by its tag, before any verification
+    let tx = protocol::PaymentTxBs58 {
+        payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs),
+        memo: None,
+    };
+    let response = process_payment_tx(app_state.clone(), web::Json(tx)).await;
+    let response: protocol::TxResponse = serde_json::from_slice(
+        &actix_web::body::to_bytes(response.into_body()).await.unwrap()
+    ).unwrap();
+
+    assert!(!response.accepted);
+    assert!(response.error.unwrap().contains("Onramp"));
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 0);
+}
+
+#[actix_web::test]
+async fn test_malformed_onramp_multi_rejected() {
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+
+    // tagged and shaped as a multi onramp, but neither its proof nor its inputs decode
+    let num_public_inputs = registry::descriptor(protocol::CircuitKind::OnrampMulti).unwrap().num_public_inputs(&app_state.statement);
+    let tx = protocol::GrothProofBs58 {
+        circuit: protocol::CircuitKind::OnrampMulti,
+        proof: "0OIl".to_string(),
+        public_inputs: vec!["0OIl".to_string(); num_public_inputs],
+    };
+    let response = process_onramp_multi_tx(app_state.clone(), web::Json(tx.clone())).await;
+    let response: protocol::TxResponse = serde_json::from_slice(
+        &actix_web::body::to_bytes(response.into_body()).await.unwrap()
+    ).unwrap();
+    assert!(!response.accepted);
+    assert!(response.error.unwrap().contains("base58"));
+
+    // it is refused, rather than panicking, by the verification itself too, so
+    // the state stays usable
+    let mut state = app_state.state.lock().unwrap();
+    assert!(verify_proof(&mut state, &tx).is_err());
+    assert_eq!(state.num_coins, 0);
+}
+
+#[actix_web::test]
+async fn test_dirty_asset_id_rejected() {
+    use actix_web::{http::StatusCode, test};
+
+    let mut state = initialize_state();
+    state.tx_log = None;
+    state.verifiers = VerifierSet::new(vec![mock_verifier(StatusCode::OK)], 1).unwrap();
+    let app_state = web::Data::new(GlobalAppState::new(state));
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    // coins of asset 3, one of whose ASSETID field also sets a byte past the u64
+    let (_, _, crs) = utils::trusted_setup();
+    let coin = |asset_id: &[u8]| {
+        let mut fields = test_coin(10).fields.clone();
+        fields[protocol::UtxoField::ASSETID as usize] = asset_id.to_vec();
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    let clean = protocol::AssetId(3).to_field_bytes();
+    let mut dirty = clean;
+    dirty[protocol::ASSET_ID_BYTES] = 1;
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let onramp = |coin: &JZRecord<5>| {
+        let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, coin);
+        protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs)
+    };
+    let submit = |tx: &protocol::GrothProofBs58| test::TestRequest::post().uri("/onramp").set_json(tx).to_request();
+
+    // the onramp circuit is satisfied by either, but the sequencer mints no
+    // coin of the dirty one, which would be a second encoding of asset 3
+    let response: protocol::TxResponse = test::call_and_read_body_json(&app, submit(&onramp(&coin(&dirty)))).await;
+    assert!(!response.accepted);
+    assert_eq!(response.error.as_deref(), Some("ASSET_ID does not fit in a u64"));
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 0);
+
+    let response: protocol::TxResponse = test::call_and_read_body_json(&app, submit(&onramp(&coin(&clean)))).await;
+    assert!(response.accepted);
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 1);
+}
+
+#[actix_web::test]
+async fn test_tx_status() {
+    use actix_web::{http::StatusCode, test};
+
+    let mut state = initialize_state();
+    state.tx_log = None;
+    state.verifiers = VerifierSet::new(vec![mock_verifier(StatusCode::OK)], 1).unwrap();
+    let app_state = web::Data::new(GlobalAppState::new(state));
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+
+    // nothing was submitted under the key yet
+    let status = test::TestRequest::get().uri("/tx/first").to_request();
+    assert_eq!(test::call_service(&app, status).await.status(), StatusCode::NOT_FOUND);
+
+    let submit = test::TestRequest::post().uri("/onramp")
+        .insert_header(("Idempotency-Key", "first"))
+        .set_json(&tx)
+        .to_request();
+    let response: protocol::TxResponse = test::call_and_read_body_json(&app, submit).await;
+    assert!(response.accepted);
+
+    // the onramp made every move up to settling, in order
+    let status = test::TestRequest::get().uri("/tx/first").to_request();
+    let transitions: Vec<protocol::TxTransition> = test::call_and_read_body_json(&app, status).await;
+    let states: Vec<protocol::TxState> = transitions.iter().map(|t| t.state.clone()).collect();
+    assert_eq!(states, vec![
+        protocol::TxState::Received,
+        protocol::TxState::Validated,
+        protocol::TxState::Staged,
+        protocol::TxState::ForwardedToVerifiers,
+        protocol::TxState::Settled { leaf_index: Some(0) },
+    ]);
+    assert!(transitions.windows(2).all(|pair| pair[0].at <= pair[1].at));
+}
+
+#[actix_web::test]
+async fn test_policy_blocks_payment() {
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+    app_state.state.lock().unwrap().operator_key = Some(operator.verifying_key());
+
+    // a payment-tagged tx, against the current root, whose nullifier the policy will block
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, _) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let com = test_coin(10).commitment().into_affine();
+    let root = app_state.state.lock().unwrap().db.commitment();
+    let public_inputs = vec![
+        root.x, root.y, ark_bw6_761::Fr::from(1u64), com.x, com.y, ark_bw6_761::Fr::from(1u64),
+        ark_bw6_761::Fr::from(0u64)
+    ];
+    let tx = protocol::PaymentTxBs58 {
+        payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs),
+        memo: None,
+    };
+    let nullifier = tx.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+
+    let signed = policy::Policy {
+        version: 1,
+        blocked_commitments: vec![],
+        blocked_nullifiers: vec![nullifier],
+    }.sign(&operator);
+    let response = process_policy_update(app_state.clone(), web::Json(signed.clone())).await;
+    assert!(response.status().is_success());
+
+    // replaying the same version is refused
+    let response = process_policy_update(app_state.clone(), web::Json(signed)).await;
+    assert_eq!(response.status(), actix_web::http::StatusCode::CONFLICT);
+
+    // the blocked tx is refused before it is verified or touches the tree
+    let response = process_payment_tx(app_state.clone(), web::Json(tx)).await;
+    assert_eq!(response.status(), actix_web::http::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 0);
+}
+
+#[actix_web::test]
+async fn test_app_registration_requires_operator() {
+    use actix_web::http::StatusCode;
+    use ark_serialize::CanonicalSerialize;
+
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let impostor = ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]);
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+
+    let (_, onramp_vk) = lib_sanctum::onramp_circuit::circuit_setup();
+    let mut vk_bytes: Vec<u8> = Vec::new();
+    onramp_vk.serialize_compressed(&mut vk_bytes).unwrap();
+    let registration = protocol::AppRegistrationBs58 {
+        name: "onramp".to_string(),
+        vk: bs58::encode(vk_bytes).into_string(),
+        public_inputs: ["asset_id", "amount", "commitment_x", "commitment_y"].map(String::from).to_vec(),
+        commitment_x_index: 2,
+        commitment_y_index: 3,
+    };
+
+    // without an operator key nobody registers apps
+    let signed = apps::sign_registration(&registration, &operator);
+    let response = process_app_registration(app_state.clone(), web::Json(signed.clone())).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // and with one, a registration signed by anyone else is refused
+    app_state.state.lock().unwrap().operator_key = Some(operator.verifying_key());
+    let forged = apps::sign_registration(&registration, &impostor);
+    let response = process_app_registration(app_state.clone(), web::Json(forged)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let mut tampered = signed.clone();
+    tampered.registration.commitment_x_index = 0;
+    let response = process_app_registration(app_state.clone(), web::Json(tampered)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // while the operator's is registered here, whether or not the verifiers
+    // it is forwarded to are up
+    let response = process_app_registration(app_state.clone(), web::Json(signed)).await;
+    assert!(![StatusCode::FORBIDDEN, StatusCode::BAD_REQUEST].contains(&response.status()));
+    assert!(app_state.state.lock().unwrap().apps.register(&registration).is_err());
+}
+
+#[actix_web::test]
+async fn test_reload_keys() {
+    use actix_web::http::StatusCode;
+
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+    app_state.state.lock().unwrap().operator_key = Some(operator.verifying_key());
+
+    // an onramp proven with the keys loaded at startup, whose verdict is cached
+    let (old_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&old_pk, &test_coin(10));
+    let old_tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+    assert!(verify_proof(&mut app_state.state.lock().unwrap(), &old_tx).unwrap());
+
+    // upgraded onramp keys, from a setup of their own, staged alongside the other current keys
+    let (_, _, crs) = utils::trusted_setup();
+    let circuit = lib_sanctum::onramp_circuit::OnRampCircuit { crs: crs.clone(), utxo: utils::get_dummy_utxo(&crs) };
+    let (new_pk, new_vk) = Groth16::<BW6_761>::circuit_specific_setup(circuit, &mut rand::rngs::OsRng).unwrap();
+
+    let key_dir = std::env::temp_dir().join(format!("sanctum-reload-keys-{}", std::process::id()));
+    std::fs::create_dir_all(&key_dir).unwrap();
+    let key_path = |kind: protocol::CircuitKind, extension: &str| {
+        let id = registry::descriptor(kind).unwrap().id;
+        key_dir.join(format!("{}.{}", id, extension)).to_str().unwrap().to_string()
+    };
+    {
+        let state = app_state.state.lock().unwrap();
+        let keys = state.keys.as_ref().unwrap();
+        for (kind, vk) in [
+            (protocol::CircuitKind::Onramp, &new_vk),
+            (protocol::CircuitKind::OnrampMulti, &keys.onramp_multi_pvk.vk),
+            (protocol::CircuitKind::Payment, &keys.payment_pvk.vk),
+            (protocol::CircuitKind::Swap, &keys.swap_pvk.vk),
+            (protocol::CircuitKind::Transfer, &keys.transfer_pvk.vk),
+        ] {
+            utils::write_groth_verification_key_to_file(vk, &key_path(kind, "vk"));
+        }
+        let merkle_update_pk = &keys.merkle_update_pk;
+        utils::write_groth_key_to_file(
+            merkle_update_pk, &key_path(protocol::CircuitKind::MerkleUpdate, "pk"),
+            &merkle_update_pk.vk, &key_path(protocol::CircuitKind::MerkleUpdate, "vk"),
+        );
+    }
+    let reload = KeyReload { version: 1, key_dir: Some(key_dir.to_str().unwrap().to_string()) };
+
+    // a reload that isn't signed by the operator is refused
+    let forged = reload.sign(&ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]));
+    let response = process_key_reload(app_state.clone(), web::Json(forged)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = process_key_reload(app_state.clone(), web::Json(reload.sign(&operator))).await;
+    assert!(response.status().is_success());
+    let reloaded: key_reload::KeysReloaded = serde_json::from_slice(
+        &actix_web::body::to_bytes(response.into_body()).await.unwrap()
+    ).unwrap();
+    assert!(reloaded.fingerprints.contains(&key_reload::fingerprint(protocol::CircuitKind::Onramp, &new_vk)));
+
+    // subsequent verifications use the new vk: the old proof is refused despite
+    // its cached verdict, and a proof made with the new pk is accepted
+    let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&new_pk, &test_coin(10));
+    let new_tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+    {
+        let mut state = app_state.state.lock().unwrap();
+        assert!(!verify_proof(&mut state, &old_tx).unwrap());
+        assert!(verify_proof(&mut state, &new_tx).unwrap());
+    }
+
+    // replaying the reload is refused
+    let response = process_key_reload(app_state.clone(), web::Json(reload.sign(&operator))).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    std::fs::remove_dir_all(key_dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_stale_root_rejected() {
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+
+    // a payment-tagged tx proven against the genesis root
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, _) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let genesis = app_state.state.lock().unwrap().db.commitment();
+    let com = test_coin(10).commitment().into_affine();
+    let public_inputs = vec![
+        genesis.x, genesis.y, ark_bw6_761::Fr::from(1u64), com.x, com.y, ark_bw6_761::Fr::from(1u64),
+        ark_bw6_761::Fr::from(0u64)
+    ];
+    let tx = protocol::PaymentTxBs58 {
+        payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs),
+        memo: None,
+    };
+
+    // push the genesis root out of a (short) window
+    {
+        let mut state = app_state.state.lock().unwrap();
+        state.root_history = MerkleRootHistory::new(2);
+        state.root_history.insert(&root_xy_bs58(&genesis));
+        for amount in 1..3u8 {
+            add_coin_to_state(&mut state, &test_coin(amount).commitment().into_affine()).unwrap();
+        }
+    }
+
+    // the tx is refused with the code clients re-prove on, before it is verified
+    let response = process_payment_tx(app_state.clone(), web::Json(tx)).await;
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+    let response: protocol::TxResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.error.as_deref(), Some(protocol::UNKNOWN_ROOT_ERROR));
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 2);
+}
+
+// a stand-in for a verifier, answering every forwarded tx with the given status
+fn mock_verifier(status: actix_web::http::StatusCode) -> String {
+    let server = HttpServer::new(move || {
+        App::new().default_service(web::to(move || async move { HttpResponse::build(status).finish() }))
+    })
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    url
+}
+
+#[actix_web::test]
+async fn test_verifier_quorum() {
+    use actix_web::{http::StatusCode, test};
+
+    let (accepting_a, accepting_b) = (mock_verifier(StatusCode::OK), mock_verifier(StatusCode::OK));
+    let rejecting = mock_verifier(StatusCode::BAD_REQUEST);
+
+    let mut state = initialize_state();
+    state.tx_log = None;
+    state.verifiers = VerifierSet::new(vec![accepting_a.clone(), rejecting.clone()], 2).unwrap();
+    let genesis_root = state.root_bs58.clone();
+    let app_state = web::Data::new(GlobalAppState::new(state));
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+    let submit = |tx: &protocol::GrothProofBs58| test::TestRequest::post().uri("/onramp").set_json(tx).to_request();
+
+    // under 2-of-2, the rejecting verifier refuses the onramp, which is rolled back
+    let response: protocol::TxResponse = test::call_and_read_body_json(&app, submit(&tx)).await;
+    assert!(!response.accepted);
+    {
+        let state = app_state.state.lock().unwrap();
+        assert_eq!((state.num_coins, &state.root_bs58), (0, &genesis_root));
+        assert_eq!(state.root_history.get_latest_root(), Some(root_xy_bs58(&state.db.commitment())));
+    }
+
+    // under 2-of-3 it is accepted, over the same verifier's dissent
+    app_state.state.lock().unwrap().verifiers =
+        VerifierSet::new(vec![accepting_a.clone(), accepting_b, rejecting.clone()], 2).unwrap();
+    let response: protocol::TxResponse = test::call_and_read_body_json(&app, submit(&tx)).await;
+    assert!(response.accepted);
+    assert_eq!(app_state.state.lock().unwrap().num_coins, 1);
+
+    let request = test::TestRequest::get().uri("/verifiers").to_request();
+    let health: Vec<protocol::VerifierHealth> = test::call_and_read_body_json(&app, request).await;
+    let of = |url: &str| health.iter().find(|v| v.url == url).unwrap().clone();
+    assert_eq!((of(&accepting_a).accepted, of(&accepting_a).agreed, of(&accepting_a).dissented), (1, 1, 0));
+    assert_eq!((of(&rejecting).rejected, of(&rejecting).agreed, of(&rejecting).dissented), (1, 0, 1));
+    assert!(of(&rejecting).healthy);
+}
+
+#[actix_web::test]
+async fn test_body_limits() {
+    use actix_web::{http::StatusCode, test};
+
+    let app_state = web::Data::new(GlobalAppState::new(initialize_state()));
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    // a query within the limit is served, and one over it is refused with a 413
+    let request = test::TestRequest::post()
+        .uri("/nullifiers/status")
+        .set_json(protocol::NullifierStatusRequestBs58 { nullifiers: vec!["n".to_string()] })
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), StatusCode::OK);
+
+    let oversized = protocol::NullifierStatusRequestBs58 {
+        nullifiers: vec!["n".repeat(1024); utils::DEFAULT_QUERY_BODY_LIMIT / 1024 + 1],
+    };
+    let request = test::TestRequest::post()
+        .uri("/nullifiers/status")
+        .set_json(oversized)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // a proof submission of realistic size reaches its handler, which rejects
+    // it for being tagged with the wrong circuit rather than for its size
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) =
+        lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let tx = protocol::PaymentTxBs58 {
+        payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs),
+        memo: Some("m".repeat(protocol::MAX_MEMO_BYTES)),
+    };
+    let request = test::TestRequest::post().uri("/payment").set_json(tx).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_replay_tx_log() {
+    let log_path = std::env::temp_dir().join(format!("sanctum-tx-log-{}.jsonl", std::process::id()));
+
+    // record two onramp txs the way the handler does
+    let mut state = initialize_state();
+    state.tx_log = Some(std::fs::File::create(&log_path).unwrap());
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    for amount in [10u8, 20u8] {
+        let (proof, public_inputs) =
+            lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(amount));
+        let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+
+        let utxo_com = verify_onramp_tx(&mut state, &tx).unwrap();
+        add_coin_to_state(&mut state, &utxo_com).unwrap();
+        log_tx(&mut state, protocol::LoggedTxBs58::Onramp(tx));
+    }
+    drop(state);
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    std::fs::remove_file(&log_path).unwrap();
+
+    // a fresh sequencer reproduces every logged root
+    let mut fresh = initialize_state();
+    fresh.tx_log = None;
+    assert_eq!(replay_tx_log(&mut fresh, &log), Ok(2));
+
+    // and a log whose roots diverge is caught at the offending line
+    let mut entries: Vec<protocol::TxLogEntryBs58> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    entries[1].root = entries[0].root.clone();
+    let tampered: Vec<String> = entries.iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+
+    let mut fresh = initialize_state();
+    fresh.tx_log = None;
+    assert!(replay_tx_log(&mut fresh, &tampered.join("\n")).unwrap_err().starts_with("line 2"));
+
+    // replayed coins keep the time their tx was logged at; a line logged
+    // without one, as before times were logged, is timed by the replay
+    let mut entries: Vec<protocol::TxLogEntryBs58> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(entries.iter().all(|e| e.timestamp.is_some()));
+    entries[0].timestamp = Some(1_000);
+    entries[1].timestamp = None;
+    let retimed: Vec<String> = entries.iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+
+    let mut fresh = initialize_state();
+    fresh.tx_log = None;
+    let before_replay = tickets::unix_time();
+    assert_eq!(replay_tx_log(&mut fresh, &retimed.join("\n")), Ok(2));
+    let events = coin_events(&fresh, 0);
+    assert_eq!(events[0].inserted_at, Some(1_000));
+    assert!(events[1].inserted_at.unwrap() >= before_replay);
+}
+
+#[test]
+fn test_verify_state() {
+    let log_path = std::env::temp_dir().join(format!("sanctum-verify-state-{}.jsonl", std::process::id()));
+
+    let mut state = initialize_state();
+    state.tx_log = Some(std::fs::File::create(&log_path).unwrap());
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    for amount in [10u8, 20u8, 30u8] {
+        let (proof, public_inputs) =
+            lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(amount));
+        let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+
+        let utxo_com = verify_onramp_tx(&mut state, &tx).unwrap();
+        add_coin_to_state(&mut state, &utxo_com).unwrap();
+        log_tx(&mut state, protocol::LoggedTxBs58::Onramp(tx));
+    }
+    let live = compute_tree_snapshot(&state);
+    drop(state);
+
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    std::fs::remove_file(&log_path).unwrap();
+    let verify = |log: &str| {
+        let mut fresh = initialize_state();
+        fresh.tx_log = None;
+        verify_state(&mut fresh, log, Some(&live))
+    };
+
+    // the intact log rebuilds the live tree
+    assert_eq!(verify(&log), Ok(3));
+
+    // a proof corrupted mid-log is caught at its line, before any later tx
+    let mut entries: Vec<protocol::TxLogEntryBs58> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let protocol::LoggedTxBs58::Onramp(first) = entries[0].tx.clone() else { unreachable!() };
+    let protocol::LoggedTxBs58::Onramp(second) = &mut entries[1].tx else { unreachable!() };
+    second.proof = first.proof;
+    let corrupted: String = entries.iter().map(|e| serde_json::to_string(e).unwrap() + "\n").collect();
+    assert_eq!(verify(&corrupted), Err("line 2: onramp proof does not verify".to_string()));
+
+    // a log that lost its last entry still replays, but not to the live tree
+    let truncated: String = log.lines().take(2).map(|line| format!("{}\n", line)).collect();
+    assert!(verify(&truncated).unwrap_err().contains("the log rebuilds 2 coins"));
+
+    // and one torn in the middle of its last entry is refused outright
+    let torn = &log[..log.len() - 10];
+    assert_eq!(verify(torn), Err("line 3: the last entry is torn".to_string()));
+}
+
+#[test]
+fn test_onramp_exactly_once_after_crash() {
+    let log_path = std::env::temp_dir().join(format!("sanctum-wal-{}.jsonl", std::process::id()));
+    let log_path = log_path.to_str().unwrap();
+    let _ = std::fs::remove_file(log_path);
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let onramp = |amount: u8| {
+        let (proof, public_inputs) =
+            lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(amount));
+        protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs)
+    };
+    let (first, second) = (onramp(10), onramp(20));
+
+    // the sequencer crashes once the onramp's log entry is synced, but before
+    // it records the ticket in memory or acknowledges the client
+    let mut state = initialize_state();
+    assert_eq!(recover_from_tx_log(&mut state, log_path), Ok(0));
+    let utxo_com = verify_onramp_tx(&mut state, &first).unwrap();
+    add_coin_to_state(&mut state, &utxo_com).unwrap();
+    // as a line written before histories were logged would be
+    let ticket = protocol::LoggedTicket { idempotency_key: "first".to_string(), accepted_at: tickets::unix_time(), history: None };
+    log_ticketed_tx(&mut state, protocol::LoggedTxBs58::Onramp(first.clone()), Some(ticket));
+    drop(state);
+
+    // after a restart, the client's retry resolves to the recorded outcome
+    let mut state = initialize_state();
+    assert_eq!(recover_from_tx_log(&mut state, log_path), Ok(1));
+    let (leaf_index, merkle_update_proof) = apply_onramp_tx(&mut state, &first, Some("first")).unwrap();
+    assert_eq!((leaf_index, merkle_update_proof.is_none(), state.num_coins), (0, true, 1));
+
+    // a crash in the middle of writing the next entry leaves a torn line,
+    // which recovery cuts off, as that onramp was never acknowledged
+    let (_, merkle_update_proof) = apply_onramp_tx(&mut state, &second, Some("second")).unwrap();
+    assert!(merkle_update_proof.is_some());
+    drop(state);
+    let log = std::fs::read_to_string(log_path).unwrap();
+    let torn = &log[..log.len() - 10];
+    std::fs::write(log_path, torn).unwrap();
+
+    let mut state = initialize_state();
+    assert_eq!(recover_from_tx_log(&mut state, log_path), Ok(1));
+    assert_eq!(state.num_coins, 1);
+
+    // so the retry of the second onramp is applied, exactly once, and logged after the first
+    let (leaf_index, merkle_update_proof) = apply_onramp_tx(&mut state, &second, Some("second")).unwrap();
+    assert_eq!((leaf_index, merkle_update_proof.is_some()), (1, true));
+    let (leaf_index, merkle_update_proof) = apply_onramp_tx(&mut state, &second, Some("second")).unwrap();
+    assert_eq!((leaf_index, merkle_update_proof.is_none(), state.num_coins), (1, true, 2));
+    drop(state);
+
+    let mut state = initialize_state();
+    assert_eq!(recover_from_tx_log(&mut state, log_path), Ok(2));
+    assert_eq!(state.tickets.len(), 2);
+
+    // both settled, or they would have been cut from the log, whether or not their moves were logged
+    for (key, leaf_index) in [("first", 0), ("second", 1)] {
+        let ticket = state.tickets.get(key, tickets::unix_time()).unwrap();
+        let states: Vec<protocol::TxState> = ticket.history.transitions().iter().map(|t| t.state.clone()).collect();
+        assert_eq!(states, vec![
+            protocol::TxState::Received,
+            protocol::TxState::Validated,
+            protocol::TxState::Staged,
+            protocol::TxState::ForwardedToVerifiers,
+            protocol::TxState::Settled { leaf_index: Some(leaf_index) },
+        ]);
+    }
+
+    std::fs::remove_file(log_path).unwrap();
+}
+
+#[test]
+fn test_resubmitted_proof_verified_once() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) =
+        lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &test_coin(10));
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+
+    // the same tx checked twice, e.g. once simulated and once submitted
+    let first = verify_onramp_tx(&mut state, &tx).unwrap();
+    let second = verify_onramp_tx(&mut state, &tx).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(state.verification_cache.misses, 1);
+    assert_eq!(state.verification_cache.hits, 1);
+
+    // the same proof bytes under another circuit's tag are not a hit
+    let mut retagged = tx.clone();
+    retagged.circuit = protocol::CircuitKind::Payment;
+    assert!(!state.verification_cache.verify(&retagged, || false));
+    assert_eq!(state.verification_cache.misses, 2);
+}
+
+#[test]
+fn test_corpus_valid_in_sequence() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+    let corpus = lib_sanctum::corpus::generate(2, &state.statement).unwrap();
+
+    for tx in corpus.onramps.iter() {
+        let utxo_com = verify_onramp_tx(&mut state, tx).unwrap();
+        add_coin_to_state(&mut state, &utxo_com).unwrap();
+    }
+
+    // every payment verifies, and was proven against the root the sequencer
+    // holds when the payment arrives
+    for input in corpus.payments.iter() {
+        let tx = &input.payment_proof;
+        assert!(verify_proof(&mut state, tx).unwrap());
+
+        let (_, public_inputs) = protocol::groth_proof_from_bs58(tx);
+        let utxo_com = protocol::affine_from_xy(
+            public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize],
+            public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize]
+        ).unwrap();
+        let merkle_update_proof = apply_payment_tx(&mut state, input, &utxo_com).unwrap();
+
+        assert_eq!(
+            tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize],
+            merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_X as usize]
+        );
+        assert_eq!(
+            tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize],
+            merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_Y as usize]
+        );
+    }
+    assert_eq!(state.num_coins, 4);
+}
+
+// a coin of the given asset owned by sk; a swap output carries the swap id as entropy
+fn swap_coin(sk: &[u8; 32], asset: u8, amount: u8, entropy: &[u8; 31], rho: u8) -> JZRecord<5> {
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let mut amount_bytes = vec![0u8; 31];
+    amount_bytes[0] = amount;
+
+    let fields: [Vec<u8>; 5] =
+    [
+        entropy.to_vec(), //entropy
+        utils::derive_pubkey(&prf_params, sk).to_vec(), //owner
+        protocol::AssetId(asset as u64).to_field_bytes().to_vec(), //asset id
+        amount_bytes, //amount
+        vec![rho; 31], //rho
+    ];
+
+    JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+}
+
+#[test]
+fn test_swap_settlement() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+
+    let (prf_params, _, _) = utils::trusted_setup();
+    let (swap_pk, _) = registry::setup(protocol::CircuitKind::Swap, &state.statement);
+    let terms = lib_sanctum::swap_circuit::SwapTerms { amounts: [10, 7], nonce: [9u8; 32] };
+    let blind = [4u8; 32];
+    let (alice_sk, bob_sk) = ([1u8; 32], [2u8; 32]);
+
+    // alice holds 10 of asset 1 and bob 7 of asset 2, as they agreed to swap;
+    // each pays the other, locked to the swap. Bob also holds 5 of asset 2
+    let alice_coin = swap_coin(&alice_sk, 1, 10, &[0u8; 31], 1);
+    let bob_coin = swap_coin(&bob_sk, 2, 7, &[0u8; 31], 2);
+    let bob_small_coin = swap_coin(&bob_sk, 2, 5, &[0u8; 31], 5);
+    insert_leaf(&mut state, &alice_coin.commitment().into_affine()).unwrap();
+    insert_leaf(&mut state, &bob_coin.commitment().into_affine()).unwrap();
+    insert_leaf(&mut state, &bob_small_coin.commitment().into_affine()).unwrap();
+
+    let want = |asset: u8| protocol::encode_constraintf_as_bs58_str(
+        &lib_sanctum::asset_tag::asset_tag(&prf_params, &protocol::AssetId(asset as u64), &blind)
+    );
+    let half = |state: &AppStateType, coin: &JZRecord<5>, index: usize, output: &JZRecord<5>, sk: &[u8; 32], want_asset: u8,
+        terms: &lib_sanctum::swap_circuit::SwapTerms, role: protocol::SwapRole| {
+        let (proof, public_inputs) = lib_sanctum::swap_circuit::generate_groth_proof(
+            &swap_pk, &state.statement, coin, output, &assemble_merkle_proof(state, index).unwrap(), sk, 0, terms, role, &blind
+        );
+        protocol::SwapHalfBs58 {
+            payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Swap, &proof, &public_inputs),
+            want_asset_tag: want(want_asset),
+            memo: None,
+        }
+    };
+    let swap_id = terms.swap_id(&prf_params);
+    let proposal = half(
+        &state, &alice_coin, 0, &swap_coin(&bob_sk, 1, 10, &swap_id, 3), &alice_sk, 2, &terms, protocol::SwapRole::Proposal
+    );
+    let acceptance = half(
+        &state, &bob_coin, 1, &swap_coin(&alice_sk, 2, 7, &swap_id, 4), &bob_sk, 1, &terms, protocol::SwapRole::Acceptance
+    );
+    let nullifier = |half: &protocol::SwapHalfBs58|
+        half.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+    let swap_id_bs58 = acceptance.payment_proof.public_inputs[protocol::SwapGrothPublicInput::SWAP_ID as usize].clone();
+
+    // a proposal wanting another asset than bob pays is not settled
+    let now = Instant::now();
+    let mut wants_other = proposal.clone();
+    wants_other.want_asset_tag = want(3);
+    propose_swap(&mut state, wants_other, now).unwrap();
+    assert_eq!(accept_swap(&mut state, &acceptance, now).unwrap_err(), "mismatched asset");
+    assert_eq!(state.num_coins, 3);
+
+    // once it times out, alice's coin is released and the swap can no longer be accepted
+    let expired = expire_swaps(&mut state, now + SWAP_TIMEOUT);
+    assert_eq!(expired.len(), 1);
+    assert!(!state.nullifiers.contains(&nullifier(&proposal)));
+    assert_eq!(accept_swap(&mut state, &acceptance, now).unwrap_err(), "no pending swap with this id");
+
+    // an acceptance is not a proposal, nor the other way around
+    assert_eq!(propose_swap(&mut state, acceptance.clone(), now).unwrap_err(), "not a swap proposal");
+    propose_swap(&mut state, proposal.clone(), now).unwrap();
+    assert_eq!(state.swaps[&swap_id_bs58].history.state(), &protocol::TxState::Escrowed);
+    assert_eq!(propose_swap(&mut state, proposal.clone(), now).unwrap_err(), "swap already proposed");
+    assert_eq!(accept_swap(&mut state, &proposal, now).unwrap_err(), "not a swap acceptance");
+
+    // a half that doesn't decode is refused, rather than panicking under the lock
+    let mut malformed = acceptance.clone();
+    malformed.payment_proof.proof = "0OIl".to_string();
+    assert!(accept_swap(&mut state, &malformed, now).unwrap_err().contains("base58"));
+    let mut malformed = proposal.clone();
+    malformed.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::COMMITMENT_X as usize] = "0OIl".to_string();
+    assert!(propose_swap(&mut state, malformed, now).unwrap_err().contains("base58"));
+
+    // bob paying 5 where he agreed to 7 proves under other terms, so he
+    // carries another swap id, and settles nothing
+    let cheaper = lib_sanctum::swap_circuit::SwapTerms { amounts: [10, 5], ..terms };
+    let cheaper_id = cheaper.swap_id(&prf_params);
+    let short = half(
+        &state, &bob_small_coin, 2, &swap_coin(&alice_sk, 2, 5, &cheaper_id, 6), &bob_sk, 1, &cheaper, protocol::SwapRole::Acceptance
+    );
+    assert_eq!(accept_swap(&mut state, &short, now).unwrap_err(), "no pending swap with this id");
+    assert_eq!(state.num_coins, 3);
+
+    // a matching acceptance settles both halves at once
+    accept_swap(&mut state, &acceptance, now).unwrap();
+    assert_eq!(state.num_coins, 5);
+    assert!(state.nullifiers.contains(&nullifier(&proposal)));
+    assert!(state.nullifiers.contains(&nullifier(&acceptance)));
+    assert!(state.swaps.is_empty());
+}
+
+#[actix_web::test]
+async fn test_cross_pool_transfer() {
+    use actix_web::{http::StatusCode, test};
+
+    // two pools, each with its own sequencer and verifier; pool 2 knows the
+    // key pool 1's sequencer signs its exports with
+    let key = ed25519_dalek::SigningKey::from_bytes(&[5u8; 32]);
+    let mut pool_1 = initialize_state();
+    pool_1.tx_log = None;
+    pool_1.statement.pool_id = Some(1u64.to_le_bytes());
+    // nullifiers are bound to the pool id, so pool 1 verifies under keys set up for it
+    pool_1.keys = Some(load_keys(&KeyReload::default(), &pool_1.statement).unwrap());
+    pool_1.signing_key = Some(key.clone());
+    pool_1.peers.insert(2, ed25519_dalek::SigningKey::from_bytes(&[6u8; 32]).verifying_key());
+    pool_1.verifiers = VerifierSet::new(vec![mock_verifier(StatusCode::OK)], 1).unwrap();
+
+    let mut pool_2 = initialize_state();
+    pool_2.tx_log = None;
+    pool_2.statement.pool_id = Some(2u64.to_le_bytes());
+    pool_2.peers.insert(1, key.verifying_key());
+    pool_2.verifiers = VerifierSet::new(vec![mock_verifier(StatusCode::BAD_REQUEST)], 1).unwrap();
+
+    // alice's coin is in pool 1, and she transfers it to bob in pool 2
+    let sk = [1u8; 32];
+    let alice_coin = swap_coin(&sk, 1, 10, &[0u8; 31], 1);
+    let bob_coin = swap_coin(&[2u8; 32], 1, 10, &[0u8; 31], 2);
+    insert_leaf(&mut pool_1, &alice_coin.commitment().into_affine()).unwrap();
+    let (transfer_pk, _) = registry::setup(protocol::CircuitKind::Transfer, &pool_1.statement);
+    let (proof, public_inputs) = lib_sanctum::transfer_circuit::generate_groth_proof(
+        &transfer_pk, &pool_1.statement, &alice_coin, &bob_coin, &assemble_merkle_proof(&pool_1, 0).unwrap(), &sk, 0, 2
+    );
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Transfer, &proof, &public_inputs);
+    let nullifier = tx.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+
+    let state_1 = web::Data::new(GlobalAppState::new(pool_1));
+    let sequencer_1 = test::init_service(App::new().app_data(state_1.clone()).configure(configure_routes)).await;
+    let state_2 = web::Data::new(GlobalAppState::new(pool_2));
+    let sequencer_2 = test::init_service(App::new().app_data(state_2.clone()).configure(configure_routes)).await;
+    let post = |route: &str, body: serde_json::Value| test::TestRequest::post().uri(route).set_json(body).to_request();
+
+    // a transfer to a pool that isn't pool 1's peer is refused before it is verified
+    let mut to_unknown = tx.clone();
+    to_unknown.public_inputs[protocol::TransferGrothPublicInput::DEST_POOL_ID as usize] =
+        protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(3u64));
+    let response: protocol::TransferResponse = test::call_and_read_body_json(&sequencer_1, post("/transfer", serde_json::json!(to_unknown))).await;
+    assert_eq!(response.error.as_deref(), Some("pool 3 is not a peer"));
+
+    // pool 1 spends alice's coin, inserting nothing, and hands back the signed export
+    let response: protocol::TransferResponse = test::call_and_read_body_json(&sequencer_1, post("/transfer", serde_json::json!(tx))).await;
+    assert!(response.accepted);
+    let export = response.export.unwrap();
+    assert!(state_1.state.lock().unwrap().nullifiers.contains(&nullifier));
+    assert_eq!(state_1.state.lock().unwrap().num_coins, 1);
+    let response: protocol::TransferResponse = test::call_and_read_body_json(&sequencer_1, post("/transfer", serde_json::json!(tx))).await;
+    assert_eq!(response.error.as_deref(), Some("duplicate nullifier"));
+
+    // pool 2's verifier refuses the first delivery, which is rolled back...
+    let response: protocol::TxResponse = test::call_and_read_body_json(&sequencer_2, post("/transfer/in", serde_json::json!(export))).await;
+    assert!(!response.accepted);
+    assert_eq!(state_2.state.lock().unwrap().num_coins, 0);
+    assert!(state_2.state.lock().unwrap().imports.is_empty());
+
+    // ...so it can be delivered again, and bob's coin lands in pool 2 exactly once
+    state_2.state.lock().unwrap().verifiers = VerifierSet::new(vec![mock_verifier(StatusCode::OK)], 1).unwrap();
+    let response: protocol::TxResponse = test::call_and_read_body_json(&sequencer_2, post("/transfer/in", serde_json::json!(export))).await;
+    assert!(response.accepted);
+    assert_eq!(response.leaf_index, Some(0));
+    assert_eq!(leaf_index_of(&state_2.state.lock().unwrap(), &bob_coin.commitment().into_affine()), Some(0));
+    let response: protocol::TxResponse = test::call_and_read_body_json(&sequencer_2, post("/transfer/in", serde_json::json!(export))).await;
+    assert_eq!(response.error.as_deref(), Some("transfer was already imported"));
+
+    // the export names pool 2, so pool 1 does not take it back
+    let response: protocol::TxResponse = test::call_and_read_body_json(&sequencer_1, post("/transfer/in", serde_json::json!(export))).await;
+    assert_eq!(response.error.as_deref(), Some("transfer is to pool 2, not to pool 1"));
+}
+
+#[test]
+fn test_spend_from_retired_tree() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+
+    // alice's coin is the first of tree 0, which is then filled up
+    let sk = [1u8; 32];
+    let alice_coin = swap_coin(&sk, 1, 10, &[0u8; 31], 1);
+    insert_leaf(&mut state, &alice_coin.commitment().into_affine()).unwrap();
+    for i in 1..protocol::TREE_CAPACITY {
+        insert_leaf(&mut state, &test_coin((i % 200) as u8 + 1).commitment().into_affine()).unwrap();
+    }
+    let opening = assemble_merkle_proof(&state, 0).unwrap();
+    let tree_0_root = state.root_bs58.clone();
+
+    // a tx the verifiers refuse after it rolled the tree over is undone along
+    // with the rollover, leaving tree 0 active and full, as before it
+    let before = checkpoint(&state);
+    make_room(&mut state, 1).unwrap();
+    insert_leaf(&mut state, &test_coin(201).commitment().into_affine()).unwrap();
+    assert_eq!(state.tree_id, 1);
+    rollback(&mut state, before).unwrap();
+    assert_eq!((state.tree_id, state.num_coins), (0, protocol::TREE_CAPACITY));
+    assert!(state.retired_trees.is_empty());
+    assert_eq!(state.root_bs58, tree_0_root);
+    assert!(state.is_real_leaf.iter().all(|&real| real));
+    assert_eq!(leaf_index_of(&state, &alice_coin.commitment().into_affine()), Some(0));
+    assert_eq!(leaf_index_of(&state, &test_coin(201).commitment().into_affine()), None);
+
+    // the next coin opens tree 1, with a fresh root lineage
+    make_room(&mut state, 1).unwrap();
+    insert_leaf(&mut state, &test_coin(201).commitment().into_affine()).unwrap();
+    assert_eq!((state.tree_id, state.num_coins), (1, 1));
+    assert_eq!(state.retired_trees[0].root_bs58, tree_0_root);
+    assert_eq!(tree_of(&state, 0).unwrap().1, &tree_0_root);
+    assert_ne!(state.root_bs58, tree_0_root);
+
+    // leaves are opened in the tree asked for, which is resolved before the
+    // index is checked against its size
+    assert!(assemble_multiproof(&state, 0, &[0, protocol::TREE_CAPACITY - 1]).is_ok());
+    assert_eq!(
+        assemble_multiproof(&state, 0, &[protocol::TREE_CAPACITY]).unwrap_err(),
+        format!("leaf index {} is out of range for a tree of {} leaves", protocol::TREE_CAPACITY, protocol::TREE_CAPACITY)
+    );
+    assert_eq!(assemble_multiproof(&state, 7, &[protocol::TREE_CAPACITY]).unwrap_err(), "unknown tree 7");
+
+    // alice spends her coin from tree 0, against its final root
+    let (payment_pk, _) = registry::setup(protocol::CircuitKind::Payment, &state.statement);
+    let (proof, public_inputs) = lib_sanctum::payment_circuit::generate_groth_proof(
+        &payment_pk, &state.statement, &alice_coin, &swap_coin(&[2u8; 32], 1, 10, &[0u8; 31], 2), &opening, &sk, 0
+    );
+    let payment = protocol::PaymentTxBs58 {
+        payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs),
+        memo: None,
+    };
+
+    // claiming the active tree, or a tree yet to exist, finds no such root
+    let claiming_tree = |tree_id: u64| {
+        let mut tx = payment.clone();
+        tx.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::TREE_ID as usize] =
+            protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(tree_id));
+        tx
+    };
+    assert_eq!(diagnose(&mut state, &claiming_tree(1), None).1.as_deref(), Some(protocol::UNKNOWN_ROOT_ERROR));
+    assert_eq!(diagnose(&mut state, &claiming_tree(7), None).1.as_deref(), Some("unknown tree 7"));
+
+    // the spend is accepted, and its output coin lands in tree 1
+    assert_eq!(diagnose(&mut state, &payment, None).1, None);
+    let utxo_com = payment_commitment(&payment).unwrap();
+    apply_payment_tx(&mut state, &payment, &utxo_com).unwrap();
+    assert_eq!((state.tree_id, state.num_coins), (1, 2));
+    assert_eq!(leaf_index_of(&state, &utxo_com), Some(1));
+    assert_eq!(diagnose(&mut state, &payment, None).1.as_deref(), Some("duplicate nullifier"));
+
+    // a replica follows the leader through the rollover
+    let mut replica = initialize_replica_state("http://leader:8080");
+    while replica.root_bs58 != state.root_bs58 {
+        let batch = replication_batch(&state, replica.tree_id, replica.num_coins).unwrap();
+        apply_replication_batch(&mut replica, &batch).unwrap();
+    }
+    assert_eq!((replica.tree_id, replica.num_coins), (1, 2));
+    assert_eq!(replica.retired_trees[0].root_bs58, tree_0_root);
+    assert_eq!(replica.nullifiers.spent_at(1), state.nullifiers.spent_at(1));
+    assert!(replication_batch(&state, 2, 0).is_err());
+}
+
+// inserts n coins of alice's, and proves a payment of each against the
+// resulting root
+fn batch_payments(state: &mut AppStateType, n: u8) -> Vec<protocol::PaymentTxBs58> {
+    let sk = [1u8; 32];
+    let coins: Vec<JZRecord<5>> = (0..n).map(|i| swap_coin(&sk, 1, 10, &[i; 31], i + 1)).collect();
+    for coin in coins.iter() {
+        insert_leaf(state, &coin.commitment().into_affine()).unwrap();
+    }
+
+    let statement = state.statement;
+    let (payment_pk, _) = registry::setup(protocol::CircuitKind::Payment, &statement);
+    coins.iter().enumerate().map(|(i, coin)| {
+        let opening = assemble_merkle_proof(state, i).unwrap();
+        let output = swap_coin(&[2u8; 32], 1, 10, &[0u8; 31], 100 + i as u8);
+        let (proof, public_inputs) = lib_sanctum::payment_circuit::generate_groth_proof(
+            &payment_pk, &statement, coin, &output, &opening, &sk, 0
+        );
+        protocol::PaymentTxBs58 {
+            payment_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Payment, &proof, &public_inputs),
+            memo: None,
+        }
+    }).collect()
+}
+
+#[actix_web::test]
+async fn test_payment_batch_progress() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+    let payments = batch_payments(&mut state, 2);
+    let app_state = web::Data::new(GlobalAppState::new(state));
+
+    let statuses = |items: &[protocol::BatchItemResult]| items.iter().map(|i| (i.index, i.status)).collect::<Vec<_>>();
+    use protocol::BatchItemStatus::*;
+
+    // the second payment is tampered with: the stream reports one result per
+    // payment, in order, then refuses the whole batch
+    let mut tampered = payments[1].clone();
+    tampered.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::ASSET_TAG as usize] =
+        protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(1u64));
+    let batch = vec![payments[0].clone(), tampered, payments[1].clone()];
+    let response = process_payment_batch(app_state.clone(), web::Query(BatchQuery { stream: true }), web::Json(batch)).await;
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+    let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+    assert_eq!(lines.len(), 4);
+
+    let items: Vec<protocol::BatchItemResult> = lines[..3].iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(statuses(&items), vec![(0, Verified), (1, Rejected), (2, Skipped)]);
+    assert_eq!(items[1].error.as_deref(), Some("invalid payment proof"));
+    let outcome: protocol::TxResponse = serde_json::from_str(lines[3]).unwrap();
+    assert_eq!(outcome.error.as_deref(), Some("payment 1: invalid payment proof"));
+
+    // unstreamed, a batch spending the same coin twice is refused at its second payment
+    let batch = vec![payments[0].clone(), payments[0].clone()];
+    let response = process_payment_batch(app_state.clone(), web::Query(BatchQuery { stream: false }), web::Json(batch)).await;
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+    let response: protocol::BatchResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(statuses(&response.items), vec![(0, Verified), (1, Rejected)]);
+    assert_eq!(response.items[1].error.as_deref(), Some("duplicate nullifier"));
+    assert!(!response.outcome.accepted);
+
+    // neither refused batch touched the state
+    let mut state = app_state.state.lock().unwrap();
+    assert_eq!(state.num_coins, 2);
+
+    // a valid batch verifies every payment, and is applied as a whole
+    let mut reported = vec![];
+    let commitments = verify_payment_batch(&mut state, &payments, &mut |result| reported.push(result)).unwrap();
+    assert_eq!(statuses(&reported), vec![(0, Verified), (1, Verified)]);
+    apply_payment_batch(&mut state, &payments, &commitments).unwrap();
+    assert_eq!(state.num_coins, 4);
+
+    let mut reported = vec![];
+    assert!(verify_payment_batch(&mut state, &payments, &mut |result| reported.push(result)).is_err());
+    assert_eq!(statuses(&reported), vec![(0, Rejected), (1, Skipped)]);
+    assert!(verify_payment_batch(&mut state, &[], &mut |_| ()).is_err());
+}
+
+// the checks run on a payment, and the error of the last one if it failed
+fn diagnose(state: &mut AppStateType, tx: &protocol::PaymentTxBs58, vk_fingerprint: Option<&str>) -> (Vec<String>, Option<String>) {
+    let diagnostics = run_payment_validators(state, &PaymentSubmission { tx, vk_fingerprint });
+    let error = diagnostics.last().and_then(|d| d.error.clone());
+
+    (diagnostics.into_iter().map(|d| d.check).collect(), error)
+}
+
+#[test]
+fn test_payment_diagnostics() {
+    let mut state = initialize_state();
+    state.tx_log = None;
+    let corpus = lib_sanctum::corpus::generate(1, &state.statement).unwrap();
+    let utxo_com = verify_onramp_tx(&mut state, &corpus.onramps[0]).unwrap();
+    add_coin_to_state(&mut state, &utxo_com).unwrap();
+
+    let payment = corpus.payments[0].clone();
+    let checks = |n: usize| PAYMENT_VALIDATORS[..n].iter().map(|(check, _)| check.to_string()).collect::<Vec<_>>();
+    let with_input = |index: usize, value: String| {
+        let mut tx = payment.clone();
+        tx.payment_proof.public_inputs[index] = value;
+        tx
+    };
+    let one = protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(1u64));
+
+    // a valid payment passes every check, including the vk it claims
+    let fingerprint = hex::encode(keys::vk_fingerprint("payment", &state.keys.as_ref().unwrap().payment_pvk.vk));
+    assert_eq!(diagnose(&mut state, &payment, Some(&fingerprint)), (checks(9), None));
+
+    let mut tx = payment.clone();
+    tx.payment_proof.public_inputs.pop();
+    let (run, error) = diagnose(&mut state, &tx, None);
+    assert_eq!(run, checks(1));
+    assert!(error.unwrap().contains("expected 7 public inputs"));
+
+    let (run, error) = diagnose(&mut state, &with_input(0, "0OIl".to_string()), None);
+    assert_eq!(run, checks(2));
+    assert!(error.unwrap().contains("public input 0 is not valid base58"));
+
+    let mut tx = payment.clone();
+    tx.memo = Some("0OIl".to_string());
+    let (run, error) = diagnose(&mut state, &tx, None);
+    assert_eq!(run, checks(3));
+    assert!(error.unwrap().contains("memo"));
+
+    let (run, error) = diagnose(&mut state, &with_input(protocol::PaymentGrothPublicInput::ROOT_X as usize, one.clone()), None);
+    assert_eq!((run, error.as_deref()), (checks(4), Some(protocol::UNKNOWN_ROOT_ERROR)));
+
+    let (run, _) = diagnose(&mut state, &with_input(protocol::PaymentGrothPublicInput::COMMITMENT_Y as usize, one.clone()), None);
+    assert_eq!(run, checks(5));
+
+    // a wrong vk, or a tampered input, is only caught once the state checks pass
+    let (run, error) = diagnose(&mut state, &payment, Some("00"));
+    assert_eq!(run, checks(8));
+    assert!(error.unwrap().contains("proof was made for vk 00"));
+
+    let (run, error) = diagnose(&mut state, &with_input(protocol::PaymentGrothPublicInput::ASSET_TAG as usize, one), None);
+    assert_eq!((run, error.as_deref()), (checks(9), Some("invalid payment proof")));
+
+    let nullifier = payment.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+    state.policy.blocked_nullifiers.push(nullifier.clone());
+    let (run, error) = diagnose(&mut state, &payment, None);
+    assert_eq!((run, error.as_deref()), (checks(7), Some("blocked by policy")));
+
+    state.nullifiers.insert(&nullifier, 1);
+    let (run, error) = diagnose(&mut state, &payment, None);
+    assert_eq!((run, error.as_deref()), (checks(6), Some("duplicate nullifier")));
+}
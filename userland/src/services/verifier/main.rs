@@ -1,212 +1,1044 @@
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::error::JsonPayloadError;
+use serde::Deserialize;
+use tracing::{debug, error, info};
+use utoipa::OpenApi;
 
 use ark_bw6_761::BW6_761;
+use ark_ff::PrimeField;
 use ark_groth16::*;
 use ark_snark::SNARK;
 use std::borrow::BorrowMut;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Mutex;
 use std::time::Instant;
 
+use lib_sanctum::apps::{self, AppRegistry};
+use lib_sanctum::key_reload::{self, KeyReload, VkFingerprint};
+use lib_sanctum::config::{self, VerifierConfig};
+use lib_sanctum::policy;
 use lib_sanctum::protocol;
-
-const ROOT_HISTORY_SIZE: u32 = 30;
+use lib_sanctum::utils;
+use lib_sanctum::audit::NullifierAuditLog;
+use lib_sanctum::dedup::{self, DedupWindow};
+use lib_sanctum::nullifiers::NullifierStore;
+use lib_sanctum::tickets;
+use lib_sanctum::transfers::{self, PeerPools};
+use lib_sanctum::root_archive::{RootArchive, MAX_ROOT_RANGE};
+use lib_sanctum::root_history::MerkleRootHistory;
 
 
 pub struct AppStateType {
-    onramp_vk: VerifyingKey<BW6_761>,
-    payment_vk: VerifyingKey<BW6_761>,
-    merkle_update_vk: VerifyingKey<BW6_761>,
-    merkle_root_history: MerkleRootHistory,
+    // verifying keys are preprocessed once, rather than on every verification
+    onramp_pvk: PreparedVerifyingKey<BW6_761>,
+    onramp_multi_pvk: PreparedVerifyingKey<BW6_761>,
+    payment_pvk: PreparedVerifyingKey<BW6_761>,
+    swap_pvk: PreparedVerifyingKey<BW6_761>,
+    transfer_pvk: PreparedVerifyingKey<BW6_761>,
+    merkle_update_pvk: PreparedVerifyingKey<BW6_761>,
+    // the key key reloads and app registrations must be signed by, and the
+    // version of the last reload applied, kept across restarts if
+    // SANCTUM_KEY_RELOAD_FILE is set; 0 until one is applied
+    operator_key: Option<ed25519_dalek::VerifyingKey>,
+    keys_version: u64,
+    // one window of accepted roots per tree, the active tree's last; a
+    // payment may prove membership in any tree that has been rolled over
+    merkle_root_histories: Vec<MerkleRootHistory>,
+    // every root ever accepted, for verifying receipts whose root has left
+    // the window; it is never consulted for new proofs
+    root_archive: RootArchive,
+    // the root of a tree holding no coins, which every new tree extends
+    empty_root: (String, String),
+    nullifiers: NullifierStore,
+    // if SANCTUM_AUDIT_LOG is set, every spent nullifier is also recorded there
+    audit_log: Option<NullifierAuditLog>,
+    // circuits registered by third-party apps
+    apps: AppRegistry,
+    // the params this pool's keys were set up under, among them its pool id,
+    // and the pools it takes transfers from, as the sequencer's
+    statement: protocol::StatementParams,
+    peers: PeerPools,
+    // every transfer imported, by transfers::export_id
+    imports: HashSet<String>,
+    // bundles applied recently, so that one forwarded again is applied once
+    dedup: DedupWindow,
 }
 
 struct GlobalAppState {
     state: Mutex<AppStateType>, // <- Mutex is necessary to mutate safely across threads
 }
 
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zkBricks verifier", version = "1"),
+    paths(
+        process_onramp_tx,
+        process_onramp_multi_tx,
+        process_payment_tx,
+        process_swap_tx,
+        process_transfer_out,
+        process_transfer_in,
+        process_key_reload,
+        serve_nullifier_status_request,
+        serve_nullifier_audit_request,
+        serve_archived_root_request,
+        serve_root_range_request,
+        process_app_registration,
+        process_app_tx,
+    ),
+    components(schemas(
+        protocol::GrothProofBs58,
+        protocol::OnRampProofBs58,
+        protocol::OnRampMultiProofBs58,
+        protocol::PaymentProofBs58,
+        protocol::SwapSettlementBs58,
+        protocol::TransferExportBs58,
+        protocol::TransferInProofBs58,
+        protocol::NullifierStatusRequestBs58,
+        protocol::NullifierStatusBs58,
+        protocol::NullifierAuditEntryBs58,
+        protocol::NullifierAuditPageBs58,
+        protocol::ArchivedRootBs58,
+        protocol::RootRangeBs58,
+        apps::SignedAppRegistration,
+        protocol::AppRegistrationBs58,
+        protocol::AppTxBs58,
+        protocol::AppProofBs58,
+        key_reload::SignedKeyReload,
+        key_reload::KeyReload,
+        key_reload::KeysReloaded,
+        key_reload::VkFingerprint,
+    ))
+)]
+struct ApiDoc;
+
+// rejects malformed json (including unknown fields) with a 400 naming the problem,
+// and bodies over the limit with a 413
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit).error_handler(|err, _req| {
+        let message = err.to_string();
+        let response = match &err {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } =>
+                HttpResponse::PayloadTooLarge().body(message),
+            _ => HttpResponse::BadRequest().body(message),
+        };
+        actix_web::error::InternalError::from_response(err, response).into()
+    })
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    config::init_from_args::<VerifierConfig>(&mut std::env::args().collect());
+
     // Note: web::Data created _outside_ HttpServer::new closure
     let app_state = web::Data::new(
         GlobalAppState {
             state: Mutex::new(initialize_state()),
         }
     );
-    println!("zkBricks verifier listening for transactions...");
+    info!("zkBricks verifier listening for transactions...");
 
     HttpServer::new(move || {
         // move counter into the closure
         App::new()
             .app_data(app_state.clone()) // <- register the created data
-            .route("/onramp", web::post().to(process_onramp_tx))
-            .route("/payment", web::post().to(process_payment_tx))
+            .configure(configure_routes)
     })
     .bind(("127.0.0.1", 8081))?
     .run()
     .await
 }
 
+// proof submissions get a larger body limit than queries; the defaults can be
+// overridden with SANCTUM_PROOF_BODY_LIMIT and SANCTUM_QUERY_BODY_LIMIT
+fn configure_routes(cfg: &mut web::ServiceConfig) {
+    let proof_limit = utils::body_limit("SANCTUM_PROOF_BODY_LIMIT", utils::DEFAULT_PROOF_BODY_LIMIT);
+    let query_limit = utils::body_limit("SANCTUM_QUERY_BODY_LIMIT", utils::DEFAULT_QUERY_BODY_LIMIT);
+
+    cfg
+        .app_data(json_config(query_limit))
+        .route("/openapi.json", web::get().to(serve_openapi_request))
+        .service(web::resource("/onramp")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_onramp_tx)))
+        .service(web::resource("/onramp_multi")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_onramp_multi_tx)))
+        .service(web::resource("/payment")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_payment_tx)))
+        .service(web::resource("/swap")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_swap_tx)))
+        .service(web::resource("/transfer/out")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_transfer_out)))
+        .service(web::resource("/transfer/in")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_transfer_in)))
+        .route("/nullifiers/status", web::post().to(serve_nullifier_status_request))
+        .route("/audit/nullifiers", web::get().to(serve_nullifier_audit_request))
+        .route("/root/history", web::get().to(serve_root_range_request))
+        .route("/root/history/{block}", web::get().to(serve_archived_root_request))
+        .service(web::resource("/admin/reload-keys")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_key_reload)))
+        .service(web::resource("/apps/register")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_app_registration)))
+        .service(web::resource("/app_tx")
+            .app_data(json_config(proof_limit))
+            .route(web::post().to(process_app_tx)));
+}
+
+async fn serve_openapi_request() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+// where a page of a paginated query starts, and how long it is
+#[derive(Deserialize)]
+struct PageQuery {
+    from: Option<usize>,
+    limit: Option<usize>,
+}
+
+// pages through the audit log of spent nullifiers, oldest first
+#[utoipa::path(get, path = "/audit/nullifiers",
+    params(
+        ("from" = Option<usize>, Query, description = "the position of the first entry; 0 if unset"),
+        ("limit" = Option<usize>, Query, description = "how many entries to serve, at most MAX_AUDIT_PAGE"),
+    ),
+    responses((status = 200, body = protocol::NullifierAuditPageBs58), (status = 400), (status = 404)))]
+async fn serve_nullifier_audit_request(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<PageQuery>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let page = match (*state).audit_log.as_ref() {
+        Some(log) => log.page(query.from.unwrap_or(0), query.limit.unwrap_or(lib_sanctum::audit::MAX_AUDIT_PAGE)),
+        None => return HttpResponse::NotFound().body("the audit log is not enabled"),
+    };
+    drop(state);
+
+    match page {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+// the root accepted at the given block, however long ago
+#[utoipa::path(get, path = "/root/history/{block}",
+    params(("block" = usize, Path, description = "the merkle update that produced the root, from 0")),
+    responses((status = 200, body = protocol::ArchivedRootBs58), (status = 404)))]
+async fn serve_archived_root_request(
+    global_state: web::Data<GlobalAppState>,
+    block: web::Path<usize>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let root = (*state).root_archive.get(block.into_inner());
+    drop(state);
+
+    match root {
+        Some(root) => HttpResponse::Ok().json(root),
+        None => HttpResponse::NotFound().body("no root at this block"),
+    }
+}
+
+// the roots accepted from a block onwards, for archiving them in bulk
+#[utoipa::path(get, path = "/root/history",
+    params(
+        ("from" = Option<usize>, Query, description = "the first block; 0 if unset"),
+        ("limit" = Option<usize>, Query, description = "how many roots to serve, at most MAX_ROOT_RANGE"),
+    ),
+    responses((status = 200, body = protocol::RootRangeBs58), (status = 400)))]
+async fn serve_root_range_request(
+    global_state: web::Data<GlobalAppState>,
+    query: web::Query<PageQuery>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let range = (*state).root_archive.range(query.from.unwrap_or(0), query.limit.unwrap_or(MAX_ROOT_RANGE));
+    drop(state);
+
+    match range {
+        Ok(range) => HttpResponse::Ok().json(range),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+// answers whether each of a batch of nullifiers has been spent
+#[utoipa::path(post, path = "/nullifiers/status", request_body = protocol::NullifierStatusRequestBs58,
+    responses((status = 200, body = protocol::NullifierStatusBs58), (status = 400)))]
+async fn serve_nullifier_status_request(
+    global_state: web::Data<GlobalAppState>,
+    request: web::Json<protocol::NullifierStatusRequestBs58>
+) -> HttpResponse {
+    let state = global_state.state.lock().unwrap();
+    let status = (*state).nullifiers.status(&request.into_inner());
+    drop(state);
+
+    match status {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+// swaps in the vks a reload signed by the operator points to, e.g. after a
+// circuit upgrade. The vks are read outside the lock and swapped in whole
+// under it, so that every bundle is verified either with the old vks or with
+// the new ones
+#[utoipa::path(post, path = "/admin/reload-keys", request_body = key_reload::SignedKeyReload,
+    responses((status = 200, body = key_reload::KeysReloaded), (status = 400), (status = 403), (status = 409), (status = 500)))]
+async fn process_key_reload(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<key_reload::SignedKeyReload>
+) -> HttpResponse {
+    let (reload, statement) = {
+        let state = global_state.state.lock().unwrap();
+
+        let operator_key = match (*state).operator_key.as_ref() {
+            Some(key) => key,
+            None => return HttpResponse::Forbidden().body("no operator key is configured"),
+        };
+        let reload = match input.verify(operator_key) {
+            Ok(reload) => reload,
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        };
+        // refused before any vk is read; the check that counts is the one
+        // made under the lock the vks are swapped in under
+        if reload.version <= (*state).keys_version {
+            return HttpResponse::Conflict().body("key reload version must increase");
+        }
+        (reload, (*state).statement)
+    };
+
+    // reading and preparing the vks takes a while, so it runs off the async workers
+    let blocking_reload = reload.clone();
+    let (pvks, fingerprints) = match web::block(move || load_keys(&blocking_reload, &statement)).await {
+        Ok(Ok(keys)) => keys,
+        Ok(Err(e)) => return HttpResponse::BadRequest().body(e),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    // the version is checked, the reload persisted and the vks swapped in
+    // under one lock, so that no other reload lands in between
+    let mut state = global_state.state.lock().unwrap();
+    if reload.version <= (*state).keys_version {
+        return HttpResponse::Conflict().body("key reload version must increase");
+    }
+    // kept before it is applied, so that a restart never falls back to older vks
+    if let Ok(path) = std::env::var("SANCTUM_KEY_RELOAD_FILE") {
+        if let Err(e) = reload.persist_applied(&path) {
+            return HttpResponse::InternalServerError().body(e);
+        }
+    }
+
+    info!(version = reload.version, key_dir = ?reload.key_dir, "reloaded keys");
+    let [onramp_pvk, onramp_multi_pvk, payment_pvk, swap_pvk, transfer_pvk, merkle_update_pvk] = pvks;
+    (*state).onramp_pvk = onramp_pvk;
+    (*state).onramp_multi_pvk = onramp_multi_pvk;
+    (*state).payment_pvk = payment_pvk;
+    (*state).swap_pvk = swap_pvk;
+    (*state).transfer_pvk = transfer_pvk;
+    (*state).merkle_update_pvk = merkle_update_pvk;
+    (*state).keys_version = reload.version;
+
+    HttpResponse::Ok().json(key_reload::KeysReloaded { version: reload.version, fingerprints })
+}
+
+// registers an app's circuit, as forwarded by the sequencer with the
+// operator's signature; nobody else may register apps
+#[utoipa::path(post, path = "/apps/register", request_body = apps::SignedAppRegistration,
+    responses((status = 200), (status = 400), (status = 403)))]
+async fn process_app_registration(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<apps::SignedAppRegistration>
+) -> HttpResponse {
+    let mut state = global_state.state.lock().unwrap();
+
+    let operator_key = match (*state).operator_key.as_ref() {
+        Some(key) => key,
+        None => return HttpResponse::Forbidden().body("no operator key is configured"),
+    };
+    let registration = match input.verify(operator_key) {
+        Ok(registration) => registration,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    match (*state).apps.register(&registration) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[utoipa::path(post, path = "/app_tx", request_body = protocol::AppProofBs58,
+    responses((status = 200), (status = 400)))]
+async fn process_app_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::AppProofBs58>
+) -> HttpResponse {
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let input_proofs = input.into_inner();
+
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/app_tx", &input_proofs);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/app_tx", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_app_tx(state.borrow_mut(), &input_proofs);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+fn apply_app_tx(state: &mut AppStateType, input_proofs: &protocol::AppProofBs58) -> Result<(), String> {
+    protocol::check_circuit_kind(&input_proofs.merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
+
+    // let's verify the app proof against the app's registered circuit
+    let now = Instant::now();
+    let commitment = state.apps.verify(&input_proofs.app_tx)?;
+    info!(elapsed_ms = now.elapsed().as_millis() as u64, "app proof verified");
+
+    // the leaf being inserted must be the commitment the app declared
+    check_inserted_leaf(&input_proofs.merkle_update_proof, &commitment)?;
+
+    // record the new merkle root if it extends the old root
+    update_merkle_root(state, &input_proofs.merkle_update_proof)
+}
+
+#[utoipa::path(post, path = "/onramp", request_body = protocol::OnRampProofBs58,
+    responses((status = 200), (status = 400)))]
 async fn process_onramp_tx(
     global_state: web::Data<GlobalAppState>,
     input: web::Json<protocol::OnRampProofBs58>
-) -> String {
+) -> HttpResponse {
 
     let mut state = global_state.state.lock().unwrap();
 
     let input_proofs = input.into_inner();
 
-    // let's parse the onramp proof
-    let (proof, public_inputs) = 
-        protocol::groth_proof_from_bs58(&input_proofs.on_ramp_proof);
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/onramp", &input_proofs);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/onramp", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_onramp(state.borrow_mut(), &input_proofs);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+fn apply_onramp(state: &mut AppStateType, input_proofs: &protocol::OnRampProofBs58) -> Result<(), String> {
+    // each proof must have been generated for the circuit whose vk we use
+    protocol::check_circuit_kind(&input_proofs.on_ramp_proof, protocol::CircuitKind::Onramp, &state.statement)?;
+    protocol::check_circuit_kind(&input_proofs.merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
+    // and mint coins of an asset id, with no byte set past its u64
+    protocol::asset_id_of(&input_proofs.on_ramp_proof)?;
 
     // let's verify the onramp proof
-    let now = Instant::now();
-    assert!(Groth16::<BW6_761>::verify(&(*state).onramp_vk, &public_inputs, &proof).unwrap());
-    println!("onramp proof verified in {}.{} secs", 
-        now.elapsed().as_secs(), now.elapsed().subsec_millis());
+    verify_proof(&state.onramp_pvk, &input_proofs.on_ramp_proof, "onramp")?;
 
     // record the new merkle root if it extends the old root
-    update_merkle_root(state.borrow_mut(), &input_proofs.merkle_update_proof);
+    update_merkle_root(state, &input_proofs.merkle_update_proof)
+}
 
-    drop(state);
-    return "OK".to_string();
+// a deposit minting several notes arrives with one merkle update per minted note,
+// each inserting that note's commitment, chained in order
+#[utoipa::path(post, path = "/onramp_multi", request_body = protocol::OnRampMultiProofBs58,
+    responses((status = 200), (status = 400)))]
+async fn process_onramp_multi_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::OnRampMultiProofBs58>
+) -> HttpResponse {
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let input_proofs = input.into_inner();
+
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/onramp_multi", &input_proofs);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/onramp_multi", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_onramp_multi(state.borrow_mut(), &input_proofs);
+    respond(state.borrow_mut(), digest, result)
 
 }
 
+fn apply_onramp_multi(state: &mut AppStateType, input_proofs: &protocol::OnRampMultiProofBs58) -> Result<(), String> {
+    protocol::check_circuit_kind(&input_proofs.on_ramp_proof, protocol::CircuitKind::OnrampMulti, &state.statement)?;
+    protocol::asset_id_of(&input_proofs.on_ramp_proof)?;
+    for merkle_update_proof in input_proofs.merkle_update_proofs.iter() {
+        protocol::check_circuit_kind(merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
+    }
+
+    let public_inputs = verify_proof(&state.onramp_multi_pvk, &input_proofs.on_ramp_proof, "onramp multi")?;
+
+    // every minted note, and nothing else, must be inserted
+    let commitments = lib_sanctum::onramp_multi_circuit::minted_commitments(&public_inputs)?;
+    if commitments.len() != input_proofs.merkle_update_proofs.len() {
+        return Err(format!(
+            "{} notes were minted, but {} merkle updates insert them",
+            commitments.len(), input_proofs.merkle_update_proofs.len()
+        ));
+    }
+
+    for (commitment, merkle_update_proof) in commitments.iter().zip(input_proofs.merkle_update_proofs.iter()) {
+        check_inserted_leaf(merkle_update_proof, commitment)?;
+
+        // record the new merkle root if it extends the old root
+        update_merkle_root(state, merkle_update_proof)?;
+    }
+
+    Ok(())
+}
+
 // mirrors the logic on L1 contract, but stores the entire state (rather than frontier)
+#[utoipa::path(post, path = "/payment", request_body = protocol::PaymentProofBs58,
+    responses((status = 200), (status = 400)))]
 async fn process_payment_tx(
     global_state: web::Data<GlobalAppState>,
     input: web::Json<protocol::PaymentProofBs58>
-) -> String {
+) -> HttpResponse {
 
     let mut state = global_state.state.lock().unwrap();
 
     let input_proofs = input.into_inner();
 
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/payment", &input_proofs);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/payment", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_payment(state.borrow_mut(), &input_proofs, protocol::CircuitKind::Payment);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+// both halves of a settled swap are applied together, each as a payment
+#[utoipa::path(post, path = "/swap", request_body = protocol::SwapSettlementBs58,
+    responses((status = 200), (status = 400)))]
+async fn process_swap_tx(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::SwapSettlementBs58>
+) -> HttpResponse {
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let settlement = input.into_inner();
+
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/swap", &settlement);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/swap", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_swap(state.borrow_mut(), &settlement);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+fn apply_swap(state: &mut AppStateType, settlement: &protocol::SwapSettlementBs58) -> Result<(), String> {
+    if settlement.halves.len() != 2 {
+        return Err(format!("a swap settles 2 halves, not {}", settlement.halves.len()));
+    }
+
+    for half in settlement.halves.iter() {
+        protocol::check_circuit_kind(&half.payment_proof, protocol::CircuitKind::Swap, &state.statement)?;
+        protocol::check_circuit_kind(&half.merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
+    }
+
+    // both halves must belong to the same swap
+    let swap_id = |half: &protocol::PaymentProofBs58|
+        half.payment_proof.public_inputs[protocol::SwapGrothPublicInput::SWAP_ID as usize].clone();
+    if swap_id(&settlement.halves[0]) != swap_id(&settlement.halves[1]) {
+        return Err("the halves belong to different swaps".to_string());
+    }
+
+    // the first half is the proposal, which the acceptance settles
+    if protocol::swap_role_of(&settlement.halves[0].payment_proof)? != protocol::SwapRole::Proposal ||
+        protocol::swap_role_of(&settlement.halves[1].payment_proof)? != protocol::SwapRole::Acceptance {
+        return Err("the halves are not a proposal and its acceptance".to_string());
+    }
+
+    // both halves are checked in full before either is applied, so that a
+    // settlement is applied whole or not at all; the second update extends
+    // the root of the first, in the same tree
+    let checked = settlement.halves
+        .iter()
+        .map(|half| check_payment(state, half, protocol::CircuitKind::Swap))
+        .collect::<Result<Vec<_>, String>>()?;
+    if checked[0].1 == checked[1].1 {
+        return Err("nullifier was already spent".to_string());
+    }
+
+    let [first, second] = [&settlement.halves[0].merkle_update_proof, &settlement.halves[1].merkle_update_proof];
+    let latest_root = state.merkle_root_histories.last().unwrap().get_latest_root();
+    let rolls_over = check_merkle_update(state, first, latest_root.as_ref())?;
+    if check_merkle_update(state, second, Some(&new_root_of(first)))? {
+        return Err("the halves of a swap land in different trees".to_string());
+    }
+
+    record_merkle_root(state, first, rolls_over);
+    record_merkle_root(state, second, false);
+    for (half, (claimed_root, nullifier)) in settlement.halves.iter().zip(checked.iter()) {
+        record_nullifier(state, &half.merkle_update_proof, nullifier, claimed_root);
+    }
+
+    Ok(())
+}
+
+// a transfer out of this pool spends its nullifier here, and inserts nothing
+#[utoipa::path(post, path = "/transfer/out", request_body = protocol::GrothProofBs58,
+    responses((status = 200), (status = 400)))]
+async fn process_transfer_out(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::GrothProofBs58>
+) -> HttpResponse {
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let tx = input.into_inner();
+
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/transfer/out", &tx);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/transfer/out", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_transfer_out(state.borrow_mut(), &tx);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+fn apply_transfer_out(state: &mut AppStateType, tx: &protocol::GrothProofBs58) -> Result<(), String> {
+    protocol::check_circuit_kind(tx, protocol::CircuitKind::Transfer, &state.statement)?;
+
     // check if proof is constructed w.r.t. a known merkle root
-    let claimed_root_x = input_proofs
-        .payment_proof
-        .public_inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize]
-        .clone();
-    let claimed_root_y = input_proofs
-        .payment_proof
-        .public_inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize]
-        .clone();
-    assert!(state.merkle_root_history.is_known_root(&(claimed_root_x, claimed_root_y)));
+    let claimed_root = check_known_root(state, tx)?;
+
+    // the output coin goes to another pool
+    if Some(protocol::dest_pool_id_of(tx)?) == state.statement.pool() {
+        return Err("a transfer out must go to another pool".to_string());
+    }
+
+    // check for double spending
+    let nullifier = check_unspent(state, tx)?;
+
+    verify_proof(&state.transfer_pvk, tx, "transfer")?;
+
+    state.nullifiers.export(&nullifier);
+    audit(state, &nullifier, &claimed_root);
+
+    Ok(())
+}
+
+// a transfer into this pool is taken on its source sequencer's signature, as
+// the sequencer takes it; its proof was made under the source pool's keys
+#[utoipa::path(post, path = "/transfer/in", request_body = protocol::TransferInProofBs58,
+    responses((status = 200), (status = 400)))]
+async fn process_transfer_in(
+    global_state: web::Data<GlobalAppState>,
+    input: web::Json<protocol::TransferInProofBs58>
+) -> HttpResponse {
+
+    let mut state = global_state.state.lock().unwrap();
+
+    let input_proofs = input.into_inner();
+
+    // a bundle forwarded again gets the response it got the first time
+    let digest = dedup::bundle_digest("/transfer/in", &input_proofs);
+    if let Some(response) = (*state).dedup.get(&digest) {
+        debug!(route = "/transfer/in", "bundle was already applied");
+        return HttpResponse::Ok().body(response.to_string());
+    }
+
+    let result = apply_transfer_in(state.borrow_mut(), &input_proofs);
+    respond(state.borrow_mut(), digest, result)
+
+}
+
+fn apply_transfer_in(state: &mut AppStateType, input_proofs: &protocol::TransferInProofBs58) -> Result<(), String> {
+    protocol::check_circuit_kind(&input_proofs.merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
+
+    let pool_id = state.statement.pool().ok_or("this pool has no SANCTUM_POOL_ID to transfer to")?;
+    let commitment = transfers::verify_export(&input_proofs.export, &state.peers, pool_id)?;
+
+    // each transfer is imported once
+    let id = transfers::export_id(&input_proofs.export);
+    if state.imports.contains(&id) {
+        return Err("transfer was already imported".to_string());
+    }
+
+    // the leaf being inserted must be the transfer's output coin
+    check_inserted_leaf(&input_proofs.merkle_update_proof, &commitment)?;
+
+    // record the new merkle root if it extends the old root
+    update_merkle_root(state, &input_proofs.merkle_update_proof)?;
+    state.imports.insert(id);
+
+    Ok(())
+}
+
+// the answer to a bundle: OK once it has been applied, remembered so that the
+// bundle forwarded again gets it too, or a 400 naming why it was refused
+fn respond(state: &mut AppStateType, digest: [u8; 32], result: Result<(), String>) -> HttpResponse {
+    match result {
+        Ok(()) => {
+            state.dedup.insert(digest, "OK");
+            HttpResponse::Ok().body("OK")
+        },
+        Err(e) => {
+            debug!(error = %e, "refused bundle");
+            HttpResponse::BadRequest().body(e)
+        },
+    }
+}
 
-    // let's parse the onramp proof
-    let (proof, public_inputs) =
-        protocol::groth_proof_from_bs58(&input_proofs.payment_proof);
+// decodes a proof and checks it against a prepared vk, returning its public
+// inputs; a proof that doesn't decode is refused, rather than panicked on
+fn verify_proof(
+    pvk: &PreparedVerifyingKey<BW6_761>,
+    proof: &protocol::GrothProofBs58,
+    circuit: &str
+) -> Result<Vec<ark_bw6_761::Fr>, String> {
+    protocol::check_canonical_groth_proof(proof)?;
+    let (proof, public_inputs) = protocol::groth_proof_from_bs58(proof);
 
-    // let's verify the payment proof
     let now = Instant::now();
-    assert!(Groth16::<BW6_761>::verify(&(*state).payment_vk, &public_inputs, &proof).unwrap());
-    println!("payment proof verified in {}.{} secs",
-        now.elapsed().as_secs(), now.elapsed().subsec_millis());
+    let valid = Groth16::<BW6_761>::verify_with_processed(pvk, &public_inputs, &proof)
+        .map_err(|e| format!("{} proof does not verify: {}", circuit, e))?;
+    if !valid {
+        return Err(format!("{} proof does not verify", circuit));
+    }
+    info!(elapsed_ms = now.elapsed().as_millis() as u64, "{} proof verified", circuit);
+
+    Ok(public_inputs)
+}
+
+// the root a spending proof claims membership against, once it is known to
+// the window of the tree the proof names
+fn check_known_root(state: &AppStateType, tx: &protocol::GrothProofBs58) -> Result<(String, String), String> {
+    let claimed_root = (
+        tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_X as usize].clone(),
+        tx.public_inputs[protocol::PaymentGrothPublicInput::ROOT_Y as usize].clone(),
+    );
+    let tree_id = protocol::tree_id_of(tx)? as usize;
+    let root_history = state.merkle_root_histories
+        .get(tree_id)
+        .ok_or_else(|| format!("unknown tree {}", tree_id))?;
+    if !root_history.is_known_root(&claimed_root) {
+        return Err("proof is against an unknown merkle root".to_string());
+    }
+
+    Ok(claimed_root)
+}
+
+// the nullifier a spending proof declares, once it is known to be unspent
+fn check_unspent(state: &AppStateType, tx: &protocol::GrothProofBs58) -> Result<String, String> {
+    let nullifier = tx.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+    if state.nullifiers.contains(&nullifier) {
+        return Err("nullifier was already spent".to_string());
+    }
+
+    Ok(nullifier)
+}
+
+// the leaf a merkle update inserts must be the commitment the bundle declares
+fn check_inserted_leaf(
+    merkle_update_proof: &protocol::GrothProofBs58,
+    commitment: &ark_bls12_377::G1Affine
+) -> Result<(), String> {
+    let leaf_inputs = &merkle_update_proof.public_inputs;
+    if leaf_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_X as usize] !=
+        protocol::encode_constraintf_as_bs58_str(&commitment.x) ||
+        leaf_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_Y as usize] !=
+        protocol::encode_constraintf_as_bs58_str(&commitment.y) {
+        return Err("merkle update does not insert the declared commitment".to_string());
+    }
+
+    Ok(())
+}
+
+// the audit log is the verifier's own file, so failing to write it is logged
+// rather than refusing a tx that has been applied
+fn audit(state: &mut AppStateType, nullifier: &str, claimed_root: &(String, String)) {
+    if let Some(log) = state.audit_log.as_mut() {
+        if let Err(e) = log.append(nullifier, claimed_root, tickets::unix_time()) {
+            error!(error = %e, "unable to audit nullifier");
+        }
+    }
+}
+
+// checks a payment, or a swap half, given the kind of proof it must carry,
+// against its vk and the state, then records its new root and its nullifier
+fn apply_payment(
+    state: &mut AppStateType,
+    input_proofs: &protocol::PaymentProofBs58,
+    kind: protocol::CircuitKind
+) -> Result<(), String> {
+    let (claimed_root, nullifier) = check_payment(state, input_proofs, kind)?;
 
     // record the new merkle root if it extends the old root
-    update_merkle_root(state.borrow_mut(), &input_proofs.merkle_update_proof);
+    update_merkle_root(state, &input_proofs.merkle_update_proof)?;
 
-    drop(state);
-    return "OK".to_string();
+    record_nullifier(state, &input_proofs.merkle_update_proof, &nullifier, &claimed_root);
 
+    Ok(())
 }
 
-fn update_merkle_root(state: &mut AppStateType, merkle_update_proof: &protocol::GrothProofBs58) {
-    // check that we are extending from the latest old root
-    if let Some(latest_root) = state.merkle_root_history.get_latest_root() {
-        let old_root_x = merkle_update_proof
-            .public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_X as usize]
-            .clone();
-        let old_root_y = merkle_update_proof
-            .public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_Y as usize]
-            .clone();
+// checks a payment, or a swap half, short of its merkle update; returns the
+// root it claims membership against and the nullifier it spends
+fn check_payment(
+    state: &AppStateType,
+    input_proofs: &protocol::PaymentProofBs58,
+    kind: protocol::CircuitKind
+) -> Result<((String, String), String), String> {
+    // each proof must have been generated for the circuit whose vk we use
+    protocol::check_circuit_kind(&input_proofs.payment_proof, kind, &state.statement)?;
+    protocol::check_circuit_kind(&input_proofs.merkle_update_proof, protocol::CircuitKind::MerkleUpdate, &state.statement)?;
 
-        assert!(latest_root == (old_root_x, old_root_y));
-    } // else is for the first ever root
+    // check if proof is constructed w.r.t. a known merkle root
+    let claimed_root = check_known_root(state, &input_proofs.payment_proof)?;
 
-    // let's parse the merkle update proof
-    let (proof, public_inputs) = 
-        protocol::groth_proof_from_bs58(&merkle_update_proof);
+    // check for double spending
+    let nullifier = check_unspent(state, &input_proofs.payment_proof)?;
 
-    // verify the proof
-    let now = Instant::now();
-    assert!(Groth16::<BW6_761>::verify(&(*state).merkle_update_vk, &public_inputs, &proof).unwrap());
-    println!("merkle update proof verified in {}.{} secs\n",
-        now.elapsed().as_secs(), now.elapsed().subsec_millis());
+    // let's verify the payment proof, or the swap half, against its own vk
+    let pvk = match kind {
+        protocol::CircuitKind::Swap => &state.swap_pvk,
+        _ => &state.payment_pvk,
+    };
+    verify_proof(pvk, &input_proofs.payment_proof, "payment")?;
 
-    // store the new root
-    let new_root_x = merkle_update_proof
-    .public_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_X as usize]
-    .clone();
-    let new_root_y = merkle_update_proof
-        .public_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_Y as usize]
-        .clone();
+    Ok((claimed_root, nullifier))
+}
+
+// records the nullifier of an applied payment against the leaf its merkle
+// update inserted
+fn record_nullifier(
+    state: &mut AppStateType,
+    merkle_update_proof: &protocol::GrothProofBs58,
+    nullifier: &str,
+    claimed_root: &(String, String)
+) {
+    let (_, merkle_update_public_inputs) = protocol::groth_proof_from_bs58(merkle_update_proof);
+    let leaf_index = merkle_update_public_inputs
+        [protocol::MerkleUpdateGrothPublicInput::LEAF_INDEX as usize]
+        .into_bigint()
+        .as_ref()[0] as usize;
+    state.nullifiers.insert(&nullifier.to_string(), leaf_index);
+    audit(state, nullifier, claimed_root);
+}
 
-    state.merkle_root_history.insert(&(new_root_x, new_root_y));
+fn update_merkle_root(state: &mut AppStateType, merkle_update_proof: &protocol::GrothProofBs58) -> Result<(), String> {
+    let latest_root = state.merkle_root_histories.last().unwrap().get_latest_root();
+    let rolls_over = check_merkle_update(state, merkle_update_proof, latest_root.as_ref())?;
+    record_merkle_root(state, merkle_update_proof, rolls_over);
 
+    Ok(())
 }
 
-fn initialize_state() -> AppStateType {
-    let (_, onramp_vk) = lib_sanctum::onramp_circuit::circuit_setup();
-    let (_, payment_vk) = lib_sanctum::payment_circuit::circuit_setup();
-    let (_, merkle_update_vk) = lib_sanctum::merkle_update_circuit::circuit_setup();
+// checks a merkle update, extending latest_root, short of recording it;
+// returns whether it rolls the active tree over
+fn check_merkle_update(
+    state: &AppStateType,
+    merkle_update_proof: &protocol::GrothProofBs58,
+    latest_root: Option<&(String, String)>
+) -> Result<bool, String> {
+    let old_root = (
+        merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_X as usize].clone(),
+        merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::OLD_ROOT_Y as usize].clone(),
+    );
+    let leaf_index = merkle_update_proof
+        .public_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_INDEX as usize]
+        .clone();
 
-    AppStateType {
-        onramp_vk,
-        payment_vk,
-        merkle_update_vk,
-        merkle_root_history: MerkleRootHistory::new(ROOT_HISTORY_SIZE),
+    // the sequencer rolled over if it inserts the first leaf of an empty tree
+    // while ours holds coins; the tree we had becomes spend-only
+    let rolls_over = latest_root != Some(&state.empty_root)
+        && old_root == state.empty_root
+        && leaf_index == protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(0u64));
+
+    // check that we are extending from the latest old root; every history
+    // starts at the empty root, so the first update of a tree extends it too
+    if !rolls_over && latest_root != Some(&old_root) {
+        return Err("merkle update does not extend the latest root".to_string());
     }
+
+    // the leaf being inserted must be a coin commitment, i.e. a valid G1 point
+    protocol::check_canonical_groth_proof(merkle_update_proof)?;
+    let (_, public_inputs) = protocol::groth_proof_from_bs58(merkle_update_proof);
+    protocol::affine_from_xy(
+        public_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_X as usize],
+        public_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_Y as usize]
+    ).map_err(|e| format!("inserted leaf is not a coin commitment: {}", e))?;
+
+    // verify the proof
+    verify_proof(&state.merkle_update_pvk, merkle_update_proof, "merkle update")?;
+
+    Ok(rolls_over)
 }
 
-// base58 encoded (x,y) coordinates
-type Hash = (String, String);
+fn new_root_of(merkle_update_proof: &protocol::GrothProofBs58) -> (String, String) {
+    (
+        merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_X as usize].clone(),
+        merkle_update_proof.public_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_Y as usize].clone(),
+    )
+}
 
-pub struct MerkleRootHistory {
-    pub root_history_size: u32,
-    historical_roots: HashMap<u32, Hash>,
-    next_root_index: u32,
+// stores the new root of a checked merkle update, in a fresh tree if it rolls over
+fn record_merkle_root(state: &mut AppStateType, merkle_update_proof: &protocol::GrothProofBs58, rolls_over: bool) {
+    if rolls_over {
+        let root_history_size = state.merkle_root_histories.last().unwrap().root_history_size;
+        let history = genesis_history(root_history_size, &state.empty_root);
+        state.merkle_root_histories.push(history);
+        state.nullifiers.start_new_tree();
+    }
+
+    // store the new root
+    let new_root = new_root_of(merkle_update_proof);
+    state.merkle_root_histories.last_mut().unwrap().insert(&new_root);
+    persist_root_histories(&state.merkle_root_histories);
+    if let Err(e) = state.root_archive.append(&new_root) {
+        error!(error = %e, "unable to archive root");
+    }
 }
 
-impl MerkleRootHistory {
+fn initialize_state() -> AppStateType {
+    // the vks loaded at startup are those of the last reload applied, or
+    // those a reload without a key dir would load if none was
+    let applied = KeyReload::load_applied(std::env::var("SANCTUM_KEY_RELOAD_FILE").ok().as_deref())
+        .unwrap_or_else(|e| panic!("{}", e));
+    let statement = utils::statement_params();
+    let ([onramp_pvk, onramp_multi_pvk, payment_pvk, swap_pvk, transfer_pvk, merkle_update_pvk], _) =
+        load_keys(&applied, &statement).unwrap_or_else(|e| panic!("{}", e));
 
-    // create a new merkle tree with no leaves
-    pub fn new(root_history_size: u32) -> Self
-    {
-        MerkleRootHistory {
-            root_history_size,
-            historical_roots: HashMap::new(),
-            next_root_index: 0,
-        }
+    // there is no L1 connection to query the contract's window and depth from
+    // yet, so surface them for operators to check against root_history_size()
+    // and tree_levels()
+    let root_history_size = lib_sanctum::utils::root_history_size();
+    info!(root_history_size, merkle_tree_levels = protocol::MERKLE_TREE_LEVELS, "verifier parameters");
+
+    // the root every tree starts from, as the sequencer computes it for the configured depth
+    let empty_root = empty_root();
+    info!(root_x = %empty_root.0, root_y = %empty_root.1, "genesis root");
+
+    AppStateType {
+        onramp_pvk,
+        onramp_multi_pvk,
+        payment_pvk,
+        swap_pvk,
+        transfer_pvk,
+        merkle_update_pvk,
+        operator_key: std::env::var("SANCTUM_OPERATOR_KEY")
+            .ok()
+            .map(|key| policy::operator_key_from_bs58(&key).unwrap()),
+        keys_version: applied.version,
+        merkle_root_histories: load_root_histories(root_history_size, &empty_root),
+        root_archive: RootArchive::from_env().expect("unable to open root archive"),
+        empty_root,
+        nullifiers: NullifierStore::new(),
+        audit_log: NullifierAuditLog::from_env().expect("unable to open audit log"),
+        apps: AppRegistry::new(),
+        statement,
+        peers: PeerPools::from_env().expect("unable to parse SANCTUM_PEER_POOLS"),
+        imports: HashSet::new(),
+        dedup: DedupWindow::from_env(),
     }
+}
+
+// the vks of every circuit the verifier checks proofs of, prepared, in the
+// order of the fields of AppStateType; every vk is read before any is swapped in
+fn load_keys(
+    reload: &KeyReload,
+    statement: &protocol::StatementParams
+) -> Result<([PreparedVerifyingKey<BW6_761>; 6], Vec<VkFingerprint>), String> {
+    let mut fingerprints = Vec::new();
+    let mut pvks = Vec::new();
+    for kind in [
+        protocol::CircuitKind::Onramp,
+        protocol::CircuitKind::OnrampMulti,
+        protocol::CircuitKind::Payment,
+        protocol::CircuitKind::Swap,
+        protocol::CircuitKind::Transfer,
+        protocol::CircuitKind::MerkleUpdate,
+    ] {
+        let vk = reload.verifying_key(kind, statement)
+            .map_err(|e| format!("unable to load the {:?} vk: {}", kind, e))?;
+        let fingerprint = key_reload::fingerprint(kind, &vk);
+        info!(circuit = %fingerprint.circuit, fingerprint = %fingerprint.fingerprint, "vk fingerprint");
 
-    // insert a new leaf into the merkle tree
-    pub fn insert(&mut self, root: &Hash) {
-        self.historical_roots.insert(self.next_root_index , root.clone());
-        self.next_root_index = (self.next_root_index + 1) % self.root_history_size;
+        fingerprints.push(fingerprint);
+        pvks.push(prepare_verifying_key(&vk));
     }
 
-    pub fn is_known_root(&self, root: &Hash) -> bool {
-        let start_index = self.next_root_index - 1;
-        let mut i = start_index;
+    Ok((pvks.try_into().unwrap_or_else(|_| unreachable!()), fingerprints))
+}
 
-        loop {
-            if !self.historical_roots.contains_key(&i) { return false; }
-            if self.historical_roots.get(&i).unwrap() == root { return true; }
+fn empty_root() -> (String, String) {
+    let root = utils::empty_tree().commitment();
 
-            if i == 0 { i = self.root_history_size; }
-            i = i - 1;
+    (
+        protocol::encode_constraintf_as_bs58_str(&root.x),
+        protocol::encode_constraintf_as_bs58_str(&root.y),
+    )
+}
 
-            if i == start_index { break; } // have we tried everything?
-        }
+// the window of a tree holding no coins yet, whose first update must extend
+// the empty root
+fn genesis_history(root_history_size: u32, empty_root: &(String, String)) -> MerkleRootHistory {
+    let mut history = MerkleRootHistory::new(root_history_size);
+    history.insert(empty_root);
 
-        return false;
+    history
+}
+
+// if SANCTUM_ROOT_HISTORY_FILE is set, the accepted-root windows are reloaded
+// from it at startup and rewritten after every insert; a file written before
+// trees rolled over holds the window of tree 0 alone
+fn load_root_histories(root_history_size: u32, empty_root: &(String, String)) -> Vec<MerkleRootHistory> {
+    let path = match std::env::var("SANCTUM_ROOT_HISTORY_FILE") {
+        Ok(path) => path,
+        Err(_) => return vec![genesis_history(root_history_size, empty_root)],
+    };
+
+    match std::fs::read(&path) {
+        Ok(bytes) => {
+            let mut histories: Vec<MerkleRootHistory> = serde_json::from_slice(&bytes)
+                .or_else(|_| serde_json::from_slice::<MerkleRootHistory>(&bytes).map(|h| vec![h]))
+                .expect("unable to parse root history file");
+            for history in histories.iter_mut() {
+                assert_eq!(history.root_history_size, root_history_size,
+                    "root history file was written with a different window");
+                // a window persisted empty is anchored at the empty root, like a new one
+                if history.get_latest_root().is_none() {
+                    history.insert(empty_root);
+                }
+            }
+            info!(trees = histories.len(), path = %path, "reloaded root history");
+            histories
+        },
+        Err(_) => vec![genesis_history(root_history_size, empty_root)],
     }
+}
 
-    pub fn get_latest_root(&self) -> Option<Hash> {
-        let last_index: u32 = self.next_root_index - 1;
-        return self.historical_roots.get(&last_index).cloned();
+fn persist_root_histories(histories: &[MerkleRootHistory]) {
+    if let Ok(path) = std::env::var("SANCTUM_ROOT_HISTORY_FILE") {
+        // write then rename, so a crash never leaves a truncated file behind
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_vec(histories).unwrap()).expect("unable to write root history");
+        std::fs::rename(&tmp, &path).expect("unable to write root history");
     }
 }
 
+mod test;
@@ -0,0 +1,318 @@
+#![cfg(test)]
+
+use ark_ec::CurveGroup;
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::{
+    JZVectorCommitmentOpeningProof, JZVectorDB, config::ed_on_bw6_761::MerkleTreeParams as MTParams,
+};
+
+use lib_sanctum::test_coins::test_coin;
+
+use super::*;
+
+// the onramp of a coin into the first leaf of an empty tree, bundled with the
+// merkle update inserting it, as the sequencer forwards it
+fn onramp_bundle(amount: u8) -> protocol::OnRampProofBs58 {
+    let coin = test_coin(amount);
+
+    let (onramp_pk, _) = lib_sanctum::onramp_circuit::circuit_setup();
+    let (proof, public_inputs) = lib_sanctum::onramp_circuit::generate_groth_proof(&onramp_pk, &coin);
+
+    let opening_proof = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>| JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(0).clone(),
+        path: db.proof(0),
+    };
+    let mut db = utils::empty_tree();
+    let old_merkle_proof = opening_proof(&db);
+    db.update(0, &coin.commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db);
+
+    let (merkle_update_pk, _) = lib_sanctum::merkle_update_circuit::circuit_setup();
+    let (update_proof, update_inputs) = lib_sanctum::merkle_update_circuit::generate_groth_proof(
+        &merkle_update_pk, &old_merkle_proof, &new_merkle_proof, 0
+    );
+
+    protocol::OnRampProofBs58 {
+        on_ramp_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs),
+        merkle_update_proof: protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &update_proof, &update_inputs),
+    }
+}
+
+#[actix_web::test]
+async fn test_retried_bundle_applied_once() {
+    use actix_web::{http::StatusCode, test};
+
+    let bundle = onramp_bundle(10);
+    let app_state = web::Data::new(
+        GlobalAppState {
+            state: Mutex::new(initialize_state()),
+        }
+    );
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    // the sequencer retries a forward whose first attempt went through
+    for _ in 0..2 {
+        let request = test::TestRequest::post().uri("/onramp").set_json(&bundle).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "OK");
+    }
+
+    // the root advanced once, to the root of the bundle's merkle update
+    let state = app_state.state.lock().unwrap();
+    let update_inputs = &bundle.merkle_update_proof.public_inputs;
+    let new_root = (
+        update_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_X as usize].clone(),
+        update_inputs[protocol::MerkleUpdateGrothPublicInput::NEW_ROOT_Y as usize].clone(),
+    );
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(new_root));
+    assert_eq!(state.root_archive.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_refused_bundle_leaves_verifier_serving() {
+    use actix_web::{http::StatusCode, test};
+
+    let app_state = web::Data::new(
+        GlobalAppState {
+            state: Mutex::new(initialize_state()),
+        }
+    );
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    // a payment of the right shape, but against a root the verifier never
+    // accepted, and with a proof that isn't a payment proof at all
+    let bundle = onramp_bundle(10);
+    let zero = protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(0u64));
+    let payment = protocol::PaymentProofBs58 {
+        payment_proof: protocol::GrothProofBs58 {
+            circuit: protocol::CircuitKind::Payment,
+            proof: bundle.on_ramp_proof.proof.clone(),
+            public_inputs: vec![zero; protocol::PaymentGrothPublicInput::TREE_ID as usize + 1],
+        },
+        merkle_update_proof: bundle.merkle_update_proof.clone(),
+    };
+
+    // it is refused with a 400 naming why, where it used to panic while
+    // holding the state lock and so poison it for every later request
+    let request = test::TestRequest::post().uri("/payment").set_json(&payment).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::read_body(response).await, "proof is against an unknown merkle root");
+
+    // as is a payment carrying a proof made for another circuit
+    let mislabeled = protocol::PaymentProofBs58 {
+        payment_proof: bundle.on_ramp_proof.clone(),
+        merkle_update_proof: bundle.merkle_update_proof.clone(),
+    };
+    let request = test::TestRequest::post().uri("/payment").set_json(&mislabeled).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::read_body(response).await, "expected a Payment proof, got a Onramp proof");
+
+    // and the verifier goes on applying bundles
+    let request = test::TestRequest::post().uri("/onramp").set_json(&bundle).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!app_state.state.is_poisoned());
+}
+
+#[test]
+fn test_update_must_insert_a_coin_commitment() {
+    let mut state = initialize_state();
+
+    // an update extending the latest root, but whose leaf is not a point on
+    // the curve, is refused before its proof is even checked
+    let mut update = onramp_bundle(10).merkle_update_proof;
+    update.public_inputs[protocol::MerkleUpdateGrothPublicInput::LEAF_VALUE_Y as usize] =
+        protocol::encode_constraintf_as_bs58_str(&ark_bw6_761::Fr::from(1u64));
+    let e = update_merkle_root(&mut state, &update).unwrap_err();
+    assert!(e.contains("not a coin commitment"), "{}", e);
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(state.empty_root.clone()));
+}
+
+#[test]
+fn test_first_update_must_extend_genesis() {
+    let mut state = initialize_state();
+    let genesis = state.empty_root.clone();
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(genesis.clone()));
+
+    // a valid merkle update, but one inserting into a tree that already holds
+    // a coin, as if the verifier had missed the update inserting it
+    let opening_proof = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>| JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(1).clone(),
+        path: db.proof(1),
+    };
+    let mut db = utils::empty_tree();
+    db.update(0, &test_coin(10).commitment().into_affine());
+    let old_merkle_proof = opening_proof(&db);
+    db.update(1, &test_coin(11).commitment().into_affine());
+    let new_merkle_proof = opening_proof(&db);
+
+    let (merkle_update_pk, _) = lib_sanctum::merkle_update_circuit::circuit_setup();
+    let (update_proof, update_inputs) = lib_sanctum::merkle_update_circuit::generate_groth_proof(
+        &merkle_update_pk, &old_merkle_proof, &new_merkle_proof, 1
+    );
+    let fabricated = protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &update_proof, &update_inputs);
+
+    // it is refused, and the history stays anchored at the genesis root
+    let refused = update_merkle_root(&mut state, &fabricated).unwrap_err();
+    assert!(refused.contains("does not extend the latest root"), "{}", refused);
+    assert_eq!(state.merkle_root_histories.len(), 1);
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(genesis));
+    assert_eq!(state.root_archive.len(), 0);
+}
+
+#[test]
+fn test_swap_applied_whole_or_not_at_all() {
+    let mut state = initialize_state();
+    let (prf_params, _, crs) = utils::trusted_setup();
+    let terms = lib_sanctum::swap_circuit::SwapTerms { amounts: [10, 10], nonce: [9u8; 32] };
+    let swap_id = terms.swap_id(&prf_params);
+    let (alice_sk, bob_sk) = ([1u8; 32], [2u8; 32]);
+
+    // a coin of 10 owned by sk
+    let coin = |sk: &[u8; 32], entropy: &[u8; 31], rho: u8| {
+        let mut fields = test_coin(10).fields.clone();
+        fields[protocol::UtxoField::ENTROPY as usize] = entropy.to_vec();
+        fields[protocol::UtxoField::OWNER as usize] = utils::derive_pubkey(&prf_params, sk).to_vec();
+        fields[protocol::UtxoField::RHO as usize] = vec![rho; 31];
+        JZRecord::<5>::new(&crs, &fields, &[0u8; 31].to_vec())
+    };
+    let (alice_coin, bob_coin) = (coin(&alice_sk, &[0u8; 31], 1), coin(&bob_sk, &[0u8; 31], 2));
+    let (to_bob, to_alice) = (coin(&bob_sk, &swap_id, 3), coin(&alice_sk, &swap_id, 4));
+
+    // both coins are in the tree the verifier last accepted the root of
+    let opening_proof = |db: &JZVectorDB<MTParams, ark_bls12_377::G1Affine>, index: usize| JZVectorCommitmentOpeningProof {
+        root: db.commitment(),
+        record: db.get_record(index).clone(),
+        path: db.proof(index),
+    };
+    let mut db = utils::empty_tree();
+    db.update(0, &alice_coin.commitment().into_affine());
+    db.update(1, &bob_coin.commitment().into_affine());
+    let root = db.commitment();
+    let pre_swap_root = (
+        protocol::encode_constraintf_as_bs58_str(&root.x),
+        protocol::encode_constraintf_as_bs58_str(&root.y),
+    );
+    state.merkle_root_histories.last_mut().unwrap().insert(&pre_swap_root);
+
+    let statement = state.statement;
+    let (swap_pk, _) = lib_sanctum::swap_circuit::circuit_setup(&statement);
+    let half = |coin: &JZRecord<5>, index: usize, output: &JZRecord<5>, sk: &[u8; 32], role: protocol::SwapRole| {
+        let (proof, public_inputs) = lib_sanctum::swap_circuit::generate_groth_proof(
+            &swap_pk, &statement, coin, output, &opening_proof(&db, index), sk, 0, &terms, role, &[4u8; 32]
+        );
+        protocol::groth_proof_to_bs58(protocol::CircuitKind::Swap, &proof, &public_inputs)
+    };
+    let proposal = half(&alice_coin, 0, &to_bob, &alice_sk, protocol::SwapRole::Proposal);
+    let acceptance = half(&bob_coin, 1, &to_alice, &bob_sk, protocol::SwapRole::Acceptance);
+
+    // the sequencer inserts both output coins, one after the other
+    let (merkle_update_pk, _) = lib_sanctum::merkle_update_circuit::circuit_setup();
+    let mut updates = vec![];
+    for (index, output) in [(2, &to_bob), (3, &to_alice)] {
+        let old_merkle_proof = opening_proof(&db, index);
+        db.update(index, &output.commitment().into_affine());
+        let (proof, public_inputs) = lib_sanctum::merkle_update_circuit::generate_groth_proof(
+            &merkle_update_pk, &old_merkle_proof, &opening_proof(&db, index), index
+        );
+        updates.push(protocol::groth_proof_to_bs58(protocol::CircuitKind::MerkleUpdate, &proof, &public_inputs));
+    }
+    let settlement = |second_update: &protocol::GrothProofBs58| protocol::SwapSettlementBs58 {
+        halves: vec![
+            protocol::PaymentProofBs58 { payment_proof: proposal.clone(), merkle_update_proof: updates[0].clone() },
+            protocol::PaymentProofBs58 { payment_proof: acceptance.clone(), merkle_update_proof: second_update.clone() },
+        ],
+    };
+    let nullifier = |half: &protocol::GrothProofBs58|
+        half.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone();
+
+    // a settlement whose second update does not extend the first's root is
+    // refused whole: the first half's root and nullifier are not recorded either
+    let refused = apply_swap(&mut state, &settlement(&updates[0])).unwrap_err();
+    assert!(refused.contains("does not extend the latest root"), "{}", refused);
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(pre_swap_root));
+    assert!(!state.nullifiers.contains(&nullifier(&proposal)));
+    assert_eq!(state.root_archive.len(), 0);
+
+    // nor are the halves taken in the other order
+    let mut reversed = settlement(&updates[1]);
+    reversed.halves.reverse();
+    assert_eq!(apply_swap(&mut state, &reversed).unwrap_err(), "the halves are not a proposal and its acceptance");
+
+    // the settlement as the sequencer forwards it applies both halves
+    apply_swap(&mut state, &settlement(&updates[1])).unwrap();
+    assert_eq!(state.merkle_root_histories.last().unwrap().get_latest_root(), Some(new_root_of(&updates[1])));
+    assert!(state.nullifiers.contains(&nullifier(&proposal)));
+    assert!(state.nullifiers.contains(&nullifier(&acceptance)));
+    assert_eq!(state.root_archive.len(), 2);
+}
+
+#[actix_web::test]
+async fn test_app_routes() {
+    use actix_web::{http::StatusCode, test};
+    use ark_serialize::CanonicalSerialize;
+
+    let operator = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let impostor = ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]);
+    let app_state = web::Data::new(
+        GlobalAppState {
+            state: Mutex::new(initialize_state()),
+        }
+    );
+    let app = test::init_service(App::new().app_data(app_state.clone()).configure(configure_routes)).await;
+
+    // the onramp circuit, registered as an app that inserts the coin it onramps
+    let (_, onramp_vk) = lib_sanctum::onramp_circuit::circuit_setup();
+    let mut vk_bytes: Vec<u8> = Vec::new();
+    onramp_vk.serialize_compressed(&mut vk_bytes).unwrap();
+    let registration = protocol::AppRegistrationBs58 {
+        name: "onramp".to_string(),
+        vk: bs58::encode(vk_bytes).into_string(),
+        public_inputs: ["asset_id", "amount", "commitment_x", "commitment_y"].map(String::from).to_vec(),
+        commitment_x_index: 2,
+        commitment_y_index: 3,
+    };
+    let register = |signed: &apps::SignedAppRegistration|
+        test::TestRequest::post().uri("/apps/register").set_json(signed).to_request();
+
+    // without an operator key nobody registers apps, and with one only the operator does
+    let response = test::call_service(&app, register(&apps::sign_registration(&registration, &operator))).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    app_state.state.lock().unwrap().operator_key = Some(operator.verifying_key());
+    let response = test::call_service(&app, register(&apps::sign_registration(&registration, &impostor))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = test::call_service(&app, register(&apps::sign_registration(&registration, &operator))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // a tx whose merkle update inserts another coin than the app declares is
+    // refused, as is one through an unknown app, without poisoning the state
+    let app_tx = |app: &str, bundle: &protocol::OnRampProofBs58, merkle_update_proof: &protocol::GrothProofBs58|
+        protocol::AppProofBs58 {
+            app_tx: protocol::AppTxBs58 {
+                app: app.to_string(),
+                proof: protocol::GrothProofBs58 { circuit: protocol::CircuitKind::App, ..bundle.on_ramp_proof.clone() },
+            },
+            merkle_update_proof: merkle_update_proof.clone(),
+        };
+    let submit = |tx: &protocol::AppProofBs58|
+        test::TestRequest::post().uri("/app_tx").set_json(tx).to_request();
+    let (bundle, other_bundle) = (onramp_bundle(10), onramp_bundle(11));
+
+    let response = test::call_service(&app, submit(&app_tx("onramp", &bundle, &other_bundle.merkle_update_proof))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::read_body(response).await, "merkle update does not insert the declared commitment");
+    let response = test::call_service(&app, submit(&app_tx("unknown", &bundle, &bundle.merkle_update_proof))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!app_state.state.is_poisoned());
+
+    // while the tx as the app declares it is applied
+    let response = test::call_service(&app, submit(&app_tx("onramp", &bundle, &bundle.merkle_update_proof))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app_state.state.lock().unwrap().root_archive.len(), 1);
+}
@@ -1,36 +1,40 @@
-use lib_sanctum::{ payment_circuit, onramp_circuit, utils};
+use lib_sanctum::{keys, logging, registry, utils};
 
 #[tokio::main]
 async fn main() -> reqwest::Result<()> {
     //parse_args();
+    logging::init(&logging::LogConfig::from_env(logging::DEFAULT_LEVEL)).unwrap();
     std::fs::create_dir_all("/tmp/sanctum").unwrap();
 
-    println!("initiating circuit setup for onramp circuit...");
-    let (onramp_pk, onramp_vk) = onramp_circuit::circuit_setup();
-    utils::write_groth_key_to_file(
-        &onramp_pk,
-        "/tmp/sanctum/onramp.pk",
-        &onramp_vk,
-        "/tmp/sanctum/onramp.vk"
-    );
+    // the keys are set up under the params the services will be configured with
+    let statement = utils::statement_params();
+    let mut manifest = Vec::new();
+    for descriptor in registry::CIRCUITS.iter() {
+        println!("initiating circuit setup for {} circuit...", descriptor.id);
+        let (pk, vk) = (descriptor.setup)(&statement);
+        let pk_path = format!("/tmp/sanctum/{}.pk", descriptor.id);
+        utils::write_groth_key_to_file(
+            &pk,
+            &pk_path,
+            &vk,
+            &format!("/tmp/sanctum/{}.vk", descriptor.id)
+        );
 
-    println!("initiating circuit setup for payment circuit...");
-    let (payment_pk, payment_vk) = payment_circuit::circuit_setup();
-    utils::write_groth_key_to_file(
-        &payment_pk,
-        "/tmp/sanctum/payment.pk",
-        &payment_vk,
-        "/tmp/sanctum/payment.vk"
-    );
+        manifest.push(serde_json::json!({
+            "id": descriptor.id,
+            "public_inputs": descriptor.input_labels(&statement),
+            "vk_fingerprint": hex::encode(keys::vk_fingerprint(descriptor.id, &vk)),
+            // what provers reading the pk unchecked compare the file against
+            "pk_sha256": hex::encode(utils::key_file_sha256(&pk_path)),
+        }));
+    }
 
-    println!("initiating circuit setup for merkle update circuit...");
-    let (merkle_update_pk, merkle_update_vk) = lib_sanctum::merkle_update_circuit::circuit_setup();
-    utils::write_groth_key_to_file(
-        &merkle_update_pk,
-        "/tmp/sanctum/merkle_update.pk",
-        &merkle_update_vk,
-        "/tmp/sanctum/merkle_update.vk"
-    );
+    // the manifest lets operators check which keys, and which input layouts,
+    // a deployment was set up with
+    std::fs::write(
+        format!("/tmp/sanctum/{}", registry::MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest).unwrap()
+    ).unwrap();
 
     println!("completed trusted setup...");
 
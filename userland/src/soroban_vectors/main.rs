@@ -0,0 +1,160 @@
+use ark_bls12_377::{Bls12_377, Fr};
+use ark_ff::{Field, PrimeField};
+use ark_groth16::{Groth16, Proof, VerifyingKey};
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_serialize::CanonicalSerialize;
+use ark_snark::SNARK;
+use rand_chacha::rand_core::SeedableRng;
+use sha2::{Digest, Sha256};
+
+use lib_sanctum::protocol;
+
+/// CubeCircuit proves knowledge of an x whose square and cube are the public
+/// inputs. The soroban verifier checks groth16 proofs over BLS12-377, which
+/// none of the protocol circuits (over BW6-761) produce, so the vectors are
+/// proofs of this statement instead; they pin down the byte encodings alone.
+struct CubeCircuit {
+    x: Fr,
+}
+
+impl ConstraintSynthesizer<Fr> for CubeCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let square = FpVar::new_input(cs.clone(), || Ok(self.x.square()))?;
+        let cube = FpVar::new_input(cs.clone(), || Ok(self.x.square() * self.x))?;
+        let x = FpVar::new_witness(cs, || Ok(self.x))?;
+
+        square.enforce_equal(&(&x * &x))?;
+        cube.enforce_equal(&(&square * &x))?;
+
+        Ok(())
+    }
+}
+
+/// PaymentInputsCircuit stands in for the statement the payment contract has
+/// proven: its public inputs are a payment's, packed as the contract packs
+/// them, so that the contract can be run against a real verifier
+struct PaymentInputsCircuit {
+    inputs: Vec<Fr>,
+}
+
+impl ConstraintSynthesizer<Fr> for PaymentInputsCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        for input in self.inputs.iter() {
+            let public = FpVar::new_input(cs.clone(), || Ok(*input))?;
+            let witness = FpVar::new_witness(cs.clone(), || Ok(*input))?;
+            public.enforce_equal(&witness)?;
+        }
+
+        Ok(())
+    }
+}
+
+// keys from a fixed seed, so that regenerating the vectors yields the same bytes
+fn setup(seed: u8) -> (ark_groth16::ProvingKey<Bls12_377>, VerifyingKey<Bls12_377>) {
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([seed; 32]);
+    Groth16::<Bls12_377>::circuit_specific_setup(CubeCircuit { x: Fr::from(0u64) }, &mut rng).unwrap()
+}
+
+fn to_hex<T: CanonicalSerialize>(value: &T) -> String {
+    let mut bytes = Vec::new();
+    value.serialize_uncompressed(&mut bytes).unwrap();
+    hex::encode(bytes)
+}
+
+fn vector(
+    name: &str,
+    vk: &VerifyingKey<Bls12_377>,
+    proof_bytes: String,
+    image: &[Fr],
+    expected: bool
+) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "vk_bytes": to_hex(vk),
+        "proof_bytes": proof_bytes,
+        "image_bytes": image.iter().map(to_hex).collect::<Vec<String>>(),
+        "expected": expected,
+    })
+}
+
+// the root the payment contract starts each tree at, zeros(levels - 1) in its utils
+fn contract_empty_root() -> [u8; 32] {
+    let mut node: [u8; 32] = Sha256::digest([0u8; 32]).into();
+    for _ in 1..protocol::MERKLE_TREE_LEVELS {
+        node = Sha256::digest([node, node].concat()).into();
+    }
+    node
+}
+
+// a payment of the coin sha256([0; 32]) against the empty root, spending the
+// nullifier sha256([1; 32]), proven under its own keys
+fn payment_vector() -> serde_json::Value {
+    let root = contract_empty_root();
+    let new_coin_hash: [u8; 32] = Sha256::digest([0u8; 32]).into();
+    let nullifier: [u8; 32] = Sha256::digest([1u8; 32]).into();
+
+    let image: Vec<Fr> = protocol::contract_payment_inputs(&root, &new_coin_hash, &nullifier)
+        .iter()
+        .map(|input| Fr::from_le_bytes_mod_order(input))
+        .collect();
+
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([3u8; 32]);
+    let blank = PaymentInputsCircuit { inputs: vec![Fr::from(0u64); image.len()] };
+    let (pk, vk) = Groth16::<Bls12_377>::circuit_specific_setup(blank, &mut rng).unwrap();
+    let proof = Groth16::<Bls12_377>::prove(&pk, PaymentInputsCircuit { inputs: image.clone() }, &mut rng).unwrap();
+    assert!(Groth16::<Bls12_377>::verify(&vk, &image, &proof).unwrap());
+
+    serde_json::json!({
+        "name": "payment",
+        "vk_bytes": to_hex(&vk),
+        "proof_bytes": to_hex(&proof),
+        "root": hex::encode(root),
+        "new_coin_hash": hex::encode(new_coin_hash),
+        "nullifier": hex::encode(nullifier),
+        "image_bytes": image.iter().map(to_hex).collect::<Vec<String>>(),
+    })
+}
+
+// usage: gen-soroban-vectors [--payment] <out.json>
+// writes the groth verifier's vectors, or with --payment the payment
+// contract's
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 3 && args[1] == "--payment" {
+        let vector = payment_vector();
+        std::fs::write(&args[2], serde_json::to_string_pretty(&vector).unwrap() + "\n").unwrap();
+        println!("wrote the payment vector to {}", args[2]);
+        return;
+    }
+    if args.len() != 2 {
+        eprintln!("usage: {} [--payment] <out.json>", args[0]);
+        std::process::exit(2);
+    }
+
+    let (pk, vk) = setup(0);
+    let (_, other_vk) = setup(1);
+
+    let x = Fr::from(3u64);
+    let image = [x.square(), x.square() * x];
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([2u8; 32]);
+    let proof: Proof<Bls12_377> = Groth16::<Bls12_377>::prove(&pk, CubeCircuit { x }, &mut rng).unwrap();
+    assert!(Groth16::<Bls12_377>::verify(&vk, &image, &proof).unwrap());
+
+    let wrong_image = [image[0], image[1] + Fr::from(1u64)];
+
+    // flips a bit of A's x coordinate, leaving the encoding's flags alone
+    let mut corrupted = hex::decode(to_hex(&proof)).unwrap();
+    corrupted[0] ^= 1;
+
+    let vectors = vec![
+        vector("valid proof", &vk, to_hex(&proof), &image, true),
+        vector("wrong public input", &vk, to_hex(&proof), &wrong_image, false),
+        vector("corrupted proof byte", &vk, hex::encode(corrupted), &image, false),
+        vector("wrong vk", &other_vk, to_hex(&proof), &image, false),
+    ];
+
+    std::fs::write(&args[1], serde_json::to_string_pretty(&vectors).unwrap() + "\n").unwrap();
+    println!("wrote {} vectors to {}", vectors.len(), args[1]);
+}
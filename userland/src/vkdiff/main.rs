@@ -0,0 +1,28 @@
+use lib_sanctum::{keys, utils};
+
+// usage: vkdiff <a.vk> <b.vk>
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <a.vk> <b.vk>", args[0]);
+        std::process::exit(2);
+    }
+
+    let read = |path: &str| utils::read_groth_verification_key_from_file(path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let a = read(&args[1]);
+    let b = read(&args[2]);
+
+    let diffs = keys::vk_diff(&a, &b);
+    if diffs.is_empty() {
+        println!("verifying keys are identical");
+        return;
+    }
+
+    for diff in diffs.iter() {
+        println!("{}", diff);
+    }
+    std::process::exit(1);
+}
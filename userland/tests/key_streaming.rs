@@ -0,0 +1,73 @@
+// measures the peak heap of reading a proving key; this is its own test
+// binary, since dhat replaces the global allocator and allows only one
+// profiler per process
+
+use ark_bw6_761::{BW6_761, Fr};
+use ark_groth16::{Groth16, ProvingKey};
+use ark_relations::lc;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_serialize::CanonicalDeserialize;
+use ark_snark::SNARK;
+use rand_chacha::rand_core::SeedableRng;
+
+use lib_sanctum::utils;
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+// a chain of squarings, whose keys grow with its length
+struct Squarings(usize);
+
+impl ConstraintSynthesizer<Fr> for Squarings {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let mut x = cs.new_witness_variable(|| Ok(Fr::from(3u64)))?;
+        for _ in 0..self.0 {
+            let y = cs.new_witness_variable(|| Ok(Fr::from(9u64)))?;
+            cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + y)?;
+            x = y;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_proving_key_is_streamed() {
+    let dir = std::env::temp_dir().join(format!("sanctum-key-streaming-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pk_path = dir.join("medium.pk").to_str().unwrap().to_string();
+    let vk_path = dir.join("medium.vk").to_str().unwrap().to_string();
+
+    // a key of a few MB, set up before profiling so only the reads count
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed([0u8; 32]);
+    let (pk, vk) = Groth16::<BW6_761>::circuit_specific_setup(Squarings(1 << 13), &mut rng).unwrap();
+    utils::write_groth_key_to_file(&pk, &pk_path, &vk, &vk_path);
+    let file_len = std::fs::metadata(&pk_path).unwrap().len() as usize;
+    let checksum = utils::key_file_sha256(&pk_path);
+
+    let _profiler = dhat::Profiler::builder().testing().build();
+
+    // streamed, checked or not, the key itself is the most held at once...
+    let streamed = utils::read_groth_proving_key_from_file(&pk_path).unwrap();
+    dhat::assert!(streamed == pk);
+    drop(streamed);
+    let unchecked = utils::read_groth_proving_key_from_file_unchecked(&pk_path, &checksum).unwrap();
+    dhat::assert!(unchecked == pk);
+    drop(unchecked);
+    let streaming_peak = dhat::HeapStats::get().max_bytes;
+
+    // ...where reading the file whole first also held a copy of its bytes
+    let bytes = std::fs::read(&pk_path).unwrap();
+    let header_len = 8 + std::mem::size_of_val(&utils::KEY_FILE_VERSION);
+    let whole = ProvingKey::<BW6_761>::deserialize_uncompressed(&bytes[header_len..]).unwrap();
+    drop((bytes, whole));
+    let whole_file_peak = dhat::HeapStats::get().max_bytes;
+    dhat::assert!(whole_file_peak >= streaming_peak + file_len / 2);
+
+    // and an unchecked read refuses a file that isn't the one setup wrote
+    let mut other = checksum;
+    other[0] ^= 1;
+    dhat::assert!(utils::read_groth_proving_key_from_file_unchecked(&pk_path, &other).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,66 @@
+// counts the heap allocations of assembling a payment circuit; this is its own
+// test binary, since dhat replaces the global allocator and allows only one
+// profiler per process
+
+use lib_mpc_zexe::record_commitment::kzg::JZRecord;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::*;
+use lib_mpc_zexe::vector_commitment::bytes::pedersen::config::ed_on_bw6_761::MerkleTreeParams as MTParams;
+
+use lib_sanctum::payment_circuit::PaymentCircuit;
+use lib_sanctum::utils;
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+// the heap blocks allocated while running f
+fn blocks_allocated<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    let before = dhat::HeapStats::get().total_blocks;
+    let value = f();
+    (value, dhat::HeapStats::get().total_blocks - before)
+}
+
+#[test]
+fn test_payment_circuit_borrows_its_witness() {
+    let _profiler = dhat::Profiler::builder().testing().build();
+
+    let (prf_params, vc_params, crs) = utils::shared_setup();
+    let db = utils::empty_tree();
+    let merkle_proof = JZVectorCommitmentOpeningProof::<MTParams, ark_bls12_377::G1Affine> {
+        root: db.commitment(),
+        record: db.get_record(0).clone(),
+        path: db.proof(0),
+    };
+    let input_utxo: JZRecord<5> = utils::get_dummy_utxo(crs);
+    let output_utxo: JZRecord<5> = utils::get_dummy_utxo(crs);
+
+    // the circuit borrows the parameters, coins and opening proof...
+    let (circuit, borrowed) = blocks_allocated(|| PaymentCircuit {
+        crs,
+        prf_params,
+        vc_params,
+        input_utxo: &input_utxo,
+        output_utxo: &output_utxo,
+        sk: [0u8; 32],
+        asset_blind: [0u8; 32],
+        pool_id: None,
+        tree_id: 0,
+        expose_leaf_index: false,
+        unspent_coin_existence_proof: &merkle_proof,
+    });
+    drop(circuit);
+    dhat::assert_eq!(borrowed, 0);
+
+    // ...where it used to own copies of all of them, as cloned here
+    let (copies, cloned) = blocks_allocated(|| (
+        utils::trusted_setup(),
+        input_utxo.clone(),
+        output_utxo.clone(),
+        merkle_proof.clone(),
+    ));
+    drop(copies);
+    dhat::assert!(cloned > 0);
+
+    // and sampling the parameters again happens only once per process
+    let (_, resampled) = blocks_allocated(utils::shared_setup);
+    dhat::assert_eq!(resampled, 0);
+}
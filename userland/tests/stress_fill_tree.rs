@@ -0,0 +1,164 @@
+// fills the whole tree with onramps and the payments spending them, through
+// a real sequencer forwarding to a real verifier, checking after every tx that
+// the two agree on the root, and that a replayed payment is refused by both.
+// It proves a few hundred txs and binds the services' fixed ports, so it
+// only runs when asked for:
+//     cargo test --features insecure-setup --test stress_fill_tree -- --ignored
+
+use std::net::TcpStream;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+
+use lib_sanctum::{corpus, onramp_circuit, protocol, registry, test_coins, utils};
+
+const VERIFIER_ADDR: &str = "127.0.0.1:8081";
+const SEQUENCER_ADDR: &str = "127.0.0.1:8080";
+
+// without a key dir, each service runs the insecure setup itself
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(600);
+
+// a service, killed when the test ends however it ends
+struct Service(Child);
+
+impl Service {
+    fn spawn(path: &str, addr: &str) -> Self {
+        // neither service may pick up state left behind by an earlier run
+        let child = Command::new(path)
+            .env_remove("SANCTUM_KEY_DIR")
+            .env_remove("SANCTUM_TX_LOG")
+            .env_remove("SANCTUM_ROOT_HISTORY_FILE")
+            .env_remove("SANCTUM_ROOT_ARCHIVE")
+            .env_remove("SANCTUM_AUDIT_LOG")
+            .env("SANCTUM_VERIFIER_URLS", format!("http://{}", VERIFIER_ADDR))
+            .spawn()
+            .unwrap_or_else(|e| panic!("failed to start {}: {}", path, e));
+        let mut service = Service(child);
+
+        let start = Instant::now();
+        while TcpStream::connect(addr).is_err() {
+            assert!(service.0.try_wait().unwrap().is_none(), "{} exited before listening", path);
+            assert!(start.elapsed() < STARTUP_TIMEOUT, "timed out waiting for {}", addr);
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        service
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+async fn submit<T: serde::Serialize>(client: &Client, route: &str, body: &T) -> protocol::TxResponse {
+    client.post(format!("http://{}{}", SEQUENCER_ADDR, route))
+        .json(body)
+        .send().await.unwrap()
+        .json().await.unwrap()
+}
+
+async fn sequencer_tree(client: &Client) -> protocol::TreeSnapshot {
+    client.get(format!("http://{}/tree", SEQUENCER_ADDR))
+        .send().await.unwrap()
+        .json().await.unwrap()
+}
+
+// the root the verifier accepted at block, i.e. with the block+1'th update
+async fn verifier_root(client: &Client, block: usize) -> (String, String) {
+    let range: protocol::RootRangeBs58 = client
+        .get(format!("http://{}/root/history?from={}&limit=1", VERIFIER_ADDR, block))
+        .send().await.unwrap()
+        .json().await.unwrap();
+    assert_eq!(range.roots.len(), 1, "the verifier accepted no root at block {}", block);
+
+    let root = &range.roots[0];
+    (root.root_x.clone(), root.root_y.clone())
+}
+
+async fn assert_roots_agree(client: &Client, block: usize) {
+    let tree = sequencer_tree(client).await;
+    assert_eq!(verifier_root(client, block).await, (tree.root_x, tree.root_y));
+}
+
+#[actix_web::test]
+#[ignore]
+async fn test_stress_fill_tree() {
+    // half the leaves hold funded coins, the other half the payments spending them
+    let capacity = 1usize << protocol::MERKLE_TREE_LEVELS;
+    let n = capacity / 2;
+    // the services are spawned with this process's env, so their keys are set up under the same params
+    let statement = utils::statement_params();
+    let corpus = corpus::generate(n, &statement).unwrap();
+
+    // the verifier must be up before the sequencer forwards anything to it
+    let _verifier = Service::spawn(env!("CARGO_BIN_EXE_verifier"), VERIFIER_ADDR);
+    let _sequencer = Service::spawn(env!("CARGO_BIN_EXE_sequencer"), SEQUENCER_ADDR);
+    let client = Client::new();
+
+    let mut block = 0;
+    for tx in corpus.onramps.iter() {
+        let response = submit(&client, "/onramp", tx).await;
+        assert!(response.accepted, "onramp refused: {:?}", response.error);
+        assert_roots_agree(&client, block).await;
+        block += 1;
+    }
+
+    // the root history window wraps many times over these
+    for input in corpus.payments.iter() {
+        let response = submit(&client, "/payment", input).await;
+        assert!(response.accepted, "payment refused: {:?}", response.error);
+        assert_roots_agree(&client, block).await;
+        block += 1;
+
+        // a double spend is refused by the sequencer, and leaves its tree alone...
+        let num_coins = sequencer_tree(&client).await.num_coins;
+        let response = submit(&client, "/payment", input).await;
+        assert_eq!(response.error.as_deref(), Some("duplicate nullifier"));
+        assert_eq!(sequencer_tree(&client).await.num_coins, num_coins);
+
+        // ...and by the verifier, before it looks at the update it came with
+        let replay = protocol::PaymentProofBs58 {
+            payment_proof: input.payment_proof.clone(),
+            merkle_update_proof: protocol::GrothProofBs58 {
+                circuit: protocol::CircuitKind::MerkleUpdate,
+                ..input.payment_proof.clone()
+            },
+        };
+        let response = client.post(format!("http://{}/payment", VERIFIER_ADDR))
+            .json(&replay)
+            .send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(response.text().await.unwrap(), "duplicate nullifier");
+    }
+
+    // both services end up with the same root, and every nullifier spent
+    let tree = sequencer_tree(&client).await;
+    assert_eq!((tree.tree_id, tree.num_coins), (0, capacity));
+    assert_roots_agree(&client, block - 1).await;
+
+    let nullifiers = corpus.payments.iter()
+        .map(|input| input.payment_proof.public_inputs[protocol::PaymentGrothPublicInput::NULLIFIER as usize].clone())
+        .collect();
+    let status: protocol::NullifierStatusBs58 = client
+        .post(format!("http://{}/nullifiers/status", VERIFIER_ADDR))
+        .json(&protocol::NullifierStatusRequestBs58 { nullifiers })
+        .send().await.unwrap()
+        .json().await.unwrap();
+    assert!(status.spent.iter().all(|spent| *spent));
+
+    // a full tree rolls over rather than refusing further coins, on both
+    let (onramp_pk, _) = registry::keys(protocol::CircuitKind::Onramp, &statement).unwrap();
+    // a coin the corpus never onramps, to overflow the full tree with
+    let coin = test_coins::coin(&[1u8; 31], 1, 0xff);
+    let (proof, public_inputs) = onramp_circuit::generate_groth_proof(&onramp_pk, &coin);
+    let tx = protocol::groth_proof_to_bs58(protocol::CircuitKind::Onramp, &proof, &public_inputs);
+
+    let response = submit(&client, "/onramp", &tx).await;
+    assert!(response.accepted, "onramp into a new tree refused: {:?}", response.error);
+    let tree = sequencer_tree(&client).await;
+    assert_eq!((tree.tree_id, tree.num_coins), (1, 1));
+    assert_roots_agree(&client, block).await;
+}